@@ -18,12 +18,22 @@ pub fn run() {
             net_ops::check_nat_type,
             system::get_system_info,
             net_ops::run_nslookup,
+            net_ops::run_dns_benchmark,
+            net_ops::test_encrypted_dns,
             net_ops::run_traceroute,
             net_ops::run_port_scan,
             net_ops::run_throughput_test,
             net_ops::get_geo_ip,
             net_ops::scan_local_network,
             pcap_analysis::analyze_pcap_file,
+            pcap_analysis::cancel_pcap_analysis,
+            pcap_analysis::get_pcap_packets,
+            pcap_analysis::get_pcap_packet_detail,
+            pcap_analysis::follow_pcap_stream,
+            pcap_analysis::get_pcap_io_graph,
+            pcap_analysis::compare_pcap_files,
+            pcap_analysis::sanitize_pcap_file,
+            pcap_analysis::start_pcap_capture,
             wifi::get_wifi_signal_strength
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,568 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use hickory_resolver::proto::op::{Message, MessageType, OpCode, Query};
+use hickory_resolver::proto::rr::{Name, RecordType};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc::Sender;
+
+use crate::modules::utils::{
+    DnsLookupResult, DnsRecord, DnsBenchmarkResult, DnsBenchmarkResolver, DnsDisagreement, DnsBenchmarkProgress,
+    get_default_gateway
+};
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+pub const BENCHMARK_DOMAINS: &[&str] = &[
+    "google.com", "youtube.com", "facebook.com", "amazon.com", "wikipedia.org",
+    "microsoft.com", "apple.com", "netflix.com", "cloudflare.com", "github.com",
+];
+const PUBLIC_RESOLVERS: &[(&str, [u8; 4])] = &[
+    ("Cloudflare", [1, 1, 1, 1]),
+    ("Google", [8, 8, 8, 8]),
+    ("Quad9", [9, 9, 9, 9]),
+];
+// A resolver that fails this many queries before answering any is given up on.
+const UNREACHABLE_AFTER: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+impl Transport {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "" | "udp" => Ok(Transport::Udp),
+            "tcp" => Ok(Transport::Tcp),
+            other => Err(format!("Unknown DNS transport '{}'", other)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Transport::Udp => "UDP",
+            Transport::Tcp => "TCP",
+        }
+    }
+}
+
+/// Parses a nameserver given as `1.1.1.1`, `1.1.1.1:5353`, `2606:4700::1111` or
+/// `[2606:4700::1111]:53`.
+pub fn parse_nameserver(s: &str) -> Result<SocketAddr, String> {
+    let s = s.trim();
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    s.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .map_err(|_| format!("'{}' is not a nameserver address", s))
+}
+
+/// The nameservers from the system configuration (resolv.conf or the Windows registry),
+/// without duplicates from the UDP/TCP pairs hickory lists.
+pub fn system_nameservers() -> Result<Vec<SocketAddr>, String> {
+    let (config, _) = hickory_resolver::system_conf::read_system_conf().map_err(|e| e.to_string())?;
+    let mut servers: Vec<SocketAddr> = Vec::new();
+    for ns in config.name_servers() {
+        if !servers.contains(&ns.socket_addr) {
+            servers.push(ns.socket_addr);
+        }
+    }
+    if servers.is_empty() {
+        return Err("No nameservers are configured".into());
+    }
+    Ok(servers)
+}
+
+/// The name to ask for: IP addresses become their reverse (`in-addr.arpa`/`ip6.arpa`)
+/// name for PTR lookups, anything else must be a valid domain.
+pub fn query_name(domain: &str, record_type: RecordType) -> Result<Name, String> {
+    let domain = domain.trim();
+    if record_type == RecordType::PTR {
+        if let Ok(ip) = domain.parse::<IpAddr>() {
+            return Ok(Name::from(ip));
+        }
+    }
+    let mut name = Name::from_str(domain).map_err(|e| format!("'{}' is not a valid name: {}", domain, e))?;
+    name.set_fqdn(true);
+    Ok(name)
+}
+
+pub fn parse_record_type(s: &str) -> Result<RecordType, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(RecordType::A);
+    }
+    RecordType::from_str(&s.to_ascii_uppercase()).map_err(|_| format!("Unknown record type '{}'", s))
+}
+
+/// A recursive query for `name`.
+pub fn query_message(name: &Name, record_type: RecordType, id: u16) -> Message {
+    let mut message = Message::new();
+    message.set_id(id)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .add_query(Query::query(name.clone(), record_type));
+    message
+}
+
+/// The same query in wire format.
+pub fn build_query(name: &Name, record_type: RecordType, id: u16) -> Result<Vec<u8>, String> {
+    query_message(name, record_type, id).to_vec().map_err(|e| e.to_string())
+}
+
+/// Sends `query` to `server` and returns the matching response. Over UDP, datagrams
+/// with the wrong ID or from the wrong address are ignored.
+pub async fn exchange(server: SocketAddr, transport: Transport, query: &[u8]) -> Result<Vec<u8>, String> {
+    let id = &query[..2];
+    let io = async {
+        match transport {
+            Transport::Udp => {
+                let local: SocketAddr = if server.is_ipv4() {
+                    (Ipv4Addr::UNSPECIFIED, 0).into()
+                } else {
+                    (Ipv6Addr::UNSPECIFIED, 0).into()
+                };
+                let socket = UdpSocket::bind(local).await?;
+                socket.connect(server).await?;
+                socket.send(query).await?;
+                let mut buf = vec![0u8; 65535];
+                loop {
+                    let len = socket.recv(&mut buf).await?;
+                    if len >= 12 && &buf[..2] == id {
+                        buf.truncate(len);
+                        return Ok(buf);
+                    }
+                }
+            }
+            Transport::Tcp => {
+                let mut stream = TcpStream::connect(server).await?;
+                let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+                framed.extend_from_slice(query);
+                stream.write_all(&framed).await?;
+                let len = stream.read_u16().await? as usize;
+                let mut buf = vec![0u8; len];
+                stream.read_exact(&mut buf).await?;
+                // One query per connection, so anything else is a broken or spoofed reply.
+                if len < 12 || &buf[..2] != id {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "response does not match the query ID"));
+                }
+                Ok(buf)
+            }
+        }
+    };
+    match tokio::time::timeout(TIMEOUT, io).await {
+        Ok(res) => res.map_err(|e: std::io::Error| e.to_string()),
+        Err(_) => Err(format!("No response from {} within {}s", server, TIMEOUT.as_secs())),
+    }
+}
+
+pub fn new_result(domain: &str, type_str: &str, transport: &str) -> DnsLookupResult {
+    DnsLookupResult {
+        domain: domain.to_string(),
+        record_type: type_str.to_ascii_uppercase(),
+        nameserver: String::new(),
+        transport: transport.to_ascii_uppercase(),
+        status: "Error".to_string(),
+        authoritative: false,
+        truncated: false,
+        recursion_available: false,
+        time_ms: None,
+        records: vec![],
+        details: String::new(),
+    }
+}
+
+/// Turns a response into the lookup result. `status` is the response code.
+pub fn parse_response(data: &[u8], result: &mut DnsLookupResult) -> Result<(), String> {
+    let message = Message::from_vec(data).map_err(|e| format!("Malformed response: {}", e))?;
+    // The RFC mnemonic: NOERROR, NXDOMAIN, SERVFAIL, ...
+    result.status = format!("{:?}", message.response_code()).to_ascii_uppercase();
+    result.authoritative = message.authoritative();
+    result.truncated = message.truncated();
+    result.recursion_available = message.recursion_available();
+    result.records = message.answers().iter().map(|record| DnsRecord {
+        name: record.name().to_string(),
+        record_type: record.record_type().to_string(),
+        value: record.data().to_string(),
+        ttl: record.ttl(),
+    }).collect();
+    Ok(())
+}
+
+/// Looks up `domain` for one record type, against `nameserver` or else the system's
+/// nameservers in order until one answers. `transport` is "udp" (the default) or "tcp".
+pub async fn lookup(domain: &str, type_str: &str, nameserver: Option<&str>, transport: &str) -> DnsLookupResult {
+    let mut result = new_result(domain, type_str, transport);
+
+    let prepared = Transport::parse(transport).and_then(|transport| {
+        let record_type = parse_record_type(type_str)?;
+        let name = query_name(domain, record_type)?;
+        let servers = match nameserver.filter(|ns| !ns.trim().is_empty()) {
+            Some(ns) => vec![parse_nameserver(ns)?],
+            None => system_nameservers()?,
+        };
+        Ok((transport, record_type, name, servers))
+    });
+    let (transport, record_type, name, servers) = match prepared {
+        Ok(p) => p,
+        Err(e) => {
+            result.details = e;
+            return result;
+        }
+    };
+    result.record_type = record_type.to_string();
+    result.transport = transport.name().to_string();
+
+    let query = match build_query(&name, record_type, rand::random()) {
+        Ok(q) => q,
+        Err(e) => {
+            result.details = e;
+            return result;
+        }
+    };
+    let mut errors = Vec::new();
+    for server in servers {
+        result.nameserver = server.to_string();
+        let start = Instant::now();
+        let response = exchange(server, transport, &query).await;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        match response.and_then(|data| parse_response(&data, &mut result)) {
+            Ok(()) => {
+                result.time_ms = Some(elapsed);
+                result.details = format!("{} answer(s) from {} over {} in {:.1} ms", result.records.len(), server, transport.name(), elapsed);
+                if result.truncated && transport == Transport::Udp {
+                    result.details.push_str("; truncated, retry over TCP for the full answer");
+                }
+                return result;
+            }
+            Err(e) => errors.push(format!("{}: {}", server, e)),
+        }
+    }
+    result.details = errors.join("; ");
+    result
+}
+
+/// The resolvers to compare: the system's, the gateway, the public ones and any custom
+/// ones. A server in several roles is listed once, e.g. as "System/Gateway".
+pub fn benchmark_resolvers(custom: &[String]) -> Result<Vec<(String, SocketAddr)>, String> {
+    let mut resolvers: Vec<(String, SocketAddr)> = Vec::new();
+    let mut add = |name: &str, addr: SocketAddr| match resolvers.iter_mut().find(|(_, a)| *a == addr) {
+        Some((existing, _)) if !existing.split('/').any(|n| n == name) => *existing = format!("{}/{}", existing, name),
+        Some(_) => {}
+        None => resolvers.push((name.to_string(), addr)),
+    };
+    for addr in system_nameservers().unwrap_or_default() {
+        add("System", addr);
+    }
+    if let Some(gateway) = get_default_gateway() {
+        add("Gateway", SocketAddr::new(gateway, 53));
+    }
+    for (name, ip) in PUBLIC_RESOLVERS {
+        add(name, SocketAddr::from((*ip, 53)));
+    }
+    for ns in custom.iter().filter(|ns| !ns.trim().is_empty()) {
+        add("Custom", parse_nameserver(ns)?);
+    }
+    Ok(resolvers)
+}
+
+#[derive(Clone, Debug)]
+struct Answer {
+    status: String,
+    addresses: Vec<String>,
+}
+
+struct ResolverRun {
+    label: String,
+    stats: DnsBenchmarkResolver,
+    answers: HashMap<String, Answer>,
+}
+
+/// Queries every domain's A record twice against each resolver (cold, then again for the
+/// cached latency), plus a name that cannot exist to catch NXDOMAIN hijacking, and
+/// compares the answers across resolvers. Resolvers run in parallel; each query is
+/// reported on `progress`.
+pub async fn benchmark(resolvers: Vec<(String, SocketAddr)>, domains: Vec<String>, progress: Sender<DnsBenchmarkProgress>) -> DnsBenchmarkResult {
+    let start = Instant::now();
+    let domains = Arc::new(domains);
+    // Random so that no resolver can have it cached, under a real TLD since hijackers
+    // tend to leave .invalid alone.
+    let probe = Arc::new(format!("nxdomain-check-{:016x}.com", rand::random::<u64>()));
+    let total = resolvers.len() * (domains.len() * 2 + 1);
+    let completed = Arc::new(AtomicUsize::new(0));
+
+    let handles: Vec<_> = resolvers.into_iter().map(|(name, addr)| {
+        let (domains, probe, progress, completed) = (domains.clone(), probe.clone(), progress.clone(), completed.clone());
+        tokio::spawn(async move {
+            bench_resolver(name, addr, &domains, &probe, &progress, &completed, total).await
+        })
+    }).collect();
+    let mut runs = Vec::new();
+    for handle in handles {
+        if let Ok(run) = handle.await {
+            runs.push(run);
+        }
+    }
+
+    let disagreements = find_disagreements(&domains, &runs);
+    for run in runs.iter_mut() {
+        run.stats.disagreements = disagreements.iter().filter(|d| d.resolver == run.label).count();
+    }
+    let mut resolvers: Vec<DnsBenchmarkResolver> = runs.into_iter().map(|run| run.stats).collect();
+    resolvers.sort_by(|a, b| match (a.cold_median_ms, b.cold_median_ms) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (x, y) => y.is_some().cmp(&x.is_some()),
+    });
+
+    let fastest = resolvers.first().filter(|r| r.cold_median_ms.is_some());
+    let mut details = match fastest {
+        Some(r) => vec![format!("Fastest: {} ({}) at {:.1} ms cold median", r.name, r.address, r.cold_median_ms.unwrap_or_default())],
+        None => vec!["No resolver answered".to_string()],
+    };
+    let hijackers: Vec<&str> = resolvers.iter().filter(|r| r.nxdomain_hijack).map(|r| r.name.as_str()).collect();
+    if !hijackers.is_empty() {
+        details.push(format!("NXDOMAIN hijacked by {}", hijackers.join(", ")));
+    }
+    if !disagreements.is_empty() {
+        details.push(format!("{} inconsistent answer(s)", disagreements.len()));
+    }
+
+    DnsBenchmarkResult {
+        domains: domains.to_vec(),
+        fastest: fastest.map(|r| format!("{} ({})", r.name, r.address)),
+        resolvers,
+        disagreements,
+        time_ms: start.elapsed().as_millis() as u64,
+        details: details.join("; "),
+    }
+}
+
+fn new_stats(name: String, address: String) -> DnsBenchmarkResolver {
+    DnsBenchmarkResolver {
+        name,
+        address,
+        queries: 0,
+        failures: 0,
+        failure_rate: 0.0,
+        cold_avg_ms: None,
+        cold_median_ms: None,
+        cached_avg_ms: None,
+        cached_median_ms: None,
+        nxdomain_hijack: false,
+        hijack_answers: vec![],
+        disagreements: 0,
+    }
+}
+
+async fn bench_resolver(name: String, addr: SocketAddr, domains: &[String], probe: &str, progress: &Sender<DnsBenchmarkProgress>, completed: &AtomicUsize, total: usize) -> ResolverRun {
+    let label = format!("{} ({})", name, addr);
+    let server = addr.to_string();
+    let mut stats = new_stats(name, server.clone());
+    let mut answers: HashMap<String, Answer> = HashMap::new();
+    let (mut cold, mut cached) = (Vec::new(), Vec::new());
+    let mut answered = false;
+
+    let plan = domains.iter()
+        .flat_map(|domain| [(domain.as_str(), "cold"), (domain.as_str(), "cached")])
+        .chain(std::iter::once((probe, "nxdomain")));
+    for (domain, phase) in plan {
+        stats.queries += 1;
+        let (status, time_ms, answer) = if !answered && stats.failures >= UNREACHABLE_AFTER {
+            ("Skipped".to_string(), None, None)
+        } else {
+            let result = lookup(domain, "A", Some(&server), "udp").await;
+            let answer = matches!(result.status.as_str(), "NOERROR" | "NXDOMAIN").then(|| Answer {
+                status: result.status.clone(),
+                addresses: result.records.iter().filter(|r| r.record_type == "A").map(|r| r.value.clone()).collect(),
+            });
+            (result.status, result.time_ms, answer)
+        };
+        let _ = progress.send(DnsBenchmarkProgress {
+            resolver: stats.name.clone(),
+            domain: domain.to_string(),
+            phase: phase.to_string(),
+            status,
+            time_ms,
+            completed: completed.fetch_add(1, Ordering::Relaxed) + 1,
+            total,
+        }).await;
+
+        let Some(mut answer) = answer else {
+            stats.failures += 1;
+            continue;
+        };
+        answered = true;
+        answer.addresses.sort();
+        match phase {
+            "cold" => cold.extend(time_ms),
+            "cached" => cached.extend(time_ms),
+            _ => {
+                stats.nxdomain_hijack = !answer.addresses.is_empty();
+                stats.hijack_answers = answer.addresses;
+                continue;
+            }
+        }
+        answers.entry(domain.to_string()).or_insert(answer);
+    }
+
+    stats.failure_rate = round2(stats.failures as f64 * 100.0 / stats.queries as f64);
+    (stats.cold_avg_ms, stats.cold_median_ms) = (average(&cold), median(&mut cold));
+    (stats.cached_avg_ms, stats.cached_median_ms) = (average(&cached), median(&mut cached));
+    ResolverRun { label, stats, answers }
+}
+
+/// Per domain, flags resolvers whose response code differs from the majority, that
+/// answer with a blocking or private address the others don't, or whose addresses
+/// share nothing with any other resolver's.
+fn find_disagreements(domains: &[String], runs: &[ResolverRun]) -> Vec<DnsDisagreement> {
+    let mut disagreements = Vec::new();
+    for domain in domains {
+        let answers: Vec<(&str, &Answer)> = runs.iter()
+            .filter_map(|run| run.answers.get(domain).map(|a| (run.label.as_str(), a)))
+            .collect();
+        if answers.len() < 2 {
+            continue;
+        }
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (_, answer) in &answers {
+            *counts.entry(answer.status.as_str()).or_default() += 1;
+        }
+        let (majority, majority_count) = counts.iter()
+            .max_by_key(|(status, count)| (**count, **status == "NOERROR"))
+            .map(|(status, count)| (*status, *count))
+            .unwrap_or(("NOERROR", 0));
+
+        for (resolver, answer) in &answers {
+            let others = || answers.iter().filter(|(r, _)| r != resolver).map(|(_, a)| a);
+            let sinkhole = answer.addresses.iter().find(|a| is_sinkhole(a));
+            let reason = if answer.status != majority {
+                format!("{} while {} of {} resolvers returned {}", answer.status, majority_count, answers.len(), majority)
+            } else if let Some(address) = sinkhole.filter(|_| others().any(|a| !a.addresses.iter().any(|x| is_sinkhole(x)))) {
+                format!("Answered with the blocking or private address {}", address)
+            } else if !answer.addresses.is_empty()
+                && others().any(|a| !a.addresses.is_empty())
+                && !others().any(|a| a.addresses.iter().any(|x| answer.addresses.contains(x))) {
+                "No addresses in common with any other resolver (normal for CDN-hosted names)".to_string()
+            } else {
+                continue;
+            };
+            disagreements.push(DnsDisagreement {
+                domain: domain.clone(),
+                resolver: resolver.to_string(),
+                status: answer.status.clone(),
+                answers: answer.addresses.clone(),
+                reason,
+            });
+        }
+    }
+    disagreements
+}
+
+/// Addresses filters and hijacking resolvers hand out instead of the real one.
+fn is_sinkhole(address: &str) -> bool {
+    match address.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => ip.is_unspecified() || ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        Ok(IpAddr::V6(ip)) => ip.is_unspecified() || ip.is_loopback(),
+        Err(_) => false,
+    }
+}
+
+fn average(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| round2(values.iter().sum::<f64>() / values.len() as f64))
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    match values.len() {
+        0 => None,
+        n if n % 2 == 0 => Some(round2((values[mid - 1] + values[mid]) / 2.0)),
+        _ => Some(round2(values[mid])),
+    }
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_resolver::proto::op::ResponseCode;
+    use hickory_resolver::proto::rr::{rdata, RData, Record};
+
+    #[test]
+    fn test_names_and_nameservers() {
+        assert_eq!(query_name("8.8.4.4", RecordType::PTR).unwrap().to_string(), "4.4.8.8.in-addr.arpa.");
+        assert_eq!(query_name("example.com", RecordType::MX).unwrap().to_string(), "example.com.");
+        assert_eq!(parse_record_type("caa").unwrap(), RecordType::CAA);
+        assert_eq!(parse_record_type("").unwrap(), RecordType::A);
+        assert!(parse_record_type("BOGUS").is_err());
+        assert_eq!(parse_nameserver("1.1.1.1").unwrap(), "1.1.1.1:53".parse().unwrap());
+        assert_eq!(parse_nameserver("[2606:4700::1111]:5353").unwrap(), "[2606:4700::1111]:5353".parse().unwrap());
+        assert_eq!(parse_nameserver("2606:4700::1111").unwrap(), "[2606:4700::1111]:53".parse().unwrap());
+        assert!(parse_nameserver("dns.google").is_err());
+        assert!(Transport::parse("quic").is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let name = query_name("example.com", RecordType::MX).unwrap();
+        let query = Message::from_vec(&build_query(&name, RecordType::MX, 0x1234).unwrap()).unwrap();
+        assert_eq!(query.id(), 0x1234);
+        assert!(query.recursion_desired());
+
+        let mut response = query.clone();
+        response.set_message_type(MessageType::Response)
+            .set_authoritative(true)
+            .set_truncated(true)
+            .set_response_code(ResponseCode::NoError)
+            .add_answer(Record::from_rdata(name.clone(), 300, RData::MX(rdata::MX::new(10, Name::from_str("mail.example.com.").unwrap()))));
+
+        let mut result = new_result("example.com", "MX", "udp");
+        parse_response(&response.to_vec().unwrap(), &mut result).unwrap();
+        assert_eq!(result.status, "NOERROR");
+        assert!(result.authoritative && result.truncated && !result.recursion_available);
+        assert_eq!(result.records.len(), 1);
+        assert_eq!(result.records[0].record_type, "MX");
+        assert_eq!(result.records[0].value, "10 mail.example.com.");
+        assert_eq!(result.records[0].ttl, 300);
+        assert!(parse_response(&[0, 1, 2], &mut result).is_err());
+    }
+
+    #[test]
+    fn test_find_disagreements() {
+        let run = |label: &str, answers: &[(&str, &str, &[&str])]| ResolverRun {
+            label: label.to_string(),
+            stats: new_stats(label.to_string(), String::new()),
+            answers: answers.iter().map(|(domain, status, addresses)| (domain.to_string(), Answer {
+                status: status.to_string(),
+                addresses: addresses.iter().map(|a| a.to_string()).collect(),
+            })).collect(),
+        };
+        let runs = vec![
+            run("A", &[("shop.example", "NOERROR", &["93.184.216.34"]), ("cdn.example", "NOERROR", &["1.2.3.4"])]),
+            run("B", &[("shop.example", "NOERROR", &["93.184.216.34"]), ("cdn.example", "NOERROR", &["1.2.3.4", "1.2.3.5"])]),
+            run("C", &[("shop.example", "NXDOMAIN", &[]), ("cdn.example", "NOERROR", &["0.0.0.0"])]),
+        ];
+        let domains = vec!["shop.example".to_string(), "cdn.example".to_string(), "gone.example".to_string()];
+        let found = find_disagreements(&domains, &runs);
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].domain.as_str(), found[0].resolver.as_str(), found[0].status.as_str()), ("shop.example", "C", "NXDOMAIN"));
+        assert_eq!(found[0].reason, "NXDOMAIN while 2 of 3 resolvers returned NOERROR");
+        assert_eq!((found[1].domain.as_str(), found[1].resolver.as_str()), ("cdn.example", "C"));
+        assert!(found[1].reason.contains("0.0.0.0"));
+
+        assert_eq!(median(&mut [5.0, 1.0, 3.0, 2.0]), Some(2.5));
+        assert_eq!(average(&[1.0, 2.0]), Some(1.5));
+        assert_eq!(median(&mut []), None);
+    }
+}
@@ -0,0 +1,369 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use hickory_resolver::config::{NameServerConfig, ResolverOpts};
+use hickory_resolver::name_server::{ConnectionProvider, TokioConnectionProvider};
+use hickory_resolver::proto::xfer::{DnsHandle, DnsRequest, DnsRequestOptions, FirstAnswer, Protocol as Transport};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::crypto::CryptoProvider;
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use tokio::net::TcpStream;
+
+use crate::modules::dns;
+use crate::modules::utils::{DnsLookupResult, EncryptedDnsResult};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const DOT_PORT: u16 = 853;
+const DOQ_PORT: u16 = 853;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Protocol {
+    Doh,
+    Dot,
+    Doq,
+}
+
+impl Protocol {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "doh" | "https" => Ok(Protocol::Doh),
+            "dot" | "tls" => Ok(Protocol::Dot),
+            "doq" | "quic" => Ok(Protocol::Doq),
+            other => Err(format!("Unknown encrypted DNS protocol '{}'", other)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Protocol::Doh => "DoH",
+            Protocol::Dot => "DoT",
+            Protocol::Doq => "DoQ",
+        }
+    }
+
+    fn transport(self) -> Transport {
+        match self {
+            Protocol::Doh => Transport::Https,
+            Protocol::Dot => Transport::Tls,
+            Protocol::Doq => Transport::Quic,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Endpoint {
+    host: String, // name checked against the certificate
+    port: u16,
+    path: String, // DoH only
+}
+
+/// DoH takes a URL (`https://dns.google/dns-query`); DoT and DoQ take `host`,
+/// `host:port` or an IP address, with port 853 by default.
+fn parse_target(protocol: Protocol, target: &str) -> Result<Endpoint, String> {
+    let target = target.trim();
+    if protocol == Protocol::Doh {
+        let with_scheme = if target.contains("://") { target.to_string() } else { format!("https://{}", target) };
+        let url = reqwest::Url::parse(&with_scheme).map_err(|e| format!("'{}' is not a DoH URL: {}", target, e))?;
+        if url.scheme() != "https" {
+            return Err("DoH URLs must use https".into());
+        }
+        let host = url.host_str().ok_or("The DoH URL has no host")?;
+        let path = match (url.path(), url.query()) {
+            ("/", None) => "/dns-query".to_string(),
+            (path, None) => path.to_string(),
+            (path, Some(query)) => format!("{}?{}", path, query),
+        };
+        return Ok(Endpoint {
+            host: host.trim_start_matches('[').trim_end_matches(']').to_string(),
+            port: url.port_or_known_default().unwrap_or(443),
+            path,
+        });
+    }
+
+    let target = target.split("://").last().unwrap_or(target).trim_end_matches('/');
+    let default_port = if protocol == Protocol::Dot { DOT_PORT } else { DOQ_PORT };
+    let (host, port) = if let Ok(addr) = target.parse::<SocketAddr>() {
+        (addr.ip().to_string(), addr.port())
+    } else if let Ok(ip) = target.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        (ip.to_string(), default_port)
+    } else if let Some((host, port)) = target.rsplit_once(':') {
+        (host.to_string(), port.parse().map_err(|_| format!("'{}' is not a valid port", port))?)
+    } else {
+        (target.to_string(), default_port)
+    };
+    if host.is_empty() {
+        return Err("No server given".into());
+    }
+    Ok(Endpoint { host, port, path: String::new() })
+}
+
+/// Queries `domain` over DoH, DoT or DoQ through hickory's name server connections,
+/// timing the connection setup and the query separately, then asks the same server (or
+/// else the system resolver) over plain UDP to spot answers being rewritten on one path
+/// or the other.
+pub async fn test(protocol: &str, target: &str, domain: &str, type_str: &str) -> EncryptedDnsResult {
+    let mut result = EncryptedDnsResult {
+        protocol: protocol.to_ascii_uppercase(),
+        target: target.to_string(),
+        server: None,
+        status: "Error".to_string(),
+        connect_ms: None,
+        handshake_ms: None,
+        query_ms: None,
+        certificate_valid: None,
+        certificate_error: None,
+        response: None,
+        plain: None,
+        answers_match: None,
+        details: String::new(),
+    };
+    if let Err(e) = run(&mut result, protocol, target, domain, type_str).await {
+        result.details = e;
+        // An interceptor may accept the handshake and then drop the query.
+        if result.certificate_valid == Some(false) {
+            result.status = "Certificate Error".to_string();
+            result.details = format!("The certificate failed validation, then the query failed: {}", result.details);
+        }
+    }
+    result
+}
+
+async fn run(result: &mut EncryptedDnsResult, protocol: &str, target: &str, domain: &str, type_str: &str) -> Result<(), String> {
+    let protocol = Protocol::parse(protocol)?;
+    result.protocol = protocol.name().to_string();
+    let endpoint = parse_target(protocol, target)?;
+    let record_type = dns::parse_record_type(type_str)?;
+    let name = dns::query_name(domain, record_type)?;
+
+    let addr = tokio::net::lookup_host((endpoint.host.as_str(), endpoint.port)).await
+        .map_err(|e| format!("Could not resolve {}: {}", endpoint.host, e))?
+        .next()
+        .ok_or_else(|| format!("{} has no addresses", endpoint.host))?;
+    result.server = Some(addr.to_string());
+
+    // A bare TCP connect first tells a blocked port apart from a failed handshake. QUIC
+    // has no separate connect step.
+    if protocol != Protocol::Doq {
+        let start = Instant::now();
+        match tokio::time::timeout(TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => result.connect_ms = Some(elapsed_ms(start)),
+            Ok(Err(e)) => return blocked(result, format!("TCP connection to {} failed: {}", addr, e)),
+            Err(_) => return blocked(result, format!("TCP connection to {} timed out", addr)),
+        }
+    }
+
+    let certificate = Arc::new(Mutex::new(None));
+    let mut options = ResolverOpts::default();
+    options.timeout = TIMEOUT;
+    options.tls_config = tls_config(certificate.clone())?;
+    let mut config = NameServerConfig::new(addr, protocol.transport());
+    config.tls_dns_name = Some(endpoint.host.clone());
+    if protocol == Protocol::Doh {
+        config.http_endpoint = Some(endpoint.path.clone());
+    }
+
+    // The connection is ready once TCP and TLS (plus HTTP/2 for DoH), or the QUIC
+    // handshake, are done.
+    let start = Instant::now();
+    let connecting = TokioConnectionProvider::default().new_connection(&config, &options).map_err(|e| e.to_string())?;
+    let handshake = if protocol == Protocol::Doq { "QUIC" } else { "TLS" };
+    let connection = match tokio::time::timeout(TIMEOUT, connecting).await {
+        Ok(Ok(connection)) => connection,
+        Ok(Err(e)) => return blocked(result, format!("{} handshake with {} failed: {}", handshake, addr, e)),
+        Err(_) => return blocked(result, format!("{} handshake with {} timed out", handshake, addr)),
+    };
+    let setup_ms = elapsed_ms(start);
+    result.handshake_ms = Some(round2((setup_ms - result.connect_ms.unwrap_or_default()).max(0.0)));
+    match certificate.lock().unwrap().take() {
+        Some(Ok(())) => result.certificate_valid = Some(true),
+        Some(Err(e)) => {
+            result.certificate_valid = Some(false);
+            result.certificate_error = Some(e);
+        }
+        None => {}
+    }
+
+    // RFC 8484 asks DoH clients for ID 0 so that responses are cacheable.
+    let id = if protocol == Protocol::Doh { 0 } else { rand::random() };
+    let request = DnsRequest::new(dns::query_message(&name, record_type, id), DnsRequestOptions::default());
+    let start = Instant::now();
+    let answer = tokio::time::timeout(TIMEOUT, connection.send(request).first_answer()).await
+        .map_err(|_| "The query timed out".to_string())?
+        .map_err(|e| format!("The query failed: {}", e))?;
+    let query_ms = elapsed_ms(start);
+    result.query_ms = Some(query_ms);
+
+    let mut response = dns::new_result(domain, type_str, protocol.name());
+    dns::parse_response(answer.as_buffer(), &mut response)?;
+    response.record_type = record_type.to_string();
+    response.nameserver = addr.to_string();
+    response.time_ms = Some(query_ms);
+
+    // The same server on port 53 makes the fairest comparison; DoH frontends often don't
+    // serve plain DNS, so fall back to the system resolver.
+    let mut plain = dns::lookup(domain, type_str, Some(&SocketAddr::new(addr.ip(), 53).to_string()), "udp").await;
+    if plain.time_ms.is_none() {
+        plain = dns::lookup(domain, type_str, None, "udp").await;
+    }
+    let answers_match = plain.time_ms.map(|_| same_answers(&response, &plain));
+    result.answers_match = answers_match;
+
+    let setup = match result.connect_ms {
+        Some(connect_ms) => format!("connect {:.1} ms, handshake {:.1} ms", connect_ms, result.handshake_ms.unwrap_or_default()),
+        None => format!("QUIC handshake {:.1} ms", result.handshake_ms.unwrap_or_default()),
+    };
+    let mut details = vec![format!(
+        "{} {} in {:.1} ms ({})",
+        response.records.len(), if response.records.len() == 1 { "answer" } else { "answers" }, query_ms, setup,
+    )];
+    if result.certificate_valid == Some(false) {
+        result.status = "Certificate Error".to_string();
+        details.push("the certificate failed validation: the server's certificate is invalid, or something on the path is intercepting TLS".into());
+    } else {
+        result.status = "OK".to_string();
+    }
+    match answers_match {
+        Some(true) => details.push(format!("plain DNS via {} agrees", plain.nameserver)),
+        Some(false) => details.push(format!(
+            "plain DNS via {} answered differently ({}): plain DNS may be intercepted or filtered, though CDNs also vary answers",
+            plain.nameserver, plain.status,
+        )),
+        None => details.push("plain DNS could not be compared".into()),
+    }
+    result.response = Some(response);
+    result.plain = Some(plain);
+    result.details = details.join("; ");
+    Ok(())
+}
+
+fn blocked(result: &mut EncryptedDnsResult, details: String) -> Result<(), String> {
+    result.status = "Blocked".to_string();
+    result.details = details;
+    Ok(())
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+    round2(start.elapsed().as_secs_f64() * 1000.0)
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// A rustls config that checks the certificate against the Mozilla roots but lets the
+/// handshake go ahead either way, leaving the verdict in `certificate`. That way an
+/// intercepted connection still shows what the interceptor answers.
+fn tls_config(certificate: Arc<Mutex<Option<Result<(), String>>>>) -> Result<ClientConfig, String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = RecordingVerifier::new(provider.clone(), certificate)?;
+    Ok(ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth())
+}
+
+#[derive(Debug)]
+struct RecordingVerifier {
+    webpki: Arc<WebPkiServerVerifier>,
+    certificate: Arc<Mutex<Option<Result<(), String>>>>,
+}
+
+impl RecordingVerifier {
+    fn new(provider: Arc<CryptoProvider>, certificate: Arc<Mutex<Option<Result<(), String>>>>) -> Result<Self, String> {
+        let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+        let webpki = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(RecordingVerifier { webpki, certificate })
+    }
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verdict = self.webpki.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now);
+        *self.certificate.lock().unwrap() = Some(verdict.map(|_| ()).map_err(|e| e.to_string()));
+        Ok(ServerCertVerified::assertion())
+    }
+
+    // The handshake signatures are still checked, against whatever key the certificate holds.
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.webpki.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.webpki.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.webpki.supported_verify_schemes()
+    }
+}
+
+/// Same response code, and the answers overlap (or are both empty).
+fn same_answers(a: &DnsLookupResult, b: &DnsLookupResult) -> bool {
+    a.status == b.status
+        && (a.records.is_empty() && b.records.is_empty()
+            || a.records.iter().any(|r| b.records.iter().any(|s| s.record_type == r.record_type && s.value == r.value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let doh = parse_target(Protocol::Doh, "https://cloudflare-dns.com/dns-query").unwrap();
+        assert_eq!((doh.host.as_str(), doh.port, doh.path.as_str()), ("cloudflare-dns.com", 443, "/dns-query"));
+        let doh = parse_target(Protocol::Doh, "dns.google").unwrap();
+        assert_eq!((doh.host.as_str(), doh.path.as_str()), ("dns.google", "/dns-query"));
+        assert!(parse_target(Protocol::Doh, "http://dns.google/dns-query").is_err());
+
+        assert_eq!(parse_target(Protocol::Dot, "1.1.1.1").unwrap(), Endpoint { host: "1.1.1.1".into(), port: 853, path: String::new() });
+        assert_eq!(parse_target(Protocol::Dot, "tls://dns.quad9.net:8853").unwrap().port, 8853);
+        assert_eq!(parse_target(Protocol::Doq, "[2606:4700::1111]:784").unwrap().host, "2606:4700::1111");
+        assert_eq!(parse_target(Protocol::Doq, "2606:4700::1111").unwrap().port, 853);
+        assert!(Protocol::parse("dnscrypt").is_err());
+    }
+
+    #[test]
+    fn test_recording_verifier_lets_bad_certificates_through() {
+        let certificate = Arc::new(Mutex::new(None));
+        assert!(tls_config(certificate.clone()).is_ok());
+        let verifier = RecordingVerifier::new(Arc::new(rustls::crypto::ring::default_provider()), certificate.clone()).unwrap();
+        let bogus = CertificateDer::from(vec![0x30, 0x03, 0x02, 0x01, 0x00]);
+        let name = ServerName::try_from("dns.google").unwrap();
+        assert!(verifier.verify_server_cert(&bogus, &[], &name, &[], UnixTime::now()).is_ok());
+        assert!(matches!(certificate.lock().unwrap().take(), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_same_answers() {
+        let record = |value: &str| crate::modules::utils::DnsRecord {
+            name: "example.com.".into(), record_type: "A".into(), value: value.into(), ttl: 60,
+        };
+        let mut a = dns::new_result("example.com", "A", "doh");
+        let mut b = dns::new_result("example.com", "A", "udp");
+        a.status = "NOERROR".into();
+        b.status = "NOERROR".into();
+        assert!(same_answers(&a, &b));
+        a.records = vec![record("192.0.2.1"), record("192.0.2.2")];
+        b.records = vec![record("192.0.2.2")];
+        assert!(same_answers(&a, &b));
+        b.records = vec![record("10.0.0.1")];
+        assert!(!same_answers(&a, &b));
+        b.status = "NXDOMAIN".into();
+        b.records.clear();
+        assert!(!same_answers(&a, &b));
+    }
+}
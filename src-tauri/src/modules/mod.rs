@@ -1,5 +1,8 @@
 pub mod utils;
 pub mod system;
 pub mod net_ops;
+pub mod ping;
+pub mod dns;
+pub mod encrypted_dns;
 pub mod pcap_analysis;
 pub mod wifi;
@@ -12,76 +12,61 @@ use std::sync::Arc;
 use regex::Regex;
 
 use crate::modules::utils::{
-    PingResult, JitterResult, MtuResult, TcpResult, NatResult, DnsRecord, TracerouteHop, 
+    PingResult, PingOptions, JitterResult, MtuResult, TcpResult, NatResult, DnsLookupResult, DnsBenchmarkResult, EncryptedDnsResult, TracerouteHop, 
     PortScanProgress, PortScanResult, ThroughputResult, GeoIp, LanDevice,
-    parse_ping_time, get_mac_from_arp
+    get_mac_from_arp
 };
+use crate::modules::{dns, encrypted_dns, ping};
 
 #[tauri::command]
-pub async fn execute_ping(host: String) -> PingResult {
-    #[cfg(target_os = "windows")]
-    let args = ["-n", "1", "-w", "1000", &host];
-    #[cfg(not(target_os = "windows"))]
-    let args = ["-c", "1", "-W", "1", &host];
-
-    let mut cmd = Command::new("ping");
-    cmd.args(args);
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
-
-    let output = cmd.output();
-
-    match output {
-        Ok(o) => {
-            let stdout = String::from_utf8_lossy(&o.stdout).to_string();
-            let success = o.status.success() && (stdout.contains("Reply from") || stdout.contains("bytes from"));
-            let time_ms = if success { parse_ping_time(&stdout) } else { None };
+pub async fn execute_ping(host: String, options: Option<PingOptions>) -> PingResult {
+    let options = options.unwrap_or_default();
+    let query = host.clone();
+    let res = tokio::task::spawn_blocking(move || {
+        let target = ping::resolve(&query)?;
+        ping::ping(target, &options).map(|rtts| (target, rtts))
+    }).await.map_err(|e| e.to_string()).and_then(|r| r);
+
+    match res {
+        Ok((target, rtts)) => {
+            let output = rtts.iter().enumerate().map(|(seq, rtt)| match rtt {
+                Some(ms) => format!("Reply from {}: seq={} time={:.2} ms", target, seq, ms),
+                None => format!("Request timed out: seq={}", seq),
+            }).collect::<Vec<_>>().join("\n");
+            let time_ms = rtts.iter().flatten().next().copied();
 
             PingResult {
                 host,
-                status: if success { "Success".to_string() } else { "Timeout".to_string() },
+                address: Some(target.to_string()),
+                status: if time_ms.is_some() { "Success".to_string() } else { "Timeout".to_string() },
                 time_ms,
-                output: stdout.lines().take(4).collect::<Vec<&str>>().join("\n"),
+                rtts_ms: rtts,
+                output,
             }
         },
-        Err(e) => PingResult { host, status: "Error".to_string(), time_ms: None, output: e.to_string() }
+        Err(e) => PingResult { host, address: None, status: "Error".to_string(), time_ms: None, rtts_ms: vec![], output: e }
     }
 }
 
 #[tauri::command]
-pub async fn run_jitter_test(host: String, count: u8) -> JitterResult {
-    let count_str = count.to_string();
-    
-    #[cfg(target_os = "windows")]
-    let args = ["-n", &count_str, "-w", "1000", &host];
-    #[cfg(not(target_os = "windows"))]
-    let args = ["-c", &count_str, &host];
-
-    let mut cmd = Command::new("ping");
-    cmd.args(args);
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
-
-    match cmd.output() {
-        Ok(o) => {
-            let stdout = String::from_utf8_lossy(&o.stdout).to_string();
-            let mut latencies: Vec<u64> = Vec::new();
-            for line in stdout.lines() {
-                if let Some(ms) = parse_ping_time(line) {
-                    latencies.push(ms);
-                }
-            }
+pub async fn run_jitter_test(host: String, count: u8, options: Option<PingOptions>) -> JitterResult {
+    let options = PingOptions { count: count as u32, ..options.unwrap_or_default() };
+    let query = host.clone();
+    let res = tokio::task::spawn_blocking(move || {
+        ping::ping(ping::resolve(&query)?, &options)
+    }).await.map_err(|e| e.to_string()).and_then(|r| r);
 
+    match res {
+        Ok(rtts) => {
+            let latencies: Vec<f64> = rtts.iter().flatten().copied().collect();
             let received = latencies.len();
             if received == 0 {
-                return JitterResult { host, avg_latency: 0, jitter: 0.0, packet_loss: 100.0, details: "100% Packet Loss".to_string() };
+                return JitterResult { host, avg_latency: 0.0, jitter: 0.0, packet_loss: 100.0, rtts_ms: rtts, details: "100% Packet Loss".to_string() };
             }
 
-            let sum: u64 = latencies.iter().sum();
-            let avg = sum / received as u64;
-            
+            let avg = latencies.iter().sum::<f64>() / received as f64;
             let variance: f64 = latencies.iter().map(|&x| {
-                let diff = x as f64 - avg as f64;
+                let diff = x - avg;
                 diff * diff
             }).sum::<f64>() / received as f64;
             let jitter = variance.sqrt();
@@ -89,43 +74,45 @@ pub async fn run_jitter_test(host: String, count: u8) -> JitterResult {
 
             JitterResult {
                 host,
-                avg_latency: avg,
+                avg_latency: (avg * 100.0).round() / 100.0,
                 jitter: (jitter * 100.0).round() / 100.0,
                 packet_loss: loss_pct,
+                rtts_ms: rtts,
                 details: format!("Recv: {}/{}, Jitter: {:.2}ms", received, count, jitter),
             }
         },
-        Err(e) => JitterResult { host, avg_latency: 0, jitter: 0.0, packet_loss: 100.0, details: e.to_string() }
+        Err(e) => JitterResult { host, avg_latency: 0.0, jitter: 0.0, packet_loss: 100.0, rtts_ms: vec![], details: e }
     }
 }
 
 #[tauri::command]
 pub async fn check_mtu(host: String) -> MtuResult {
-    let sizes = [1472, 1400, 1300, 1200, 500];
-    for &size in sizes.iter() {
-        #[cfg(target_os = "windows")]
-        let args = ["-n", "1", "-f", "-l", &size.to_string(), &host];
-        #[cfg(not(target_os = "windows"))]
-        let args = ["-c", "1", "-M", "do", "-s", &size.to_string(), &host];
-
-        let mut cmd = Command::new("ping");
-        cmd.args(args);
-        #[cfg(target_os = "windows")]
-        cmd.creation_flags(0x08000000);
-
-        if let Ok(o) = cmd.output() {
-             let stdout = String::from_utf8_lossy(&o.stdout).to_string();
-             if !stdout.contains("Fragment") && !stdout.contains("too large") && (stdout.contains("Reply") || stdout.contains("bytes from")) {
-                 return MtuResult {
-                     host,
-                     mtu: size + 28,
-                     status: "Pass".to_string(),
-                     details: format!("Max: {} bytes", size + 28)
-                 };
-             }
+    let query = host.clone();
+    let res = tokio::task::spawn_blocking(move || {
+        let target = ping::resolve(&query)?;
+        // IPv4 + ICMP headers are 28 bytes, IPv6 + ICMPv6 48; the floors are the minimum
+        // MTUs each protocol guarantees.
+        let (overhead, mut low, mut high) = if target.is_ipv6() { (48, 1232, 1452) } else { (28, 548, 1472) };
+        let fits = |size: usize| -> Result<bool, String> {
+            let options = PingOptions { count: 2, interval_ms: 100, size, dont_fragment: true, ..Default::default() };
+            Ok(ping::ping(target, &options)?.iter().any(|rtt| rtt.is_some()))
+        };
+        if !fits(low)? {
+            return Ok(None);
+        }
+        // Binary search for the largest payload that gets through unfragmented.
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if fits(mid)? { low = mid; } else { high = mid - 1; }
         }
+        Ok(Some(low + overhead))
+    }).await.map_err(|e| e.to_string()).and_then(|r: Result<Option<usize>, String>| r);
+
+    match res {
+        Ok(Some(mtu)) => MtuResult { host, mtu: mtu as u16, status: "Pass".to_string(), details: format!("Max: {} bytes", mtu) },
+        Ok(None) => MtuResult { host, mtu: 0, status: "Fail".to_string(), details: "Blocked/Unknown".to_string() },
+        Err(e) => MtuResult { host, mtu: 0, status: "Fail".to_string(), details: e },
     }
-    MtuResult { host, mtu: 0, status: "Fail".to_string(), details: "Blocked/Unknown".to_string() }
 }
 
 #[tauri::command]
@@ -173,24 +160,34 @@ pub async fn check_nat_type() -> NatResult {
 }
 
 #[tauri::command]
-pub async fn run_nslookup(domain: String, _type_str: String) -> Vec<DnsRecord> {
-    let domain_clone = domain.clone();
-    let res = tokio::task::spawn_blocking(move || {
-        (domain_clone.as_str(), 0).to_socket_addrs()
-    }).await;
-
-    let mut results = Vec::new();
-    if let Ok(Ok(iter)) = res {
-        for addr in iter {
-            let ip = addr.ip();
-            results.push(DnsRecord {
-                record_type: if ip.is_ipv4() { "A".to_string() } else { "AAAA".to_string() },
-                value: ip.to_string(),
-                ttl: 0,
-            });
+pub async fn run_nslookup(domain: String, type_str: String, nameserver: Option<String>, transport: Option<String>) -> DnsLookupResult {
+    dns::lookup(&domain, &type_str, nameserver.as_deref(), transport.as_deref().unwrap_or("udp")).await
+}
+
+#[tauri::command]
+pub async fn run_dns_benchmark(app: tauri::AppHandle, domains: Option<Vec<String>>, resolvers: Option<Vec<String>>) -> Result<DnsBenchmarkResult, String> {
+    let servers = tokio::task::spawn_blocking(move || dns::benchmark_resolvers(&resolvers.unwrap_or_default()))
+        .await.map_err(|e| e.to_string())??;
+    let domains: Vec<String> = match domains {
+        Some(d) if !d.is_empty() => d,
+        _ => dns::BENCHMARK_DOMAINS.iter().map(|d| d.to_string()).collect(),
+    };
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+    let app_handle = app.clone();
+    tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            let _ = app_handle.emit("dns_benchmark_progress", msg);
         }
-    }
-    results
+    });
+
+    Ok(dns::benchmark(servers, domains, tx).await)
+}
+
+#[tauri::command]
+pub async fn test_encrypted_dns(protocol: String, target: String, domain: Option<String>, record_type: Option<String>) -> EncryptedDnsResult {
+    let domain = domain.filter(|d| !d.trim().is_empty()).unwrap_or_else(|| "example.com".to_string());
+    encrypted_dns::test(&protocol, &target, &domain, record_type.as_deref().unwrap_or("A")).await
 }
 
 #[tauri::command]
@@ -358,14 +355,18 @@ pub async fn get_geo_ip(ip: String) -> GeoIp {
 }
 
 #[tauri::command]
-pub async fn scan_local_network(app: tauri::AppHandle) -> Vec<LanDevice> {
+pub async fn scan_local_network(app: tauri::AppHandle) -> Result<Vec<LanDevice>, String> {
     let my_local_ip = local_ip().unwrap_or("127.0.0.1".parse().unwrap());
     let ip_str = my_local_ip.to_string();
     let parts: Vec<&str> = ip_str.split('.').collect();
     
     if parts.len() != 4 {
-        return vec![];
+        return Ok(vec![]);
     }
+
+    // Without ICMP access every host would just look offline.
+    tokio::task::spawn_blocking(move || ping::check_access(my_local_ip))
+        .await.map_err(|e| e.to_string())??;
     
     let subnet = format!("{}.{}.{}.", parts[0], parts[1], parts[2]);
     let mut devices = Vec::new();
@@ -395,31 +396,25 @@ pub async fn scan_local_network(app: tauri::AppHandle) -> Vec<LanDevice> {
             let _permit = sem.acquire_owned().await.unwrap();
             
             // Ping
-            #[cfg(target_os = "windows")]
-            let args = ["-n", "1", "-w", "200", &target_ip]; // Fast ping
-            #[cfg(not(target_os = "windows"))]
-            let args = ["-c", "1", "-W", "1", &target_ip];
-
-            let mut cmd = Command::new("ping");
-            cmd.args(args);
-            #[cfg(target_os = "windows")]
-            cmd.creation_flags(0x08000000);
-
-            if let Ok(status) = cmd.status() {
-                if status.success() {
-                    // It's alive. Try to get MAC.
-                    let mac = get_mac_from_arp(&target_ip);
-                    let hostname = "Unknown".to_string(); 
-
-                    let dev = LanDevice {
-                        ip: target_ip,
-                        hostname,
-                        mac,
-                        status: "Online".to_string()
-                    };
-                    let _ = tx_scan.send(dev.clone()).await;
-                    return Some(dev);
-                }
+            let target = target_ip.parse().ok()?;
+            let options = PingOptions { timeout_ms: 500, ..Default::default() };
+            let alive = tokio::task::spawn_blocking(move || {
+                ping::ping(target, &options).is_ok_and(|rtts| rtts[0].is_some())
+            }).await.unwrap_or(false);
+
+            if alive {
+                // It's alive. Try to get MAC.
+                let mac = get_mac_from_arp(&target_ip);
+                let hostname = "Unknown".to_string(); 
+
+                let dev = LanDevice {
+                    ip: target_ip,
+                    hostname,
+                    mac,
+                    status: "Online".to_string()
+                };
+                let _ = tx_scan.send(dev.clone()).await;
+                return Some(dev);
             }
             None
         }));
@@ -431,5 +426,5 @@ pub async fn scan_local_network(app: tauri::AppHandle) -> Vec<LanDevice> {
          }
     }
     
-    devices
+    Ok(devices)
 }
@@ -0,0 +1,651 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use etherparse::{ArpPacket, Icmpv4Type, Icmpv6Type, LinkHeader, NetHeaders, PacketHeaders, TcpHeader, TransportHeader};
+
+use crate::modules::utils::PcapIssue;
+use super::detail::mac;
+use super::dhcp;
+
+// A source that probes this many ports on one host, or one port on this many hosts,
+// with at most `SCAN_MAX_ANSWERED` of the probes answered, is scanning.
+const SCAN_PORTS: usize = 100;
+const SWEEP_HOSTS: usize = 50;
+const SCAN_MAX_ANSWERED: f64 = 0.2;
+// SYNs within one second to one service, with at most `SYN_FLOOD_MAX_ANSWERED` of
+// them answered, that make a flood.
+const SYN_FLOOD_RATE: usize = 200;
+const SYN_FLOOD_MAX_ANSWERED: f64 = 0.5;
+// Gratuitous ARPs from one host within the window that make a storm.
+const GARP_STORM: usize = 20;
+const GARP_WINDOW: f64 = 10.0;
+// Beaconing: this many connections to one destination at intervals that vary by at
+// most `BEACON_MAX_JITTER` of their mean. A packet after `BEACON_IDLE_GAP` seconds of
+// quiet starts a new UDP "connection".
+const BEACON_MIN_CONNECTIONS: usize = 6;
+const BEACON_MAX_JITTER: f64 = 0.1;
+const BEACON_IDLE_GAP: f64 = 2.0;
+const MAX_BEACON_SAMPLES: usize = 1000;
+// Bounds memory on captures with millions of conversations.
+const MAX_TRACKED: usize = 500_000;
+const MAX_SOURCES: usize = 65_536;
+// Hosts or ports named in one issue before the rest are summarized as "+N more".
+const MAX_LISTED: usize = 5;
+
+const TCP: u8 = 6;
+const UDP: u8 = 17;
+
+struct Probe {
+    ts: f64,
+    answered: bool,
+}
+
+/// Probes from one source to one host, or to one port across hosts.
+struct ScanTally {
+    probes: usize,
+    answered: usize,
+    first_ts: f64,
+}
+
+impl ScanTally {
+    fn new(ts: f64) -> Self {
+        ScanTally { probes: 0, answered: 0, first_ts: ts }
+    }
+
+    fn add(&mut self, probe: &Probe) {
+        self.probes += 1;
+        self.answered += probe.answered as usize;
+        self.first_ts = self.first_ts.min(probe.ts);
+    }
+
+    fn is_scan(&self, threshold: usize) -> bool {
+        self.probes >= threshold && self.answered as f64 <= self.probes as f64 * SCAN_MAX_ANSWERED
+    }
+}
+
+#[derive(Default)]
+struct SynTarget {
+    window_start: f64,
+    window_syns: usize,
+    peak_rate: usize,
+    peak_ts: f64,
+    syns: usize,
+    answered: usize,
+    sources: HashSet<IpAddr>,
+}
+
+struct ArpConflict {
+    ts: f64,
+    macs: Vec<[u8; 6]>,
+    changes: usize,
+}
+
+#[derive(Default)]
+struct GratuitousArps {
+    recent: VecDeque<f64>,
+    total: usize,
+    peak: usize,
+    storm_ts: Option<f64>,
+}
+
+struct DhcpServer {
+    ts: f64,
+    replies: usize,
+    mac: Option<[u8; 6]>,
+}
+
+struct Redirect {
+    ts: f64,
+    count: usize,
+    gateways: HashSet<IpAddr>,
+    destinations: HashSet<IpAddr>,
+}
+
+struct Beacon {
+    last_seen: f64,
+    starts: Vec<f64>,
+}
+
+/// Heuristics for attacks and anomalies: port scans and host sweeps, SYN floods, ARP
+/// spoofing and gratuitous ARP storms, rogue DHCP servers, ICMP redirects, and
+/// beaconing. Each finding becomes a `PcapIssue` naming the hosts involved.
+#[derive(Default)]
+pub struct AnomalyDetector {
+    // (source, destination, port) of each TCP SYN or UDP datagram that opened a conversation
+    probes: HashMap<(IpAddr, IpAddr, u16), Probe>,
+    syn_targets: HashMap<(IpAddr, u16), SynTarget>,
+    arp_bindings: HashMap<Ipv4Addr, [u8; 6]>,
+    arp_conflicts: HashMap<Ipv4Addr, ArpConflict>,
+    gratuitous: HashMap<[u8; 6], GratuitousArps>,
+    dhcp_servers: HashMap<Ipv4Addr, DhcpServer>,
+    // (router, host told to use another gateway)
+    redirects: HashMap<(IpAddr, IpAddr), Redirect>,
+    // (protocol, source, destination, port)
+    beacons: HashMap<(u8, IpAddr, IpAddr, u16), Beacon>,
+}
+
+impl AnomalyDetector {
+    /// Returns true when the packet itself is suspect (an ARP binding change or an
+    /// ICMP redirect); scans, floods and beacons only show up in aggregate.
+    pub fn process(&mut self, headers: &PacketHeaders, ts: f64) -> bool {
+        let (src, dst) = match &headers.net {
+            Some(NetHeaders::Arp(arp)) => return self.process_arp(arp, ts),
+            Some(NetHeaders::Ipv4(ip, _)) => (IpAddr::from(ip.source), IpAddr::from(ip.destination)),
+            Some(NetHeaders::Ipv6(ip, _)) => (IpAddr::from(ip.source), IpAddr::from(ip.destination)),
+            None => return false,
+        };
+        let payload = headers.payload.slice();
+        match &headers.transport {
+            Some(TransportHeader::Tcp(tcp)) => {
+                self.process_tcp(src, dst, tcp, ts);
+                false
+            },
+            Some(TransportHeader::Udp(udp)) => {
+                self.process_udp(src, dst, udp.source_port, udp.destination_port, ts);
+                if udp.source_port == 67 && udp.destination_port == 68 {
+                    let source_mac = match &headers.link {
+                        Some(LinkHeader::Ethernet2(eth)) => Some(eth.source),
+                        _ => None,
+                    };
+                    self.process_dhcp(src, source_mac, payload, ts);
+                }
+                false
+            },
+            Some(TransportHeader::Icmpv4(icmp)) => {
+                let Icmpv4Type::Redirect(redirect) = &icmp.icmp_type else { return false };
+                // The payload is the header of the datagram that triggered the redirect.
+                let destination = payload.get(16..20).map(|d| IpAddr::from([d[0], d[1], d[2], d[3]]));
+                self.record_redirect(src, dst, IpAddr::from(redirect.gateway_internet_address), destination, ts);
+                true
+            },
+            Some(TransportHeader::Icmpv6(icmp)) => {
+                if icmp.icmp_type != Icmpv6Type::Redirect { return false; }
+                let address = |at: usize| payload.get(at..at + 16)
+                    .and_then(|a| <[u8; 16]>::try_from(a).ok())
+                    .map(|a| IpAddr::from(Ipv6Addr::from(a)));
+                let Some(target) = address(0) else { return false };
+                self.record_redirect(src, dst, target, address(16), ts);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn process_tcp(&mut self, src: IpAddr, dst: IpAddr, tcp: &TcpHeader, ts: f64) {
+        let (sport, dport) = (tcp.source_port, tcp.destination_port);
+        if tcp.syn && !tcp.ack {
+            self.record_probe(src, dst, dport, ts);
+            self.record_activity(TCP, src, dst, dport, ts, true);
+            let target = self.syn_targets.entry((dst, dport)).or_default();
+            if target.syns == 0 || ts - target.window_start >= 1.0 {
+                target.window_start = ts;
+                target.window_syns = 0;
+            }
+            target.window_syns += 1;
+            target.syns += 1;
+            if target.window_syns > target.peak_rate {
+                target.peak_rate = target.window_syns;
+                target.peak_ts = target.window_start;
+            }
+            if target.sources.len() < MAX_SOURCES {
+                target.sources.insert(src);
+            }
+            return;
+        }
+        if tcp.syn && tcp.ack {
+            if let Some(probe) = self.probes.get_mut(&(dst, src, sport)) {
+                probe.answered = true;
+            }
+            if let Some(target) = self.syn_targets.get_mut(&(src, sport)) {
+                target.answered += 1;
+            }
+        }
+        self.record_activity(TCP, src, dst, dport, ts, false);
+    }
+
+    fn process_udp(&mut self, src: IpAddr, dst: IpAddr, sport: u16, dport: u16, ts: f64) {
+        // A datagram back to the port that was probed is an answer, not a probe of its own.
+        match self.probes.get_mut(&(dst, src, sport)) {
+            Some(probe) => probe.answered = true,
+            None => self.record_probe(src, dst, dport, ts),
+        }
+        self.record_activity(UDP, src, dst, dport, ts, true);
+    }
+
+    fn record_probe(&mut self, src: IpAddr, dst: IpAddr, port: u16, ts: f64) {
+        if is_group(dst) || (self.probes.len() >= MAX_TRACKED && !self.probes.contains_key(&(src, dst, port))) {
+            return;
+        }
+        self.probes.entry((src, dst, port)).or_insert(Probe { ts, answered: false });
+    }
+
+    /// Notes traffic from `src` to `dst:port`; a packet that `can_start` a connection
+    /// and follows a quiet spell is counted as a new connection for beacon detection.
+    fn record_activity(&mut self, protocol: u8, src: IpAddr, dst: IpAddr, port: u16, ts: f64, can_start: bool) {
+        // NTP polls on a fixed schedule by design.
+        if is_group(dst) || (protocol == UDP && port == 123) {
+            return;
+        }
+        let key = (protocol, src, dst, port);
+        let room = self.beacons.len() < MAX_TRACKED;
+        match self.beacons.get_mut(&key) {
+            Some(beacon) => {
+                // TCP connections are counted by SYN, so any gap will do there.
+                let quiet = protocol == TCP || ts - beacon.last_seen > BEACON_IDLE_GAP;
+                if can_start && quiet && beacon.starts.len() < MAX_BEACON_SAMPLES {
+                    beacon.starts.push(ts);
+                }
+                beacon.last_seen = ts;
+            },
+            None if can_start && room => {
+                self.beacons.insert(key, Beacon { last_seen: ts, starts: vec![ts] });
+            },
+            None => {},
+        }
+    }
+
+    fn process_arp(&mut self, arp: &ArpPacket, ts: f64) -> bool {
+        let Ok(arp) = arp.try_eth_ipv4() else { return false };
+        let sender_ip = Ipv4Addr::from(arp.sender_ipv4);
+        // Probes from hosts still checking for address conflicts claim no address.
+        if sender_ip.is_unspecified() {
+            return false;
+        }
+
+        let announcement = arp.sender_ipv4 == arp.target_ipv4;
+        if announcement {
+            let garp = self.gratuitous.entry(arp.sender_mac).or_default();
+            garp.total += 1;
+            garp.recent.push_back(ts);
+            while garp.recent.front().is_some_and(|&t| ts - t > GARP_WINDOW) {
+                garp.recent.pop_front();
+            }
+            garp.peak = garp.peak.max(garp.recent.len());
+            if garp.recent.len() >= GARP_STORM && garp.storm_ts.is_none() {
+                garp.storm_ts = garp.recent.front().copied();
+            }
+        }
+
+        let previous = self.arp_bindings.insert(sender_ip, arp.sender_mac);
+        match previous {
+            Some(old) if old != arp.sender_mac => {
+                let conflict = self.arp_conflicts.entry(sender_ip).or_insert(ArpConflict { ts, macs: vec![old], changes: 0 });
+                conflict.changes += 1;
+                if !conflict.macs.contains(&arp.sender_mac) {
+                    conflict.macs.push(arp.sender_mac);
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// DHCP OFFERs and ACKs, keyed by the server identifier option, or the source
+    /// address of the reply when it has none.
+    fn process_dhcp(&mut self, src: IpAddr, source_mac: Option<[u8; 6]>, msg: &[u8], ts: f64) {
+        let Some(msg) = dhcp::parse_message(msg).filter(|m| m.is_reply) else { return };
+        if !matches!(msg.message_type, Some(dhcp::OFFER | dhcp::ACK)) {
+            return;
+        }
+        let server = match (msg.server_id, src) {
+            (Some(id), _) => id,
+            (None, IpAddr::V4(v4)) => v4,
+            (None, IpAddr::V6(_)) => return,
+        };
+        let entry = self.dhcp_servers.entry(server).or_insert(DhcpServer { ts, replies: 0, mac: source_mac });
+        entry.replies += 1;
+    }
+
+    fn record_redirect(&mut self, router: IpAddr, host: IpAddr, gateway: IpAddr, destination: Option<IpAddr>, ts: f64) {
+        let redirect = self.redirects.entry((router, host)).or_insert_with(|| Redirect {
+            ts,
+            count: 0,
+            gateways: HashSet::new(),
+            destinations: HashSet::new(),
+        });
+        redirect.count += 1;
+        redirect.gateways.insert(gateway);
+        redirect.destinations.extend(destination);
+    }
+
+    pub fn finish(self, issues: &mut Vec<PcapIssue>) {
+        let mut found = Vec::new();
+        self.report_scans(&mut found);
+        self.report_syn_floods(&mut found);
+        self.report_arp(&mut found);
+        self.report_dhcp(&mut found);
+        self.report_redirects(&mut found);
+        self.report_beacons(&mut found);
+        found.sort_by(|a, b| a.timestamp.unwrap_or(0.0).total_cmp(&b.timestamp.unwrap_or(0.0)));
+        issues.extend(found);
+    }
+
+    fn report_scans(&self, issues: &mut Vec<PcapIssue>) {
+        let mut by_host: HashMap<(IpAddr, IpAddr), ScanTally> = HashMap::new();
+        let mut by_port: HashMap<(IpAddr, u16), ScanTally> = HashMap::new();
+        for (&(src, dst, port), probe) in &self.probes {
+            by_host.entry((src, dst)).or_insert_with(|| ScanTally::new(probe.ts)).add(probe);
+            by_port.entry((src, port)).or_insert_with(|| ScanTally::new(probe.ts)).add(probe);
+        }
+
+        // One issue per source, however many hosts or ports it went through.
+        let mut scans: HashMap<IpAddr, Vec<(IpAddr, ScanTally)>> = HashMap::new();
+        for ((src, dst), tally) in by_host {
+            if tally.is_scan(SCAN_PORTS) {
+                scans.entry(src).or_default().push((dst, tally));
+            }
+        }
+        for (src, mut targets) in scans {
+            targets.sort_by(|a, b| b.1.probes.cmp(&a.1.probes).then(a.0.cmp(&b.0)));
+            let probes: usize = targets.iter().map(|(_, t)| t.probes).sum();
+            let answered: usize = targets.iter().map(|(_, t)| t.answered).sum();
+            let hosts: Vec<String> = targets.iter().map(|(dst, t)| format!("{} ({} ports)", dst, t.probes)).collect();
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "Port Scan".into(),
+                description: format!("{} probed {} ports across {}; {} answered.", src, probes, listed(&hosts), answered),
+                timestamp: targets.iter().map(|(_, t)| t.first_ts).reduce(f64::min),
+            });
+        }
+
+        let mut sweeps: HashMap<IpAddr, Vec<(u16, ScanTally)>> = HashMap::new();
+        for ((src, port), tally) in by_port {
+            if tally.is_scan(SWEEP_HOSTS) {
+                sweeps.entry(src).or_default().push((port, tally));
+            }
+        }
+        for (src, mut ports) in sweeps {
+            ports.sort_by(|a, b| b.1.probes.cmp(&a.1.probes).then(a.0.cmp(&b.0)));
+            let hosts = ports.iter().map(|(_, t)| t.probes).max().unwrap_or(0);
+            let answered: usize = ports.iter().map(|(_, t)| t.answered).sum();
+            let names: Vec<String> = ports.iter().map(|(port, _)| port.to_string()).collect();
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "Host Sweep".into(),
+                description: format!(
+                    "{} probed up to {} hosts on {} {}; {} probes answered.",
+                    src, hosts, if names.len() == 1 { "port" } else { "ports" }, listed(&names), answered,
+                ),
+                timestamp: ports.iter().map(|(_, t)| t.first_ts).reduce(f64::min),
+            });
+        }
+    }
+
+    fn report_syn_floods(&self, issues: &mut Vec<PcapIssue>) {
+        for (&(dst, port), target) in &self.syn_targets {
+            if target.peak_rate < SYN_FLOOD_RATE || target.answered as f64 > target.syns as f64 * SYN_FLOOD_MAX_ANSWERED {
+                continue;
+            }
+            let mut sources: Vec<String> = target.sources.iter().map(IpAddr::to_string).collect();
+            sources.sort();
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "SYN Flood".into(),
+                description: format!(
+                    "Up to {} SYNs per second to {}; {} of {} answered. Sources ({}): {}.",
+                    target.peak_rate, endpoint(dst, port), target.answered, target.syns, target.sources.len(), listed(&sources),
+                ),
+                timestamp: Some(target.peak_ts),
+            });
+        }
+    }
+
+    fn report_arp(&self, issues: &mut Vec<PcapIssue>) {
+        for (ip, conflict) in &self.arp_conflicts {
+            let macs: Vec<String> = conflict.macs.iter().map(mac).collect();
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "ARP Spoofing".into(),
+                description: format!(
+                    "{} changed MAC address {} time(s), claimed by {}. Unless this is a failover or a re-addressed host, someone is poisoning ARP caches.",
+                    ip, conflict.changes, macs.join(", "),
+                ),
+                timestamp: Some(conflict.ts),
+            });
+        }
+        for (sender, garp) in &self.gratuitous {
+            let Some(ts) = garp.storm_ts else { continue };
+            let claimed: Vec<String> = self.arp_bindings.iter()
+                .filter(|(_, m)| *m == sender)
+                .map(|(ip, _)| ip.to_string())
+                .collect();
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "Gratuitous ARP Storm".into(),
+                description: format!(
+                    "{} sent {} gratuitous ARPs, up to {} within {} s, for {}.",
+                    mac(sender), garp.total, garp.peak, GARP_WINDOW, listed(&claimed),
+                ),
+                timestamp: Some(ts),
+            });
+        }
+    }
+
+    fn report_dhcp(&self, issues: &mut Vec<PcapIssue>) {
+        if self.dhcp_servers.len() < 2 {
+            return;
+        }
+        let mut servers: Vec<(&Ipv4Addr, &DhcpServer)> = self.dhcp_servers.iter().collect();
+        servers.sort_by(|a, b| b.1.replies.cmp(&a.1.replies).then(a.0.cmp(b.0)));
+        let names: Vec<String> = servers.iter().map(|(ip, s)| match &s.mac {
+            Some(m) => format!("{} [{}] ({} replies)", ip, mac(m), s.replies),
+            None => format!("{} ({} replies)", ip, s.replies),
+        }).collect();
+        issues.push(PcapIssue {
+            severity: "critical".into(),
+            title: "Rogue DHCP Server".into(),
+            description: format!(
+                "{} DHCP servers answered clients: {}. Unless they are a failover pair, all but one are rogue.",
+                servers.len(), names.join(", "),
+            ),
+            timestamp: servers.iter().map(|(_, s)| s.ts).reduce(f64::max),
+        });
+    }
+
+    fn report_redirects(&self, issues: &mut Vec<PcapIssue>) {
+        for (&(router, host), redirect) in &self.redirects {
+            let mut gateways: Vec<String> = redirect.gateways.iter().map(IpAddr::to_string).collect();
+            let mut destinations: Vec<String> = redirect.destinations.iter().map(IpAddr::to_string).collect();
+            gateways.sort();
+            destinations.sort();
+            let towards = if destinations.is_empty() { String::new() } else { format!(" for {}", listed(&destinations)) };
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "ICMP Redirect".into(),
+                description: format!(
+                    "{} told {} to use {}{} ({} redirect(s)). Redirects reroute traffic and are rarely needed; check routing, or whether {} is a router at all.",
+                    router, host, listed(&gateways), towards, redirect.count, router,
+                ),
+                timestamp: Some(redirect.ts),
+            });
+        }
+    }
+
+    fn report_beacons(&self, issues: &mut Vec<PcapIssue>) {
+        for (&(protocol, src, dst, port), beacon) in &self.beacons {
+            if beacon.starts.len() < BEACON_MIN_CONNECTIONS {
+                continue;
+            }
+            let intervals: Vec<f64> = beacon.starts.windows(2).map(|w| w[1] - w[0]).collect();
+            let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+            let deviation = (intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / intervals.len() as f64).sqrt();
+            if mean <= BEACON_IDLE_GAP || deviation > mean * BEACON_MAX_JITTER {
+                continue;
+            }
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "Periodic Beaconing".into(),
+                description: format!(
+                    "{} contacted {} over {} {} times, every {:.1} s (±{:.1} s). Regular check-ins like this are typical of malware command and control, or of update and telemetry agents.",
+                    src, endpoint(dst, port), if protocol == TCP { "TCP" } else { "UDP" }, beacon.starts.len(), mean, deviation,
+                ),
+                timestamp: beacon.starts.first().copied(),
+            });
+        }
+    }
+}
+
+fn is_group(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_multicast() || v4.is_broadcast(),
+        IpAddr::V6(v6) => v6.is_multicast(),
+    }
+}
+
+fn endpoint(ip: IpAddr, port: u16) -> String {
+    match ip {
+        IpAddr::V4(v4) => format!("{}:{}", v4, port),
+        IpAddr::V6(v6) => format!("[{}]:{}", v6, port),
+    }
+}
+
+fn listed(items: &[String]) -> String {
+    match items.len() {
+        n if n > MAX_LISTED => format!("{} (+{} more)", items[..MAX_LISTED].join(", "), n - MAX_LISTED),
+        _ => items.join(", "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::icmpv4::{RedirectCode, RedirectHeader};
+    use etherparse::{ArpEthIpv4Packet, ArpOperation, PacketBuilder};
+
+    fn feed(detector: &mut AnomalyDetector, frame: &[u8], ts: f64) -> bool {
+        detector.process(&PacketHeaders::from_ethernet_slice(frame).unwrap(), ts)
+    }
+
+    fn syn(src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16, ack: bool) -> Vec<u8> {
+        let mut frame = Vec::new();
+        let builder = PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2]).ipv4(src, dst, 64).tcp(sport, dport, 1, 64240).syn();
+        let builder = if ack { builder.ack(2) } else { builder };
+        builder.write(&mut frame, &[]).unwrap();
+        frame
+    }
+
+    fn udp(src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16, payload: &[u8], mac: [u8; 6]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        PacketBuilder::ethernet2(mac, [0xff; 6]).ipv4(src, dst, 64).udp(sport, dport).write(&mut frame, payload).unwrap();
+        frame
+    }
+
+    fn arp(mac: [u8; 6], ip: [u8; 4], target: [u8; 4]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        let packet = ArpEthIpv4Packet {
+            operation: ArpOperation::REPLY,
+            sender_mac: mac,
+            sender_ipv4: ip,
+            target_mac: [0xff; 6],
+            target_ipv4: target,
+        };
+        PacketBuilder::ethernet2(mac, [0xff; 6]).arp(packet.into()).write(&mut frame).unwrap();
+        frame
+    }
+
+    fn dhcp_offer(server: [u8; 4]) -> Vec<u8> {
+        let mut msg = vec![0u8; 236];
+        msg[0] = 2;
+        msg.extend_from_slice(&[99, 130, 83, 99, 53, 1, 2, 54, 4]);
+        msg.extend_from_slice(&server);
+        msg.push(255);
+        msg
+    }
+
+    fn finish(detector: AnomalyDetector) -> Vec<PcapIssue> {
+        let mut issues = Vec::new();
+        detector.finish(&mut issues);
+        issues
+    }
+
+    fn titles(issues: &[PcapIssue]) -> Vec<&str> {
+        issues.iter().map(|i| i.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_scans_and_syn_flood() {
+        let mut detector = AnomalyDetector::default();
+        let (scanner, target) = ([10, 0, 0, 66], [10, 0, 0, 5]);
+        for port in 1..=150 {
+            feed(&mut detector, &syn(scanner, target, 40000, port, false), 1.0 + port as f64 * 0.001);
+        }
+        // Two ports are open.
+        feed(&mut detector, &syn(target, scanner, 22, 40000, true), 1.2);
+        feed(&mut detector, &syn(target, scanner, 80, 40000, true), 1.2);
+        for host in 1..=60 {
+            feed(&mut detector, &syn(scanner, [10, 0, 1, host], 40001, 3389, false), 2.0 + host as f64 * 0.01);
+        }
+        // 300 spoofed SYNs to a web server in under a second, none answered.
+        for i in 0..300u32 {
+            let src = [198, 51, (i / 250) as u8, (i % 250) as u8 + 1];
+            feed(&mut detector, &syn(src, [10, 0, 0, 80], 1024 + i as u16, 443, false), 5.0 + i as f64 * 0.003);
+        }
+        // A client opening connections to 60 sites, all answered, is none of these.
+        for i in 0..60u8 {
+            feed(&mut detector, &syn([10, 0, 0, 7], [93, 184, i, 1], 50000 + i as u16, 443, false), 10.0 + i as f64 * 0.3);
+            feed(&mut detector, &syn([93, 184, i, 1], [10, 0, 0, 7], 443, 50000 + i as u16, true), 10.01 + i as f64 * 0.3);
+        }
+
+        let issues = finish(detector);
+        assert_eq!(titles(&issues), vec!["Port Scan", "Host Sweep", "SYN Flood"]);
+        assert!(issues[0].description.starts_with("10.0.0.66 probed 150 ports across 10.0.0.5 (150 ports); 2 answered"));
+        assert!(issues[1].description.starts_with("10.0.0.66 probed up to 60 hosts on port 3389"));
+        assert!(issues[2].description.starts_with("Up to 300 SYNs per second to 10.0.0.80:443; 0 of 300 answered"));
+        assert_eq!(issues[2].timestamp, Some(5.0));
+    }
+
+    #[test]
+    fn test_arp_dhcp_and_redirects() {
+        let mut detector = AnomalyDetector::default();
+        let (router, attacker) = ([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 0x66]);
+        assert!(!feed(&mut detector, &arp(router, [10, 0, 0, 1], [10, 0, 0, 5]), 1.0));
+        assert!(feed(&mut detector, &arp(attacker, [10, 0, 0, 1], [10, 0, 0, 5]), 2.0));
+        for i in 0..25 {
+            feed(&mut detector, &arp(attacker, [10, 0, 0, 1], [10, 0, 0, 1]), 3.0 + i as f64 * 0.1);
+        }
+
+        feed(&mut detector, &udp([10, 0, 0, 1], [255; 4], 67, 68, &dhcp_offer([10, 0, 0, 1]), router), 4.0);
+        feed(&mut detector, &udp([10, 0, 0, 66], [255; 4], 67, 68, &dhcp_offer([10, 0, 0, 66]), attacker), 4.1);
+        feed(&mut detector, &udp([10, 0, 0, 66], [255; 4], 67, 68, &dhcp_offer([10, 0, 0, 66]), attacker), 4.2);
+
+        let mut redirect = Vec::new();
+        let header = RedirectHeader { code: RedirectCode::RedirectForHost, gateway_internet_address: [10, 0, 0, 66] };
+        let mut original = Vec::new();
+        PacketBuilder::ipv4([10, 0, 0, 5], [8, 8, 8, 8], 64).udp(5000, 53).write(&mut original, &[]).unwrap();
+        PacketBuilder::ethernet2(attacker, [2, 0, 0, 0, 0, 5])
+            .ipv4([10, 0, 0, 1], [10, 0, 0, 5], 64)
+            .icmpv4(Icmpv4Type::Redirect(header))
+            .write(&mut redirect, &original)
+            .unwrap();
+        assert!(feed(&mut detector, &redirect, 5.0));
+
+        let issues = finish(detector);
+        assert_eq!(titles(&issues), vec!["ARP Spoofing", "Gratuitous ARP Storm", "Rogue DHCP Server", "ICMP Redirect"]);
+        assert!(issues[0].description.contains("02:00:00:00:00:01, 02:00:00:00:00:66"));
+        assert!(issues[1].description.starts_with("02:00:00:00:00:66 sent 25 gratuitous ARPs"));
+        assert!(issues[2].description.contains("10.0.0.66 [02:00:00:00:00:66] (2 replies), 10.0.0.1"));
+        assert!(issues[3].description.starts_with("10.0.0.1 told 10.0.0.5 to use 10.0.0.66 for 8.8.8.8"));
+    }
+
+    #[test]
+    fn test_beaconing() {
+        let mut detector = AnomalyDetector::default();
+        let mac = [2, 0, 0, 0, 0, 9];
+        for i in 0..11 {
+            let ts = 100.0 + i as f64 * 60.0 + if i % 2 == 0 { 0.4 } else { -0.4 };
+            // Each check-in is a short exchange, not a single packet.
+            for j in 0..3 {
+                feed(&mut detector, &udp([10, 0, 0, 9], [203, 0, 113, 9], 5000, 8443, b"ping", mac), ts + j as f64 * 0.05);
+            }
+            feed(&mut detector, &syn([10, 0, 0, 9], [198, 51, 100, 7], 40000 + i, 443, false), 100.0 + (i * i) as f64 * 7.0);
+        }
+        // NTP polls just as regularly and is left alone.
+        for i in 0..10 {
+            feed(&mut detector, &udp([10, 0, 0, 9], [192, 0, 2, 123], 123, 123, b"ntp", mac), 64.0 * i as f64);
+        }
+
+        let issues = finish(detector);
+        assert_eq!(titles(&issues), vec!["Periodic Beaconing"]);
+        assert!(issues[0].description.starts_with("10.0.0.9 contacted 203.0.113.9:8443 over UDP 11 times, every 60.0 s (±0.8 s)"));
+        assert_eq!(issues[0].timestamp, Some(100.4));
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+use etherparse::{ArpEthIpv4Packet, ArpOperation};
+
+use crate::modules::utils::{ArpDuplicateIp, ArpSummary, ArpUnanswered, PcapIssue};
+use super::detail::mac;
+
+// A reply this much later than the request no longer answers it; the host has long retried.
+const REPLY_TIMEOUT: f64 = 3.0;
+// Two MACs claiming one address this close together are both in use, not a NIC swap.
+const DUPLICATE_WINDOW: f64 = 5.0;
+// Fewer unanswered requests than this for one target is normal churn.
+const UNANSWERED_MIN_REQUESTS: usize = 3;
+const MAX_UNANSWERED: usize = 50;
+const MAX_TRACKED: usize = 100_000;
+const MAX_LISTED: usize = 5;
+
+struct Pending {
+    first_ts: f64,
+    requests: usize,
+}
+
+#[derive(Default)]
+struct Tally {
+    requests: usize,
+    requesters: HashSet<Ipv4Addr>,
+}
+
+struct Duplicate {
+    ts: f64,
+    macs: Vec<[u8; 6]>,
+}
+
+/// Summarises ARP (IPv4 over Ethernet) request/reply behaviour: requests nobody
+/// answers, and addresses two hosts are using at once, whether seen as competing
+/// claims or as a reply to an address conflict probe (RFC 5227).
+#[derive(Default)]
+pub struct ArpAnalyzer {
+    summary: ArpSummary,
+    // (requester, target) pairs waiting for a reply
+    pending: HashMap<(Ipv4Addr, Ipv4Addr), Pending>,
+    unanswered: HashMap<Ipv4Addr, Tally>,
+    // latest claim of each address: who and when
+    claims: HashMap<Ipv4Addr, ([u8; 6], f64)>,
+    // addresses being probed, by the MAC of the prober
+    probes: HashMap<Ipv4Addr, [u8; 6]>,
+    duplicates: HashMap<Ipv4Addr, Duplicate>,
+}
+
+impl ArpAnalyzer {
+    pub fn process(&mut self, arp: &ArpEthIpv4Packet, ts: f64) {
+        let sender = Ipv4Addr::from(arp.sender_ipv4);
+        let target = Ipv4Addr::from(arp.target_ipv4);
+
+        if arp.operation == ArpOperation::REQUEST {
+            if sender.is_unspecified() {
+                self.summary.probes += 1;
+                if self.probes.len() < MAX_TRACKED {
+                    self.probes.insert(target, arp.sender_mac);
+                }
+                return;
+            }
+            self.claim(sender, arp.sender_mac, ts);
+            if sender == target {
+                self.summary.gratuitous += 1;
+                return;
+            }
+            self.summary.requests += 1;
+            if let Some(pending) = self.pending.get_mut(&(sender, target)) {
+                if ts - pending.first_ts <= REPLY_TIMEOUT {
+                    pending.requests += 1;
+                    return;
+                }
+                let expired = self.pending.remove(&(sender, target)).map(|p| p.requests).unwrap_or(0);
+                self.record_unanswered(sender, target, expired);
+            }
+            if self.pending.len() < MAX_TRACKED {
+                self.pending.insert((sender, target), Pending { first_ts: ts, requests: 1 });
+            }
+        } else if arp.operation == ArpOperation::REPLY {
+            self.summary.replies += 1;
+            if sender == target {
+                self.summary.gratuitous += 1;
+            }
+            self.claim(sender, arp.sender_mac, ts);
+            self.pending.remove(&(target, sender));
+            if let Some(prober) = self.probes.get(&sender).copied().filter(|p| *p != arp.sender_mac) {
+                self.record_duplicate(sender, prober, arp.sender_mac, ts);
+            }
+        }
+    }
+
+    fn claim(&mut self, ip: Ipv4Addr, sender_mac: [u8; 6], ts: f64) {
+        if self.claims.len() >= MAX_TRACKED && !self.claims.contains_key(&ip) {
+            return;
+        }
+        if let Some((previous, seen)) = self.claims.insert(ip, (sender_mac, ts)) {
+            if previous != sender_mac && ts - seen <= DUPLICATE_WINDOW {
+                self.record_duplicate(ip, previous, sender_mac, ts);
+            }
+        }
+    }
+
+    fn record_duplicate(&mut self, ip: Ipv4Addr, first: [u8; 6], second: [u8; 6], ts: f64) {
+        let duplicate = self.duplicates.entry(ip).or_insert(Duplicate { ts, macs: vec![first] });
+        if !duplicate.macs.contains(&second) {
+            duplicate.macs.push(second);
+        }
+    }
+
+    fn record_unanswered(&mut self, requester: Ipv4Addr, target: Ipv4Addr, requests: usize) {
+        self.summary.unanswered_requests += requests;
+        let tally = self.unanswered.entry(target).or_default();
+        tally.requests += requests;
+        tally.requesters.insert(requester);
+    }
+
+    pub fn finish(mut self, issues: &mut Vec<PcapIssue>) -> ArpSummary {
+        for ((requester, target), pending) in std::mem::take(&mut self.pending) {
+            self.record_unanswered(requester, target, pending.requests);
+        }
+        let mut summary = self.summary;
+        summary.hosts = self.claims.len();
+
+        let mut unanswered: Vec<(Ipv4Addr, Tally)> = self.unanswered.into_iter().collect();
+        unanswered.sort_by(|a, b| b.1.requests.cmp(&a.1.requests).then_with(|| a.0.cmp(&b.0)));
+        summary.unanswered = unanswered.into_iter().take(MAX_UNANSWERED).map(|(target, tally)| {
+            let mut requesters: Vec<Ipv4Addr> = tally.requesters.into_iter().collect();
+            requesters.sort();
+            ArpUnanswered {
+                target: target.to_string(),
+                requests: tally.requests,
+                requesters: requesters.iter().map(Ipv4Addr::to_string).collect(),
+            }
+        }).collect();
+
+        let mut duplicates: Vec<(Ipv4Addr, Duplicate)> = self.duplicates.into_iter().collect();
+        duplicates.sort_by(|a, b| a.1.ts.total_cmp(&b.1.ts));
+        for (ip, duplicate) in &duplicates {
+            let macs: Vec<String> = duplicate.macs.iter().map(mac).collect();
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "Duplicate IP Address".into(),
+                description: format!(
+                    "{} is in use by {} at the same time. Expect intermittent connectivity for both hosts; look for a static address inside the DHCP scope.",
+                    ip, macs.join(" and "),
+                ),
+                timestamp: Some(duplicate.ts),
+            });
+        }
+        summary.duplicate_ips = duplicates.into_iter().map(|(ip, duplicate)| ArpDuplicateIp {
+            ip: ip.to_string(),
+            macs: duplicate.macs.iter().map(mac).collect(),
+            timestamp: duplicate.ts,
+        }).collect();
+
+        let silent: Vec<String> = summary.unanswered.iter()
+            .filter(|u| u.requests >= UNANSWERED_MIN_REQUESTS)
+            .map(|u| format!("{} ({} requests)", u.target, u.requests))
+            .collect();
+        if !silent.is_empty() {
+            let shown = silent.len().min(MAX_LISTED);
+            let more = if silent.len() > shown { format!(" (+{} more)", silent.len() - shown) } else { String::new() };
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "Unanswered ARP Requests".into(),
+                description: format!(
+                    "Nobody answered ARP for {}{}. The hosts are down, or a static route or mask points at an address that isn't on the link.",
+                    silent[..shown].join(", "), more,
+                ),
+                timestamp: None,
+            });
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arp(operation: ArpOperation, sender_mac: [u8; 6], sender: [u8; 4], target: [u8; 4]) -> ArpEthIpv4Packet {
+        ArpEthIpv4Packet { operation, sender_mac, sender_ipv4: sender, target_mac: [0; 6], target_ipv4: target }
+    }
+
+    #[test]
+    fn test_arp_summary() {
+        let (a, b, c) = ([2, 0, 0, 0, 0, 0xa], [2, 0, 0, 0, 0, 0xb], [2, 0, 0, 0, 0, 0xc]);
+        let mut analyzer = ArpAnalyzer::default();
+        analyzer.process(&arp(ArpOperation::REQUEST, a, [10, 0, 0, 5], [10, 0, 0, 1]), 1.0);
+        analyzer.process(&arp(ArpOperation::REPLY, b, [10, 0, 0, 1], [10, 0, 0, 5]), 1.001);
+        // Two hosts answering for .1 within a second of each other.
+        analyzer.process(&arp(ArpOperation::REPLY, c, [10, 0, 0, 1], [10, 0, 0, 5]), 1.5);
+        // Retries towards a host that is down, then again after the timeout.
+        for ts in [2.0, 3.0, 4.0, 10.0] {
+            analyzer.process(&arp(ArpOperation::REQUEST, a, [10, 0, 0, 5], [10, 0, 0, 99]), ts);
+        }
+        analyzer.process(&arp(ArpOperation::REQUEST, a, [10, 0, 0, 5], [10, 0, 0, 5]), 11.0);
+        // A new host probes for .20, which c already uses.
+        analyzer.process(&arp(ArpOperation::REQUEST, b, [0, 0, 0, 0], [10, 0, 0, 20]), 12.0);
+        analyzer.process(&arp(ArpOperation::REPLY, c, [10, 0, 0, 20], [0, 0, 0, 0]), 12.01);
+
+        let mut issues = Vec::new();
+        let summary = analyzer.finish(&mut issues);
+        assert_eq!((summary.requests, summary.replies, summary.gratuitous, summary.probes), (5, 3, 1, 1));
+        assert_eq!((summary.unanswered_requests, summary.hosts), (4, 3));
+        assert_eq!(summary.unanswered.len(), 1);
+        assert_eq!((summary.unanswered[0].target.as_str(), summary.unanswered[0].requests), ("10.0.0.99", 4));
+        assert_eq!(summary.unanswered[0].requesters, vec!["10.0.0.5"]);
+
+        let duplicated: Vec<&str> = summary.duplicate_ips.iter().map(|d| d.ip.as_str()).collect();
+        assert_eq!(duplicated, vec!["10.0.0.1", "10.0.0.20"]);
+        assert_eq!(summary.duplicate_ips[1].macs, vec!["02:00:00:00:00:0b", "02:00:00:00:00:0c"]);
+
+        let titles: Vec<&str> = issues.iter().map(|i| i.title.as_str()).collect();
+        assert_eq!(titles, vec!["Duplicate IP Address", "Duplicate IP Address", "Unanswered ARP Requests"]);
+        assert!(issues[2].description.contains("10.0.0.99 (4 requests)"));
+    }
+}
@@ -0,0 +1,433 @@
+use std::sync::atomic::AtomicBool;
+
+use crate::modules::utils::{PcapAnalysisResult, PcapCaptureOptions, PcapCaptureStats};
+use super::index::PacketIndex;
+
+// Linux caps classic BPF programs at this many instructions (BPF_MAXINSNS).
+const MAX_BPF_INSTRUCTIONS: usize = 4096;
+
+/// One classic BPF instruction, as handed to `SO_ATTACH_FILTER`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct BpfInstruction {
+    code: u16,
+    jt: u8,
+    jf: u8,
+    k: u32,
+}
+
+/// Captures from `interface` into a pcapng file at `output_path`, analyzing each packet
+/// as it arrives, until a limit in `options` is reached or `cancel` is set. `on_stats`
+/// is called about once a second with the running totals. A cancelled capture still
+/// returns its results, marked `cancelled`, along with the index of the saved packets.
+pub fn run_capture<F>(
+    interface: &str, output_path: &str, options: &PcapCaptureOptions, cancel: &AtomicBool, on_stats: F,
+) -> Result<(PcapAnalysisResult, PacketIndex), String>
+where
+    F: FnMut(PcapCaptureStats),
+{
+    let filter = match options.bpf_filter.as_deref().map(str::trim) {
+        None | Some("") => Vec::new(),
+        Some(text) => parse_bpf(text).map_err(|e| format!("Invalid BPF filter: {}", e))?,
+    };
+    capture(interface, output_path, options, &filter, cancel, on_stats)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn capture<F>(
+    _interface: &str, _output_path: &str, _options: &PcapCaptureOptions, _filter: &[BpfInstruction], _cancel: &AtomicBool, _on_stats: F,
+) -> Result<(PcapAnalysisResult, PacketIndex), String>
+where
+    F: FnMut(PcapCaptureStats),
+{
+    Err("Live capture is only supported on Linux".into())
+}
+
+#[cfg(target_os = "linux")]
+fn capture<F>(
+    interface: &str, output_path: &str, options: &PcapCaptureOptions, filter: &[BpfInstruction], cancel: &AtomicBool, mut on_stats: F,
+) -> Result<(PcapAnalysisResult, PacketIndex), String>
+where
+    F: FnMut(PcapCaptureStats),
+{
+    use std::ops::ControlFlow;
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+    use super::reader::CapturedPacket;
+    use super::writer::CaptureWriter;
+    use super::PcapAnalyzer;
+
+    const STATS_INTERVAL: Duration = Duration::from_secs(1);
+    // How long to wait for packets before checking limits and `cancel` again.
+    const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+    // Enhanced Packet Block fields ahead of the packet data.
+    const EPB_HEADER_LEN: u64 = 28;
+
+    let mut socket = ring::RingSocket::open(interface, options.ring_buffer_mb, filter)?;
+    let linktype = socket.linktype;
+    let mut writer = CaptureWriter::create(output_path, true)?;
+    let mut analyzer = PcapAnalyzer::default();
+    let snaplen = options.snaplen.max(1) as usize;
+
+    let started = Instant::now();
+    let mut stats = PcapCaptureStats {
+        job_id: String::new(),
+        packets: 0,
+        bytes: 0,
+        dropped: 0,
+        elapsed_sec: 0.0,
+        packets_per_sec: 0.0,
+        bits_per_sec: 0.0,
+        flagged_packets: 0,
+    };
+    let mut last_report = (started, 0, 0);
+    let mut saved_bytes = 0u64;
+    let mut limit_reached = false;
+    let mut cancelled = false;
+    let mut write_error = None;
+
+    let mut report = |stats: &mut PcapCaptureStats, last_report: &mut (Instant, usize, u64), analyzer: &PcapAnalyzer, socket: &ring::RingSocket| {
+        let (at, packets, bytes) = *last_report;
+        let interval = at.elapsed().as_secs_f64().max(1e-3);
+        stats.dropped += socket.drops();
+        stats.elapsed_sec = started.elapsed().as_secs_f64();
+        stats.packets_per_sec = (stats.packets - packets) as f64 / interval;
+        stats.bits_per_sec = (stats.bytes - bytes) as f64 * 8.0 / interval;
+        stats.flagged_packets = analyzer.flagged_packets;
+        *last_report = (Instant::now(), stats.packets, stats.bytes);
+        on_stats(stats.clone());
+    };
+
+    while !limit_reached {
+        if cancel.load(Ordering::Relaxed) {
+            cancelled = true;
+            break;
+        }
+        if options.max_seconds.is_some_and(|secs| started.elapsed() >= Duration::from_secs(secs)) {
+            break;
+        }
+        socket.next_block(POLL_TIMEOUT, |ts, data, orig_len| {
+            let data = &data[..data.len().min(snaplen)];
+            let data_offset = match writer.write(linktype, ts, data, orig_len) {
+                Ok(offset) => offset,
+                Err(e) => {
+                    write_error = Some(e.to_string());
+                    return ControlFlow::Break(());
+                },
+            };
+            analyzer.process_packet(&CapturedPacket {
+                offset: data_offset - EPB_HEADER_LEN,
+                data_offset,
+                ts,
+                linktype,
+                data,
+                orig_len,
+            });
+            stats.packets += 1;
+            stats.bytes += orig_len as u64;
+            saved_bytes += data.len() as u64;
+            limit_reached = options.max_packets.is_some_and(|max| stats.packets >= max)
+                || options.max_bytes.is_some_and(|max| saved_bytes >= max);
+            if limit_reached { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        })?;
+        if let Some(e) = write_error { return Err(e); }
+
+        if last_report.0.elapsed() >= STATS_INTERVAL {
+            report(&mut stats, &mut last_report, &analyzer, &socket);
+            // Keeps the file on disk usable while the capture is still running.
+            writer.flush().map_err(|e| e.to_string())?;
+        }
+    }
+    report(&mut stats, &mut last_report, &analyzer, &socket);
+    writer.finish().map_err(|e| e.to_string())?;
+
+    let index = std::mem::take(&mut analyzer.index);
+    let mut result = analyzer.finish();
+    result.cancelled = cancelled;
+    Ok((result, index))
+}
+
+/// Reads a compiled filter as printed by `tcpdump -dd` (a C array) or `tcpdump -ddd`
+/// (decimal, instruction count first). Filter expressions have to be compiled by
+/// libpcap for the interface's link type, which this tool does not link against.
+fn parse_bpf(text: &str) -> Result<Vec<BpfInstruction>, String> {
+    let numbers = text.split(|c: char| c.is_whitespace() || matches!(c, ',' | '{' | '}'))
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => token.parse(),
+            }.map_err(|_| format!("'{}' is not a number; compile the expression with `tcpdump -dd`", token))
+        })
+        .collect::<Result<Vec<u64>, String>>()?;
+
+    let fields = if text.contains('{') {
+        &numbers[..]
+    } else {
+        match numbers.split_first() {
+            Some((&count, rest)) if rest.len() as u64 == count.saturating_mul(4) => rest,
+            _ => return Err("the instruction count does not match the program".into()),
+        }
+    };
+    if fields.is_empty() || !fields.len().is_multiple_of(4) {
+        return Err("expected instructions of four numbers each (code, jt, jf, k)".into());
+    }
+    if fields.len() / 4 > MAX_BPF_INSTRUCTIONS {
+        return Err(format!("programs are limited to {} instructions", MAX_BPF_INSTRUCTIONS));
+    }
+    fields.chunks(4).map(|c| {
+        match (u16::try_from(c[0]), u8::try_from(c[1]), u8::try_from(c[2]), u32::try_from(c[3])) {
+            (Ok(code), Ok(jt), Ok(jf), Ok(k)) => Ok(BpfInstruction { code, jt, jf, k }),
+            _ => Err(format!("instruction {:?} is out of range", c)),
+        }
+    }).collect()
+}
+
+/// An `AF_PACKET` socket with a `TPACKET_V3` receive ring: the kernel fills fixed-size
+/// blocks of packets in shared memory and hands each one over when it is full or has
+/// been open for `BLOCK_TIMEOUT_MS`, so nothing is copied per packet.
+#[cfg(target_os = "linux")]
+mod ring {
+    use std::ffi::CString;
+    use std::io;
+    use std::ops::ControlFlow;
+    use std::ptr;
+    use std::sync::atomic::{fence, Ordering};
+    use std::time::Duration;
+    use pcap_parser::Linktype;
+
+    use super::BpfInstruction;
+    use super::super::link::{IEEE802_11, IEEE802_11_RADIOTAP};
+
+    const BLOCK_SIZE: usize = 1 << 20;
+    const FRAME_SIZE: usize = 2048;
+    const BLOCK_TIMEOUT_MS: u32 = 100;
+    // Bit of CAP_NET_RAW in the capability sets of /proc/<pid>/status.
+    const CAP_NET_RAW: u32 = 13;
+
+    pub struct RingSocket {
+        fd: libc::c_int,
+        ring: *mut u8,
+        blocks: usize,
+        current: usize,
+        // Loopback frames are seen leaving and arriving; only arrivals are kept, as libpcap does.
+        loopback: bool,
+        pub linktype: Linktype,
+    }
+
+    impl RingSocket {
+        pub fn open(interface: &str, ring_buffer_mb: u32, filter: &[BpfInstruction]) -> Result<Self, String> {
+            if !has_net_raw() {
+                return Err(privilege_error());
+            }
+            let linktype = interface_linktype(interface)?;
+            let name = CString::new(interface).map_err(|_| format!("Invalid interface name '{}'", interface))?;
+            let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+            if ifindex == 0 {
+                return Err(format!("No interface named '{}'", interface));
+            }
+
+            // Protocol 0 receives nothing until `bind`, after the filter is in place.
+            let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+            if fd < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::PermissionDenied {
+                    return Err(privilege_error());
+                }
+                return Err(format!("Could not open a packet socket: {}", err));
+            }
+            let loopback = std::fs::read_to_string(format!("/sys/class/net/{}/type", interface)).is_ok_and(|t| t.trim() == "772");
+            let mut socket = RingSocket {
+                fd,
+                ring: ptr::null_mut(),
+                blocks: ring_buffer_mb.clamp(1, 1024) as usize,
+                current: 0,
+                loopback,
+                linktype,
+            };
+            socket.setup(ifindex, filter)?;
+            Ok(socket)
+        }
+
+        fn setup(&mut self, ifindex: u32, filter: &[BpfInstruction]) -> Result<(), String> {
+            let version = libc::tpacket_versions::TPACKET_V3 as libc::c_int;
+            self.set_option(libc::SOL_PACKET, libc::PACKET_VERSION, &version, "select TPACKET_V3")?;
+            let request = libc::tpacket_req3 {
+                tp_block_size: BLOCK_SIZE as u32,
+                tp_block_nr: self.blocks as u32,
+                tp_frame_size: FRAME_SIZE as u32,
+                tp_frame_nr: (BLOCK_SIZE / FRAME_SIZE * self.blocks) as u32,
+                tp_retire_blk_tov: BLOCK_TIMEOUT_MS,
+                tp_sizeof_priv: 0,
+                tp_feature_req_word: 0,
+            };
+            self.set_option(libc::SOL_PACKET, libc::PACKET_RX_RING, &request, "allocate the ring buffer")?;
+
+            let ring = unsafe {
+                libc::mmap(ptr::null_mut(), BLOCK_SIZE * self.blocks, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, self.fd, 0)
+            };
+            if ring == libc::MAP_FAILED {
+                return Err(format!("Could not map the ring buffer: {}", io::Error::last_os_error()));
+            }
+            self.ring = ring as *mut u8;
+
+            if !filter.is_empty() {
+                let mut program: Vec<libc::sock_filter> = filter.iter()
+                    .map(|i| libc::sock_filter { code: i.code, jt: i.jt, jf: i.jf, k: i.k })
+                    .collect();
+                let fprog = libc::sock_fprog { len: program.len() as u16, filter: program.as_mut_ptr() };
+                self.set_option(libc::SOL_SOCKET, libc::SO_ATTACH_FILTER, &fprog, "attach the BPF filter")?;
+            }
+
+            let address = libc::sockaddr_ll {
+                sll_family: libc::AF_PACKET as u16,
+                sll_protocol: (libc::ETH_P_ALL as u16).to_be(),
+                sll_ifindex: ifindex as i32,
+                sll_hatype: 0,
+                sll_pkttype: 0,
+                sll_halen: 0,
+                sll_addr: [0; 8],
+            };
+            let rc = unsafe {
+                libc::bind(self.fd, &address as *const _ as *const libc::sockaddr, size_of::<libc::sockaddr_ll>() as libc::socklen_t)
+            };
+            if rc < 0 {
+                return Err(format!("Could not bind to the interface: {}", io::Error::last_os_error()));
+            }
+            Ok(())
+        }
+
+        fn set_option<T>(&self, level: libc::c_int, name: libc::c_int, value: &T, what: &str) -> Result<(), String> {
+            let rc = unsafe {
+                libc::setsockopt(self.fd, level, name, value as *const T as *const libc::c_void, size_of::<T>() as libc::socklen_t)
+            };
+            if rc < 0 {
+                return Err(format!("Could not {}: {}", what, io::Error::last_os_error()));
+            }
+            Ok(())
+        }
+
+        /// Waits up to `timeout` for the kernel to hand over the next block, then passes
+        /// its packets to `on_packet` as (timestamp, captured bytes, length on the wire)
+        /// and gives the block back. Returns without calling `on_packet` on timeout.
+        pub fn next_block<F>(&mut self, timeout: Duration, mut on_packet: F) -> Result<(), String>
+        where
+            F: FnMut(f64, &[u8], u32) -> ControlFlow<()>,
+        {
+            let block = unsafe { self.ring.add(self.current * BLOCK_SIZE) } as *mut libc::tpacket_block_desc;
+            // The kernel writes the status word concurrently, so it must not be cached.
+            let status = unsafe { ptr::addr_of!((*block).hdr.bh1.block_status).read_volatile() };
+            if status & libc::TP_STATUS_USER == 0 {
+                let mut pfd = libc::pollfd { fd: self.fd, events: libc::POLLIN | libc::POLLERR, revents: 0 };
+                let rc = unsafe { libc::poll(&mut pfd, 1, timeout.as_millis() as libc::c_int) };
+                let err = io::Error::last_os_error();
+                if rc < 0 && err.kind() != io::ErrorKind::Interrupted {
+                    return Err(format!("Waiting for packets failed: {}", err));
+                }
+                return Ok(());
+            }
+            fence(Ordering::Acquire);
+
+            let (count, first) = unsafe { ((*block).hdr.bh1.num_pkts, (*block).hdr.bh1.offset_to_first_pkt) };
+            let mut frame = unsafe { (block as *const u8).add(first as usize) };
+            for _ in 0..count {
+                let header = unsafe { &*(frame as *const libc::tpacket3_hdr) };
+                // The sender's address follows the header, aligned to TPACKET_ALIGNMENT.
+                let address_at = size_of::<libc::tpacket3_hdr>().next_multiple_of(libc::TPACKET_ALIGNMENT);
+                let address = unsafe { &*(frame.add(address_at) as *const libc::sockaddr_ll) };
+                if self.loopback && address.sll_pkttype == libc::PACKET_OUTGOING {
+                    frame = unsafe { frame.add(header.tp_next_offset as usize) };
+                    continue;
+                }
+                let data = unsafe { std::slice::from_raw_parts(frame.add(header.tp_mac as usize), header.tp_snaplen as usize) };
+                let ts = header.tp_sec as f64 + header.tp_nsec as f64 / 1e9;
+                if on_packet(ts, data, header.tp_len).is_break() {
+                    break;
+                }
+                frame = unsafe { frame.add(header.tp_next_offset as usize) };
+            }
+
+            fence(Ordering::Release);
+            unsafe { ptr::addr_of_mut!((*block).hdr.bh1.block_status).write_volatile(libc::TP_STATUS_KERNEL) };
+            self.current = (self.current + 1) % self.blocks;
+            Ok(())
+        }
+
+        /// Packets the kernel dropped because the ring was full, since the last call.
+        pub fn drops(&self) -> u64 {
+            let mut stats: libc::tpacket_stats_v3 = unsafe { std::mem::zeroed() };
+            let mut len = size_of::<libc::tpacket_stats_v3>() as libc::socklen_t;
+            let rc = unsafe {
+                libc::getsockopt(self.fd, libc::SOL_PACKET, libc::PACKET_STATISTICS, &mut stats as *mut _ as *mut libc::c_void, &mut len)
+            };
+            if rc == 0 { stats.tp_drops as u64 } else { 0 }
+        }
+    }
+
+    impl Drop for RingSocket {
+        fn drop(&mut self) {
+            unsafe {
+                if !self.ring.is_null() {
+                    libc::munmap(self.ring as *mut libc::c_void, BLOCK_SIZE * self.blocks);
+                }
+                libc::close(self.fd);
+            }
+        }
+    }
+
+    /// Whether the effective capability set includes CAP_NET_RAW. Assumes it does when
+    /// /proc is unreadable and lets `socket` decide.
+    fn has_net_raw() -> bool {
+        let Ok(status) = std::fs::read_to_string("/proc/self/status") else { return true };
+        status.lines()
+            .find_map(|line| line.strip_prefix("CapEff:"))
+            .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+            .is_none_or(|caps| caps & (1 << CAP_NET_RAW) != 0)
+    }
+
+    fn privilege_error() -> String {
+        let exe = std::env::current_exe()
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|_| "<path to this app>".into());
+        format!("Live capture needs the CAP_NET_RAW capability. Run as root, or grant it once with: sudo setcap cap_net_raw=eip {}", exe)
+    }
+
+    /// Maps the interface's ARPHRD_* hardware type to the link type the frames arrive as.
+    fn interface_linktype(interface: &str) -> Result<Linktype, String> {
+        if interface.is_empty() || interface.contains('/') {
+            return Err(format!("Invalid interface name '{}'", interface));
+        }
+        let hw_type = std::fs::read_to_string(format!("/sys/class/net/{}/type", interface))
+            .map_err(|_| format!("No interface named '{}'", interface))?;
+        match hw_type.trim() {
+            // Ethernet and loopback
+            "1" | "772" => Ok(Linktype::ETHERNET),
+            // TUN devices and other links without a hardware header
+            "65534" => Ok(Linktype::RAW),
+            "801" => Ok(IEEE802_11),
+            "803" => Ok(IEEE802_11_RADIOTAP),
+            other => Err(format!("Capturing on '{}' (hardware type {}) is not supported", interface, other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bpf_program() {
+        // tcpdump -dd 'udp' on Ethernet, abridged to the IPv4 branch
+        let dd = "{ 0x28, 0, 0, 0x0000000c },\n{ 0x15, 0, 3, 0x00000800 },\n{ 0x30, 0, 0, 0x00000017 },\n{ 0x15, 0, 1, 0x00000011 },\n{ 0x6, 0, 0, 0x00040000 },\n{ 0x6, 0, 0, 0x00000000 },\n";
+        let ddd = "6\n40 0 0 12\n21 0 3 2048\n48 0 0 23\n21 0 1 17\n6 0 0 262144\n6 0 0 0\n";
+        let program = parse_bpf(dd).unwrap();
+        assert_eq!(program.len(), 6);
+        assert_eq!(program[1], BpfInstruction { code: 0x15, jt: 0, jf: 3, k: 0x800 });
+        assert_eq!(parse_bpf(ddd).unwrap(), program);
+
+        assert!(parse_bpf("udp port 53").unwrap_err().contains("tcpdump -dd"));
+        assert!(parse_bpf("2\n40 0 0 12\n").is_err()); // count says two, one given
+        assert!(parse_bpf("{ 0x28, 0, 0 }").is_err());
+        assert!(parse_bpf("{ 0x28, 0, 300, 0 }").is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::IpAddr;
+use std::ops::ControlFlow;
+use etherparse::{NetHeaders, PacketHeaders, TransportHeader};
+
+use crate::modules::utils::{ComparedFlow, NatTranslation, PcapComparison, PcapIssue};
+use super::link::decode_packet;
+use super::reader::CaptureReader;
+
+// Share of packets lost across the link above which the loss is critical.
+const CRITICAL_LOSS_PERCENT: f64 = 5.0;
+
+/// Addresses as one capture saw them; ports are 0 for protocols without them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Tuple {
+    protocol: u8,
+    src: IpAddr,
+    dst: IpAddr,
+    sport: u16,
+    dport: u16,
+}
+
+impl Tuple {
+    fn source(&self) -> String {
+        endpoint(self.src, self.sport, self.protocol)
+    }
+
+    fn destination(&self) -> String {
+        endpoint(self.dst, self.dport, self.protocol)
+    }
+}
+
+struct Packet {
+    // Hash of everything NAT leaves alone: IP ID, TCP seq/ack/flags, ICMP type/code, payload.
+    key: u64,
+    ts: f64,
+    tuple: Tuple,
+    ttl: u8,
+    dscp: u8,
+    matched: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Side { A, B }
+
+/// One direction of a conversation, keyed by the tuple its sender's capture saw.
+#[derive(Default)]
+struct FlowAgg {
+    translated: Option<Tuple>,
+    sent: usize,
+    received: usize,
+    ttl_decrement: Option<i32>,
+    dscp: Option<(u8, u8)>,
+    remarked: usize,
+    // Receive time minus send time, each on its own capture's clock.
+    deltas: Vec<f64>,
+}
+
+/// Correlates two captures of the same traffic taken at different points, such as
+/// either side of a firewall or WAN link.
+///
+/// Packets are matched on fields that survive NAT (IP ID, TCP sequence numbers and
+/// payload), so devices that randomize TCP sequence numbers defeat matching for TCP.
+/// Direction comes from the TTL drop between the two points, falling back to timing,
+/// and the clock offset is estimated from the fastest packet each way.
+pub fn compare_captures(file_a: &str, file_b: &str) -> Result<PcapComparison, String> {
+    let mut a = load(file_a)?;
+    let mut b = load(file_b)?;
+
+    let mut by_key: HashMap<u64, VecDeque<usize>> = HashMap::new();
+    for (i, p) in a.iter().enumerate() {
+        by_key.entry(p.key).or_default().push_back(i);
+    }
+    let mut pairs = Vec::new();
+    for (j, pb) in b.iter_mut().enumerate() {
+        // Identical packets (retransmitted with the same IP ID) pair up in order.
+        if let Some(i) = by_key.get_mut(&pb.key).and_then(VecDeque::pop_front) {
+            pb.matched = true;
+            a[i].matched = true;
+            pairs.push((i, j));
+        }
+    }
+
+    // Positive when a conversation direction travels from A to B.
+    let mut votes_a: HashMap<Tuple, i64> = HashMap::new();
+    let mut votes_b: HashMap<Tuple, i64> = HashMap::new();
+    for &(i, j) in &pairs {
+        let (pa, pb) = (&a[i], &b[j]);
+        let a_to_b = if pa.ttl != pb.ttl { pa.ttl > pb.ttl } else { pb.ts >= pa.ts };
+        let vote = if a_to_b { 1 } else { -1 };
+        *votes_a.entry(pa.tuple).or_default() += vote;
+        *votes_b.entry(pb.tuple).or_default() += vote;
+    }
+
+    let mut flows: HashMap<(Side, Tuple), FlowAgg> = HashMap::new();
+    for &(i, j) in &pairs {
+        let (pa, pb) = (&a[i], &b[j]);
+        let (side, sent, recv) = if votes_a[&pa.tuple] >= 0 { (Side::A, pa, pb) } else { (Side::B, pb, pa) };
+        let flow = flows.entry((side, sent.tuple)).or_default();
+        flow.translated = Some(recv.tuple);
+        flow.sent += 1;
+        flow.received += 1;
+        flow.ttl_decrement = Some(sent.ttl as i32 - recv.ttl as i32);
+        flow.dscp.get_or_insert((sent.dscp, recv.dscp));
+        if sent.dscp != recv.dscp { flow.remarked += 1; }
+        flow.deltas.push(recv.ts - sent.ts);
+    }
+
+    // Unmatched packets count against the flow they were sent on. Packets of flows that
+    // never matched at all are reported as one-sided; unmatched arrivals are ignored.
+    for (side, packets, votes) in [(Side::A, &a, &votes_a), (Side::B, &b, &votes_b)] {
+        let is_receiver = |vote: i64| if side == Side::A { vote < 0 } else { vote >= 0 };
+        for p in packets.iter().filter(|p| !p.matched) {
+            match votes.get(&p.tuple) {
+                Some(vote) if is_receiver(*vote) => {},
+                _ => flows.entry((side, p.tuple)).or_default().sent += 1,
+            }
+        }
+    }
+
+    let min_delta = |side: Side| flows.iter()
+        .filter(|((s, _), _)| *s == side)
+        .flat_map(|(_, f)| f.deltas.iter().copied())
+        .reduce(f64::min);
+    // Offset of B's clock from A's, assuming the path is equally fast both ways.
+    let clock_offset = match (min_delta(Side::A), min_delta(Side::B)) {
+        (Some(ab), Some(ba)) => Some((ab - ba) / 2.0),
+        _ => None,
+    };
+
+    let mut nat: HashMap<(u8, String, String), usize> = HashMap::new();
+    let mut result_flows: Vec<ComparedFlow> = flows.into_iter().map(|((side, tuple), flow)| {
+        let correction = match side {
+            Side::A => -clock_offset.unwrap_or(0.0),
+            Side::B => clock_offset.unwrap_or(0.0),
+        };
+        let latencies: Vec<f64> = flow.deltas.iter().map(|d| (d + correction) * 1000.0).collect();
+        let direction = match (side, flow.translated.is_some()) {
+            (Side::A, true) => "a_to_b",
+            (Side::B, true) => "b_to_a",
+            (Side::A, false) => "a_only",
+            (Side::B, false) => "b_only",
+        };
+        if let Some(translated) = &flow.translated {
+            let (seen_a, seen_b) = if side == Side::A { (&tuple, translated) } else { (translated, &tuple) };
+            if (seen_a.src, seen_a.sport) != (seen_b.src, seen_b.sport) {
+                *nat.entry((tuple.protocol, seen_a.source(), seen_b.source())).or_default() += 1;
+            }
+            if (seen_a.dst, seen_a.dport) != (seen_b.dst, seen_b.dport) {
+                *nat.entry((tuple.protocol, seen_a.destination(), seen_b.destination())).or_default() += 1;
+            }
+        }
+        ComparedFlow {
+            protocol: protocol_name(tuple.protocol),
+            direction: direction.to_string(),
+            source: tuple.source(),
+            destination: tuple.destination(),
+            translated_source: flow.translated.map(|t| t.source()).unwrap_or_default(),
+            translated_destination: flow.translated.map(|t| t.destination()).unwrap_or_default(),
+            packets_sent: flow.sent,
+            packets_received: flow.received,
+            dropped: if flow.translated.is_some() { flow.sent - flow.received } else { 0 },
+            ttl_decrement: flow.ttl_decrement,
+            dscp_sent: flow.dscp.map(|d| d.0),
+            dscp_received: flow.dscp.map(|d| d.1),
+            dscp_remarked: flow.remarked,
+            min_latency_ms: latencies.iter().copied().reduce(f64::min),
+            avg_latency_ms: (!latencies.is_empty()).then(|| latencies.iter().sum::<f64>() / latencies.len() as f64),
+            max_latency_ms: latencies.iter().copied().reduce(f64::max),
+        }
+    }).collect();
+    result_flows.sort_by(|x, y| y.dropped.cmp(&x.dropped).then(y.packets_sent.cmp(&x.packets_sent)));
+
+    let mut nat_translations: Vec<NatTranslation> = nat.into_iter().map(|((protocol, seen_in_a, seen_in_b), flows)| NatTranslation {
+        protocol: protocol_name(protocol),
+        seen_in_a,
+        seen_in_b,
+        flows,
+    }).collect();
+    nat_translations.sort_by(|x, y| y.flows.cmp(&x.flows).then_with(|| x.seen_in_a.cmp(&y.seen_in_a)));
+
+    let issues = comparison_issues(&result_flows, &nat_translations, a.len(), b.len(), pairs.len(), clock_offset);
+    Ok(PcapComparison {
+        file_a: file_a.to_string(),
+        file_b: file_b.to_string(),
+        packets_a: a.len(),
+        packets_b: b.len(),
+        matched: pairs.len(),
+        clock_offset_ms: clock_offset.map(|o| o * 1000.0),
+        flows: result_flows,
+        nat_translations,
+        issues,
+    })
+}
+
+/// Every IP packet of a capture, keyed for matching.
+fn load(file_path: &str) -> Result<Vec<Packet>, String> {
+    let mut reader = CaptureReader::open(file_path)?;
+    let mut packets = Vec::new();
+    reader.read_packets(|pkt| {
+        if let Some(packet) = decode_packet(pkt.linktype, pkt.data).and_then(|h| correlate(&h, pkt.ts)) {
+            packets.push(packet);
+        }
+        ControlFlow::Continue(())
+    })?;
+    Ok(packets)
+}
+
+fn correlate(headers: &PacketHeaders, ts: f64) -> Option<Packet> {
+    let mut hasher = DefaultHasher::new();
+    let (src, dst, ttl, dscp, ip_protocol) = match &headers.net {
+        Some(NetHeaders::Ipv4(ip, _)) => {
+            ip.identification.hash(&mut hasher);
+            (IpAddr::from(ip.source), IpAddr::from(ip.destination), ip.time_to_live, ip.dscp.value(), ip.protocol.0)
+        },
+        Some(NetHeaders::Ipv6(ip, _)) => {
+            (IpAddr::from(ip.source), IpAddr::from(ip.destination), ip.hop_limit, ip.traffic_class >> 2, ip.next_header.0)
+        },
+        _ => return None,
+    };
+    let (protocol, sport, dport) = match &headers.transport {
+        Some(TransportHeader::Tcp(t)) => {
+            (t.sequence_number, t.acknowledgment_number, t.syn, t.ack, t.fin, t.rst).hash(&mut hasher);
+            (6, t.source_port, t.destination_port)
+        },
+        Some(TransportHeader::Udp(u)) => (17, u.source_port, u.destination_port),
+        // Echo identifiers are rewritten by NAT, so only type and code count.
+        Some(TransportHeader::Icmpv4(i)) => {
+            i.to_bytes()[..2].hash(&mut hasher);
+            (1, 0, 0)
+        },
+        Some(TransportHeader::Icmpv6(i)) => {
+            i.to_bytes()[..2].hash(&mut hasher);
+            (58, 0, 0)
+        },
+        None => (ip_protocol, 0, 0),
+    };
+    protocol.hash(&mut hasher);
+    headers.payload.slice().hash(&mut hasher);
+
+    Some(Packet {
+        key: hasher.finish(),
+        ts,
+        tuple: Tuple { protocol, src, dst, sport, dport },
+        ttl,
+        dscp,
+        matched: false,
+    })
+}
+
+fn comparison_issues(
+    flows: &[ComparedFlow], nat: &[NatTranslation], packets_a: usize, packets_b: usize, matched: usize, clock_offset: Option<f64>,
+) -> Vec<PcapIssue> {
+    let mut issues = Vec::new();
+    if matched == 0 && packets_a > 0 && packets_b > 0 {
+        issues.push(PcapIssue {
+            severity: "critical".into(),
+            title: "No Common Packets".into(),
+            description: format!("None of the {} packets in A matched the {} packets in B; check that both captures cover the same traffic and time window.", packets_a, packets_b),
+            timestamp: None,
+        });
+        return issues;
+    }
+
+    let crossing: Vec<&ComparedFlow> = flows.iter().filter(|f| f.packets_received > 0).collect();
+    let sent: usize = crossing.iter().map(|f| f.packets_sent).sum();
+    let dropped: usize = crossing.iter().map(|f| f.dropped).sum();
+    if dropped > 0 {
+        let percent = dropped as f64 / sent as f64 * 100.0;
+        let worst = crossing.iter().max_by_key(|f| f.dropped).unwrap();
+        issues.push(PcapIssue {
+            severity: if percent >= CRITICAL_LOSS_PERCENT { "critical".into() } else { "warn".into() },
+            title: "Packets Dropped in Transit".into(),
+            description: format!(
+                "{} of {} packets ({:.1}%) sent across the link never arrived. Worst: {} {} -> {} lost {}.",
+                dropped, sent, percent, worst.protocol, worst.source, worst.destination, worst.dropped
+            ),
+            timestamp: None,
+        });
+    }
+
+    let only_a = flows.iter().filter(|f| f.direction == "a_only").count();
+    let only_b = flows.iter().filter(|f| f.direction == "b_only").count();
+    if only_a + only_b > 0 {
+        issues.push(PcapIssue {
+            severity: "info".into(),
+            title: "Flows Seen on One Side Only".into(),
+            description: format!("{} flow(s) appear only in A and {} only in B; they were blocked, routed another way, or stayed local to one segment.", only_a, only_b),
+            timestamp: None,
+        });
+    }
+
+    let remarked: Vec<&ComparedFlow> = crossing.iter().copied().filter(|f| f.dscp_remarked > 0).collect();
+    if let Some(first) = remarked.first() {
+        issues.push(PcapIssue {
+            severity: "warn".into(),
+            title: "DSCP Remarked".into(),
+            description: format!(
+                "{} flow(s) had their DSCP changed in transit, e.g. {} -> {} went from {} to {}.",
+                remarked.len(), first.source, first.destination, first.dscp_sent.unwrap_or(0), first.dscp_received.unwrap_or(0)
+            ),
+            timestamp: None,
+        });
+    }
+
+    if let Some(first) = nat.first() {
+        issues.push(PcapIssue {
+            severity: "info".into(),
+            title: "NAT Detected".into(),
+            description: format!("{} address translation(s) between the captures, e.g. {} -> {}.", nat.len(), first.seen_in_a, first.seen_in_b),
+            timestamp: None,
+        });
+    }
+
+    if clock_offset.is_none() && matched > 0 {
+        issues.push(PcapIssue {
+            severity: "info".into(),
+            title: "Clock Offset Unknown".into(),
+            description: "Traffic crossed between the capture points in one direction only, so one-way latency assumes both clocks are in sync.".into(),
+            timestamp: None,
+        });
+    }
+    issues
+}
+
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "ICMP".into(),
+        6 => "TCP".into(),
+        17 => "UDP".into(),
+        58 => "ICMPv6".into(),
+        n => format!("IP proto {}", n),
+    }
+}
+
+fn endpoint(ip: IpAddr, port: u16, protocol: u8) -> String {
+    if !matches!(protocol, 6 | 17) {
+        ip.to_string()
+    } else {
+        super::endpoint(&ip.to_string(), port)
+    }
+}
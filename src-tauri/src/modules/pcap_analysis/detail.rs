@@ -0,0 +1,509 @@
+use std::fmt::Write;
+use std::fs::File;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use etherparse::{LinkExtHeader, LinkHeader, NetHeaders, PacketHeaders, TcpOptionElement, TransportHeader};
+
+use crate::modules::utils::{PcapField, PcapLayer, PcapPacketDetail, PcapPacketPage, PcapPacketSummary};
+use super::dns;
+use super::filter::DisplayFilter;
+use super::index::{read_frame, IndexEntry, PacketIndex};
+use super::link::{self, decode_packet};
+
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+const HTTP_METHODS: [&str; 9] = ["GET", "POST", "PUT", "DELETE", "HEAD", "OPTIONS", "PATCH", "CONNECT", "TRACE"];
+
+/// Summaries of up to `limit` packets after the first `offset`. With a filter, `offset`
+/// and `total` count only the matching packets; packet numbers stay those of the file.
+pub fn packet_page(index: &PacketIndex, file_path: &str, offset: usize, limit: usize, filter: Option<&DisplayFilter>) -> Result<PcapPacketPage, String> {
+    let mut file = index.open(file_path)?;
+    let matching = filter.map(|f| matching_packets(index, &mut file, f)).transpose()?;
+    let total = matching.as_ref().map_or(index.len(), |m| m.len());
+    let end = offset.saturating_add(limit.min(MAX_PAGE_SIZE)).min(total);
+    let mut packets = Vec::new();
+    for i in offset..end {
+        let number = matching.as_ref().map_or(i + 1, |m| m[i]);
+        let Some(entry) = index.get(number) else { break };
+        let data = read_frame(&mut file, entry)?;
+        packets.push(summarize(number, entry, index.first_ts(), &data));
+    }
+    Ok(PcapPacketPage { total, packets })
+}
+
+/// Numbers of the packets `filter` matches, cached on the index so paging through
+/// them doesn't rescan the file.
+fn matching_packets(index: &PacketIndex, file: &mut File, filter: &DisplayFilter) -> Result<Arc<Vec<usize>>, String> {
+    if let Some(numbers) = index.cached_matches(filter.text()) {
+        return Ok(numbers);
+    }
+    let mut numbers = Vec::new();
+    for number in 1..=index.len() {
+        let Some(entry) = index.get(number) else { break };
+        let data = read_frame(file, entry)?;
+        if filter.matches(decode_packet(entry.linktype, &data).as_ref(), data.len()) {
+            numbers.push(number);
+        }
+    }
+    let numbers = Arc::new(numbers);
+    index.cache_matches(filter.text(), numbers.clone());
+    Ok(numbers)
+}
+
+/// One packet's header tree and hex dump.
+pub fn packet_detail(index: &PacketIndex, file_path: &str, number: usize) -> Result<PcapPacketDetail, String> {
+    let entry = index.get(number).ok_or_else(|| format!("No packet {} in this capture", number))?;
+    let data = read_frame(&mut index.open(file_path)?, entry)?;
+    let summary = summarize(number, entry, index.first_ts(), &data);
+
+    let mut layers = vec![layer("Frame", vec![
+        ("Number", number.to_string()),
+        ("Arrival time", format!("{:.6}", entry.ts)),
+        ("Time since first packet", format!("{:.6} s", entry.ts - index.first_ts())),
+        ("Captured length", format!("{} bytes", data.len())),
+        ("Link type", link::linktype_name(entry.linktype)),
+    ])];
+    if let Some(headers) = decode_packet(entry.linktype, &data) {
+        dissect(&headers, &mut layers);
+    }
+    Ok(PcapPacketDetail { summary, layers, hex_dump: hex_dump(&data) })
+}
+
+pub fn summarize(number: usize, entry: &IndexEntry, first_ts: f64, data: &[u8]) -> PcapPacketSummary {
+    let mut summary = PcapPacketSummary {
+        number,
+        timestamp: entry.ts,
+        relative_time: entry.ts - first_ts,
+        length: data.len(),
+        source: String::new(),
+        destination: String::new(),
+        protocol: link::linktype_name(entry.linktype),
+        info: "Undecodable frame".into(),
+    };
+    let Some(headers) = decode_packet(entry.linktype, data) else { return summary };
+
+    if let Some(LinkHeader::Ethernet2(eth)) = &headers.link {
+        summary.source = mac(&eth.source);
+        summary.destination = mac(&eth.destination);
+        summary.protocol = "Ethernet".into();
+        summary.info = format!("EtherType 0x{:04x}", eth.ether_type.0);
+    }
+    match &headers.net {
+        Some(NetHeaders::Ipv4(ip, _)) => {
+            summary.source = Ipv4Addr::from(ip.source).to_string();
+            summary.destination = Ipv4Addr::from(ip.destination).to_string();
+            summary.protocol = "IPv4".into();
+            summary.info = ip_info(ip.protocol.0, ip.is_fragmenting_payload());
+        },
+        Some(NetHeaders::Ipv6(ip, exts)) => {
+            summary.source = Ipv6Addr::from(ip.source).to_string();
+            summary.destination = Ipv6Addr::from(ip.destination).to_string();
+            summary.protocol = "IPv6".into();
+            summary.info = ip_info(ip.next_header.0, exts.fragment.is_some());
+        },
+        Some(NetHeaders::Arp(arp)) => {
+            summary.protocol = "ARP".into();
+            summary.info = match arp.try_eth_ipv4() {
+                Ok(a) if a.operation.0 == 1 => format!(
+                    "Who has {}? Tell {}", Ipv4Addr::from(a.target_ipv4), Ipv4Addr::from(a.sender_ipv4)
+                ),
+                Ok(a) => format!("{} is at {}", Ipv4Addr::from(a.sender_ipv4), mac(&a.sender_mac)),
+                Err(_) => format!("Operation {}", arp.operation.0),
+            };
+        },
+        None => {},
+    }
+
+    let payload = headers.payload.slice();
+    match &headers.transport {
+        Some(TransportHeader::Tcp(tcp)) => {
+            let flags = tcp_flags(tcp);
+            summary.protocol = "TCP".into();
+            summary.info = format!(
+                "{} → {} [{}] Seq={} Ack={} Win={} Len={}",
+                tcp.source_port, tcp.destination_port, flags, tcp.sequence_number,
+                tcp.acknowledgment_number, tcp.window_size, payload.len()
+            );
+        },
+        Some(TransportHeader::Udp(udp)) => {
+            summary.protocol = "UDP".into();
+            summary.info = format!("{} → {} Len={}", udp.source_port, udp.destination_port, payload.len());
+        },
+        Some(TransportHeader::Icmpv4(icmp)) => {
+            let raw = icmp.to_bytes();
+            summary.protocol = "ICMP".into();
+            summary.info = icmpv4_name(raw[0], raw[1]);
+        },
+        Some(TransportHeader::Icmpv6(icmp)) => {
+            let raw = icmp.to_bytes();
+            summary.protocol = "ICMPv6".into();
+            summary.info = icmpv6_name(raw[0], raw[1]);
+        },
+        None => {},
+    }
+    if let Some((protocol, info, _)) = application(&headers) {
+        summary.protocol = protocol.into();
+        summary.info = info;
+    }
+    summary
+}
+
+/// Appends one layer per decoded header, outermost first.
+fn dissect(headers: &PacketHeaders, layers: &mut Vec<PcapLayer>) {
+    match &headers.link {
+        Some(LinkHeader::Ethernet2(eth)) => layers.push(layer("Ethernet II", vec![
+            ("Destination", mac(&eth.destination)),
+            ("Source", mac(&eth.source)),
+            ("Type", format!("0x{:04x}", eth.ether_type.0)),
+        ])),
+        Some(LinkHeader::LinuxSll(sll)) => layers.push(layer("Linux cooked capture", vec![
+            ("ARPHRD type", sll.arp_hrd_type.0.to_string()),
+        ])),
+        None => {},
+    }
+    for ext in &headers.link_exts {
+        if let LinkExtHeader::Vlan(vlan) = ext {
+            layers.push(layer("802.1Q VLAN", vec![
+                ("Priority", vlan.pcp.value().to_string()),
+                ("Drop eligible", vlan.drop_eligible_indicator.to_string()),
+                ("ID", vlan.vlan_id.value().to_string()),
+                ("Type", format!("0x{:04x}", vlan.ether_type.0)),
+            ]));
+        }
+    }
+
+    match &headers.net {
+        Some(NetHeaders::Ipv4(ip, _)) => layers.push(layer("Internet Protocol Version 4", vec![
+            ("Header length", format!("{} bytes", ip.header_len())),
+            ("DSCP", ip.dscp.value().to_string()),
+            ("ECN", ip.ecn.value().to_string()),
+            ("Total length", ip.total_len.to_string()),
+            ("Identification", format!("0x{:04x} ({})", ip.identification, ip.identification)),
+            ("Flags", format!("DF={} MF={}", ip.dont_fragment as u8, ip.more_fragments as u8)),
+            ("Fragment offset", ip.fragment_offset.byte_offset().to_string()),
+            ("Time to live", ip.time_to_live.to_string()),
+            ("Protocol", ip_protocol_name(ip.protocol.0)),
+            ("Header checksum", format!("0x{:04x}", ip.header_checksum)),
+            ("Source", Ipv4Addr::from(ip.source).to_string()),
+            ("Destination", Ipv4Addr::from(ip.destination).to_string()),
+        ])),
+        Some(NetHeaders::Ipv6(ip, exts)) => {
+            layers.push(layer("Internet Protocol Version 6", vec![
+                ("Traffic class", format!("0x{:02x} (DSCP {})", ip.traffic_class, ip.traffic_class >> 2)),
+                ("Flow label", format!("0x{:05x}", ip.flow_label.value())),
+                ("Payload length", ip.payload_length.to_string()),
+                ("Next header", ip_protocol_name(ip.next_header.0)),
+                ("Hop limit", ip.hop_limit.to_string()),
+                ("Source", Ipv6Addr::from(ip.source).to_string()),
+                ("Destination", Ipv6Addr::from(ip.destination).to_string()),
+            ]));
+            if let Some(fh) = &exts.fragment {
+                let frag = super::frag::Fragment::from_ipv6(fh);
+                layers.push(layer("IPv6 Fragment", vec![
+                    ("Next header", ip_protocol_name(fh.next_header.0)),
+                    ("Offset", frag.offset.to_string()),
+                    ("More fragments", frag.more.to_string()),
+                    ("Identification", format!("0x{:08x}", fh.identification)),
+                ]));
+            }
+        },
+        Some(NetHeaders::Arp(arp)) => {
+            let mut fields = vec![("Operation", if arp.operation.0 == 1 { "request".to_string() } else { format!("reply ({})", arp.operation.0) })];
+            if let Ok(a) = arp.try_eth_ipv4() {
+                fields.push(("Sender MAC", mac(&a.sender_mac)));
+                fields.push(("Sender IP", Ipv4Addr::from(a.sender_ipv4).to_string()));
+                fields.push(("Target MAC", mac(&a.target_mac)));
+                fields.push(("Target IP", Ipv4Addr::from(a.target_ipv4).to_string()));
+            }
+            layers.push(layer("Address Resolution Protocol", fields));
+        },
+        None => {},
+    }
+
+    let payload = headers.payload.slice();
+    match &headers.transport {
+        Some(TransportHeader::Tcp(tcp)) => {
+            let mut fields = vec![
+                ("Source port", tcp.source_port.to_string()),
+                ("Destination port", tcp.destination_port.to_string()),
+                ("Sequence number", tcp.sequence_number.to_string()),
+                ("Acknowledgment number", tcp.acknowledgment_number.to_string()),
+                ("Header length", format!("{} bytes", tcp.header_len())),
+                ("Flags", tcp_flags(tcp)),
+                ("Window", tcp.window_size.to_string()),
+                ("Checksum", format!("0x{:04x}", tcp.checksum)),
+                ("Urgent pointer", tcp.urgent_pointer.to_string()),
+            ];
+            let options: Vec<String> = tcp.options_iterator().filter_map(Result::ok).map(|o| match o {
+                TcpOptionElement::MaximumSegmentSize(mss) => format!("MSS={}", mss),
+                TcpOptionElement::WindowScale(ws) => format!("WS={}", ws),
+                TcpOptionElement::SelectiveAcknowledgementPermitted => "SACK_PERM".into(),
+                TcpOptionElement::SelectiveAcknowledgement(_, _) => "SACK".into(),
+                TcpOptionElement::Timestamp(val, ecr) => format!("TSval={} TSecr={}", val, ecr),
+                TcpOptionElement::Noop => "NOP".into(),
+            }).collect();
+            if !options.is_empty() { fields.push(("Options", options.join(", "))); }
+            fields.push(("Payload", format!("{} bytes", payload.len())));
+            layers.push(layer("Transmission Control Protocol", fields));
+        },
+        Some(TransportHeader::Udp(udp)) => layers.push(layer("User Datagram Protocol", vec![
+            ("Source port", udp.source_port.to_string()),
+            ("Destination port", udp.destination_port.to_string()),
+            ("Length", udp.length.to_string()),
+            ("Checksum", format!("0x{:04x}", udp.checksum)),
+        ])),
+        Some(TransportHeader::Icmpv4(icmp)) => {
+            let raw = icmp.to_bytes();
+            layers.push(layer("Internet Control Message Protocol", vec![
+                ("Type", format!("{} ({})", raw[0], icmpv4_name(raw[0], raw[1]))),
+                ("Code", raw[1].to_string()),
+                ("Checksum", format!("0x{:02x}{:02x}", raw[2], raw[3])),
+            ]));
+        },
+        Some(TransportHeader::Icmpv6(icmp)) => {
+            let raw = icmp.to_bytes();
+            layers.push(layer("Internet Control Message Protocol v6", vec![
+                ("Type", format!("{} ({})", raw[0], icmpv6_name(raw[0], raw[1]))),
+                ("Code", raw[1].to_string()),
+                ("Checksum", format!("0x{:02x}{:02x}", raw[2], raw[3])),
+            ]));
+        },
+        None => {},
+    }
+
+    if let Some((protocol, _, fields)) = application(headers) {
+        layers.push(PcapLayer { name: protocol.to_string(), fields });
+    }
+}
+
+/// Recognises the application protocol of a transport payload regardless of port
+/// (except DNS, which is only looked for on port 53).
+pub fn app_protocol(headers: &PacketHeaders) -> Option<&'static str> {
+    let payload = headers.payload.slice();
+    let (sport, dport, is_tcp) = match &headers.transport {
+        Some(TransportHeader::Tcp(t)) => (t.source_port, t.destination_port, true),
+        Some(TransportHeader::Udp(u)) => (u.source_port, u.destination_port, false),
+        _ => return None,
+    };
+    if payload.is_empty() { return None; }
+
+    if sport == 53 || dport == 53 {
+        let msg = if is_tcp { payload.get(2..)? } else { payload };
+        return dns::parse_message(msg).map(|_| "DNS");
+    }
+    if is_tcp && payload.len() >= 6 && (20..=23).contains(&payload[0]) && payload[1] == 3 {
+        return Some("TLS");
+    }
+    let first_line = first_line(payload);
+    if first_line.contains("SIP/2.0") {
+        Some("SIP")
+    } else if is_tcp && (first_line.starts_with("HTTP/1.") || HTTP_METHODS.iter().any(|m| first_line.starts_with(&format!("{} ", m)))) {
+        Some("HTTP")
+    } else {
+        None
+    }
+}
+
+fn first_line(payload: &[u8]) -> String {
+    let line = payload.split(|b| *b == b'\n').next().unwrap_or_default();
+    String::from_utf8_lossy(line).trim_end().to_string()
+}
+
+/// The application protocol's name, a one-line summary and its decoded fields.
+fn application(headers: &PacketHeaders) -> Option<(&'static str, String, Vec<PcapField>)> {
+    let protocol = app_protocol(headers)?;
+    let payload = headers.payload.slice();
+    match protocol {
+        "DNS" => {
+            let is_tcp = matches!(headers.transport, Some(TransportHeader::Tcp(_)));
+            dns_message(if is_tcp { &payload[2..] } else { payload })
+        },
+        "TLS" => Some(tls_record(payload)),
+        _ => {
+            // Start line plus headers, up to the blank line.
+            let first_line = first_line(payload);
+            let text = String::from_utf8_lossy(payload);
+            let head = text.split("\r\n\r\n").next().unwrap_or_default();
+            let mut fields = vec![field("Start line", first_line.clone())];
+            fields.extend(head.lines().skip(1).filter_map(|l| l.split_once(':')).map(|(k, v)| field(k.trim(), v.trim().to_string())));
+            Some((protocol, first_line, fields))
+        },
+    }
+}
+
+fn dns_message(data: &[u8]) -> Option<(&'static str, String, Vec<PcapField>)> {
+    let msg = dns::parse_message(data)?;
+    let question = msg.questions.first()
+        .map(|q| format!(" {} {}", dns::type_name(q.qtype), q.name))
+        .unwrap_or_default();
+    let mut info = if msg.is_response {
+        format!("Standard query response 0x{:04x}{}", msg.id, question)
+    } else {
+        format!("Standard query 0x{:04x}{}", msg.id, question)
+    };
+    if msg.is_response && msg.rcode != 0 {
+        info.push_str(&format!(" {}", dns::rcode_name(msg.rcode)));
+    }
+    for a in msg.answers.iter().take(3) {
+        info.push_str(&format!(" {} {}", dns::type_name(a.rtype), a.data));
+    }
+
+    let mut fields = vec![
+        field("Transaction ID", format!("0x{:04x}", msg.id)),
+        field("Type", if msg.is_response { "Response".into() } else { "Query".into() }),
+    ];
+    if msg.is_response { fields.push(field("Reply code", dns::rcode_name(msg.rcode))); }
+    for q in &msg.questions {
+        fields.push(field("Query", format!("{} {}", q.name, dns::type_name(q.qtype))));
+    }
+    for a in &msg.answers {
+        fields.push(field("Answer", format!("{} {} (TTL {})", dns::type_name(a.rtype), a.data, a.ttl)));
+    }
+    Some(("DNS", info, fields))
+}
+
+fn tls_record(payload: &[u8]) -> (&'static str, String, Vec<PcapField>) {
+    let content = match payload[0] {
+        20 => "Change Cipher Spec".to_string(),
+        21 => "Alert".to_string(),
+        22 => match payload[5] {
+            1 => "Client Hello".to_string(),
+            2 => "Server Hello".to_string(),
+            4 => "New Session Ticket".to_string(),
+            11 => "Certificate".to_string(),
+            12 => "Server Key Exchange".to_string(),
+            14 => "Server Hello Done".to_string(),
+            16 => "Client Key Exchange".to_string(),
+            // Encrypted handshake messages after ChangeCipherSpec look like anything.
+            _ => "Encrypted Handshake Message".to_string(),
+        },
+        _ => "Application Data".to_string(),
+    };
+    let fields = vec![
+        field("Content type", content.clone()),
+        field("Version", format!("0x{:02x}{:02x}", payload[1], payload[2])),
+        field("Length", u16::from_be_bytes([payload[3], payload[4]]).to_string()),
+    ];
+    ("TLS", content, fields)
+}
+
+/// Classic 16-bytes-per-line dump with offsets and an ASCII column.
+pub fn hex_dump(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in data.chunks(16).enumerate() {
+        let _ = write!(out, "{:04x}  ", i * 16);
+        for j in 0..16 {
+            match chunk.get(j) {
+                Some(b) => { let _ = write!(out, "{:02x} ", b); },
+                None => out.push_str("   "),
+            }
+            if j == 7 { out.push(' '); }
+        }
+        out.push(' ');
+        out.extend(chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+        out.push('\n');
+    }
+    out
+}
+
+fn ip_info(protocol: u8, fragment: bool) -> String {
+    let name = ip_protocol_name(protocol);
+    if fragment { format!("Fragmented {} datagram", name) } else { name }
+}
+
+fn ip_protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "ICMP (1)".into(),
+        2 => "IGMP (2)".into(),
+        6 => "TCP (6)".into(),
+        17 => "UDP (17)".into(),
+        41 => "IPv6 (41)".into(),
+        44 => "IPv6 Fragment (44)".into(),
+        47 => "GRE (47)".into(),
+        50 => "ESP (50)".into(),
+        58 => "ICMPv6 (58)".into(),
+        89 => "OSPF (89)".into(),
+        132 => "SCTP (132)".into(),
+        n => n.to_string(),
+    }
+}
+
+fn icmpv4_name(icmp_type: u8, code: u8) -> String {
+    match icmp_type {
+        0 => "Echo (ping) reply".into(),
+        3 => format!("Destination unreachable (code {})", code),
+        5 => "Redirect".into(),
+        8 => "Echo (ping) request".into(),
+        11 => "Time-to-live exceeded".into(),
+        _ => format!("Type {} code {}", icmp_type, code),
+    }
+}
+
+fn icmpv6_name(icmp_type: u8, code: u8) -> String {
+    match icmp_type {
+        1 => format!("Destination unreachable (code {})", code),
+        2 => "Packet too big".into(),
+        3 => "Time exceeded".into(),
+        128 => "Echo (ping) request".into(),
+        129 => "Echo (ping) reply".into(),
+        133 => "Router Solicitation".into(),
+        134 => "Router Advertisement".into(),
+        135 => "Neighbor Solicitation".into(),
+        136 => "Neighbor Advertisement".into(),
+        _ => format!("Type {} code {}", icmp_type, code),
+    }
+}
+
+fn tcp_flags(tcp: &etherparse::TcpHeader) -> String {
+    let flags = [
+        (tcp.syn, "SYN"), (tcp.fin, "FIN"), (tcp.rst, "RST"), (tcp.psh, "PSH"),
+        (tcp.ack, "ACK"), (tcp.urg, "URG"), (tcp.ece, "ECE"), (tcp.cwr, "CWR"),
+    ];
+    flags.iter().filter(|(set, _)| *set).map(|(_, name)| *name).collect::<Vec<_>>().join(", ")
+}
+
+pub fn mac(addr: &[u8; 6]) -> String {
+    addr.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+}
+
+fn field(name: &str, value: String) -> PcapField {
+    PcapField { name: name.to_string(), value }
+}
+
+fn layer(name: &str, fields: Vec<(&str, String)>) -> PcapLayer {
+    PcapLayer {
+        name: name.to_string(),
+        fields: fields.into_iter().map(|(n, v)| field(n, v)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pcap_parser::Linktype;
+
+    #[test]
+    fn test_hex_dump_layout() {
+        let dump = hex_dump(b"GET / HTTP/1.1\r\nHost");
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "0000  47 45 54 20 2f 20 48 54  54 50 2f 31 2e 31 0d 0a  GET / HTTP/1.1..");
+        assert!(lines[1].starts_with("0010  48 6f 73 74 "));
+        assert!(lines[1].ends_with("  Host"));
+    }
+
+    #[test]
+    fn test_summarize_dns_query() {
+        let mut frame = Vec::new();
+        etherparse::PacketBuilder::ipv4([10, 0, 0, 5], [10, 0, 0, 1], 64)
+            .udp(50000, 53)
+            .write(&mut frame, b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x07example\x03com\x00\x00\x01\x00\x01")
+            .unwrap();
+        let entry = IndexEntry::new(0, frame.len() as u32, Linktype::RAW, 2.5);
+        let s = summarize(7, &entry, 2.0, &frame);
+        assert_eq!((s.source.as_str(), s.destination.as_str()), ("10.0.0.5", "10.0.0.1"));
+        assert_eq!(s.protocol, "DNS");
+        assert_eq!(s.info, "Standard query 0x1234 A example.com");
+        assert!((s.relative_time - 0.5).abs() < 1e-9);
+    }
+}
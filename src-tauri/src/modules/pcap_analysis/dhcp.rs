@@ -0,0 +1,326 @@
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+
+use crate::modules::utils::{DhcpClient, DhcpServerStats, DhcpSummary, PcapIssue};
+use super::detail::mac;
+
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+// Getting a lease should take well under a second on a healthy LAN.
+const SLOW_LEASE_SECS: f64 = 3.0;
+const MAX_LISTED: usize = 5;
+
+pub const DISCOVER: u8 = 1;
+pub const OFFER: u8 = 2;
+pub const REQUEST: u8 = 3;
+pub const ACK: u8 = 5;
+pub const NAK: u8 = 6;
+
+/// The parts of a DHCP message (RFC 2131/2132) the analysis looks at.
+#[derive(Debug, Default)]
+pub struct DhcpMessage {
+    pub is_reply: bool,
+    pub xid: u32,
+    pub client_mac: Option<[u8; 6]>,
+    pub your_ip: Option<Ipv4Addr>,
+    pub message_type: Option<u8>,
+    pub server_id: Option<Ipv4Addr>,
+    pub lease_time: Option<u32>,
+    pub subnet_mask: Option<Ipv4Addr>,
+    pub routers: Vec<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr>,
+    pub hostname: Option<String>,
+    pub domain: Option<String>,
+}
+
+pub fn parse_message(data: &[u8]) -> Option<DhcpMessage> {
+    if data.len() < 240 || data[236..240] != MAGIC_COOKIE {
+        return None;
+    }
+    let your_ip = Ipv4Addr::new(data[16], data[17], data[18], data[19]);
+    let mut msg = DhcpMessage {
+        is_reply: data[0] == 2,
+        xid: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+        // Ethernet hardware addresses only.
+        client_mac: (data[1] == 1 && data[2] == 6).then(|| [data[28], data[29], data[30], data[31], data[32], data[33]]),
+        your_ip: (!your_ip.is_unspecified()).then_some(your_ip),
+        ..Default::default()
+    };
+
+    let addresses = |value: &[u8]| value.chunks_exact(4).map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3])).collect::<Vec<_>>();
+    let text = |value: &[u8]| Some(String::from_utf8_lossy(value).trim_end_matches('\0').to_string());
+    let mut at = 240;
+    while let Some(&code) = data.get(at) {
+        match code {
+            0 => { at += 1; continue; },
+            255 => break,
+            _ => {},
+        }
+        let Some(&len) = data.get(at + 1) else { break };
+        let Some(value) = data.get(at + 2..at + 2 + len as usize) else { break };
+        match (code, value) {
+            (1, [a, b, c, d]) => msg.subnet_mask = Some(Ipv4Addr::new(*a, *b, *c, *d)),
+            (3, _) => msg.routers = addresses(value),
+            (6, _) => msg.dns_servers = addresses(value),
+            (12, _) => msg.hostname = text(value),
+            (15, _) => msg.domain = text(value),
+            (51, [a, b, c, d]) => msg.lease_time = Some(u32::from_be_bytes([*a, *b, *c, *d])),
+            (53, [t]) => msg.message_type = Some(*t),
+            (54, [a, b, c, d]) => msg.server_id = Some(Ipv4Addr::new(*a, *b, *c, *d)),
+            _ => {},
+        }
+        at += 2 + len as usize;
+    }
+    Some(msg)
+}
+
+#[derive(Default)]
+struct ClientState {
+    stats: DhcpClient,
+    // When the exchange still waiting for its ACK began.
+    exchange_start: Option<f64>,
+}
+
+struct ServerState {
+    stats: DhcpServerStats,
+    clients: HashSet<[u8; 6]>,
+}
+
+/// Follows DISCOVER/OFFER/REQUEST/ACK exchanges per client hardware address: how long
+/// leases take, what they hand out, NAKs, and DISCOVERs nobody answers. DHCPv4 only.
+#[derive(Default)]
+pub struct DhcpAnalyzer {
+    clients: HashMap<[u8; 6], ClientState>,
+    servers: HashMap<Ipv4Addr, ServerState>,
+    // DISCOVER transactions no OFFER has matched yet, by transaction ID
+    pending: HashMap<u32, [u8; 6]>,
+    lease_times: Vec<f64>,
+    summary: DhcpSummary,
+}
+
+impl DhcpAnalyzer {
+    pub fn process_message(&mut self, src_ip: &str, payload: &[u8], ts: f64) {
+        let Some(msg) = parse_message(payload) else { return };
+        let (Some(client_mac), Some(message_type)) = (msg.client_mac, msg.message_type) else { return };
+        let client = self.clients.entry(client_mac).or_insert_with(|| ClientState {
+            stats: DhcpClient { mac: mac(&client_mac), ..Default::default() },
+            exchange_start: None,
+        });
+
+        if !msg.is_reply {
+            if msg.hostname.is_some() {
+                client.stats.hostname = msg.hostname;
+            }
+            match message_type {
+                DISCOVER => {
+                    self.summary.discovers += 1;
+                    client.stats.discovers += 1;
+                    client.exchange_start.get_or_insert(ts);
+                    self.pending.entry(msg.xid).or_insert(client_mac);
+                },
+                REQUEST => {
+                    self.summary.requests += 1;
+                    client.stats.requests += 1;
+                    // Renewals and reboots skip DISCOVER.
+                    client.exchange_start.get_or_insert(ts);
+                },
+                _ => {},
+            }
+            return;
+        }
+
+        let server_ip = match msg.server_id {
+            Some(id) => id,
+            None => match src_ip.parse() {
+                Ok(ip) => ip,
+                Err(_) => return,
+            },
+        };
+        let server = self.servers.entry(server_ip).or_insert_with(|| ServerState {
+            stats: DhcpServerStats { server: server_ip.to_string(), offers: 0, acks: 0, naks: 0, clients: 0 },
+            clients: HashSet::new(),
+        });
+        server.clients.insert(client_mac);
+        let server_name = server_ip.to_string();
+
+        match message_type {
+            OFFER => {
+                self.summary.offers += 1;
+                client.stats.offers += 1;
+                server.stats.offers += 1;
+                self.pending.remove(&msg.xid);
+                if !client.stats.offered_by.contains(&server_name) {
+                    client.stats.offered_by.push(server_name);
+                }
+            },
+            ACK => {
+                self.summary.acks += 1;
+                client.stats.acks += 1;
+                server.stats.acks += 1;
+                // An ACK to an INFORM carries options but no address.
+                if let Some(start) = client.exchange_start.take().filter(|_| msg.your_ip.is_some()) {
+                    let elapsed_ms = (ts - start) * 1000.0;
+                    client.stats.time_to_lease_ms = Some(elapsed_ms);
+                    self.lease_times.push(elapsed_ms);
+                }
+                let stats = &mut client.stats;
+                if let Some(ip) = msg.your_ip {
+                    stats.leased_ip = Some(ip.to_string());
+                }
+                stats.server = Some(server_name);
+                stats.lease_time_sec = msg.lease_time.or(stats.lease_time_sec);
+                stats.subnet_mask = msg.subnet_mask.map(|m| m.to_string()).or(stats.subnet_mask.take());
+                if !msg.routers.is_empty() {
+                    stats.routers = msg.routers.iter().map(Ipv4Addr::to_string).collect();
+                }
+                if !msg.dns_servers.is_empty() {
+                    stats.dns_servers = msg.dns_servers.iter().map(Ipv4Addr::to_string).collect();
+                }
+                stats.domain = msg.domain.or(stats.domain.take());
+            },
+            NAK => {
+                // The client starts over; the time it loses doing so counts towards its lease.
+                self.summary.naks += 1;
+                client.stats.naks += 1;
+                server.stats.naks += 1;
+            },
+            _ => {},
+        }
+    }
+
+    pub fn finish(self, issues: &mut Vec<PcapIssue>) -> DhcpSummary {
+        let mut summary = self.summary;
+        let mut clients = self.clients;
+        for client_mac in self.pending.values() {
+            if let Some(client) = clients.get_mut(client_mac) {
+                client.stats.unanswered_discovers += 1;
+            }
+        }
+        summary.unanswered_discovers = self.pending.len();
+        summary.avg_time_to_lease_ms = (!self.lease_times.is_empty())
+            .then(|| self.lease_times.iter().sum::<f64>() / self.lease_times.len() as f64);
+        summary.max_time_to_lease_ms = self.lease_times.iter().copied().reduce(f64::max);
+
+        summary.clients = clients.into_values().map(|c| c.stats).collect();
+        summary.clients.sort_by(|a, b| a.mac.cmp(&b.mac));
+        summary.servers = self.servers.into_values().map(|s| DhcpServerStats { clients: s.clients.len(), ..s.stats }).collect();
+        summary.servers.sort_by(|a, b| (b.offers + b.acks).cmp(&(a.offers + a.acks)).then_with(|| a.server.cmp(&b.server)));
+
+        let macs_where = |keep: &dyn Fn(&DhcpClient) -> bool| -> Vec<String> {
+            summary.clients.iter().filter(|c| keep(c)).map(|c| c.mac.clone()).collect()
+        };
+        if summary.unanswered_discovers > 0 {
+            let waiting = macs_where(&|c| c.unanswered_discovers > 0);
+            let (severity, title, advice) = if summary.offers == 0 {
+                ("critical", "No DHCP Offers", "No server offered an address at all; check the DHCP server, the relay (ip helper-address) and the VLAN.")
+            } else {
+                ("warn", "Unanswered DHCP Discovers", "Check the server's scope for free addresses and any MAC filtering.")
+            };
+            issues.push(PcapIssue {
+                severity: severity.into(),
+                title: title.into(),
+                description: format!(
+                    "{} DISCOVER(s) from {} got no OFFER. {}", summary.unanswered_discovers, listed(&waiting), advice,
+                ),
+                timestamp: None,
+            });
+        }
+        if summary.naks > 0 {
+            let refused = macs_where(&|c| c.naks > 0);
+            let servers: Vec<String> = summary.servers.iter().filter(|s| s.naks > 0).map(|s| s.server.clone()).collect();
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "DHCP NAKs".into(),
+                description: format!(
+                    "{} sent {} NAK(s) to {}. Clients asked for addresses the server won't give, typically after moving subnets or when two servers have different scopes.",
+                    servers.join(", "), summary.naks, listed(&refused),
+                ),
+                timestamp: None,
+            });
+        }
+        if summary.max_time_to_lease_ms.is_some_and(|ms| ms > SLOW_LEASE_SECS * 1000.0) {
+            let slow = macs_where(&|c| c.time_to_lease_ms.is_some_and(|ms| ms > SLOW_LEASE_SECS * 1000.0));
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "Slow DHCP".into(),
+                description: format!(
+                    "{} took over {} s to get a lease (slowest {:.1} s). Look for NAKs, retransmitted DISCOVERs or a slow relay.",
+                    listed(&slow), SLOW_LEASE_SECS, summary.max_time_to_lease_ms.unwrap_or(0.0) / 1000.0,
+                ),
+                timestamp: None,
+            });
+        }
+        summary
+    }
+}
+
+fn listed(items: &[String]) -> String {
+    match items.len() {
+        n if n > MAX_LISTED => format!("{} (+{} more)", items[..MAX_LISTED].join(", "), n - MAX_LISTED),
+        _ => items.join(", "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(op: u8, xid: u32, client: [u8; 6], your_ip: [u8; 4], options: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut msg = vec![0u8; 236];
+        msg[0] = op;
+        msg[1] = 1;
+        msg[2] = 6;
+        msg[4..8].copy_from_slice(&xid.to_be_bytes());
+        msg[16..20].copy_from_slice(&your_ip);
+        msg[28..34].copy_from_slice(&client);
+        msg.extend_from_slice(&MAGIC_COOKIE);
+        for (code, value) in options {
+            msg.push(*code);
+            msg.push(value.len() as u8);
+            msg.extend_from_slice(value);
+        }
+        msg.push(255);
+        msg
+    }
+
+    #[test]
+    fn test_dhcp_exchanges() {
+        let (laptop, phone) = ([2, 0, 0, 0, 0, 0xa1], [2, 0, 0, 0, 0, 0xb2]);
+        let server: &[u8] = &[10, 0, 0, 1];
+        let lease_options: &[(u8, &[u8])] = &[
+            (53, &[ACK]), (54, server), (51, &86400u32.to_be_bytes()), (1, &[255, 255, 255, 0]),
+            (3, &[10, 0, 0, 1]), (6, &[10, 0, 0, 53, 1, 1, 1, 1]), (15, b"corp.example"),
+        ];
+        let mut dhcp = DhcpAnalyzer::default();
+        dhcp.process_message("0.0.0.0", &message(1, 7, laptop, [0; 4], &[(53, &[DISCOVER]), (12, b"laptop")]), 10.0);
+        dhcp.process_message("10.0.0.1", &message(2, 7, laptop, [10, 0, 0, 50], &[(53, &[OFFER]), (54, server)]), 10.2);
+        dhcp.process_message("0.0.0.0", &message(1, 7, laptop, [0; 4], &[(53, &[REQUEST]), (54, server)]), 10.3);
+        dhcp.process_message("10.0.0.1", &message(2, 7, laptop, [10, 0, 0, 50], lease_options), 10.5);
+
+        // The phone asks for its old address on a new network, is refused, and never gets an offer.
+        dhcp.process_message("0.0.0.0", &message(1, 9, phone, [0; 4], &[(53, &[REQUEST])]), 20.0);
+        dhcp.process_message("10.0.0.1", &message(2, 9, phone, [0; 4], &[(53, &[NAK]), (54, server)]), 20.1);
+        dhcp.process_message("0.0.0.0", &message(1, 10, phone, [0; 4], &[(53, &[DISCOVER])]), 20.2);
+        dhcp.process_message("0.0.0.0", &message(1, 10, phone, [0; 4], &[(53, &[DISCOVER])]), 24.2);
+
+        let mut issues = Vec::new();
+        let summary = dhcp.finish(&mut issues);
+        assert_eq!((summary.discovers, summary.offers, summary.requests, summary.acks, summary.naks), (3, 1, 2, 1, 1));
+        assert_eq!(summary.unanswered_discovers, 1);
+        assert!((summary.avg_time_to_lease_ms.unwrap() - 500.0).abs() < 1e-6);
+
+        let client = &summary.clients[0];
+        assert_eq!((client.mac.as_str(), client.hostname.as_deref()), ("02:00:00:00:00:a1", Some("laptop")));
+        assert_eq!(client.leased_ip.as_deref(), Some("10.0.0.50"));
+        assert_eq!(client.lease_time_sec, Some(86400));
+        assert_eq!(client.routers, vec!["10.0.0.1"]);
+        assert_eq!(client.dns_servers, vec!["10.0.0.53", "1.1.1.1"]);
+        assert_eq!(client.domain.as_deref(), Some("corp.example"));
+        assert_eq!(client.offered_by, vec!["10.0.0.1"]);
+        assert_eq!((summary.clients[1].naks, summary.clients[1].unanswered_discovers), (1, 1));
+        assert_eq!((summary.servers[0].offers, summary.servers[0].acks, summary.servers[0].clients), (1, 1, 2));
+
+        let titles: Vec<&str> = issues.iter().map(|i| i.title.as_str()).collect();
+        assert_eq!(titles, vec!["Unanswered DHCP Discovers", "DHCP NAKs"]);
+        assert!(issues[0].description.starts_with("1 DISCOVER(s) from 02:00:00:00:00:b2"));
+    }
+}
@@ -0,0 +1,533 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::modules::utils::{DnsDomainStats, DnsResolverStats, DnsSlowResponse, DnsSummary, PcapIssue};
+
+const SLOW_RESPONSE_SECS: f64 = 0.3;
+const MAX_SLOW_RESPONSES: usize = 50;
+const TOP_DOMAINS: usize = 20;
+
+const TYPE_NULL: u16 = 10;
+const TYPE_TXT: u16 = 16;
+
+const RCODE_SERVFAIL: u8 = 2;
+const RCODE_NXDOMAIN: u8 = 3;
+const RCODE_REFUSED: u8 = 5;
+
+#[derive(Debug)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub qtype: u16,
+}
+
+#[derive(Debug)]
+pub struct DnsAnswer {
+    pub rtype: u16,
+    pub ttl: u32,
+    pub data: String,
+}
+
+#[derive(Debug)]
+pub struct DnsMessage {
+    pub id: u16,
+    pub is_response: bool,
+    pub rcode: u8,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsAnswer>,
+}
+
+/// Parses a DNS message (RFC 1035) including compressed names.
+pub fn parse_message(data: &[u8]) -> Option<DnsMessage> {
+    if data.len() < 12 { return None; }
+    let flags = u16::from_be_bytes([data[2], data[3]]);
+    let qdcount = u16::from_be_bytes([data[4], data[5]]);
+    let ancount = u16::from_be_bytes([data[6], data[7]]);
+
+    let mut msg = DnsMessage {
+        id: u16::from_be_bytes([data[0], data[1]]),
+        is_response: flags & 0x8000 != 0,
+        rcode: (flags & 0x000f) as u8,
+        questions: Vec::new(),
+        answers: Vec::new(),
+    };
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        let (name, next) = read_name(data, pos)?;
+        let qtype = u16::from_be_bytes([*data.get(next)?, *data.get(next + 1)?]);
+        data.get(next + 3)?; // qclass
+        msg.questions.push(DnsQuestion { name, qtype });
+        pos = next + 4;
+    }
+    for _ in 0..ancount {
+        let (_, next) = read_name(data, pos)?;
+        let fixed = data.get(next..next + 10)?;
+        let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+        let rdlen = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let rdata_start = next + 10;
+        let rdata = data.get(rdata_start..rdata_start + rdlen)?;
+        let rdata_str = match rtype {
+            1 if rdlen == 4 => std::net::Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]).to_string(),
+            28 if rdlen == 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(rdata);
+                std::net::Ipv6Addr::from(octets).to_string()
+            },
+            2 | 5 | 12 => read_name(data, rdata_start).map(|(n, _)| n).unwrap_or_default(),
+            15 if rdlen > 2 => read_name(data, rdata_start + 2).map(|(n, _)| n).unwrap_or_default(),
+            TYPE_TXT => String::from_utf8_lossy(rdata.get(1..).unwrap_or_default()).to_string(),
+            _ => format!("{} bytes", rdlen),
+        };
+        msg.answers.push(DnsAnswer { rtype, ttl, data: rdata_str });
+        pos = rdata_start + rdlen;
+    }
+    Some(msg)
+}
+
+/// Reads a possibly compressed domain name, returning it and the offset just past it.
+fn read_name(data: &[u8], start: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut pos = start;
+    let mut end = None;
+    // Bounded so a pointer loop in a hostile packet cannot spin forever.
+    for _ in 0..128 {
+        let len = *data.get(pos)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        }
+        if len & 0xc0 == 0xc0 {
+            let ptr = ((len & 0x3f) << 8) | *data.get(pos + 1)? as usize;
+            end.get_or_insert(pos + 2);
+            pos = ptr;
+            continue;
+        }
+        let label = data.get(pos + 1..pos + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).to_string());
+        pos += 1 + len;
+    }
+    None
+}
+
+/// Splits a DNS-over-TCP segment into its length-prefixed messages. A segment that
+/// doesn't hold whole messages (one continuing an earlier message, or cut short) yields
+/// none rather than misparsed fragments.
+pub fn tcp_messages(payload: &[u8]) -> Vec<&[u8]> {
+    let mut messages = Vec::new();
+    let mut rest = payload;
+    while rest.len() >= 2 {
+        let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        let Some(message) = rest.get(2..2 + len).filter(|m| m.len() >= 12) else {
+            return Vec::new();
+        };
+        messages.push(message);
+        rest = &rest[2 + len..];
+    }
+    if !rest.is_empty() {
+        return Vec::new();
+    }
+    messages
+}
+
+pub fn type_name(t: u16) -> String {
+    let name = match t {
+        1 => "A", 2 => "NS", 5 => "CNAME", 6 => "SOA", 10 => "NULL", 12 => "PTR", 15 => "MX",
+        16 => "TXT", 28 => "AAAA", 33 => "SRV", 64 => "SVCB", 65 => "HTTPS", 255 => "ANY", 257 => "CAA",
+        _ => return format!("TYPE{}", t),
+    };
+    name.to_string()
+}
+
+pub fn rcode_name(rcode: u8) -> String {
+    let name = match rcode {
+        0 => "NOERROR", 1 => "FORMERR", 2 => "SERVFAIL", 3 => "NXDOMAIN", 4 => "NOTIMP", 5 => "REFUSED",
+        _ => return format!("RCODE{}", rcode),
+    };
+    name.to_string()
+}
+
+/// Splits a query name into (subdomain part, registered domain).
+///
+/// Without a public suffix list this treats `co.uk`-style two-letter country code
+/// suffixes as part of the registered domain, which covers the common cases.
+fn split_domain(name: &str) -> (String, String) {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let labels: Vec<&str> = name.split('.').collect();
+    let second_level = ["co", "com", "net", "org", "gov", "ac", "edu"];
+    let keep = if labels.len() >= 3
+        && labels[labels.len() - 1].len() == 2
+        && second_level.contains(&labels[labels.len() - 2]) { 3 } else { 2 };
+    if labels.len() <= keep {
+        return (String::new(), name);
+    }
+    let split = labels.len() - keep;
+    (labels[..split].join("."), labels[split..].join("."))
+}
+
+/// Shannon entropy in bits per character.
+fn shannon_entropy(s: &str) -> f64 {
+    let chars: Vec<char> = s.chars().filter(|c| *c != '.').collect();
+    if chars.is_empty() { return 0.0; }
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in &chars { *counts.entry(*c).or_insert(0) += 1; }
+    let n = chars.len() as f64;
+    counts.values().map(|&c| {
+        let p = c as f64 / n;
+        -p * p.log2()
+    }).sum()
+}
+
+#[derive(Default)]
+struct DomainAcc {
+    queries: usize,
+    subdomains: HashSet<String>,
+    total_len: usize,
+    max_len: usize,
+    entropy_sum: f64,
+    entropy_samples: usize,
+    txt_null: usize,
+    nxdomain: usize,
+    first_ts: f64,
+    last_ts: f64,
+}
+
+#[derive(Default)]
+struct ResolverAcc {
+    queries: usize,
+    responses: usize,
+    failures: usize,
+    latency_sum: f64,
+    latency_count: usize,
+}
+
+struct PendingQuery {
+    name: String,
+    qtype: u16,
+    ts: f64,
+}
+
+#[derive(Default)]
+pub struct DnsAnalyzer {
+    pending: HashMap<String, PendingQuery>,
+    domains: HashMap<String, DomainAcc>,
+    resolvers: HashMap<String, ResolverAcc>,
+    // client -> NXDOMAIN'd registered domains that look machine generated
+    dga_candidates: HashMap<String, HashSet<String>>,
+    slow: Vec<DnsSlowResponse>,
+    slow_count: usize,
+    queries: usize,
+    responses: usize,
+    nxdomain: usize,
+    servfail: usize,
+    latency_sum: f64,
+    latency_count: usize,
+    first_failure_ts: HashMap<String, f64>,
+}
+
+impl DnsAnalyzer {
+    /// Queries seen so far, for telling whether the last message was one.
+    pub fn queries(&self) -> usize {
+        self.queries
+    }
+
+    pub fn process_message(&mut self, src: &str, src_ip: &str, dst: &str, dst_ip: &str, payload: &[u8], ts: f64) {
+        let Some(msg) = parse_message(payload) else { return };
+        let Some(question) = msg.questions.first() else { return };
+
+        if !msg.is_response {
+            self.queries += 1;
+            self.resolvers.entry(dst_ip.to_string()).or_default().queries += 1;
+
+            let (sub, base) = split_domain(&question.name);
+            let acc = self.domains.entry(base).or_default();
+            if acc.queries == 0 { acc.first_ts = ts; }
+            acc.queries += 1;
+            acc.last_ts = ts;
+            acc.total_len += question.name.len();
+            acc.max_len = acc.max_len.max(question.name.len());
+            if question.qtype == TYPE_TXT || question.qtype == TYPE_NULL { acc.txt_null += 1; }
+            if !sub.is_empty() {
+                acc.entropy_sum += shannon_entropy(&sub);
+                acc.entropy_samples += 1;
+                acc.subdomains.insert(sub);
+            }
+
+            // Retransmitted queries keep the original send time.
+            self.pending.entry(format!("{}|{}|{}", src, dst_ip, msg.id)).or_insert(PendingQuery {
+                name: question.name.clone(),
+                qtype: question.qtype,
+                ts,
+            });
+            return;
+        }
+
+        self.responses += 1;
+        let resolver = self.resolvers.entry(src_ip.to_string()).or_default();
+        resolver.responses += 1;
+
+        match msg.rcode {
+            RCODE_NXDOMAIN => {
+                self.nxdomain += 1;
+                let (_, base) = split_domain(&question.name);
+                if looks_generated(&base) {
+                    self.dga_candidates.entry(dst_ip.to_string()).or_default().insert(base.clone());
+                }
+                if let Some(acc) = self.domains.get_mut(&base) { acc.nxdomain += 1; }
+            },
+            RCODE_SERVFAIL | RCODE_REFUSED => {
+                if msg.rcode == RCODE_SERVFAIL { self.servfail += 1; }
+                resolver.failures += 1;
+                self.first_failure_ts.entry(src_ip.to_string()).or_insert(ts);
+            },
+            _ => {}
+        }
+
+        if let Some(query) = self.pending.remove(&format!("{}|{}|{}", dst, src_ip, msg.id)) {
+            let latency = ts - query.ts;
+            if latency >= 0.0 {
+                resolver.latency_sum += latency;
+                resolver.latency_count += 1;
+                self.latency_sum += latency;
+                self.latency_count += 1;
+                if latency >= SLOW_RESPONSE_SECS { self.slow_count += 1; }
+                if latency >= SLOW_RESPONSE_SECS && self.slow.len() < MAX_SLOW_RESPONSES {
+                    self.slow.push(DnsSlowResponse {
+                        query: query.name,
+                        record_type: type_name(query.qtype),
+                        client: dst.to_string(),
+                        resolver: src_ip.to_string(),
+                        latency_ms: latency * 1000.0,
+                        rcode: rcode_name(msg.rcode),
+                        answers: msg.answers.iter().map(|a| format!("{} {} (TTL {})", type_name(a.rtype), a.data, a.ttl)).collect(),
+                        timestamp: ts,
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn finish(self, issues: &mut Vec<PcapIssue>) -> DnsSummary {
+        let unanswered_by_resolver = self.pending.keys().fold(HashMap::new(), |mut acc: HashMap<String, usize>, k| {
+            if let Some(resolver) = k.split('|').nth(1) { *acc.entry(resolver.to_string()).or_insert(0) += 1; }
+            acc
+        });
+
+        let mut domain_stats: Vec<DnsDomainStats> = self.domains.into_iter().map(|(domain, acc)| {
+            let span = acc.last_ts - acc.first_ts;
+            DnsDomainStats {
+                domain,
+                queries: acc.queries,
+                unique_subdomains: acc.subdomains.len(),
+                avg_query_len: acc.total_len as f64 / acc.queries as f64,
+                max_query_len: acc.max_len,
+                avg_label_entropy: if acc.entropy_samples > 0 { acc.entropy_sum / acc.entropy_samples as f64 } else { 0.0 },
+                txt_null_queries: acc.txt_null,
+                nxdomain: acc.nxdomain,
+                queries_per_sec: if span > 0.0 { acc.queries as f64 / span } else { acc.queries as f64 },
+            }
+        }).collect();
+        domain_stats.sort_by(|a, b| b.queries.cmp(&a.queries).then_with(|| a.domain.cmp(&b.domain)));
+
+        for d in &domain_stats {
+            if let Some(reason) = tunneling_reason(d) {
+                issues.push(PcapIssue {
+                    severity: "critical".into(),
+                    title: "Possible DNS Tunneling".into(),
+                    description: format!("{}: {} queries, {} unique subdomains; {}.", d.domain, d.queries, d.unique_subdomains, reason),
+                    timestamp: None,
+                });
+            }
+        }
+
+        let mut dga_clients: Vec<(&String, &HashSet<String>)> = self.dga_candidates.iter().filter(|(_, d)| d.len() >= 5).collect();
+        dga_clients.sort_by(|a, b| a.0.cmp(b.0));
+        for (client, domains) in dga_clients {
+            let mut sample: Vec<&String> = domains.iter().collect();
+            sample.sort();
+            let sample: Vec<&str> = sample.iter().take(3).map(|s| s.as_str()).collect();
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "DGA-like DNS Lookups".into(),
+                description: format!("{} got NXDOMAIN for {} random-looking domains (e.g. {}).", client, domains.len(), sample.join(", ")),
+                timestamp: None,
+            });
+        }
+
+        let mut resolvers: Vec<DnsResolverStats> = self.resolvers.into_iter().map(|(resolver, acc)| {
+            let unanswered = unanswered_by_resolver.get(&resolver).copied().unwrap_or(0);
+            DnsResolverStats {
+                avg_latency_ms: if acc.latency_count > 0 { Some(acc.latency_sum / acc.latency_count as f64 * 1000.0) } else { None },
+                resolver,
+                queries: acc.queries,
+                responses: acc.responses,
+                failures: acc.failures,
+                unanswered,
+            }
+        }).collect();
+        resolvers.sort_by(|a, b| b.queries.cmp(&a.queries).then_with(|| a.resolver.cmp(&b.resolver)));
+
+        for r in &resolvers {
+            let bad = r.failures + r.unanswered;
+            if r.queries >= 5 && bad * 5 >= r.queries {
+                issues.push(PcapIssue {
+                    severity: if bad * 2 >= r.queries { "critical".into() } else { "warn".into() },
+                    title: "Failing DNS Resolver".into(),
+                    description: format!("{}: {} of {} queries failed ({} SERVFAIL/REFUSED, {} unanswered).", r.resolver, bad, r.queries, r.failures, r.unanswered),
+                    timestamp: self.first_failure_ts.get(&r.resolver).copied(),
+                });
+            }
+        }
+
+        if !self.slow.is_empty() {
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "Slow DNS Responses".into(),
+                description: format!("{} DNS responses took longer than {} ms.", self.slow_count, (SLOW_RESPONSE_SECS * 1000.0) as u64),
+                timestamp: self.slow.first().map(|s| s.timestamp),
+            });
+        }
+
+        DnsSummary {
+            queries: self.queries,
+            responses: self.responses,
+            unanswered: self.pending.len(),
+            nxdomain: self.nxdomain,
+            servfail: self.servfail,
+            avg_latency_ms: if self.latency_count > 0 { Some(self.latency_sum / self.latency_count as f64 * 1000.0) } else { None },
+            top_domains: domain_stats.into_iter().take(TOP_DOMAINS).collect(),
+            slow_responses: self.slow,
+            resolvers,
+        }
+    }
+}
+
+/// Tunnels encode data in long, high-entropy, ever-changing subdomains or in TXT/NULL answers.
+fn tunneling_reason(d: &DnsDomainStats) -> Option<String> {
+    if d.unique_subdomains < 10 { return None; }
+    if d.avg_label_entropy > 3.5 && d.avg_query_len > 40.0 {
+        return Some(format!("subdomain entropy {:.1} bits/char, average name length {:.0}", d.avg_label_entropy, d.avg_query_len));
+    }
+    if d.txt_null_queries * 2 >= d.queries {
+        return Some(format!("{} TXT/NULL queries", d.txt_null_queries));
+    }
+    None
+}
+
+fn looks_generated(base: &str) -> bool {
+    let label = base.split('.').next().unwrap_or_default();
+    label.len() >= 10 && shannon_entropy(label) >= 3.2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_name(name: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        for label in name.split('.') {
+            out.push(label.len() as u8);
+            out.extend_from_slice(label.as_bytes());
+        }
+        out.push(0);
+        out
+    }
+
+    fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+        let mut msg = id.to_be_bytes().to_vec();
+        msg.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        msg.extend(encode_name(name));
+        msg.extend_from_slice(&qtype.to_be_bytes());
+        msg.extend_from_slice(&[0, 1]);
+        msg
+    }
+
+    fn response(id: u16, name: &str, rcode: u8, answer: Option<[u8; 4]>) -> Vec<u8> {
+        let mut msg = id.to_be_bytes().to_vec();
+        msg.extend_from_slice(&[0x81, 0x80 | rcode, 0, 1, 0, answer.is_some() as u8, 0, 0, 0, 0]);
+        msg.extend(encode_name(name));
+        msg.extend_from_slice(&[0, 1, 0, 1]);
+        if let Some(ip) = answer {
+            // Name is a compression pointer back to the question.
+            msg.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0x0e, 0x10, 0, 4]);
+            msg.extend_from_slice(&ip);
+        }
+        msg
+    }
+
+    const CLIENT: &str = "10.0.0.5:40000";
+    const RESOLVER: &str = "10.0.0.1:53";
+
+    fn exchange(dns: &mut DnsAnalyzer, id: u16, name: &str, qtype: u16, rcode: u8, ts: f64, latency: f64) {
+        dns.process_message(CLIENT, "10.0.0.5", RESOLVER, "10.0.0.1", &query(id, name, qtype), ts);
+        let answer = if rcode == 0 { Some([93, 184, 216, 34]) } else { None };
+        dns.process_message(RESOLVER, "10.0.0.1", CLIENT, "10.0.0.5", &response(id, name, rcode, answer), ts + latency);
+    }
+
+    #[test]
+    fn test_parse_response_with_compression() {
+        let msg = parse_message(&response(7, "www.example.com", 0, Some([1, 2, 3, 4]))).unwrap();
+        assert!(msg.is_response);
+        assert_eq!(msg.questions[0].name, "www.example.com");
+        assert_eq!(msg.answers[0].data, "1.2.3.4");
+        assert_eq!(msg.answers[0].ttl, 3600);
+    }
+
+    #[test]
+    fn test_tcp_messages() {
+        let (a, b) = (query(1, "a.example.com", 1), query(2, "b.example.com", 28));
+        let mut segment = (a.len() as u16).to_be_bytes().to_vec();
+        segment.extend_from_slice(&a);
+        segment.extend_from_slice(&(b.len() as u16).to_be_bytes());
+        segment.extend_from_slice(&b);
+        assert_eq!(tcp_messages(&segment), vec![&a[..], &b[..]]);
+        // The second message continues in the next segment, so this one is skipped.
+        assert!(tcp_messages(&segment[..segment.len() - 1]).is_empty());
+        assert!(tcp_messages(&segment[2..]).is_empty());
+    }
+
+    #[test]
+    fn test_latency_matching_and_slow_responses() {
+        let mut dns = DnsAnalyzer::default();
+        exchange(&mut dns, 1, "www.example.com", 1, 0, 0.0, 0.02);
+        exchange(&mut dns, 2, "api.example.com", 1, 0, 1.0, 0.8);
+
+        let mut issues = Vec::new();
+        let summary = dns.finish(&mut issues);
+        assert_eq!(summary.queries, 2);
+        assert_eq!(summary.responses, 2);
+        assert_eq!(summary.top_domains[0].domain, "example.com");
+        assert_eq!(summary.slow_responses.len(), 1);
+        assert_eq!(summary.slow_responses[0].query, "api.example.com");
+        assert!((summary.slow_responses[0].latency_ms - 800.0).abs() < 1e-6);
+        assert!((summary.avg_latency_ms.unwrap() - 410.0).abs() < 1e-6);
+        assert!(issues.iter().any(|i| i.title == "Slow DNS Responses"));
+    }
+
+    #[test]
+    fn test_tunneling_and_failing_resolver() {
+        let mut dns = DnsAnalyzer::default();
+        for i in 0..30u16 {
+            let chunk = format!("{:016x}{:016x}", (i as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15), (i as u64).wrapping_mul(0xc2b2ae3d27d4eb4f));
+            exchange(&mut dns, i, &format!("{}.t.exfil.net", chunk), TYPE_TXT, RCODE_SERVFAIL, i as f64, 0.01);
+        }
+        let mut issues = Vec::new();
+        let summary = dns.finish(&mut issues);
+        let d = &summary.top_domains[0];
+        assert_eq!(d.domain, "exfil.net");
+        assert_eq!(d.unique_subdomains, 30);
+        assert_eq!(d.txt_null_queries, 30);
+        assert!(issues.iter().any(|i| i.title == "Possible DNS Tunneling" && i.description.starts_with("exfil.net")));
+        assert!(issues.iter().any(|i| i.title == "Failing DNS Resolver" && i.severity == "critical"));
+    }
+
+    #[test]
+    fn test_dga_nxdomain_burst() {
+        let mut dns = DnsAnalyzer::default();
+        for (i, name) in ["xkq3vbz9wplm.com", "r7tnq2ykd8ha.net", "mvbq0x4zje1s.org", "h2pw9fk3lq7c.info", "zt8yq1nm5rvk.com", "bbs.co.uk"].iter().enumerate() {
+            exchange(&mut dns, i as u16, name, 1, RCODE_NXDOMAIN, i as f64, 0.01);
+        }
+        let mut issues = Vec::new();
+        dns.finish(&mut issues);
+        let dga = issues.iter().find(|i| i.title == "DGA-like DNS Lookups").unwrap();
+        assert!(dga.description.starts_with("10.0.0.5 got NXDOMAIN for 5 random-looking domains"));
+    }
+}
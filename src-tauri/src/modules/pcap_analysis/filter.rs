@@ -0,0 +1,586 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use etherparse::{LinkExtHeader, LinkHeader, NetHeaders, PacketHeaders, TransportHeader};
+
+use super::detail::app_protocol;
+
+/// A Wireshark-style display filter such as `ip.addr == 10.0.0.5 && tcp.port == 443`
+/// or `udp && !dns`.
+///
+/// Bare protocol names test for that layer, bare fields test for presence (or for a
+/// set flag), and comparisons use `==`, `!=`, `<`, `<=`, `>`, `>=` (or `eq`, `ne`, ...).
+/// Fields such as `ip.addr` that name both ends match if either end does, and `!=`
+/// means neither does. Addresses accept CIDR prefixes: `ip.src == 10.0.0.0/8`.
+pub struct DisplayFilter {
+    text: String,
+    expr: Expr,
+}
+
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Protocol(Protocol),
+    Present(Field),
+    Compare(Field, Op, Literal),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op { Eq, Ne, Lt, Le, Gt, Ge }
+
+enum Literal {
+    Num(u64),
+    V4([u8; 4], u8),
+    V6([u8; 16], u8),
+    Mac([u8; 6]),
+}
+
+#[derive(Clone, Copy)]
+enum Protocol { Eth, Vlan, Arp, Ip, Ipv6, Tcp, Udp, Icmp, Icmpv6, App(&'static str) }
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind { Num, Bool, V4, V6, Mac }
+
+#[derive(Clone, Copy)]
+enum Field {
+    FrameLen,
+    EthSrc, EthDst, EthAddr, EthType,
+    VlanId, VlanPriority,
+    ArpOpcode, ArpSrcIp, ArpDstIp,
+    IpSrc, IpDst, IpAddr, IpTtl, IpProto, IpLen, IpId, IpDscp, IpDf, IpMf,
+    Ipv6Src, Ipv6Dst, Ipv6Addr, Ipv6Hlim, Ipv6Nxt, Ipv6Flow, Ipv6Plen,
+    TcpSrcPort, TcpDstPort, TcpPort, TcpSeq, TcpAck, TcpWindow, TcpLen,
+    TcpSyn, TcpAckFlag, TcpFin, TcpRst, TcpPsh, TcpUrg,
+    UdpSrcPort, UdpDstPort, UdpPort, UdpLength,
+    IcmpType, IcmpCode, Icmpv6Type, Icmpv6Code,
+}
+
+const FIELDS: &[(&str, Field, Kind)] = &[
+    ("frame.len", Field::FrameLen, Kind::Num),
+    ("eth.src", Field::EthSrc, Kind::Mac),
+    ("eth.dst", Field::EthDst, Kind::Mac),
+    ("eth.addr", Field::EthAddr, Kind::Mac),
+    ("eth.type", Field::EthType, Kind::Num),
+    ("vlan.id", Field::VlanId, Kind::Num),
+    ("vlan.priority", Field::VlanPriority, Kind::Num),
+    ("arp.opcode", Field::ArpOpcode, Kind::Num),
+    ("arp.src.proto_ipv4", Field::ArpSrcIp, Kind::V4),
+    ("arp.dst.proto_ipv4", Field::ArpDstIp, Kind::V4),
+    ("ip.src", Field::IpSrc, Kind::V4),
+    ("ip.dst", Field::IpDst, Kind::V4),
+    ("ip.addr", Field::IpAddr, Kind::V4),
+    ("ip.ttl", Field::IpTtl, Kind::Num),
+    ("ip.proto", Field::IpProto, Kind::Num),
+    ("ip.len", Field::IpLen, Kind::Num),
+    ("ip.id", Field::IpId, Kind::Num),
+    ("ip.dsfield.dscp", Field::IpDscp, Kind::Num),
+    ("ip.flags.df", Field::IpDf, Kind::Bool),
+    ("ip.flags.mf", Field::IpMf, Kind::Bool),
+    ("ipv6.src", Field::Ipv6Src, Kind::V6),
+    ("ipv6.dst", Field::Ipv6Dst, Kind::V6),
+    ("ipv6.addr", Field::Ipv6Addr, Kind::V6),
+    ("ipv6.hlim", Field::Ipv6Hlim, Kind::Num),
+    ("ipv6.nxt", Field::Ipv6Nxt, Kind::Num),
+    ("ipv6.flow", Field::Ipv6Flow, Kind::Num),
+    ("ipv6.plen", Field::Ipv6Plen, Kind::Num),
+    ("tcp.srcport", Field::TcpSrcPort, Kind::Num),
+    ("tcp.dstport", Field::TcpDstPort, Kind::Num),
+    ("tcp.port", Field::TcpPort, Kind::Num),
+    ("tcp.seq", Field::TcpSeq, Kind::Num),
+    ("tcp.ack", Field::TcpAck, Kind::Num),
+    ("tcp.window_size", Field::TcpWindow, Kind::Num),
+    ("tcp.len", Field::TcpLen, Kind::Num),
+    ("tcp.flags.syn", Field::TcpSyn, Kind::Bool),
+    ("tcp.flags.ack", Field::TcpAckFlag, Kind::Bool),
+    ("tcp.flags.fin", Field::TcpFin, Kind::Bool),
+    ("tcp.flags.reset", Field::TcpRst, Kind::Bool),
+    ("tcp.flags.push", Field::TcpPsh, Kind::Bool),
+    ("tcp.flags.urg", Field::TcpUrg, Kind::Bool),
+    ("udp.srcport", Field::UdpSrcPort, Kind::Num),
+    ("udp.dstport", Field::UdpDstPort, Kind::Num),
+    ("udp.port", Field::UdpPort, Kind::Num),
+    ("udp.length", Field::UdpLength, Kind::Num),
+    ("icmp.type", Field::IcmpType, Kind::Num),
+    ("icmp.code", Field::IcmpCode, Kind::Num),
+    ("icmpv6.type", Field::Icmpv6Type, Kind::Num),
+    ("icmpv6.code", Field::Icmpv6Code, Kind::Num),
+];
+
+const PROTOCOLS: &[(&str, Protocol)] = &[
+    ("eth", Protocol::Eth),
+    ("vlan", Protocol::Vlan),
+    ("arp", Protocol::Arp),
+    ("ip", Protocol::Ip),
+    ("ipv6", Protocol::Ipv6),
+    ("tcp", Protocol::Tcp),
+    ("udp", Protocol::Udp),
+    ("icmp", Protocol::Icmp),
+    ("icmpv6", Protocol::Icmpv6),
+    ("dns", Protocol::App("DNS")),
+    ("http", Protocol::App("HTTP")),
+    ("tls", Protocol::App("TLS")),
+    ("sip", Protocol::App("SIP")),
+];
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Op(&'static str),
+    Word(String),
+}
+
+impl DisplayFilter {
+    /// Parses `text`, reporting the 1-based character position of the first problem.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, pos: 0, end: text.chars().count() + 1 };
+        if parser.tokens.is_empty() {
+            return Err("Empty filter at position 1".into());
+        }
+        let expr = parser.or()?;
+        if let Some((tok, at)) = parser.tokens.get(parser.pos) {
+            return Err(format!("Unexpected {} at position {}", describe(tok), at));
+        }
+        Ok(DisplayFilter { text: text.trim().to_string(), expr })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// `headers` is `None` for frames the link decoder could not make sense of; only
+    /// `frame.len` and negations can match those.
+    pub fn matches(&self, headers: Option<&PacketHeaders>, frame_len: usize) -> bool {
+        eval(&self.expr, headers, frame_len)
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let at = i + 1;
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match c {
+            _ if c.is_whitespace() => { i += 1; continue; },
+            '(' => (Token::LParen, 1),
+            ')' => (Token::RParen, 1),
+            '&' if next == Some('&') => (Token::And, 2),
+            '|' if next == Some('|') => (Token::Or, 2),
+            '!' if next == Some('=') => (Token::Op("!="), 2),
+            '!' => (Token::Not, 1),
+            '=' if next == Some('=') => (Token::Op("=="), 2),
+            '<' if next == Some('=') => (Token::Op("<="), 2),
+            '>' if next == Some('=') => (Token::Op(">="), 2),
+            '<' => (Token::Op("<"), 1),
+            '>' => (Token::Op(">"), 1),
+            _ if is_word_char(c) => {
+                let len = chars[i..].iter().take_while(|c| is_word_char(**c)).count();
+                let word: String = chars[i..i + len].iter().collect();
+                let token = match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "eq" => Token::Op("=="),
+                    "ne" => Token::Op("!="),
+                    "lt" => Token::Op("<"),
+                    "le" => Token::Op("<="),
+                    "gt" => Token::Op(">"),
+                    "ge" => Token::Op(">="),
+                    _ => Token::Word(word),
+                };
+                (token, len)
+            },
+            _ => return Err(format!("Unexpected character '{}' at position {}", c, at)),
+        };
+        tokens.push((token, at));
+        i += len;
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | ':' | '/' | '-')
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::LParen => "'('".into(),
+        Token::RParen => "')'".into(),
+        Token::And => "'&&'".into(),
+        Token::Or => "'||'".into(),
+        Token::Not => "'!'".into(),
+        Token::Op(op) => format!("'{}'", op),
+        Token::Word(w) => format!("'{}'", w),
+    }
+}
+
+/// Recursive descent: `or := and ("||" and)*`, `and := unary ("&&" unary)*`,
+/// `unary := "!" unary | "(" or ")" | name [op value]`.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize, // position reported for "unexpected end of filter"
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(_, at)| *at)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let at = self.position();
+        match self.tokens.get(self.pos).map(|(t, _)| t) {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            },
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let inner = self.or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(format!("Expected ')' to close the '(' at position {} at position {}", at, self.position()));
+                }
+                self.pos += 1;
+                Ok(inner)
+            },
+            Some(Token::Word(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                self.name(&name, at)
+            },
+            Some(tok) => Err(format!("Expected a field or protocol, found {} at position {}", describe(tok), at)),
+            None => Err(format!("Unexpected end of filter at position {}", at)),
+        }
+    }
+
+    /// A protocol, a field on its own, or a field comparison.
+    fn name(&mut self, name: &str, at: usize) -> Result<Expr, String> {
+        let op = match self.peek() {
+            Some(Token::Op(op)) => *op,
+            _ => {
+                if let Some((_, proto)) = PROTOCOLS.iter().find(|(n, _)| *n == name) {
+                    return Ok(Expr::Protocol(*proto));
+                }
+                return lookup(name, at).map(|(field, _)| Expr::Present(field));
+            },
+        };
+        let (field, kind) = lookup(name, at)?;
+        let op_at = self.position();
+        self.pos += 1;
+        let op = match op {
+            "==" => Op::Eq,
+            "!=" => Op::Ne,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            _ => Op::Ge,
+        };
+        if !matches!(op, Op::Eq | Op::Ne) && !matches!(kind, Kind::Num) {
+            return Err(format!("'{}' can only be compared with '==' or '!=' at position {}", name, op_at));
+        }
+
+        let value_at = self.position();
+        let value = match self.tokens.get(self.pos) {
+            Some((Token::Word(w), _)) => w.clone(),
+            Some((tok, _)) => return Err(format!("Expected a value, found {} at position {}", describe(tok), value_at)),
+            None => return Err(format!("Expected a value after '{}' at position {}", name, value_at)),
+        };
+        self.pos += 1;
+        let literal = parse_literal(&value, kind)
+            .ok_or_else(|| format!("'{}' is not a valid {} for '{}' at position {}", value, kind_name(kind), name, value_at))?;
+        Ok(Expr::Compare(field, op, literal))
+    }
+}
+
+fn lookup(name: &str, at: usize) -> Result<(Field, Kind), String> {
+    FIELDS.iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|(_, field, kind)| (*field, *kind))
+        .ok_or_else(|| format!("Unknown field or protocol '{}' at position {}", name, at))
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Num => "number",
+        Kind::Bool => "flag value (0 or 1)",
+        Kind::V4 => "IPv4 address",
+        Kind::V6 => "IPv6 address",
+        Kind::Mac => "MAC address",
+    }
+}
+
+fn parse_literal(value: &str, kind: Kind) -> Option<Literal> {
+    match kind {
+        Kind::Num => parse_number(value).map(Literal::Num),
+        Kind::Bool => parse_number(value).filter(|n| *n <= 1).map(Literal::Num),
+        Kind::V4 => {
+            let (addr, prefix) = split_prefix(value, 32)?;
+            Some(Literal::V4(addr.parse::<Ipv4Addr>().ok()?.octets(), prefix))
+        },
+        Kind::V6 => {
+            let (addr, prefix) = split_prefix(value, 128)?;
+            Some(Literal::V6(addr.parse::<Ipv6Addr>().ok()?.octets(), prefix))
+        },
+        Kind::Mac => {
+            let parts: Vec<u8> = value.split([':', '-']).map(|p| u8::from_str_radix(p, 16).ok()).collect::<Option<_>>()?;
+            Some(Literal::Mac(parts.try_into().ok()?))
+        },
+    }
+}
+
+fn parse_number(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn split_prefix(value: &str, max: u8) -> Option<(&str, u8)> {
+    match value.split_once('/') {
+        Some((addr, prefix)) => Some((addr, prefix.parse().ok().filter(|p| *p <= max)?)),
+        None => Some((value, max)),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Value {
+    Num(u64),
+    V4([u8; 4]),
+    V6([u8; 16]),
+    Mac([u8; 6]),
+}
+
+fn eval(expr: &Expr, headers: Option<&PacketHeaders>, frame_len: usize) -> bool {
+    match expr {
+        Expr::And(a, b) => eval(a, headers, frame_len) && eval(b, headers, frame_len),
+        Expr::Or(a, b) => eval(a, headers, frame_len) || eval(b, headers, frame_len),
+        Expr::Not(inner) => !eval(inner, headers, frame_len),
+        Expr::Protocol(proto) => headers.is_some_and(|h| has_protocol(h, *proto)),
+        Expr::Present(field) => values(*field, headers, frame_len).iter().flatten().any(|v| match v {
+            // A bare flag means "is set"; any other bare field means "is present".
+            Value::Num(n) => FIELDS.iter().any(|(_, f, k)| same_field(*f, *field) && *k != Kind::Bool) || *n != 0,
+            _ => true,
+        }),
+        Expr::Compare(field, op, literal) => {
+            let vals = values(*field, headers, frame_len);
+            let mut present = vals.iter().flatten().peekable();
+            if present.peek().is_none() { return false; }
+            match op {
+                // Multi-valued fields: "!=" holds when no value equals.
+                Op::Ne => !present.any(|v| compare(v, Op::Eq, literal)),
+                _ => present.any(|v| compare(v, *op, literal)),
+            }
+        },
+    }
+}
+
+fn same_field(a: Field, b: Field) -> bool {
+    std::mem::discriminant(&a) == std::mem::discriminant(&b)
+}
+
+fn compare(value: &Value, op: Op, literal: &Literal) -> bool {
+    match (value, literal) {
+        (Value::Num(v), Literal::Num(l)) => match op {
+            Op::Eq => v == l,
+            Op::Ne => v != l,
+            Op::Lt => v < l,
+            Op::Le => v <= l,
+            Op::Gt => v > l,
+            Op::Ge => v >= l,
+        },
+        (Value::V4(v), Literal::V4(net, prefix)) => prefix_eq(v, net, *prefix),
+        (Value::V6(v), Literal::V6(net, prefix)) => prefix_eq(v, net, *prefix),
+        (Value::Mac(v), Literal::Mac(l)) => v == l,
+        _ => false,
+    }
+}
+
+fn prefix_eq(addr: &[u8], net: &[u8], prefix: u8) -> bool {
+    let (bytes, bits) = ((prefix / 8) as usize, prefix % 8);
+    if addr[..bytes] != net[..bytes] { return false; }
+    bits == 0 || {
+        let mask = 0xffu8 << (8 - bits);
+        addr[bytes] & mask == net[bytes] & mask
+    }
+}
+
+fn has_protocol(h: &PacketHeaders, proto: Protocol) -> bool {
+    match proto {
+        Protocol::Eth => matches!(h.link, Some(LinkHeader::Ethernet2(_))),
+        Protocol::Vlan => h.link_exts.iter().any(|e| matches!(e, LinkExtHeader::Vlan(_))),
+        Protocol::Arp => matches!(h.net, Some(NetHeaders::Arp(_))),
+        Protocol::Ip => matches!(h.net, Some(NetHeaders::Ipv4(..))),
+        Protocol::Ipv6 => matches!(h.net, Some(NetHeaders::Ipv6(..))),
+        Protocol::Tcp => matches!(h.transport, Some(TransportHeader::Tcp(_))),
+        Protocol::Udp => matches!(h.transport, Some(TransportHeader::Udp(_))),
+        Protocol::Icmp => matches!(h.transport, Some(TransportHeader::Icmpv4(_))),
+        Protocol::Icmpv6 => matches!(h.transport, Some(TransportHeader::Icmpv6(_))),
+        Protocol::App(name) => app_protocol(h) == Some(name),
+    }
+}
+
+/// Up to two values of `field` (both ends for `.addr`/`.port` fields).
+fn values(field: Field, headers: Option<&PacketHeaders>, frame_len: usize) -> [Option<Value>; 2] {
+    use Field::*;
+    let num = |n: u64| [Some(Value::Num(n)), None];
+    let pair = |a: Value, b: Value| [Some(a), Some(b)];
+    let one = |v: Value| [Some(v), None];
+    const NONE: [Option<Value>; 2] = [None, None];
+
+    if let FrameLen = field { return num(frame_len as u64); }
+    let Some(h) = headers else { return NONE };
+
+    let eth = match &h.link { Some(LinkHeader::Ethernet2(e)) => Some(e), _ => None };
+    let vlan = h.link_exts.iter().find_map(|e| match e { LinkExtHeader::Vlan(v) => Some(v), _ => None });
+    let arp = match &h.net { Some(NetHeaders::Arp(a)) => a.try_eth_ipv4().ok(), _ => None };
+    let v4 = match &h.net { Some(NetHeaders::Ipv4(ip, _)) => Some(ip), _ => None };
+    let v6 = match &h.net { Some(NetHeaders::Ipv6(ip, _)) => Some(ip), _ => None };
+    let tcp = match &h.transport { Some(TransportHeader::Tcp(t)) => Some(t), _ => None };
+    let udp = match &h.transport { Some(TransportHeader::Udp(u)) => Some(u), _ => None };
+    let icmp4 = match &h.transport { Some(TransportHeader::Icmpv4(i)) => Some(i.to_bytes()), _ => None };
+    let icmp6 = match &h.transport { Some(TransportHeader::Icmpv6(i)) => Some(i.to_bytes()), _ => None };
+
+    let found = match field {
+        FrameLen => unreachable!(),
+        EthSrc => eth.map(|e| one(Value::Mac(e.source))),
+        EthDst => eth.map(|e| one(Value::Mac(e.destination))),
+        EthAddr => eth.map(|e| pair(Value::Mac(e.source), Value::Mac(e.destination))),
+        EthType => eth.map(|e| num(e.ether_type.0 as u64)),
+        VlanId => vlan.map(|v| num(v.vlan_id.value() as u64)),
+        VlanPriority => vlan.map(|v| num(v.pcp.value() as u64)),
+        ArpOpcode => arp.map(|a| num(a.operation.0 as u64)),
+        ArpSrcIp => arp.map(|a| one(Value::V4(a.sender_ipv4))),
+        ArpDstIp => arp.map(|a| one(Value::V4(a.target_ipv4))),
+        IpSrc => v4.map(|ip| one(Value::V4(ip.source))),
+        IpDst => v4.map(|ip| one(Value::V4(ip.destination))),
+        IpAddr => v4.map(|ip| pair(Value::V4(ip.source), Value::V4(ip.destination))),
+        IpTtl => v4.map(|ip| num(ip.time_to_live as u64)),
+        IpProto => v4.map(|ip| num(ip.protocol.0 as u64)),
+        IpLen => v4.map(|ip| num(ip.total_len as u64)),
+        IpId => v4.map(|ip| num(ip.identification as u64)),
+        IpDscp => v4.map(|ip| num(ip.dscp.value() as u64)),
+        IpDf => v4.map(|ip| num(ip.dont_fragment as u64)),
+        IpMf => v4.map(|ip| num(ip.more_fragments as u64)),
+        Ipv6Src => v6.map(|ip| one(Value::V6(ip.source))),
+        Ipv6Dst => v6.map(|ip| one(Value::V6(ip.destination))),
+        Ipv6Addr => v6.map(|ip| pair(Value::V6(ip.source), Value::V6(ip.destination))),
+        Ipv6Hlim => v6.map(|ip| num(ip.hop_limit as u64)),
+        Ipv6Nxt => v6.map(|ip| num(ip.next_header.0 as u64)),
+        Ipv6Flow => v6.map(|ip| num(ip.flow_label.value() as u64)),
+        Ipv6Plen => v6.map(|ip| num(ip.payload_length as u64)),
+        TcpSrcPort => tcp.map(|t| num(t.source_port as u64)),
+        TcpDstPort => tcp.map(|t| num(t.destination_port as u64)),
+        TcpPort => tcp.map(|t| pair(Value::Num(t.source_port as u64), Value::Num(t.destination_port as u64))),
+        TcpSeq => tcp.map(|t| num(t.sequence_number as u64)),
+        TcpAck => tcp.map(|t| num(t.acknowledgment_number as u64)),
+        TcpWindow => tcp.map(|t| num(t.window_size as u64)),
+        TcpLen => tcp.map(|_| num(h.payload.slice().len() as u64)),
+        TcpSyn => tcp.map(|t| num(t.syn as u64)),
+        TcpAckFlag => tcp.map(|t| num(t.ack as u64)),
+        TcpFin => tcp.map(|t| num(t.fin as u64)),
+        TcpRst => tcp.map(|t| num(t.rst as u64)),
+        TcpPsh => tcp.map(|t| num(t.psh as u64)),
+        TcpUrg => tcp.map(|t| num(t.urg as u64)),
+        UdpSrcPort => udp.map(|u| num(u.source_port as u64)),
+        UdpDstPort => udp.map(|u| num(u.destination_port as u64)),
+        UdpPort => udp.map(|u| pair(Value::Num(u.source_port as u64), Value::Num(u.destination_port as u64))),
+        UdpLength => udp.map(|u| num(u.length as u64)),
+        IcmpType => icmp4.map(|b| num(b[0] as u64)),
+        IcmpCode => icmp4.map(|b| num(b[1] as u64)),
+        Icmpv6Type => icmp6.map(|b| num(b[0] as u64)),
+        Icmpv6Code => icmp6.map(|b| num(b[1] as u64)),
+    };
+    found.unwrap_or(NONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::PacketBuilder;
+
+    fn udp_dns(src: [u8; 4], dst: [u8; 4]) -> Vec<u8> {
+        let mut out = Vec::new();
+        PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+            .ipv4(src, dst, 64)
+            .udp(50000, 53)
+            .write(&mut out, b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x01a\x00\x00\x01\x00\x01")
+            .unwrap();
+        out
+    }
+
+    fn tcp_syn(dport: u16) -> Vec<u8> {
+        let mut out = Vec::new();
+        PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+            .ipv4([10, 0, 0, 5], [93, 184, 216, 34], 64)
+            .tcp(50000, dport, 0, 64240)
+            .syn()
+            .write(&mut out, &[])
+            .unwrap();
+        out
+    }
+
+    fn matches(filter: &str, frame: &[u8]) -> bool {
+        let headers = PacketHeaders::from_ethernet_slice(frame).ok();
+        DisplayFilter::parse(filter).unwrap().matches(headers.as_ref(), frame.len())
+    }
+
+    #[test]
+    fn test_matching() {
+        let dns = udp_dns([10, 0, 0, 5], [10, 0, 0, 1]);
+        let syn = tcp_syn(443);
+
+        assert!(matches("ip.addr == 10.0.0.5 && tcp.port == 443", &syn));
+        assert!(!matches("ip.addr == 10.0.0.5 && tcp.port == 443", &dns));
+        assert!(matches("udp && dns", &dns));
+        assert!(!matches("udp && !dns", &dns));
+        assert!(matches("ip.src == 10.0.0.0/8 and ip.dst eq 10.0.0.1", &dns));
+        assert!(matches("ip.addr != 192.168.1.1", &dns));
+        assert!(!matches("ip.addr != 10.0.0.1", &dns)); // one end is 10.0.0.1
+        assert!(matches("tcp.flags.syn && !tcp.flags.ack && tcp.dstport >= 0x100", &syn));
+        assert!(matches("(tcp.port == 80 || tcp.port == 443) && eth.src == 02:00:00:00:00:01", &syn));
+        assert!(matches("!(icmp || arp) && frame.len > 50", &syn));
+        assert!(!matches("tcp.flags.fin", &syn));
+        assert!(matches("tcp.seq", &syn)); // present, even though it's zero
+    }
+
+    #[test]
+    fn test_parse_errors_report_position() {
+        let err = |f: &str| DisplayFilter::parse(f).err().unwrap();
+        assert_eq!(err("tcp.prot == 443"), "Unknown field or protocol 'tcp.prot' at position 1");
+        assert_eq!(err("ip.addr == 10.0.0.300"), "'10.0.0.300' is not a valid IPv4 address for 'ip.addr' at position 12");
+        assert_eq!(err("udp && "), "Unexpected end of filter at position 8");
+        assert_eq!(err("ip.src > 10.0.0.1"), "'ip.src' can only be compared with '==' or '!=' at position 8");
+        assert_eq!(err("(tcp || udp"), "Expected ')' to close the '(' at position 1 at position 12");
+        assert_eq!(err("tcp udp"), "Unexpected 'udp' at position 5");
+        assert_eq!(err("tcp.port == 80 # x"), "Unexpected character '#' at position 16");
+        assert_eq!(err("tcp.port =="), "Expected a value after 'tcp.port' at position 12");
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::{BTreeSet, HashMap};
+use etherparse::TcpHeader;
+
+use crate::modules::utils::{Conversation, FlowStats};
+use super::tcp::TcpEvent;
+
+// Idle time after which an unclosed flow is reported as timed out, matching the
+// Linux conntrack defaults for unreplied UDP and a conservative TCP idle limit.
+const TCP_IDLE_TIMEOUT: f64 = 300.0;
+const UDP_IDLE_TIMEOUT: f64 = 30.0;
+
+struct FlowEntry {
+    stats: FlowStats,
+    syn_ts: Option<f64>,
+}
+
+/// 5-tuple flow table. A flow's client is whoever sent the first packet, unless that
+/// packet was a SYN/ACK, in which case the roles are swapped.
+#[derive(Default)]
+pub struct FlowTable {
+    active: HashMap<String, FlowEntry>,
+    finished: Vec<FlowStats>,
+}
+
+impl FlowTable {
+    /// `tcp` carries the segment header and its analysis flags; `None` for UDP. Returns
+    /// whether the packet started a new flow.
+    pub fn record(
+        &mut self, protocol: &str, src: &str, dst: &str, frame_len: u64,
+        tcp: Option<(&TcpHeader, &[TcpEvent])>, ts: f64,
+    ) -> bool {
+        let (tcp, tcp_events) = match tcp {
+            Some((header, events)) => (Some(header), events),
+            None => (None, &[][..]),
+        };
+        let key = if src < dst {
+            format!("{} {} <-> {}", protocol, src, dst)
+        } else {
+            format!("{} {} <-> {}", protocol, dst, src)
+        };
+
+        // A fresh SYN on a 5-tuple that already closed is a new connection reusing the ports.
+        let reopened = tcp.is_some_and(|t| t.syn && !t.ack)
+            && self.active.get(&key).is_some_and(|e| e.stats.close_reason.is_some());
+        if reopened {
+            if let Some(old) = self.active.remove(&key) {
+                self.finished.push(old.stats);
+            }
+        }
+
+        let is_new = reopened || !self.active.contains_key(&key);
+        let entry = self.active.entry(key).or_insert_with(|| {
+            let swap = tcp.is_some_and(|t| t.syn && t.ack);
+            let (client, server) = if swap { (dst, src) } else { (src, dst) };
+            FlowEntry {
+                stats: FlowStats {
+                    protocol: protocol.to_string(),
+                    client: client.to_string(),
+                    server: server.to_string(),
+                    start_ts: ts,
+                    end_ts: ts,
+                    ..Default::default()
+                },
+                syn_ts: None,
+            }
+        });
+
+        let flow = &mut entry.stats;
+        flow.end_ts = ts;
+        flow.duration_sec = flow.end_ts - flow.start_ts;
+        if src == flow.client {
+            flow.packets_to_server += 1;
+            flow.bytes_to_server += frame_len;
+        } else {
+            flow.packets_to_client += 1;
+            flow.bytes_to_client += frame_len;
+        }
+
+        let Some(tcp) = tcp else { return is_new };
+        if tcp.syn && !tcp.ack {
+            entry.syn_ts = Some(ts);
+        } else if tcp.syn && tcp.ack && flow.handshake_rtt_ms.is_none() {
+            if let Some(syn_ts) = entry.syn_ts {
+                flow.handshake_rtt_ms = Some((ts - syn_ts) * 1000.0);
+            }
+        }
+
+        flow.retransmissions += tcp_events.iter().filter(|e| e.is_retransmission()).count();
+
+        // A reset outranks an earlier FIN: the close was not graceful after all.
+        if tcp.rst {
+            flow.close_reason = Some("RST".into());
+        } else if tcp.fin && flow.close_reason.is_none() {
+            flow.close_reason = Some("FIN".into());
+        }
+        is_new
+    }
+
+    /// Returns the flows sorted by total bytes, plus the per-IP-pair rollup.
+    pub fn finish(self, capture_end: f64) -> (Vec<FlowStats>, Vec<Conversation>) {
+        let mut flows = self.finished;
+        for entry in self.active.into_values() {
+            let mut flow = entry.stats;
+            let idle_limit = if flow.protocol == "TCP" { TCP_IDLE_TIMEOUT } else { UDP_IDLE_TIMEOUT };
+            if flow.close_reason.is_none() && capture_end - flow.end_ts > idle_limit {
+                flow.close_reason = Some("Timeout".into());
+            }
+            flows.push(flow);
+        }
+        sort_flows(&mut flows, FlowSort::Bytes);
+
+        let mut pairs: HashMap<(String, String), (Conversation, BTreeSet<String>)> = HashMap::new();
+        for flow in &flows {
+            let (a, b) = (host(&flow.client), host(&flow.server));
+            let key = if a < b { (a.to_string(), b.to_string()) } else { (b.to_string(), a.to_string()) };
+            let (conv, protocols) = pairs.entry(key).or_insert_with(|| (Conversation {
+                source: a.to_string(),
+                destination: b.to_string(),
+                protocol: String::new(),
+                bytes: 0,
+                packets: 0,
+                flows: 0,
+            }, BTreeSet::new()));
+            conv.bytes += flow.bytes_to_server + flow.bytes_to_client;
+            conv.packets += flow.packets_to_server + flow.packets_to_client;
+            conv.flows += 1;
+            protocols.insert(flow.protocol.clone());
+        }
+
+        let mut conversations: Vec<Conversation> = pairs.into_values().map(|(mut conv, protocols)| {
+            conv.protocol = protocols.into_iter().collect::<Vec<_>>().join("/");
+            conv
+        }).collect();
+        conversations.sort_by_key(|c| std::cmp::Reverse(c.bytes));
+        (flows, conversations)
+    }
+}
+
+/// A column flows can be ordered by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowSort {
+    Bytes,
+    Packets,
+    Duration,
+    Start,
+    Rtt,
+    Retransmissions,
+}
+
+impl FlowSort {
+    pub fn parse(key: &str) -> Result<Self, String> {
+        match key {
+            "bytes" => Ok(FlowSort::Bytes),
+            "packets" => Ok(FlowSort::Packets),
+            "duration" => Ok(FlowSort::Duration),
+            "start" => Ok(FlowSort::Start),
+            "rtt" => Ok(FlowSort::Rtt),
+            "retransmissions" => Ok(FlowSort::Retransmissions),
+            _ => Err(format!("Unknown flow sort key '{}'", key)),
+        }
+    }
+}
+
+/// Orders flows by the given column, largest first (earliest first for `start`).
+pub fn sort_flows(flows: &mut [FlowStats], key: FlowSort) {
+    match key {
+        FlowSort::Bytes => flows.sort_by_key(|f| std::cmp::Reverse(f.bytes_to_server + f.bytes_to_client)),
+        FlowSort::Packets => flows.sort_by_key(|f| std::cmp::Reverse(f.packets_to_server + f.packets_to_client)),
+        FlowSort::Duration => flows.sort_by(|a, b| b.duration_sec.total_cmp(&a.duration_sec)),
+        FlowSort::Start => flows.sort_by(|a, b| a.start_ts.total_cmp(&b.start_ts)),
+        FlowSort::Rtt => flows.sort_by(|a, b| b.handshake_rtt_ms.unwrap_or(-1.0).total_cmp(&a.handshake_rtt_ms.unwrap_or(-1.0))),
+        FlowSort::Retransmissions => flows.sort_by_key(|f| std::cmp::Reverse(f.retransmissions)),
+    }
+}
+
+/// Strips the port from an `endpoint()` string, including IPv6 brackets.
+fn host(endpoint: &str) -> &str {
+    let ip = endpoint.rsplit_once(':').map_or(endpoint, |(ip, _)| ip);
+    ip.trim_start_matches('[').trim_end_matches(']')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(syn: bool, ack: bool, fin: bool, rst: bool) -> TcpHeader {
+        let mut h = TcpHeader::new(0, 0, 0, 1000);
+        h.syn = syn;
+        h.ack = ack;
+        h.fin = fin;
+        h.rst = rst;
+        h
+    }
+
+    #[test]
+    fn test_flow_directions_rtt_and_close() {
+        let (c, s) = ("10.0.0.5:50000", "10.0.0.9:443");
+        let mut table = FlowTable::default();
+        // Capture starts at the SYN/ACK: the server is still identified correctly.
+        assert!(table.record("TCP", s, c, 66, Some((&tcp(true, true, false, false), &[])), 1.0));
+        assert!(!table.record("TCP", c, s, 54, Some((&tcp(false, true, false, false), &[])), 1.01));
+        table.record("TCP", c, s, 1500, Some((&tcp(false, true, false, false), &[TcpEvent::Retransmission])), 1.5);
+        table.record("TCP", c, s, 54, Some((&tcp(false, true, true, false), &[])), 2.0);
+        table.record("TCP", s, c, 54, Some((&tcp(false, false, false, true), &[])), 2.1);
+
+        // Port reuse after the reset starts a second flow.
+        assert!(table.record("TCP", c, s, 66, Some((&tcp(true, false, false, false), &[])), 3.0));
+        table.record("TCP", s, c, 66, Some((&tcp(true, true, false, false), &[])), 3.04);
+
+        table.record("UDP", "[fe80::1]:5353", "[ff02::fb]:5353", 120, None, 4.0);
+
+        let (flows, convs) = table.finish(40.0);
+        assert_eq!(flows.len(), 3);
+
+        let first = flows.iter().find(|f| f.start_ts == 1.0).unwrap();
+        assert_eq!((first.client.as_str(), first.server.as_str()), (c, s));
+        assert_eq!((first.packets_to_server, first.bytes_to_server), (3, 1608));
+        assert_eq!((first.packets_to_client, first.bytes_to_client), (2, 120));
+        assert_eq!(first.retransmissions, 1);
+        assert_eq!(first.close_reason.as_deref(), Some("RST"));
+        assert!((first.duration_sec - 1.1).abs() < 1e-9);
+        assert!(first.handshake_rtt_ms.is_none());
+
+        let second = flows.iter().find(|f| f.start_ts == 3.0).unwrap();
+        assert!((second.handshake_rtt_ms.unwrap() - 40.0).abs() < 1e-6);
+        assert!(second.close_reason.is_none());
+
+        let udp = flows.iter().find(|f| f.protocol == "UDP").unwrap();
+        assert_eq!(udp.close_reason.as_deref(), Some("Timeout"));
+
+        assert_eq!(convs.len(), 2);
+        assert_eq!(convs[0].flows, 2);
+        assert_eq!(convs[0].protocol, "TCP");
+        assert!(convs.iter().any(|c| c.source == "fe80::1" && c.protocol == "UDP"));
+    }
+
+    #[test]
+    fn test_sort_flows() {
+        let mut flows = vec![
+            FlowStats { duration_sec: 1.0, retransmissions: 5, ..Default::default() },
+            FlowStats { duration_sec: 9.0, handshake_rtt_ms: Some(12.0), ..Default::default() },
+        ];
+        sort_flows(&mut flows, FlowSort::parse("duration").unwrap());
+        assert_eq!(flows[0].duration_sec, 9.0);
+        sort_flows(&mut flows, FlowSort::parse("retransmissions").unwrap());
+        assert_eq!(flows[0].retransmissions, 5);
+        sort_flows(&mut flows, FlowSort::parse("rtt").unwrap());
+        assert_eq!(flows[0].handshake_rtt_ms, Some(12.0));
+        assert!(FlowSort::parse("jitter").is_err());
+    }
+}
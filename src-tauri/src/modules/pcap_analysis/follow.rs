@@ -0,0 +1,172 @@
+use etherparse::{NetHeaders, TransportHeader};
+
+use crate::modules::utils::{PcapStream, PcapStreamSegment};
+use super::detail::hex_dump;
+use super::endpoint;
+use super::index::{read_frame, PacketIndex};
+use super::link::decode_packet;
+use super::tcp::seq_gt;
+
+// Keeps a runaway bulk transfer from producing a response the UI can't render.
+const MAX_FOLLOW_BYTES: u64 = 16 * 1024 * 1024;
+// Out-of-order segments held for a gap before the gap is assumed lost.
+const MAX_HELD_SEGMENTS: usize = 256;
+
+/// One direction of a TCP conversation, put back in sequence order.
+#[derive(Default)]
+struct Direction {
+    next_seq: Option<u32>,
+    held: Vec<(u32, Vec<u8>, f64)>,
+}
+
+impl Direction {
+    /// Adds a segment and appends whatever bytes are now contiguous to `out`.
+    fn push(&mut self, seq: u32, syn: bool, payload: &[u8], ts: f64, out: &mut Vec<(Vec<u8>, f64)>) {
+        let seq = if syn {
+            self.next_seq = Some(seq.wrapping_add(1));
+            seq.wrapping_add(1)
+        } else {
+            seq
+        };
+        if payload.is_empty() { return; }
+
+        let expected = *self.next_seq.get_or_insert(seq);
+        if seq_gt(seq, expected) {
+            self.held.push((seq, payload.to_vec(), ts));
+            if self.held.len() > MAX_HELD_SEGMENTS { self.flush(out); }
+            return;
+        }
+        self.append(seq, payload, ts, out);
+        while let Some(i) = self.held.iter().position(|(s, _, _)| !seq_gt(*s, self.next_seq.unwrap_or(*s))) {
+            let (s, data, held_ts) = self.held.swap_remove(i);
+            self.append(s, &data, held_ts, out);
+        }
+    }
+
+    /// Emits held segments in sequence order, skipping over any gaps.
+    fn flush(&mut self, out: &mut Vec<(Vec<u8>, f64)>) {
+        let base = self.next_seq.unwrap_or(0);
+        self.held.sort_by_key(|(s, _, _)| s.wrapping_sub(base));
+        for (seq, data, ts) in std::mem::take(&mut self.held) {
+            if self.next_seq.is_some_and(|next| seq_gt(seq, next)) {
+                self.next_seq = Some(seq);
+            }
+            self.append(seq, &data, ts, out);
+        }
+    }
+
+    /// Appends the part of a segment not already delivered (retransmissions overlap).
+    fn append(&mut self, seq: u32, payload: &[u8], ts: f64, out: &mut Vec<(Vec<u8>, f64)>) {
+        let expected = self.next_seq.unwrap_or(seq);
+        let overlap = if seq_gt(expected, seq) { expected.wrapping_sub(seq) as usize } else { 0 };
+        let Some(new) = payload.get(overlap..).filter(|d| !d.is_empty()) else { return };
+        self.next_seq = Some(expected.wrapping_add(new.len() as u32));
+        out.push((new.to_vec(), ts));
+    }
+}
+
+/// Reassembles the conversation that packet `number` belongs to. `format` is "ascii" or "hex".
+pub fn follow_stream(index: &PacketIndex, file_path: &str, number: usize, format: &str) -> Result<PcapStream, String> {
+    if format != "ascii" && format != "hex" {
+        return Err(format!("Unknown stream format '{}'", format));
+    }
+    let stream = index.get(number)
+        .ok_or_else(|| format!("No packet {} in this capture", number))?
+        .stream()
+        .ok_or_else(|| format!("Packet {} is not part of a TCP or UDP conversation", number))?;
+    let mut file = index.open(file_path)?;
+
+    let mut result = PcapStream {
+        protocol: String::new(),
+        client: String::new(),
+        server: String::new(),
+        format: format.to_string(),
+        segments: Vec::new(),
+        bytes_to_server: 0,
+        bytes_to_client: 0,
+        truncated: false,
+    };
+    let mut raw: Vec<(bool, f64, Vec<u8>)> = Vec::new();
+    let (mut to_server, mut to_client) = (Direction::default(), Direction::default());
+
+    for (_, entry) in index.stream_packets(stream) {
+        let data = read_frame(&mut file, entry)?;
+        let Some(headers) = decode_packet(entry.linktype, &data) else { continue };
+        let (s_ip, d_ip) = match &headers.net {
+            Some(NetHeaders::Ipv4(ip, _)) => (std::net::Ipv4Addr::from(ip.source).to_string(), std::net::Ipv4Addr::from(ip.destination).to_string()),
+            Some(NetHeaders::Ipv6(ip, _)) => (std::net::Ipv6Addr::from(ip.source).to_string(), std::net::Ipv6Addr::from(ip.destination).to_string()),
+            _ => continue,
+        };
+        let payload = headers.payload.slice();
+
+        let (protocol, src, dst, tcp) = match &headers.transport {
+            Some(TransportHeader::Tcp(t)) => ("TCP", endpoint(&s_ip, t.source_port), endpoint(&d_ip, t.destination_port), Some(t)),
+            Some(TransportHeader::Udp(u)) => ("UDP", endpoint(&s_ip, u.source_port), endpoint(&d_ip, u.destination_port), None),
+            _ => continue,
+        };
+        if result.client.is_empty() {
+            // As in the flow table: whoever answers a SYN is the server.
+            let swap = tcp.is_some_and(|t| t.syn && t.ack);
+            let (client, server) = if swap { (&dst, &src) } else { (&src, &dst) };
+            result.protocol = protocol.to_string();
+            result.client = client.clone();
+            result.server = server.clone();
+        }
+        let from_client = src == result.client;
+
+        let mut chunks = Vec::new();
+        match tcp {
+            Some(t) => {
+                let dir = if from_client { &mut to_server } else { &mut to_client };
+                dir.push(t.sequence_number, t.syn, payload, entry.ts, &mut chunks);
+            },
+            None if !payload.is_empty() => chunks.push((payload.to_vec(), entry.ts)),
+            None => {},
+        }
+        if add_chunks(&mut result, &mut raw, from_client, chunks) { break; }
+    }
+
+    if !result.truncated {
+        let mut rest = Vec::new();
+        to_server.flush(&mut rest);
+        if !add_chunks(&mut result, &mut raw, true, rest) {
+            let mut rest = Vec::new();
+            to_client.flush(&mut rest);
+            add_chunks(&mut result, &mut raw, false, rest);
+        }
+    }
+
+    result.segments = raw.into_iter().map(|(from_client, timestamp, bytes)| PcapStreamSegment {
+        from_client,
+        timestamp,
+        data: if format == "hex" { hex_dump(&bytes) } else { printable(&bytes) },
+    }).collect();
+    Ok(result)
+}
+
+/// Records reassembled bytes, merging consecutive TCP data in the same direction.
+/// Returns true once the size limit is reached.
+fn add_chunks(result: &mut PcapStream, raw: &mut Vec<(bool, f64, Vec<u8>)>, from_client: bool, chunks: Vec<(Vec<u8>, f64)>) -> bool {
+    for (mut bytes, ts) in chunks {
+        let total = result.bytes_to_server + result.bytes_to_client;
+        if total + bytes.len() as u64 > MAX_FOLLOW_BYTES {
+            bytes.truncate((MAX_FOLLOW_BYTES - total) as usize);
+            result.truncated = true;
+        }
+        if from_client { result.bytes_to_server += bytes.len() as u64; } else { result.bytes_to_client += bytes.len() as u64; }
+        match raw.last_mut() {
+            Some(last) if result.protocol == "TCP" && last.0 == from_client => last.2.extend_from_slice(&bytes),
+            _ => raw.push((from_client, ts, bytes)),
+        }
+        if result.truncated { return true; }
+    }
+    false
+}
+
+/// Text as-is, with other bytes shown as dots.
+fn printable(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| match b {
+        b'\n' | b'\r' | b'\t' | 0x20..=0x7e => b as char,
+        _ => '.',
+    }).collect()
+}
@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use etherparse::{Icmpv4Header, Icmpv6Header, Ipv6FragmentHeader, TcpHeader, TransportHeader, UdpHeader};
+
+use crate::modules::utils::{FragmentationStats, FragmentedFlow, PcapIssue};
+use super::endpoint;
+
+// Linux's default ipfrag_time; RFC 791 only asks for "a few tens of seconds".
+const REASSEMBLY_TIMEOUT: f64 = 30.0;
+// Bounds memory on captures full of orphaned fragments.
+const MAX_PENDING: usize = 4096;
+const SWEEP_INTERVAL: f64 = 1.0;
+
+/// The fragmentation fields of one IPv4 packet or IPv6 Fragment extension header.
+pub struct Fragment {
+    pub id: u32,
+    pub offset: u32, // bytes
+    pub more: bool,
+    pub protocol: u8,
+}
+
+impl Fragment {
+    /// etherparse 0.19 reads the IPv6 offset and M flag from the wrong bits, but writes them
+    /// back symmetrically, so decode the original header bytes per RFC 8200 instead.
+    pub fn from_ipv6(header: &Ipv6FragmentHeader) -> Self {
+        let raw = header.to_bytes();
+        let field = u16::from_be_bytes([raw[2], raw[3]]);
+        Fragment {
+            id: header.identification,
+            offset: (field & 0xfff8) as u32,
+            more: field & 0x1 != 0,
+            protocol: header.next_header.0,
+        }
+    }
+
+    pub fn is_fragment(&self) -> bool {
+        self.more || self.offset != 0
+    }
+}
+
+/// A datagram whose fragments have all arrived.
+pub struct Reassembled {
+    pub protocol: u8,
+    pub data: Vec<u8>,
+    pub wire_bytes: u64,
+}
+
+struct Pending {
+    source: String,
+    destination: String,
+    protocol: u8,
+    first_ts: f64,
+    pieces: Vec<(u32, Vec<u8>)>,
+    total_len: Option<u32>,
+    received: u32,
+    fragments: usize,
+    wire_bytes: u64,
+    largest_fragment: u64,
+    ports: Option<(u16, u16)>,
+    overlap: bool,
+    conflict: bool,
+}
+
+impl Pending {
+    fn flow_key(&self) -> (String, String, u8) {
+        match self.ports {
+            Some((s, d)) => (endpoint(&self.source, s), endpoint(&self.destination, d), self.protocol),
+            None => (self.source.clone(), self.destination.clone(), self.protocol),
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        let Some(total) = self.total_len else { return false };
+        let mut covered = 0;
+        let mut ranges: Vec<(u32, u32)> = self.pieces.iter().map(|(o, d)| (*o, o + d.len() as u32)).collect();
+        ranges.sort_unstable();
+        for (start, end) in ranges {
+            if start > covered { return false; }
+            covered = covered.max(end);
+        }
+        covered >= total
+    }
+
+    /// Earlier fragments win where they overlap, as on most stacks.
+    fn assemble(&mut self) -> Vec<u8> {
+        let total = self.total_len.unwrap_or(0) as usize;
+        let mut data = vec![0u8; total];
+        let mut filled = vec![false; total];
+        for (offset, piece) in &self.pieces {
+            for (i, byte) in piece.iter().enumerate() {
+                let at = *offset as usize + i;
+                if at < total && !filled[at] {
+                    data[at] = *byte;
+                    filled[at] = true;
+                }
+            }
+        }
+        data
+    }
+}
+
+#[derive(Default)]
+struct FlowCounters {
+    fragments: usize,
+    datagrams: usize,
+    reassembled: usize,
+    max_datagram_size: u32,
+    max_fragment_size: u64,
+}
+
+/// IPv4/IPv6 fragment reassembly keyed by (source, destination, protocol, identification).
+#[derive(Default)]
+pub struct FragmentReassembler {
+    pending: HashMap<String, Pending>,
+    flows: HashMap<(String, String, u8), FlowCounters>,
+    stats: FragmentationStats,
+    // (flow, bytes received, expected total if known)
+    incomplete_examples: Vec<(String, u32, Option<u32>)>,
+    last_sweep: f64,
+}
+
+impl FragmentReassembler {
+    pub fn process(
+        &mut self, src_ip: &str, dst_ip: &str, frag: Fragment, payload: &[u8], wire_len: u64, ts: f64,
+    ) -> Option<Reassembled> {
+        self.stats.fragments += 1;
+        if ts - self.last_sweep >= SWEEP_INTERVAL {
+            self.sweep(ts);
+            self.last_sweep = ts;
+        }
+
+        let key = format!("{}|{}|{}|{}", src_ip, dst_ip, frag.protocol, frag.id);
+        if !self.pending.contains_key(&key) {
+            if self.pending.len() >= MAX_PENDING {
+                self.stats.dropped += 1;
+                return None;
+            }
+            self.pending.insert(key.clone(), Pending {
+                source: src_ip.to_string(),
+                destination: dst_ip.to_string(),
+                protocol: frag.protocol,
+                first_ts: ts,
+                pieces: Vec::new(),
+                total_len: None,
+                received: 0,
+                fragments: 0,
+                wire_bytes: 0,
+                largest_fragment: 0,
+                ports: None,
+                overlap: false,
+                conflict: false,
+            });
+        }
+        let dgram = self.pending.get_mut(&key)?;
+
+        let end = frag.offset + payload.len() as u32;
+        for (offset, piece) in &dgram.pieces {
+            let (start, stop) = (frag.offset.max(*offset), end.min(offset + piece.len() as u32));
+            if start < stop {
+                dgram.overlap = true;
+                let ours = &payload[(start - frag.offset) as usize..(stop - frag.offset) as usize];
+                let theirs = &piece[(start - offset) as usize..(stop - offset) as usize];
+                if ours != theirs { dgram.conflict = true; }
+            }
+        }
+        if !frag.more {
+            // Two different "last" fragments is a conflict too.
+            if dgram.total_len.is_some_and(|t| t != end) { dgram.conflict = true; }
+            dgram.total_len.get_or_insert(end);
+        }
+        if frag.offset == 0 && payload.len() >= 4 && matches!(frag.protocol, 6 | 17) {
+            dgram.ports = Some((
+                u16::from_be_bytes([payload[0], payload[1]]),
+                u16::from_be_bytes([payload[2], payload[3]]),
+            ));
+        }
+        dgram.pieces.push((frag.offset, payload.to_vec()));
+        dgram.received += payload.len() as u32;
+        dgram.fragments += 1;
+        dgram.wire_bytes += wire_len;
+        dgram.largest_fragment = dgram.largest_fragment.max(wire_len);
+
+        if !dgram.is_complete() { return None; }
+        let mut dgram = self.pending.remove(&key)?;
+        let data = dgram.assemble();
+        self.stats.reassembled += 1;
+        let flow = self.close(&dgram);
+        flow.reassembled += 1;
+        flow.max_datagram_size = flow.max_datagram_size.max(data.len() as u32);
+        Some(Reassembled { protocol: dgram.protocol, data, wire_bytes: dgram.wire_bytes })
+    }
+
+    /// Folds a finished (complete or abandoned) datagram into the per-flow counters.
+    fn close(&mut self, dgram: &Pending) -> &mut FlowCounters {
+        if dgram.overlap { self.stats.overlapping += 1; }
+        if dgram.conflict { self.stats.conflicting += 1; }
+        let flow = self.flows.entry(dgram.flow_key()).or_default();
+        flow.fragments += dgram.fragments;
+        flow.datagrams += 1;
+        flow.max_fragment_size = flow.max_fragment_size.max(dgram.largest_fragment);
+        flow
+    }
+
+    fn abandon(&mut self, dgram: Pending, timed_out: bool) {
+        if timed_out { self.stats.timed_out += 1; } else { self.stats.incomplete += 1; }
+        let (src, dst, _) = dgram.flow_key();
+        if self.incomplete_examples.len() < 5 {
+            self.incomplete_examples.push((format!("{} -> {}", src, dst), dgram.received, dgram.total_len));
+        }
+        self.close(&dgram);
+    }
+
+    fn sweep(&mut self, now: f64) {
+        let expired: Vec<String> = self.pending.iter()
+            .filter(|(_, d)| now - d.first_ts > REASSEMBLY_TIMEOUT)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in expired {
+            if let Some(dgram) = self.pending.remove(&key) {
+                self.abandon(dgram, true);
+            }
+        }
+    }
+
+    pub fn finish(mut self, capture_end: f64, issues: &mut Vec<PcapIssue>) -> FragmentationStats {
+        self.sweep(capture_end);
+        let leftover: Vec<Pending> = self.pending.drain().map(|(_, d)| d).collect();
+        for dgram in leftover {
+            self.abandon(dgram, false);
+        }
+
+        let mut flows: Vec<FragmentedFlow> = self.flows.into_iter().map(|((source, destination, protocol), c)| FragmentedFlow {
+            source,
+            destination,
+            protocol: protocol_name(protocol),
+            fragments: c.fragments,
+            datagrams: c.datagrams,
+            reassembled: c.reassembled,
+            max_datagram_size: c.max_datagram_size,
+            max_fragment_size: c.max_fragment_size,
+        }).collect();
+        flows.sort_by(|a, b| b.fragments.cmp(&a.fragments).then_with(|| a.source.cmp(&b.source)));
+
+        let stats = &self.stats;
+        if let Some(biggest) = flows.iter().filter(|f| f.reassembled > 0).max_by_key(|f| f.max_datagram_size) {
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "IP Fragmentation".into(),
+                description: format!(
+                    "{} datagrams arrived in {} fragments. Largest: {} bytes from {} to {} split into fragments of up to {} bytes on the wire. Check path MTU and PMTUD.",
+                    stats.reassembled + stats.incomplete + stats.timed_out, stats.fragments,
+                    biggest.max_datagram_size, biggest.source, biggest.destination, biggest.max_fragment_size
+                ),
+                timestamp: None,
+            });
+        }
+
+        let lost = stats.incomplete + stats.timed_out + stats.dropped;
+        if lost > 0 {
+            let examples: Vec<String> = self.incomplete_examples.iter().map(|(flow, got, total)| match total {
+                Some(total) => format!("{} ({} of {} bytes)", flow, got, total),
+                None => format!("{} ({} bytes, last fragment missing)", flow, got),
+            }).collect();
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "Incomplete Fragmented Datagrams".into(),
+                description: format!(
+                    "{} datagrams never reassembled ({} timed out after {}s, {} still pending at end of capture): {}. Lost fragments usually mean a middlebox is dropping them.",
+                    lost, stats.timed_out, REASSEMBLY_TIMEOUT, stats.incomplete + stats.dropped, examples.join(", ")
+                ),
+                timestamp: None,
+            });
+        }
+
+        if stats.overlapping > 0 {
+            issues.push(PcapIssue {
+                severity: if stats.conflicting > 0 { "critical".into() } else { "warn".into() },
+                title: "Overlapping IP Fragments".into(),
+                description: format!(
+                    "{} datagrams had overlapping fragments, {} with conflicting data (possible IDS evasion or Teardrop-style attack).",
+                    stats.overlapping, stats.conflicting
+                ),
+                timestamp: None,
+            });
+        }
+
+        FragmentationStats { flows, ..self.stats }
+    }
+}
+
+/// Parses the transport header at the start of a reassembled datagram.
+pub fn parse_transport(protocol: u8, data: &[u8]) -> Option<(TransportHeader, &[u8])> {
+    match protocol {
+        1 => Icmpv4Header::from_slice(data).ok().map(|(h, rest)| (TransportHeader::Icmpv4(h), rest)),
+        6 => TcpHeader::from_slice(data).ok().map(|(h, rest)| (TransportHeader::Tcp(h), rest)),
+        17 => UdpHeader::from_slice(data).ok().map(|(h, rest)| (TransportHeader::Udp(h), rest)),
+        58 => Icmpv6Header::from_slice(data).ok().map(|(h, rest)| (TransportHeader::Icmpv6(h), rest)),
+        _ => None,
+    }
+}
+
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        1 => "ICMP".into(),
+        6 => "TCP".into(),
+        17 => "UDP".into(),
+        58 => "ICMPv6".into(),
+        other => format!("IP proto {}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frag(id: u32, offset: u32, more: bool) -> Fragment {
+        Fragment { id, offset, more, protocol: 17 }
+    }
+
+    fn udp_datagram(len: usize) -> Vec<u8> {
+        let mut data = vec![0x13, 0x88, 0x00, 0x35]; // 5000 -> 53
+        data.extend_from_slice(&(len as u16).to_be_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data.extend((8..len).map(|i| i as u8));
+        data
+    }
+
+    #[test]
+    fn test_out_of_order_reassembly() {
+        let data = udp_datagram(3000);
+        let mut r = FragmentReassembler::default();
+        assert!(r.process("10.0.0.1", "10.0.0.2", frag(7, 2960, false), &data[2960..], 74, 0.0).is_none());
+        assert!(r.process("10.0.0.1", "10.0.0.2", frag(7, 1480, true), &data[1480..2960], 1514, 0.001).is_none());
+        let done = r.process("10.0.0.1", "10.0.0.2", frag(7, 0, true), &data[..1480], 1514, 0.002).unwrap();
+        assert_eq!(done.data, data);
+        assert_eq!(done.wire_bytes, 74 + 1514 * 2);
+
+        let (transport, payload) = parse_transport(done.protocol, &done.data).unwrap();
+        assert!(matches!(transport, TransportHeader::Udp(ref u) if u.destination_port == 53));
+        assert_eq!(payload.len(), 2992);
+
+        let mut issues = Vec::new();
+        let stats = r.finish(1.0, &mut issues);
+        assert_eq!((stats.fragments, stats.reassembled, stats.incomplete), (3, 1, 0));
+        let flow = &stats.flows[0];
+        assert_eq!((flow.source.as_str(), flow.destination.as_str()), ("10.0.0.1:5000", "10.0.0.2:53"));
+        assert_eq!((flow.fragments, flow.max_datagram_size, flow.max_fragment_size), (3, 3000, 1514));
+        let issue = issues.iter().find(|i| i.title == "IP Fragmentation").unwrap();
+        assert!(issue.description.contains("Largest: 3000 bytes"));
+    }
+
+    #[test]
+    fn test_overlap_conflict_timeout_and_incomplete() {
+        let data = udp_datagram(2000);
+        let mut r = FragmentReassembler::default();
+
+        // Overlapping tail rewrites bytes 1000..1100 with different data.
+        r.process("10.0.0.1", "10.0.0.2", frag(1, 0, true), &data[..1100], 1134, 0.0);
+        let mut evil = data[1000..].to_vec();
+        evil[0] ^= 0xff;
+        let done = r.process("10.0.0.1", "10.0.0.2", frag(1, 1000, false), &evil, 1034, 0.1).unwrap();
+        assert_eq!(done.data, data, "first copy of overlapping bytes wins");
+
+        // Never completed: one times out, one is still waiting when the capture ends.
+        r.process("10.0.0.3", "10.0.0.2", frag(2, 0, true), &data[..1480], 1514, 1.0);
+        r.process("10.0.0.3", "10.0.0.2", frag(3, 1480, false), &data[1480..], 554, 50.0);
+
+        let mut issues = Vec::new();
+        let stats = r.finish(55.0, &mut issues);
+        assert_eq!((stats.overlapping, stats.conflicting), (1, 1));
+        assert_eq!((stats.timed_out, stats.incomplete), (1, 1));
+        assert!(issues.iter().any(|i| i.title == "Overlapping IP Fragments" && i.severity == "critical"));
+        let lost = issues.iter().find(|i| i.title == "Incomplete Fragmented Datagrams").unwrap();
+        assert!(lost.description.contains("10.0.0.3:5000 -> 10.0.0.2:53 (1480 bytes, last fragment missing)"));
+        assert!(lost.description.contains("10.0.0.3 -> 10.0.0.2 (520 of 2000 bytes)"));
+    }
+}
@@ -0,0 +1,637 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use etherparse::TcpHeader;
+
+use crate::modules::utils::{HttpTransaction, PcapIssue};
+use super::tcp::{seq_gt, seq_lt};
+
+// Larger header blocks are almost certainly not HTTP/1.x; stop parsing the direction.
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+// Segments held while waiting for a gap to fill before the stream is given up on.
+const MAX_OUT_OF_ORDER: usize = 64;
+
+const SLOW_RESPONSE_MS: f64 = 2000.0;
+const BURST_WINDOW_SEC: f64 = 10.0;
+const BURST_MIN_ERRORS: usize = 5;
+
+const METHODS: [&str; 9] = ["GET", "POST", "PUT", "DELETE", "HEAD", "OPTIONS", "PATCH", "CONNECT", "TRACE"];
+// Query parameter names that carry a secret; matched case-insensitively.
+const SECRET_PARAMS: [&str; 11] = [
+    "password", "passwd", "pwd", "pass", "secret", "token", "access_token",
+    "api_key", "apikey", "auth", "session",
+];
+
+/// The start line and headers of one request or response.
+struct Head {
+    start_line: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// `(method, uri)` of a request line.
+    fn request_line(&self) -> Option<(&str, &str)> {
+        let mut parts = self.start_line.split(' ');
+        let (method, uri, version) = (parts.next()?, parts.next()?, parts.next()?);
+        version.starts_with("HTTP/1.").then_some((method, uri))
+    }
+
+    fn status(&self) -> Option<u16> {
+        let mut parts = self.start_line.split(' ');
+        if !parts.next()?.starts_with("HTTP/1.") { return None; }
+        parts.next()?.parse().ok()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Chunk {
+    Size,
+    Data(u64),
+    Trailer,
+}
+
+#[derive(Clone, Copy)]
+enum Body {
+    Length(u64),
+    Chunked(Chunk),
+    UntilClose,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Head,
+    Body(Body),
+}
+
+enum Event {
+    Head(Head, f64), // with the time of the message's first byte
+    Complete(u64, f64), // body bytes after de-chunking
+}
+
+/// Reassembles one direction of a TCP stream and splits it into HTTP messages. Bodies
+/// are counted, not kept.
+struct MessageStream {
+    is_response: bool,
+    next_seq: Option<u32>,
+    buf: Vec<u8>,
+    out_of_order: Vec<(u32, Vec<u8>)>,
+    state: State,
+    first_byte_ts: Option<f64>,
+    body_bytes: u64,
+    broken: bool,
+}
+
+impl MessageStream {
+    fn new(is_response: bool) -> Self {
+        MessageStream {
+            is_response,
+            next_seq: None,
+            buf: Vec::new(),
+            out_of_order: Vec::new(),
+            state: State::Head,
+            first_byte_ts: None,
+            body_bytes: 0,
+            broken: false,
+        }
+    }
+
+    fn push(&mut self, seq: u32, payload: &[u8], ts: f64) {
+        if self.broken || payload.is_empty() { return; }
+        let expected = *self.next_seq.get_or_insert(seq);
+        if seq_gt(seq, expected) {
+            self.out_of_order.push((seq, payload.to_vec()));
+            if self.out_of_order.len() > MAX_OUT_OF_ORDER { self.broken = true; }
+            return;
+        }
+        self.append(seq, payload, ts);
+
+        // Drain held segments that the new data made contiguous.
+        while let Some(i) = self.out_of_order.iter().position(|(s, _)| !seq_gt(*s, self.next_seq.unwrap_or(*s))) {
+            let (s, data) = self.out_of_order.swap_remove(i);
+            self.append(s, &data, ts);
+        }
+    }
+
+    /// Appends the part of a segment beyond what is already buffered.
+    fn append(&mut self, seq: u32, payload: &[u8], ts: f64) {
+        let expected = self.next_seq.unwrap_or(seq);
+        let overlap = if seq_lt(seq, expected) { expected.wrapping_sub(seq) as usize } else { 0 };
+        let Some(new) = payload.get(overlap..).filter(|d| !d.is_empty()) else { return };
+        if self.buf.is_empty() && matches!(self.state, State::Head) && self.first_byte_ts.is_none() {
+            self.first_byte_ts = Some(ts);
+        }
+        self.buf.extend_from_slice(new);
+        self.next_seq = Some(expected.wrapping_add(new.len() as u32));
+    }
+
+    /// Returns the next parsed message event, or `None` until more data arrives.
+    /// `bodyless` marks a response to HEAD, which never carries a body.
+    fn next_event(&mut self, ts: f64, bodyless: bool) -> Option<Event> {
+        while !self.broken {
+            match self.state {
+                State::Head => {
+                    let Some(end) = find(&self.buf, b"\r\n\r\n") else {
+                        if self.buf.len() > MAX_HEAD_BYTES { self.broken = true; }
+                        return None;
+                    };
+                    let Some(head) = parse_head(&self.buf[..end]) else {
+                        self.broken = true;
+                        return None;
+                    };
+                    self.buf.drain(..end + 4);
+                    self.body_bytes = 0;
+                    self.state = State::Body(self.body_kind(&head, bodyless));
+                    let first = self.first_byte_ts.take().unwrap_or(ts);
+                    return Some(Event::Head(head, first));
+                },
+                State::Body(Body::Length(remaining)) => {
+                    let left = self.consume(remaining);
+                    if left > 0 {
+                        self.state = State::Body(Body::Length(left));
+                        return None;
+                    }
+                    return Some(self.complete(ts));
+                },
+                State::Body(Body::Chunked(Chunk::Size)) => {
+                    let line = self.take_line()?;
+                    if line.is_empty() { continue; } // CRLF closing the previous chunk
+                    let size = line.split(';').next().and_then(|s| u64::from_str_radix(s.trim(), 16).ok());
+                    self.state = match size {
+                        Some(0) => State::Body(Body::Chunked(Chunk::Trailer)),
+                        Some(n) => State::Body(Body::Chunked(Chunk::Data(n))),
+                        None => {
+                            self.broken = true;
+                            return None;
+                        },
+                    };
+                },
+                State::Body(Body::Chunked(Chunk::Data(remaining))) => {
+                    let left = self.consume(remaining);
+                    self.state = State::Body(Body::Chunked(if left > 0 { Chunk::Data(left) } else { Chunk::Size }));
+                    if left > 0 { return None; }
+                },
+                State::Body(Body::Chunked(Chunk::Trailer)) => {
+                    if self.take_line()?.is_empty() { return Some(self.complete(ts)); }
+                },
+                State::Body(Body::UntilClose) => {
+                    self.body_bytes += self.buf.len() as u64;
+                    self.buf.clear();
+                    return None;
+                },
+            }
+        }
+        None
+    }
+
+    /// Ends a body delimited by the connection closing.
+    fn close(&mut self, ts: f64) -> Option<Event> {
+        if self.broken { return None; }
+        match self.state {
+            State::Body(Body::UntilClose) => {
+                self.body_bytes += self.buf.len() as u64;
+                self.buf.clear();
+                Some(self.complete(ts))
+            },
+            _ => None,
+        }
+    }
+
+    fn body_kind(&self, head: &Head, bodyless: bool) -> Body {
+        if self.is_response {
+            let status = head.status().unwrap_or(0);
+            if bodyless || (100..200).contains(&status) || status == 204 || status == 304 {
+                return Body::Length(0);
+            }
+        }
+        if head.header("Transfer-Encoding").is_some_and(|v| v.to_ascii_lowercase().contains("chunked")) {
+            return Body::Chunked(Chunk::Size);
+        }
+        match head.header("Content-Length").and_then(|v| v.trim().parse().ok()) {
+            Some(len) => Body::Length(len),
+            None if self.is_response => Body::UntilClose,
+            None => Body::Length(0),
+        }
+    }
+
+    /// Counts up to `remaining` buffered body bytes; returns how many are still to come.
+    fn consume(&mut self, remaining: u64) -> u64 {
+        let take = remaining.min(self.buf.len() as u64);
+        self.buf.drain(..take as usize);
+        self.body_bytes += take;
+        remaining - take
+    }
+
+    fn take_line(&mut self) -> Option<String> {
+        let end = find(&self.buf, b"\r\n")?;
+        let line = String::from_utf8_lossy(&self.buf[..end]).to_string();
+        self.buf.drain(..end + 2);
+        Some(line)
+    }
+
+    fn complete(&mut self, ts: f64) -> Event {
+        self.state = State::Head;
+        if !self.buf.is_empty() { self.first_byte_ts = Some(ts); }
+        Event::Complete(self.body_bytes, ts)
+    }
+}
+
+struct Transaction {
+    record: HttpTransaction,
+    is_head: bool,
+    request_done_ts: Option<f64>,
+}
+
+struct HttpFlow {
+    client: String,
+    first_seq: u32, // of the opening request, to tell a retransmitted SYN from port reuse
+    requests: MessageStream,
+    responses: MessageStream,
+    // Requests still waiting for a response, oldest first (pipelining).
+    pending: VecDeque<usize>,
+    // The transaction whose response body is being read.
+    answering: Option<usize>,
+}
+
+#[derive(Default)]
+struct Finding {
+    count: usize,
+    first_ts: f64,
+    examples: Vec<String>,
+}
+
+impl Finding {
+    fn add(&mut self, ts: f64, example: String) {
+        if self.count == 0 { self.first_ts = ts; }
+        self.count += 1;
+        if !self.examples.contains(&example) { self.examples.push(example); }
+    }
+}
+
+/// Pairs HTTP/1.x requests with their responses on every TCP flow, regardless of port.
+#[derive(Default)]
+pub struct HttpAnalyzer {
+    flows: HashMap<String, HttpFlow>,
+    transactions: Vec<Transaction>,
+    auth: BTreeMap<String, Finding>, // by scheme
+    query_credentials: Finding,
+}
+
+impl HttpAnalyzer {
+    /// `src` and `dst` are `ip:port` endpoints of the segment.
+    pub fn process_segment(&mut self, src: &str, dst: &str, tcp: &TcpHeader, payload: &[u8], ts: f64) {
+        let key = if src < dst { format!("{} <-> {}", src, dst) } else { format!("{} <-> {}", dst, src) };
+        // Data on a SYN (TCP Fast Open) starts one past the ISN.
+        let seq = tcp.sequence_number.wrapping_add(tcp.syn as u32);
+
+        // A fresh client SYN is a new connection reusing the ports; the old one is over.
+        if tcp.syn && !tcp.ack && self.flows.get(&key).is_some_and(|f| f.first_seq != seq) {
+            if let Some(mut old) = self.flows.remove(&key) {
+                self.close_body(&mut old);
+            }
+        }
+
+        if !self.flows.contains_key(&key) {
+            // The client speaks first, so a request line opens the flow.
+            if !looks_like_request(payload) { return; }
+            self.flows.insert(key.clone(), HttpFlow {
+                client: src.to_string(),
+                first_seq: seq,
+                requests: MessageStream::new(false),
+                responses: MessageStream::new(true),
+                pending: VecDeque::new(),
+                answering: None,
+            });
+        }
+        // Taken out of the map while its events update the rest of the analyzer.
+        let Some(mut flow) = self.flows.remove(&key) else { return };
+
+        if flow.client == src {
+            flow.requests.push(seq, payload, ts);
+            while let Some(event) = flow.requests.next_event(ts, false) {
+                match event {
+                    Event::Head(head, first_ts) => {
+                        let Some(txn) = self.open_transaction(src, dst, &head, first_ts) else { continue };
+                        flow.pending.push_back(txn);
+                    },
+                    Event::Complete(_, done_ts) => {
+                        if let Some(&txn) = flow.pending.back() {
+                            self.transactions[txn].request_done_ts = Some(done_ts);
+                        }
+                    },
+                }
+            }
+        } else {
+            flow.responses.push(seq, payload, ts);
+            loop {
+                let bodyless = flow.pending.front().is_some_and(|&t| self.transactions[t].is_head);
+                let Some(event) = flow.responses.next_event(ts, bodyless) else { break };
+                match event {
+                    Event::Head(head, first_ts) => {
+                        let status = head.status();
+                        // Interim responses don't answer the request; 101 hands the
+                        // connection over to another protocol.
+                        if status.is_some_and(|s| (100..200).contains(&s)) {
+                            if status == Some(101) { flow.requests.broken = true; flow.responses.broken = true; }
+                            continue;
+                        }
+                        let Some(txn) = flow.pending.pop_front() else { continue };
+                        let t = &mut self.transactions[txn];
+                        t.record.status = status;
+                        t.record.content_type = head.header("Content-Type").map(str::to_string);
+                        let sent = t.request_done_ts.unwrap_or(t.record.timestamp);
+                        t.record.response_time_ms = Some((first_ts - sent).max(0.0) * 1000.0);
+                        flow.answering = Some(txn);
+                        // A successful CONNECT turns the flow into a tunnel.
+                        if t.record.method == "CONNECT" && status.is_some_and(|s| (200..300).contains(&s)) {
+                            flow.requests.broken = true;
+                            flow.responses.broken = true;
+                        }
+                    },
+                    Event::Complete(body_bytes, _) => {
+                        if let Some(txn) = flow.answering.take() {
+                            self.transactions[txn].record.body_bytes = Some(body_bytes);
+                        }
+                    },
+                }
+            }
+            if tcp.fin || tcp.rst {
+                if let (Some(Event::Complete(body_bytes, _)), Some(txn)) = (flow.responses.close(ts), flow.answering.take()) {
+                    self.transactions[txn].record.body_bytes = Some(body_bytes);
+                }
+            }
+        }
+        self.flows.insert(key, flow);
+    }
+
+    /// Number of credential findings so far, for the running issue count.
+    pub fn findings(&self) -> usize {
+        self.auth.values().map(|f| f.count).sum::<usize>() + self.query_credentials.count
+    }
+
+    /// Ends a close-delimited response body on a connection that is going away.
+    fn close_body(&mut self, flow: &mut HttpFlow) {
+        if let (Some(Event::Complete(body_bytes, _)), Some(txn)) = (flow.responses.close(0.0), flow.answering.take()) {
+            self.transactions[txn].record.body_bytes = Some(body_bytes);
+        }
+    }
+
+    fn open_transaction(&mut self, client: &str, server: &str, head: &Head, ts: f64) -> Option<usize> {
+        let (method, uri) = head.request_line()?;
+        let host = head.header("Host").map(str::to_string);
+        let target = host.clone().unwrap_or_else(|| server.to_string());
+
+        for name in ["Authorization", "Proxy-Authorization"] {
+            if let Some(scheme) = head.header(name).and_then(|v| v.split_whitespace().next()) {
+                let scheme = match scheme.to_ascii_lowercase().as_str() {
+                    "basic" => "Basic",
+                    "digest" => "Digest",
+                    _ => continue,
+                };
+                self.auth.entry(scheme.to_string()).or_default().add(ts, target.clone());
+            }
+        }
+
+        let secrets = secret_query_params(uri);
+        if !secrets.is_empty() {
+            self.query_credentials.add(ts, format!("{} on {}", secrets.join("/"), target));
+        }
+
+        self.transactions.push(Transaction {
+            record: HttpTransaction {
+                client: client.to_string(),
+                server: server.to_string(),
+                timestamp: ts,
+                method: method.to_string(),
+                host,
+                uri: uri.to_string(),
+                user_agent: head.header("User-Agent").map(str::to_string),
+                ..Default::default()
+            },
+            is_head: method == "HEAD",
+            request_done_ts: None,
+        });
+        Some(self.transactions.len() - 1)
+    }
+
+    pub fn finish(mut self, issues: &mut Vec<PcapIssue>) -> Vec<HttpTransaction> {
+        // Bodies still open at the end of the capture were delimited by a close we never saw.
+        let mut flows = std::mem::take(&mut self.flows);
+        for flow in flows.values_mut() {
+            self.close_body(flow);
+        }
+        let transactions: Vec<HttpTransaction> = self.transactions.into_iter().map(|t| t.record).collect();
+
+        for (scheme, finding) in &self.auth {
+            issues.push(PcapIssue {
+                severity: if scheme == "Basic" { "critical".into() } else { "warn".into() },
+                title: "Cleartext HTTP Authentication".into(),
+                description: format!(
+                    "{} request(s) sent HTTP {} credentials unencrypted to {}.",
+                    finding.count, scheme, summarize(&finding.examples)
+                ),
+                timestamp: Some(finding.first_ts),
+            });
+        }
+
+        let q = &self.query_credentials;
+        if q.count > 0 {
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "Credentials in URL".into(),
+                description: format!(
+                    "{} request(s) carried secrets in the query string ({}); URLs end up in logs and proxies.",
+                    q.count, summarize(&q.examples)
+                ),
+                timestamp: Some(q.first_ts),
+            });
+        }
+
+        error_bursts(&transactions, issues);
+
+        let slow: Vec<&HttpTransaction> = transactions.iter()
+            .filter(|t| t.response_time_ms.is_some_and(|ms| ms > SLOW_RESPONSE_MS))
+            .collect();
+        if let Some(worst) = slow.iter().max_by(|a, b| a.response_time_ms.unwrap_or(0.0).total_cmp(&b.response_time_ms.unwrap_or(0.0))) {
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "Slow HTTP Responses".into(),
+                description: format!(
+                    "{} response(s) took longer than {:.0} s; slowest was {:.1} s for {} {}{}.",
+                    slow.len(), SLOW_RESPONSE_MS / 1000.0, worst.response_time_ms.unwrap_or(0.0) / 1000.0,
+                    worst.method, worst.host.as_deref().unwrap_or(&worst.server), worst.uri
+                ),
+                timestamp: Some(slow[0].timestamp),
+            });
+        }
+
+        transactions
+    }
+}
+
+/// Flags servers that returned `BURST_MIN_ERRORS` or more 5xx responses within `BURST_WINDOW_SEC`.
+fn error_bursts(transactions: &[HttpTransaction], issues: &mut Vec<PcapIssue>) {
+    let mut by_server: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+    for t in transactions.iter().filter(|t| t.status.is_some_and(|s| s >= 500)) {
+        by_server.entry(t.host.as_deref().unwrap_or(&t.server)).or_default().push(t.timestamp);
+    }
+
+    for (server, mut times) in by_server {
+        times.sort_by(f64::total_cmp);
+        let mut start = 0;
+        let mut worst = (0, 0.0);
+        for end in 0..times.len() {
+            while times[end] - times[start] > BURST_WINDOW_SEC { start += 1; }
+            if end - start + 1 > worst.0 { worst = (end - start + 1, times[start]); }
+        }
+        if worst.0 >= BURST_MIN_ERRORS {
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "HTTP 5xx Burst".into(),
+                description: format!(
+                    "{} returned {} server errors within {:.0} s ({} in total).",
+                    server, worst.0, BURST_WINDOW_SEC, times.len()
+                ),
+                timestamp: Some(worst.1),
+            });
+        }
+    }
+}
+
+fn looks_like_request(payload: &[u8]) -> bool {
+    METHODS.iter().any(|m| payload.starts_with(m.as_bytes()) && payload.get(m.len()) == Some(&b' '))
+}
+
+fn parse_head(block: &[u8]) -> Option<Head> {
+    let text = String::from_utf8_lossy(block);
+    let mut lines = text.split("\r\n");
+    let start_line = lines.next()?.to_string();
+    if !start_line.contains("HTTP/1.") { return None; }
+    let headers = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+    Some(Head { start_line, headers })
+}
+
+/// Names of query parameters that look like they carry a password or token.
+fn secret_query_params(uri: &str) -> Vec<String> {
+    let Some((_, query)) = uri.split_once('?') else { return Vec::new() };
+    let mut names: Vec<String> = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('=').filter(|(_, v)| !v.is_empty()).map(|(k, _)| k.to_ascii_lowercase()))
+        .filter(|k| SECRET_PARAMS.contains(&k.as_str()))
+        .collect();
+    names.dedup();
+    names
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn summarize(examples: &[String]) -> String {
+    let mut shown = examples.iter().take(3).cloned().collect::<Vec<_>>().join(", ");
+    if examples.len() > 3 { shown.push_str(&format!(" and {} more", examples.len() - 3)); }
+    shown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C: &str = "10.0.0.5:50000";
+    const S: &str = "93.184.216.34:80";
+
+    fn seg(seq: u32, fin: bool) -> TcpHeader {
+        let mut h = TcpHeader::new(0, 0, seq, 64240);
+        h.ack = true;
+        h.fin = fin;
+        h
+    }
+
+    #[test]
+    fn test_pipelined_chunked_and_close_delimited() {
+        let mut http = HttpAnalyzer::default();
+        let reqs = b"GET /a HTTP/1.1\r\nHost: example.com\r\nUser-Agent: curl/8.0\r\n\r\nHEAD /b HTTP/1.1\r\nHost: example.com\r\n\r\nGET /c HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        // Requests split mid-header across two segments.
+        http.process_segment(C, S, &seg(1, false), &reqs[..30], 1.0);
+        http.process_segment(C, S, &seg(31, false), &reqs[30..], 1.001);
+
+        let resp1 = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+        let resp2 = b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n"; // HEAD: no body follows
+        let resp3 = b"HTTP/1.0 500 Internal Server Error\r\n\r\npartial";
+        http.process_segment(S, C, &seg(1, false), resp1, 1.05);
+        // Retransmission of the first response must not be parsed twice.
+        http.process_segment(S, C, &seg(1, false), resp1, 1.06);
+        let next = 1 + resp1.len() as u32;
+        // Reordered: the third response arrives before the second.
+        http.process_segment(S, C, &seg(next + resp2.len() as u32, false), resp3, 3.5);
+        http.process_segment(S, C, &seg(next, false), resp2, 3.4);
+        http.process_segment(S, C, &seg(next + (resp2.len() + resp3.len()) as u32, true), b" body", 3.6);
+
+        let mut issues = Vec::new();
+        let txns = http.finish(&mut issues);
+        assert_eq!(txns.len(), 3);
+        assert_eq!(txns[0].host.as_deref(), Some("example.com"));
+        assert_eq!(txns[0].user_agent.as_deref(), Some("curl/8.0"));
+        assert_eq!((txns[0].status, txns[0].body_bytes), (Some(200), Some(11)));
+        assert_eq!(txns[0].content_type.as_deref(), Some("text/html"));
+        assert!((txns[0].response_time_ms.unwrap() - 49.0).abs() < 1e-6);
+        assert_eq!((txns[1].method.as_str(), txns[1].body_bytes), ("HEAD", Some(0)));
+        assert_eq!((txns[2].status, txns[2].body_bytes), (Some(500), Some(12)));
+
+        let slow = issues.iter().find(|i| i.title == "Slow HTTP Responses").expect("slow responses flagged");
+        assert!(slow.description.starts_with("2 response(s)"));
+    }
+
+    #[test]
+    fn test_port_reuse_starts_a_new_flow() {
+        let mut http = HttpAnalyzer::default();
+        let req = b"GET /old HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        http.process_segment(C, S, &seg(1001, false), req, 1.0);
+        http.process_segment(S, C, &seg(5001, false), b"HTTP/1.0 200 OK\r\n\r\nclose-delimited", 1.1);
+
+        // Same 4-tuple, new connection with a lower ISN. The SYN retransmitted from the
+        // first connection must not reset it.
+        let mut syn = TcpHeader::new(0, 0, 1000, 64240);
+        syn.syn = true;
+        http.process_segment(C, S, &syn, &[], 1.2);
+        syn.sequence_number = 200;
+        http.process_segment(C, S, &syn, &[], 2.0);
+        http.process_segment(C, S, &seg(201, false), b"GET /new HTTP/1.1\r\nHost: example.com\r\n\r\n", 2.1);
+        http.process_segment(S, C, &seg(301, false), b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n", 2.2);
+
+        let txns = http.finish(&mut Vec::new());
+        assert_eq!(txns.len(), 2);
+        assert_eq!((txns[0].status, txns[0].body_bytes), (Some(200), Some(15)));
+        assert_eq!((txns[1].uri.as_str(), txns[1].status), ("/new", Some(404)));
+    }
+
+    #[test]
+    fn test_credentials_and_error_burst() {
+        let mut http = HttpAnalyzer::default();
+        for i in 0..6u32 {
+            let client = format!("10.0.0.5:{}", 50000 + i);
+            let req = if i == 0 {
+                "GET /login?user=bob&Password=hunter2 HTTP/1.1\r\nHost: intranet\r\n\r\n".to_string()
+            } else {
+                "GET /api HTTP/1.1\r\nHost: intranet\r\nAuthorization: Digest username=\"bob\"\r\n\r\n".to_string()
+            };
+            http.process_segment(&client, S, &seg(1, false), req.as_bytes(), i as f64);
+            http.process_segment(S, &client, &seg(1, false), b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n", i as f64 + 0.1);
+        }
+        assert_eq!(http.findings(), 6);
+
+        let mut issues = Vec::new();
+        http.finish(&mut issues);
+        let auth = issues.iter().find(|i| i.title == "Cleartext HTTP Authentication").unwrap();
+        assert_eq!(auth.severity, "warn");
+        assert!(auth.description.contains("5 request(s) sent HTTP Digest"));
+        let query = issues.iter().find(|i| i.title == "Credentials in URL").unwrap();
+        assert!(query.description.contains("password on intranet"));
+        let burst = issues.iter().find(|i| i.title == "HTTP 5xx Burst").unwrap();
+        assert!(burst.description.starts_with("intranet returned 6 server errors"));
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use etherparse::icmpv4::{DestUnreachableHeader, TimeExceededCode};
+use etherparse::icmpv6::{self, DestUnreachableCode};
+use etherparse::{Icmpv4Type, Icmpv6Type};
+
+use crate::modules::utils::{IcmpErrorFlow, IcmpErrorRouter, IcmpErrorSummary, PcapIssue};
+use super::endpoint;
+
+// Told this often to send smaller packets and still sending them: PMTUD isn't working.
+const PMTUD_REPEATS: usize = 3;
+// UDP ports classic traceroute probes.
+const TRACEROUTE_PORTS: std::ops::RangeInclusive<u16> = 33434..=33534;
+const MAX_TRACKED: usize = 50_000;
+const MAX_FLOWS: usize = 500;
+const MAX_LISTED: usize = 5;
+
+// protocol, source, destination of the quoted packet
+type FlowKey = (String, String, String);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Category {
+    Unreachable,
+    Prohibited,
+    TtlExceeded,
+    TooBig,
+    Redirect,
+    Other,
+}
+
+/// The packet an ICMP error quotes: enough of its IP header and the first
+/// transport bytes to tell which flow it belonged to.
+struct Quoted {
+    protocol: String,
+    source: String,
+    destination: String,
+    // traceroute probes are expected to expire on the way
+    probe: bool,
+}
+
+fn parse_quoted(data: &[u8]) -> Option<Quoted> {
+    let (mut next, src, dst, mut at): (u8, String, String, usize) = match data.first()? >> 4 {
+        4 => {
+            let ihl = (data[0] & 0x0f) as usize * 4;
+            let addr = |i: usize| data.get(i..i + 4).map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3]).to_string());
+            (*data.get(9)?, addr(12)?, addr(16)?, ihl)
+        },
+        6 => {
+            let addr = |i: usize| data.get(i..i + 16)
+                .and_then(|a| <[u8; 16]>::try_from(a).ok())
+                .map(|a| Ipv6Addr::from(a).to_string());
+            (*data.get(6)?, addr(8)?, addr(24)?, 40)
+        },
+        _ => return None,
+    };
+    // Hop-by-hop, routing, fragment and destination options headers.
+    while matches!(next, 0 | 43 | 44 | 60) {
+        let len = if next == 44 { 8 } else { (*data.get(at + 1)? as usize + 1) * 8 };
+        next = *data.get(at)?;
+        at += len;
+    }
+    let ports = data.get(at..at + 4).map(|p| (u16::from_be_bytes([p[0], p[1]]), u16::from_be_bytes([p[2], p[3]])));
+    let quoted = |protocol: &str, source: String, destination: String, probe: bool| Some(Quoted {
+        protocol: protocol.to_string(), source, destination, probe,
+    });
+    match (next, ports) {
+        (6, Some((sport, dport))) => quoted("TCP", endpoint(&src, sport), endpoint(&dst, dport), false),
+        (17, Some((sport, dport))) => quoted("UDP", endpoint(&src, sport), endpoint(&dst, dport), TRACEROUTE_PORTS.contains(&dport)),
+        // Echo requests are what ping and most Windows traceroutes send.
+        (1, _) => quoted("ICMP", src, dst, data.get(at) == Some(&8)),
+        (58, _) => quoted("ICMPv6", src, dst, data.get(at) == Some(&128)),
+        (n, _) => quoted(&format!("IP proto {}", n), src, dst, false),
+    }
+}
+
+/// Decodes ICMPv4 and ICMPv6 error messages and ties each back to the flow whose
+/// packet it quotes, so unreachables, TTL expiry and path MTU problems can be read
+/// per flow and per reporting router.
+#[derive(Default)]
+pub struct IcmpAnalyzer {
+    summary: IcmpErrorSummary,
+    flows: HashMap<FlowKey, IcmpErrorFlow>,
+    routers: HashMap<String, (IcmpErrorRouter, HashSet<FlowKey>)>,
+    // TTL expiry of traffic that isn't traceroute, per router
+    expired: HashMap<String, usize>,
+    // Fragmentation Needed / Packet Too Big per flow
+    too_big: HashMap<FlowKey, usize>,
+}
+
+impl IcmpAnalyzer {
+    /// Returns true if the message was an error.
+    pub fn process_v4(&mut self, router: &str, icmp: &Icmpv4Type, payload: &[u8], ts: f64) -> bool {
+        let (kind, category, mtu) = match icmp {
+            Icmpv4Type::DestinationUnreachable(header) => match header {
+                DestUnreachableHeader::Network => ("Network Unreachable", Category::Unreachable, None),
+                DestUnreachableHeader::Host => ("Host Unreachable", Category::Unreachable, None),
+                DestUnreachableHeader::Protocol => ("Protocol Unreachable", Category::Unreachable, None),
+                DestUnreachableHeader::Port => ("Port Unreachable", Category::Unreachable, None),
+                DestUnreachableHeader::FragmentationNeeded { next_hop_mtu } => {
+                    // Routers predating RFC 1191 leave the MTU at zero.
+                    ("Fragmentation Needed", Category::TooBig, Some(*next_hop_mtu as u32).filter(|&m| m > 0))
+                },
+                DestUnreachableHeader::NetworkProhibited
+                | DestUnreachableHeader::HostProhibited
+                | DestUnreachableHeader::FilterProhibited => ("Administratively Prohibited", Category::Prohibited, None),
+                _ => ("Destination Unreachable", Category::Unreachable, None),
+            },
+            Icmpv4Type::TimeExceeded(TimeExceededCode::TtlExceededInTransit) => ("TTL Exceeded", Category::TtlExceeded, None),
+            Icmpv4Type::TimeExceeded(TimeExceededCode::FragmentReassemblyTimeExceeded) => ("Reassembly Time Exceeded", Category::Other, None),
+            Icmpv4Type::Redirect(_) => ("Redirect", Category::Redirect, None),
+            Icmpv4Type::ParameterProblem(_) => ("Parameter Problem", Category::Other, None),
+            _ => return false,
+        };
+        self.record(router, kind, category, mtu, payload, ts);
+        true
+    }
+
+    /// Returns true if the message was an error.
+    pub fn process_v6(&mut self, router: &str, icmp: &Icmpv6Type, payload: &[u8], ts: f64) -> bool {
+        let (kind, category, mtu) = match icmp {
+            Icmpv6Type::DestinationUnreachable(code) => match code {
+                DestUnreachableCode::NoRoute => ("No Route", Category::Unreachable, None),
+                DestUnreachableCode::Address => ("Address Unreachable", Category::Unreachable, None),
+                DestUnreachableCode::Port => ("Port Unreachable", Category::Unreachable, None),
+                DestUnreachableCode::BeyondScope => ("Beyond Scope", Category::Unreachable, None),
+                DestUnreachableCode::Prohibited
+                | DestUnreachableCode::SourceAddressFailedPolicy
+                | DestUnreachableCode::RejectRoute => ("Administratively Prohibited", Category::Prohibited, None),
+            },
+            Icmpv6Type::PacketTooBig { mtu } => ("Packet Too Big", Category::TooBig, Some(*mtu)),
+            Icmpv6Type::TimeExceeded(icmpv6::TimeExceededCode::HopLimitExceeded) => ("TTL Exceeded", Category::TtlExceeded, None),
+            Icmpv6Type::TimeExceeded(icmpv6::TimeExceededCode::FragmentReassemblyTimeExceeded) => ("Reassembly Time Exceeded", Category::Other, None),
+            Icmpv6Type::ParameterProblem(_) => ("Parameter Problem", Category::Other, None),
+            Icmpv6Type::Redirect => {
+                // Target and destination addresses, then options; the Redirected Header
+                // option (type 4) carries the quoted packet after 6 reserved bytes.
+                let mut at = 32;
+                let mut quoted: &[u8] = &[];
+                while let (Some(&kind), Some(&len)) = (payload.get(at), payload.get(at + 1)) {
+                    let end = (at + len as usize * 8).min(payload.len());
+                    if len == 0 { break; }
+                    if kind == 4 {
+                        quoted = payload.get(at + 8..end).unwrap_or_default();
+                        break;
+                    }
+                    at = end;
+                }
+                self.record(router, "Redirect", Category::Redirect, None, quoted, ts);
+                return true;
+            },
+            _ => return false,
+        };
+        self.record(router, kind, category, mtu, payload, ts);
+        true
+    }
+
+    fn record(&mut self, router: &str, kind: &str, category: Category, mtu: Option<u32>, quoted: &[u8], ts: f64) {
+        self.summary.errors += 1;
+        *self.summary.by_kind.entry(kind.to_string()).or_insert(0) += 1;
+
+        let reporter = self.routers.entry(router.to_string()).or_insert_with(|| (IcmpErrorRouter {
+            router: router.to_string(),
+            errors: 0, unreachable: 0, prohibited: 0, ttl_exceeded: 0, too_big: 0, redirects: 0, flows: 0,
+            next_hop_mtu: None,
+        }, HashSet::new()));
+        let stats = &mut reporter.0;
+        stats.errors += 1;
+        match category {
+            Category::Unreachable => stats.unreachable += 1,
+            Category::Prohibited => stats.prohibited += 1,
+            Category::TtlExceeded => stats.ttl_exceeded += 1,
+            Category::TooBig => stats.too_big += 1,
+            Category::Redirect => stats.redirects += 1,
+            Category::Other => {},
+        }
+        if let Some(mtu) = mtu {
+            stats.next_hop_mtu = Some(stats.next_hop_mtu.map_or(mtu, |m| m.min(mtu)));
+        }
+
+        let Some(packet) = parse_quoted(quoted) else {
+            self.summary.unmatched += 1;
+            return;
+        };
+        if category == Category::TtlExceeded && !packet.probe {
+            *self.expired.entry(router.to_string()).or_insert(0) += 1;
+        }
+        let key = (packet.protocol, packet.source, packet.destination);
+        if self.flows.len() >= MAX_TRACKED && !self.flows.contains_key(&key) {
+            return;
+        }
+        reporter.1.insert(key.clone());
+        if category == Category::TooBig {
+            *self.too_big.entry(key.clone()).or_insert(0) += 1;
+        }
+        let flow = self.flows.entry(key).or_insert_with_key(|(protocol, source, destination)| IcmpErrorFlow {
+            protocol: protocol.clone(),
+            source: source.clone(),
+            destination: destination.clone(),
+            errors: 0,
+            kinds: Vec::new(),
+            reported_by: Vec::new(),
+            next_hop_mtu: None,
+            first_seen: ts,
+            last_seen: ts,
+        });
+        flow.errors += 1;
+        flow.last_seen = ts;
+        if !flow.kinds.iter().any(|k| k == kind) {
+            flow.kinds.push(kind.to_string());
+        }
+        if !flow.reported_by.iter().any(|r| r == router) {
+            flow.reported_by.push(router.to_string());
+        }
+        if let Some(mtu) = mtu {
+            flow.next_hop_mtu = Some(flow.next_hop_mtu.map_or(mtu, |m| m.min(mtu)));
+        }
+    }
+
+    pub fn finish(self, issues: &mut Vec<PcapIssue>) -> IcmpErrorSummary {
+        let mut summary = self.summary;
+        let mut flows: Vec<IcmpErrorFlow> = self.flows.into_values().collect();
+        flows.sort_by(|a, b| b.errors.cmp(&a.errors).then_with(|| a.first_seen.total_cmp(&b.first_seen)));
+        let mut routers: Vec<IcmpErrorRouter> = self.routers.into_values()
+            .map(|(stats, flows)| IcmpErrorRouter { flows: flows.len(), ..stats })
+            .collect();
+        routers.sort_by(|a, b| b.errors.cmp(&a.errors).then_with(|| a.router.cmp(&b.router)));
+
+        let repeats = |f: &IcmpErrorFlow| self.too_big
+            .get(&(f.protocol.clone(), f.source.clone(), f.destination.clone()))
+            .copied()
+            .unwrap_or(0);
+        let ignored: Vec<(&IcmpErrorFlow, usize)> = flows.iter()
+            .map(|f| (f, repeats(f)))
+            .filter(|&(_, n)| n >= PMTUD_REPEATS)
+            .collect();
+        if !ignored.is_empty() {
+            let (first, repeated) = ignored[0];
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "PMTUD Black Hole".into(),
+                description: format!(
+                    "{} flow(s) kept sending packets too big for the path after being told the MTU, e.g. {} {} -> {} ({} errors from {}{}). The sender isn't lowering its packet size: the ICMP may be filtered before reaching it, or it ignores PMTUD. Lower the MTU or clamp the TCP MSS on the tunnel or link.",
+                    ignored.len(), first.protocol, first.source, first.destination, repeated,
+                    first.reported_by.join(", "), first.next_hop_mtu.map(|m| format!(", MTU {}", m)).unwrap_or_default(),
+                ),
+                timestamp: Some(first.first_seen),
+            });
+        }
+        let shrinking: Vec<String> = routers.iter()
+            .filter(|r| r.too_big > 0)
+            .map(|r| match r.next_hop_mtu {
+                Some(mtu) => format!("{} (MTU {}, {} flow(s))", r.router, mtu, r.flows),
+                None => format!("{} (MTU not given, {} flow(s))", r.router, r.flows),
+            })
+            .collect();
+        if !shrinking.is_empty() {
+            issues.push(PcapIssue {
+                severity: "info".into(),
+                title: "Path MTU Reduced".into(),
+                description: format!(
+                    "Routers reported a smaller next-hop MTU: {}. Normal for tunnels and VPNs as long as senders adapt.",
+                    listed(&shrinking),
+                ),
+                timestamp: None,
+            });
+        }
+
+        let filtering: Vec<String> = routers.iter()
+            .filter(|r| r.prohibited > 0)
+            .map(|r| format!("{} ({} packet(s))", r.router, r.prohibited))
+            .collect();
+        if !filtering.is_empty() {
+            let example = flows.iter().find(|f| f.kinds.iter().any(|k| k == "Administratively Prohibited"));
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "Traffic Filtered".into(),
+                description: format!(
+                    "Administratively prohibited by {}{}. An ACL or firewall is rejecting this traffic.",
+                    listed(&filtering),
+                    example.map(|f| format!(", e.g. {} {} -> {}", f.protocol, f.source, f.destination)).unwrap_or_default(),
+                ),
+                timestamp: example.map(|f| f.first_seen),
+            });
+        }
+
+        let unreachable_kinds = ["Network Unreachable", "Host Unreachable", "No Route", "Address Unreachable", "Destination Unreachable"];
+        let unreachable: Vec<&IcmpErrorFlow> = flows.iter()
+            .filter(|f| f.kinds.iter().any(|k| unreachable_kinds.contains(&k.as_str())))
+            .collect();
+        if !unreachable.is_empty() {
+            let mut destinations: Vec<String> = Vec::new();
+            for flow in &unreachable {
+                let host = host_of(&flow.destination);
+                if !destinations.contains(&host) {
+                    destinations.push(host);
+                }
+            }
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "Destination Unreachable".into(),
+                description: format!(
+                    "{} flow(s) were reported unreachable, towards {}. Check routing to those hosts, or whether they are up.",
+                    unreachable.len(), listed(&destinations),
+                ),
+                timestamp: unreachable.iter().map(|f| f.first_seen).reduce(f64::min),
+            });
+        }
+
+        let mut expired: Vec<(String, usize)> = self.expired.into_iter().collect();
+        expired.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        if !expired.is_empty() {
+            let looping: Vec<String> = expired.iter().map(|(router, n)| format!("{} ({})", router, n)).collect();
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "TTL Exceeded".into(),
+                description: format!(
+                    "Packets that aren't traceroute probes expired in transit at {}. Look for a routing loop, or a sender using a very low TTL.",
+                    listed(&looping),
+                ),
+                timestamp: None,
+            });
+        }
+
+        flows.truncate(MAX_FLOWS);
+        summary.flows = flows;
+        summary.routers = routers;
+        summary
+    }
+}
+
+fn host_of(endpoint: &str) -> String {
+    match endpoint.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() && (endpoint.starts_with('[') || !host.contains(':')) => {
+            host.trim_start_matches('[').trim_end_matches(']').to_string()
+        },
+        _ => endpoint.to_string(),
+    }
+}
+
+fn listed(items: &[String]) -> String {
+    match items.len() {
+        n if n > MAX_LISTED => format!("{} (+{} more)", items[..MAX_LISTED].join(", "), n - MAX_LISTED),
+        _ => items.join(", "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::{IpHeaders, PacketBuilder, PacketBuilderStep};
+    use etherparse::icmpv4::{RedirectCode, RedirectHeader};
+
+    // What routers quote: the IP header and the first 8 bytes after it.
+    fn quote(builder: PacketBuilderStep<IpHeaders>, tcp: bool) -> Vec<u8> {
+        let mut packet = Vec::new();
+        if tcp {
+            builder.tcp(50000, 443, 1, 64000).write(&mut packet, &[0; 100]).unwrap();
+        } else {
+            builder.udp(50001, 33440).write(&mut packet, &[0; 32]).unwrap();
+        }
+        packet.truncate(if packet[0] >> 4 == 4 { 28 } else { 48 });
+        packet
+    }
+
+    #[test]
+    fn test_icmp_errors_by_flow_and_router() {
+        let tcp_v4 = quote(PacketBuilder::ipv4([10, 0, 0, 5], [203, 0, 113, 7], 64), true);
+        let probe = quote(PacketBuilder::ipv4([10, 0, 0, 5], [203, 0, 113, 7], 3), false);
+        let tcp_v6 = quote(PacketBuilder::ipv6([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5], [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9], 64), true);
+        let mut icmp = IcmpAnalyzer::default();
+
+        let too_big = Icmpv4Type::DestinationUnreachable(DestUnreachableHeader::FragmentationNeeded { next_hop_mtu: 1400 });
+        for ts in [1.0, 1.2, 1.4] {
+            assert!(icmp.process_v4("10.0.0.1", &too_big, &tcp_v4, ts));
+        }
+        icmp.process_v4("192.0.2.1", &Icmpv4Type::TimeExceeded(TimeExceededCode::TtlExceededInTransit), &probe, 2.0);
+        icmp.process_v4("192.0.2.1", &Icmpv4Type::DestinationUnreachable(DestUnreachableHeader::FilterProhibited), &probe, 2.1);
+        let redirect = Icmpv4Type::Redirect(RedirectHeader { code: RedirectCode::RedirectForHost, gateway_internet_address: [10, 0, 0, 2] });
+        icmp.process_v4("10.0.0.1", &redirect, &tcp_v4, 3.0);
+        icmp.process_v6("2001:db8::1", &Icmpv6Type::PacketTooBig { mtu: 1280 }, &tcp_v6, 4.0);
+        icmp.process_v6("2001:db8::1", &Icmpv6Type::DestinationUnreachable(DestUnreachableCode::NoRoute), &tcp_v6, 4.5);
+        icmp.process_v4("10.0.0.1", &Icmpv4Type::DestinationUnreachable(DestUnreachableHeader::Host), &[0x45, 0], 5.0);
+        assert!(!icmp.process_v6("2001:db8::1", &Icmpv6Type::RouterSolicitation, &[], 6.0));
+
+        let mut issues = Vec::new();
+        let summary = icmp.finish(&mut issues);
+        assert_eq!((summary.errors, summary.unmatched), (9, 1));
+        assert_eq!(summary.by_kind["Fragmentation Needed"], 3);
+        assert_eq!(summary.by_kind["Administratively Prohibited"], 1);
+
+        let flow = &summary.flows[0];
+        assert_eq!((flow.protocol.as_str(), flow.source.as_str(), flow.destination.as_str()), ("TCP", "10.0.0.5:50000", "203.0.113.7:443"));
+        assert_eq!((flow.errors, flow.next_hop_mtu), (4, Some(1400)));
+        assert_eq!(flow.kinds, vec!["Fragmentation Needed", "Redirect"]);
+        let v6 = summary.flows.iter().find(|f| f.protocol == "TCP" && f.source == "[2001:db8::5]:50000").unwrap();
+        assert_eq!((v6.errors, v6.next_hop_mtu), (2, Some(1280)));
+
+        let router = &summary.routers[0];
+        assert_eq!(router.router, "10.0.0.1");
+        assert_eq!((router.errors, router.too_big, router.redirects, router.unreachable, router.flows), (5, 3, 1, 1, 1));
+        let filtering = summary.routers.iter().find(|r| r.router == "192.0.2.1").unwrap();
+        assert_eq!((filtering.ttl_exceeded, filtering.prohibited), (1, 1));
+
+        let titles: Vec<&str> = issues.iter().map(|i| i.title.as_str()).collect();
+        // The expired packet was a traceroute probe, so no TTL issue.
+        assert_eq!(titles, vec!["PMTUD Black Hole", "Path MTU Reduced", "Traffic Filtered", "Destination Unreachable"]);
+        assert!(issues[0].description.contains("TCP 10.0.0.5:50000 -> 203.0.113.7:443 (3 errors from 10.0.0.1, MTU 1400)"));
+        assert!(issues[1].description.contains("10.0.0.1 (MTU 1400, 1 flow(s))"));
+        assert!(issues[3].description.contains("towards 2001:db8::9"));
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use pcap_parser::Linktype;
+
+use super::reader::CapturedPacket;
+
+const NO_STREAM: u32 = u32::MAX;
+
+// Per-packet marks recorded during analysis for the I/O graph.
+pub const RETRANSMISSION: u8 = 1;
+pub const RESET: u8 = 1 << 1;
+pub const DNS_QUERY: u8 = 1 << 2;
+pub const NEW_FLOW: u8 = 1 << 3;
+// Skipped by the display filter, so not part of the analysis.
+pub const FILTERED_OUT: u8 = 1 << 4;
+
+/// Where one packet lives in the capture file.
+pub struct IndexEntry {
+    pub data_offset: u64,
+    pub caplen: u32,
+    pub linktype: Linktype,
+    pub ts: f64,
+    /// Length on the wire.
+    pub wire_len: u32,
+    stream: u32,
+    flags: u8,
+}
+
+impl IndexEntry {
+    pub fn new(data_offset: u64, caplen: u32, linktype: Linktype, ts: f64) -> Self {
+        IndexEntry { data_offset, caplen, linktype, ts, wire_len: caplen, stream: NO_STREAM, flags: 0 }
+    }
+
+    /// The TCP/UDP 5-tuple conversation the packet belongs to, if any.
+    pub fn stream(&self) -> Option<u32> {
+        (self.stream != NO_STREAM).then_some(self.stream)
+    }
+
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+/// Packet offsets recorded during analysis, so single packets and streams can be read
+/// back later without walking the file again. Packet numbers are 1-based, as in Wireshark.
+#[derive(Default)]
+pub struct PacketIndex {
+    entries: Vec<IndexEntry>,
+    streams: HashMap<String, u32>,
+    file_len: u64,
+    modified: Option<SystemTime>,
+    // Packet numbers matching the last display filter the packet list was paged with.
+    filter_matches: Mutex<Option<(String, Arc<Vec<usize>>)>>,
+}
+
+impl PacketIndex {
+    pub fn push(&mut self, packet: &CapturedPacket) {
+        let mut entry = IndexEntry::new(packet.data_offset, packet.data.len() as u32, packet.linktype, packet.ts);
+        entry.wire_len = packet.orig_len.max(entry.caplen);
+        self.entries.push(entry);
+    }
+
+    /// Sets `flag` on the most recently pushed packet.
+    pub fn mark(&mut self, flag: u8) {
+        if let Some(last) = self.entries.last_mut() {
+            last.flags |= flag;
+        }
+    }
+
+    /// Tags the most recently pushed packet with its TCP/UDP conversation.
+    pub fn set_stream(&mut self, protocol: &str, src: &str, dst: &str) {
+        let key = stream_key(protocol, src, dst);
+        let next_id = self.streams.len() as u32;
+        let id = *self.streams.entry(key).or_insert(next_id);
+        if let Some(last) = self.entries.last_mut() {
+            last.stream = id;
+        }
+    }
+
+    /// The conversation between endpoints `a` and `b`, in either order.
+    pub fn find_stream(&self, protocol: &str, a: &str, b: &str) -> Option<u32> {
+        self.streams.get(&stream_key(protocol, a, b)).copied()
+    }
+
+    /// Remembers the file's size and mtime so later reads can detect it being replaced.
+    pub fn seal(&mut self, file_path: &str) {
+        self.streams.shrink_to_fit();
+        if let Ok(meta) = std::fs::metadata(file_path) {
+            self.file_len = meta.len();
+            self.modified = meta.modified().ok();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, number: usize) -> Option<&IndexEntry> {
+        number.checked_sub(1).and_then(|i| self.entries.get(i))
+    }
+
+    pub fn first_ts(&self) -> f64 {
+        self.entries.first().map_or(0.0, |e| e.ts)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.entries.iter()
+    }
+
+    /// `(number, entry)` for every packet of `stream`, in capture order.
+    pub fn stream_packets(&self, stream: u32) -> impl Iterator<Item = (usize, &IndexEntry)> {
+        self.entries.iter().enumerate()
+            .filter(move |(_, e)| e.stream == stream)
+            .map(|(i, e)| (i + 1, e))
+    }
+
+    pub fn cached_matches(&self, filter: &str) -> Option<Arc<Vec<usize>>> {
+        let cached = self.filter_matches.lock().ok()?;
+        cached.as_ref().filter(|(text, _)| text == filter).map(|(_, numbers)| numbers.clone())
+    }
+
+    pub fn cache_matches(&self, filter: &str, numbers: Arc<Vec<usize>>) {
+        if let Ok(mut cached) = self.filter_matches.lock() {
+            *cached = Some((filter.to_string(), numbers));
+        }
+    }
+
+    /// Opens the capture for reading frames, refusing if it changed since it was indexed.
+    pub fn open(&self, file_path: &str) -> Result<File, String> {
+        let meta = std::fs::metadata(file_path).map_err(|e| e.to_string())?;
+        if meta.len() != self.file_len || meta.modified().ok() != self.modified {
+            return Err(format!("{} changed since it was analyzed; analyze it again", file_path));
+        }
+        File::open(file_path).map_err(|e| e.to_string())
+    }
+}
+
+fn stream_key(protocol: &str, src: &str, dst: &str) -> String {
+    if src < dst {
+        format!("{} {} <-> {}", protocol, src, dst)
+    } else {
+        format!("{} {} <-> {}", protocol, dst, src)
+    }
+}
+
+pub fn read_frame(file: &mut File, entry: &IndexEntry) -> Result<Vec<u8>, String> {
+    let mut data = vec![0; entry.caplen as usize];
+    file.seek(SeekFrom::Start(entry.data_offset)).map_err(|e| e.to_string())?;
+    file.read_exact(&mut data).map_err(|e| e.to_string())?;
+    Ok(data)
+}
@@ -0,0 +1,145 @@
+use crate::modules::utils::{IoGraphBucket, Microburst, PcapIoGraph, PcapIoGraphOptions};
+use super::index::{self, IndexEntry, PacketIndex};
+
+// Fine intervals over a long capture would otherwise make a chart nobody can draw.
+const MAX_BUCKETS: usize = 200_000;
+
+/// Buckets the analyzed packets of a capture, or of one of its flows, into fixed
+/// intervals counted from the first packet of the capture, so series for different
+/// flows line up. Packets the display filter skipped are left out, as in the analysis.
+///
+/// Microbursts are runs of intervals whose average rate is above the line rate; the
+/// interval has to be well above one packet's serialization time for that to mean much.
+pub fn io_graph(index: &PacketIndex, options: &PcapIoGraphOptions) -> Result<PcapIoGraph, String> {
+    if options.interval_ms == 0 {
+        return Err("The interval must be at least 1 ms".into());
+    }
+    if options.line_rate_mbps.is_some_and(|rate| !rate.is_finite() || rate <= 0.0) {
+        return Err("The line rate must be a positive number of Mbit/s".into());
+    }
+    let interval = options.interval_ms as f64 / 1000.0;
+    let start_ts = index.first_ts();
+
+    let (flow, packets): (Option<String>, Box<dyn Iterator<Item = &IndexEntry>>) = match &options.flow {
+        Some(f) => {
+            let stream = index.find_stream(&f.protocol, &f.client, &f.server)
+                .ok_or_else(|| format!("No {} flow between {} and {}", f.protocol, f.client, f.server))?;
+            (Some(format!("{} {} <-> {}", f.protocol, f.client, f.server)), Box::new(index.stream_packets(stream).map(|(_, e)| e)))
+        },
+        None => (None, Box::new(index.iter())),
+    };
+
+    let mut buckets: Vec<IoGraphBucket> = Vec::new();
+    for entry in packets.filter(|e| !e.has(index::FILTERED_OUT)) {
+        // Timestamps that step backwards land in the first interval.
+        let slot = ((entry.ts - start_ts).max(0.0) / interval) as usize;
+        if slot >= MAX_BUCKETS {
+            return Err(format!(
+                "The capture spans more than {} intervals of {} ms; use a larger interval", MAX_BUCKETS, options.interval_ms,
+            ));
+        }
+        if slot >= buckets.len() {
+            buckets.resize_with(slot + 1, Default::default);
+        }
+        let bucket = &mut buckets[slot];
+        bucket.packets += 1;
+        bucket.bytes += entry.wire_len as u64;
+        bucket.retransmissions += entry.has(index::RETRANSMISSION) as usize;
+        bucket.resets += entry.has(index::RESET) as usize;
+        bucket.dns_queries += entry.has(index::DNS_QUERY) as usize;
+        bucket.new_flows += entry.has(index::NEW_FLOW) as usize;
+    }
+    for (i, bucket) in buckets.iter_mut().enumerate() {
+        bucket.start = i as f64 * interval;
+        bucket.packets_per_sec = bucket.packets as f64 / interval;
+        bucket.bits_per_sec = bucket.bytes as f64 * 8.0 / interval;
+    }
+
+    let mut microbursts = Vec::new();
+    if let Some(line_rate) = options.line_rate_mbps.map(|mbps| mbps * 1e6) {
+        let mut current: Option<Microburst> = None;
+        for bucket in &buckets {
+            if bucket.bits_per_sec <= line_rate {
+                microbursts.extend(current.take());
+                continue;
+            }
+            let burst = current.get_or_insert(Microburst {
+                start: bucket.start,
+                duration_ms: 0.0,
+                bytes: 0,
+                peak_bits_per_sec: 0.0,
+                peak_utilization: 0.0,
+            });
+            burst.duration_ms += options.interval_ms as f64;
+            burst.bytes += bucket.bytes;
+            burst.peak_bits_per_sec = burst.peak_bits_per_sec.max(bucket.bits_per_sec);
+            burst.peak_utilization = burst.peak_bits_per_sec / line_rate * 100.0;
+        }
+        microbursts.extend(current);
+    }
+
+    Ok(PcapIoGraph {
+        interval_ms: options.interval_ms,
+        start_ts,
+        flow,
+        peak_bits_per_sec: buckets.iter().map(|b| b.bits_per_sec).fold(0.0, f64::max),
+        buckets,
+        microbursts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pcap_parser::Linktype;
+    use crate::modules::utils::PcapFlowRef;
+    use super::super::reader::CapturedPacket;
+
+    fn push(index: &mut PacketIndex, ts: f64, orig_len: u32, flags: u8, stream: Option<(&str, &str)>) {
+        let data = [0u8; 60];
+        index.push(&CapturedPacket { offset: 0, data_offset: 0, ts, linktype: Linktype::ETHERNET, data: &data, orig_len });
+        index.mark(flags);
+        if let Some((src, dst)) = stream {
+            index.set_stream("TCP", src, dst);
+        }
+    }
+
+    #[test]
+    fn test_io_graph_buckets_and_microbursts() {
+        let (c, s) = ("10.0.0.5:50000", "10.0.0.9:443");
+        let mut index = PacketIndex::default();
+        push(&mut index, 100.0, 60, index::NEW_FLOW, Some((c, s)));
+        push(&mut index, 100.05, 60, index::DNS_QUERY, None);
+        // 125 kB inside 100-200 ms: 10 Mbit/s over that interval.
+        for i in 0..100 {
+            push(&mut index, 100.11 + i as f64 * 0.0005, 1250, 0, Some((s, c)));
+        }
+        push(&mut index, 100.25, 1250, index::RETRANSMISSION, Some((c, s)));
+        push(&mut index, 100.26, 60, index::FILTERED_OUT, None);
+        push(&mut index, 100.45, 60, index::RESET, Some((s, c)));
+
+        let options = PcapIoGraphOptions { interval_ms: 100, flow: None, line_rate_mbps: Some(5.0) };
+        let graph = io_graph(&index, &options).unwrap();
+        assert_eq!(graph.buckets.len(), 5);
+        let counts: Vec<usize> = graph.buckets.iter().map(|b| b.packets).collect();
+        assert_eq!(counts, vec![2, 100, 1, 0, 1]);
+        assert_eq!((graph.buckets[0].new_flows, graph.buckets[0].dns_queries), (1, 1));
+        assert_eq!((graph.buckets[2].retransmissions, graph.buckets[4].resets), (1, 1));
+        assert!((graph.buckets[1].bits_per_sec - 10e6).abs() < 1.0);
+        assert!((graph.buckets[1].start - 0.1).abs() < 1e-9);
+
+        assert_eq!(graph.microbursts.len(), 1);
+        let burst = &graph.microbursts[0];
+        assert_eq!((burst.duration_ms, burst.bytes), (100.0, 125_000));
+        assert!((burst.peak_utilization - 200.0).abs() < 1e-6);
+
+        let flow = PcapFlowRef { protocol: "TCP".into(), client: c.into(), server: s.into() };
+        let graph = io_graph(&index, &PcapIoGraphOptions { flow: Some(flow), ..options.clone() }).unwrap();
+        assert_eq!(graph.buckets.iter().map(|b| b.packets).sum::<usize>(), 103);
+        assert_eq!(graph.buckets[0].dns_queries, 0);
+
+        assert!(io_graph(&index, &PcapIoGraphOptions { interval_ms: 0, ..options.clone() }).is_err());
+        let missing = PcapFlowRef { protocol: "UDP".into(), client: c.into(), server: s.into() };
+        assert!(io_graph(&index, &PcapIoGraphOptions { flow: Some(missing), ..options }).is_err());
+    }
+}
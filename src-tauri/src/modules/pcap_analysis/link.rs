@@ -0,0 +1,243 @@
+use etherparse::{EtherType, PacketHeaders};
+use pcap_parser::Linktype;
+
+// Not among pcap-parser's named link types.
+pub const IEEE802_11: Linktype = Linktype(105);
+pub const IEEE802_11_RADIOTAP: Linktype = Linktype(127);
+
+const MPLS_UNICAST: u16 = 0x8847;
+const MPLS_MULTICAST: u16 = 0x8848;
+const VLAN_TAGS: [u16; 3] = [0x8100, 0x88a8, 0x9100];
+
+/// Decodes a frame according to the link type of the interface it was captured on.
+pub fn decode_packet(linktype: Linktype, data: &[u8]) -> Option<PacketHeaders<'_>> {
+    match linktype {
+        Linktype::ETHERNET => {
+            let headers = PacketHeaders::from_ethernet_slice(data).ok()?;
+            if headers.net.is_some() || data.len() < 14 { return Some(headers); }
+            // MPLS, or a VLAN stack deeper than etherparse tracks: unwrap it ourselves
+            // and keep the Ethernet header for the MAC addresses.
+            let ether_type = u16::from_be_bytes([data[12], data[13]]);
+            match strip_tags(ether_type, &data[14..]) {
+                Some((inner_type, rest)) => {
+                    let mut inner = PacketHeaders::from_ether_type(EtherType(inner_type), rest).ok()?;
+                    inner.link = headers.link;
+                    Some(inner)
+                },
+                None => Some(headers),
+            }
+        },
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => PacketHeaders::from_ip_slice(data).ok(),
+        _ => {
+            let (ether_type, rest) = unwrap_link_header(linktype, data)?;
+            let (ether_type, rest) = strip_tags(ether_type, rest).unwrap_or((ether_type, rest));
+            PacketHeaders::from_ether_type(EtherType(ether_type), rest).ok()
+        },
+    }
+}
+
+/// The EtherType of the network layer and its offset within the frame, past any link
+/// header, VLAN tags and MPLS labels.
+pub fn network_layer(linktype: Linktype, data: &[u8]) -> Option<(u16, usize)> {
+    let (ether_type, rest) = match linktype {
+        Linktype::ETHERNET => (be16(data, 12)?, data.get(14..)?),
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => match data.first()? >> 4 {
+            4 => (0x0800, data),
+            6 => (0x86dd, data),
+            _ => return None,
+        },
+        _ => unwrap_link_header(linktype, data)?,
+    };
+    let (ether_type, rest) = strip_tags(ether_type, rest).unwrap_or((ether_type, rest));
+    Some((ether_type, data.len() - rest.len()))
+}
+
+pub fn linktype_name(linktype: Linktype) -> String {
+    match linktype {
+        Linktype::ETHERNET => "Ethernet".into(),
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => "Raw IP".into(),
+        Linktype::LINUX_SLL => "Linux cooked (SLL)".into(),
+        Linktype::LINUX_SLL2 => "Linux cooked (SLL2)".into(),
+        Linktype::NULL | Linktype::LOOP => "BSD loopback".into(),
+        IEEE802_11 => "802.11".into(),
+        IEEE802_11_RADIOTAP => "802.11 radiotap".into(),
+        other => format!("Link type {}", other.0),
+    }
+}
+
+/// Removes a non-Ethernet link header, returning the EtherType of what follows.
+fn unwrap_link_header(linktype: Linktype, data: &[u8]) -> Option<(u16, &[u8])> {
+    match linktype {
+        // Linux cooked v1: type, ARPHRD, address length, 8-byte address, protocol.
+        Linktype::LINUX_SLL => Some((be16(data, 14)?, data.get(16..)?)),
+        // Linux cooked v2: protocol, reserved, ifindex, ARPHRD, type, address length, address.
+        Linktype::LINUX_SLL2 => Some((be16(data, 0)?, data.get(20..)?)),
+        // BSD loopback: address family in the capturing host's byte order (NULL) or
+        // network order (LOOP). Only one reading of a small value fits in 16 bits.
+        Linktype::NULL | Linktype::LOOP => {
+            let raw: [u8; 4] = data.get(..4)?.try_into().ok()?;
+            let family = if linktype == Linktype::LOOP || u32::from_le_bytes(raw) > 0xffff {
+                u32::from_be_bytes(raw)
+            } else {
+                u32::from_le_bytes(raw)
+            };
+            let ether_type = match family {
+                2 => 0x0800,
+                // AF_INET6 differs between the BSDs, macOS and Linux.
+                10 | 24 | 28 | 30 => 0x86dd,
+                _ => return None,
+            };
+            Some((ether_type, &data[4..]))
+        },
+        IEEE802_11 => unwrap_80211(data),
+        IEEE802_11_RADIOTAP => {
+            let len = u16::from_le_bytes([*data.get(2)?, *data.get(3)?]) as usize;
+            unwrap_80211(data.get(len..)?)
+        },
+        _ => None,
+    }
+}
+
+/// Unencrypted 802.11 data frames carrying LLC/SNAP; everything else is not IP.
+fn unwrap_80211(frame: &[u8]) -> Option<(u16, &[u8])> {
+    let (fc, flags) = (*frame.first()?, *frame.get(1)?);
+    let (frame_type, subtype) = ((fc >> 2) & 0x3, fc >> 4);
+    let protected = flags & 0x40 != 0;
+    // Type 2 is data; subtypes with bit 2 set (null function, CF-ack/poll) carry no payload.
+    if frame_type != 2 || subtype & 0x4 != 0 || protected { return None; }
+
+    let qos = subtype & 0x8 != 0;
+    let mut hdr_len = 24;
+    if flags & 0x03 == 0x03 { hdr_len += 6; } // ToDS and FromDS: fourth address
+    if qos {
+        hdr_len += 2;
+        if flags & 0x80 != 0 { hdr_len += 4; } // +HTC
+    }
+
+    let llc = frame.get(hdr_len..hdr_len + 8)?;
+    // SNAP with RFC 1042 or 802.1H (bridge tunnel) OUI.
+    if llc[..3] != [0xaa, 0xaa, 0x03] || !(llc[3..6] == [0, 0, 0] || llc[3..6] == [0, 0, 0xf8]) {
+        return None;
+    }
+    Some((u16::from_be_bytes([llc[6], llc[7]]), &frame[hdr_len + 8..]))
+}
+
+/// Pops any number of VLAN tags and MPLS labels. Returns `None` if there were none.
+fn strip_tags(mut ether_type: u16, mut data: &[u8]) -> Option<(u16, &[u8])> {
+    let mut stripped = false;
+    loop {
+        if VLAN_TAGS.contains(&ether_type) {
+            ether_type = be16(data, 2)?;
+            data = data.get(4..)?;
+        } else if ether_type == MPLS_UNICAST || ether_type == MPLS_MULTICAST {
+            loop {
+                let bottom_of_stack = data.get(2)? & 0x01 != 0;
+                data = data.get(4..)?;
+                if bottom_of_stack { break; }
+            }
+            // MPLS doesn't say what it carries; IP is the common case.
+            ether_type = match data.first()? >> 4 {
+                4 => 0x0800,
+                6 => 0x86dd,
+                _ => return None,
+            };
+        } else {
+            return stripped.then_some((ether_type, data));
+        }
+        stripped = true;
+    }
+}
+
+fn be16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*data.get(at)?, *data.get(at + 1)?]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::{NetHeaders, PacketBuilder, TransportHeader};
+
+    fn ipv4_udp() -> Vec<u8> {
+        let mut out = Vec::new();
+        PacketBuilder::ipv4([10, 0, 0, 1], [10, 0, 0, 2], 64)
+            .udp(5000, 53)
+            .write(&mut out, b"payload")
+            .unwrap();
+        out
+    }
+
+    fn ipv6_udp() -> Vec<u8> {
+        let mut out = Vec::new();
+        PacketBuilder::ipv6([0xfe; 16], [0xfd; 16], 64)
+            .udp(5000, 53)
+            .write(&mut out, b"payload")
+            .unwrap();
+        out
+    }
+
+    fn assert_udp(linktype: Linktype, frame: &[u8], v6: bool) {
+        let headers = decode_packet(linktype, frame).unwrap_or_else(|| panic!("{:?} not decoded", linktype));
+        match (&headers.net, v6) {
+            (Some(NetHeaders::Ipv4(..)), false) | (Some(NetHeaders::Ipv6(..)), true) => {},
+            other => panic!("{:?}: unexpected network layer {:?}", linktype, other.0),
+        }
+        assert!(matches!(headers.transport, Some(TransportHeader::Udp(ref u)) if u.destination_port == 53));
+        assert_eq!(headers.payload.slice(), b"payload");
+    }
+
+    fn with_prefix(prefix: &[u8], inner: &[u8]) -> Vec<u8> {
+        [prefix, inner].concat()
+    }
+
+    #[test]
+    fn test_linux_cooked_and_loopback() {
+        let mut sll = vec![0, 0, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0];
+        sll.extend_from_slice(&[0x86, 0xdd]);
+        assert_udp(Linktype::LINUX_SLL, &with_prefix(&sll, &ipv6_udp()), true);
+
+        let sll2 = [0x08, 0x00, 0, 0, 0, 0, 0, 3, 0, 1, 0, 6, 2, 0, 0, 0, 0, 1, 0, 0];
+        assert_udp(Linktype::LINUX_SLL2, &with_prefix(&sll2, &ipv4_udp()), false);
+
+        // NULL in little-endian (x86 capture), NULL in big-endian, and LOOP (always big-endian).
+        assert_udp(Linktype::NULL, &with_prefix(&2u32.to_le_bytes(), &ipv4_udp()), false);
+        assert_udp(Linktype::NULL, &with_prefix(&30u32.to_be_bytes(), &ipv6_udp()), true);
+        assert_udp(Linktype::LOOP, &with_prefix(&2u32.to_be_bytes(), &ipv4_udp()), false);
+        assert_udp(Linktype::IPV4, &ipv4_udp(), false);
+    }
+
+    #[test]
+    fn test_radiotap_80211_qos_data() {
+        let radiotap = [0, 0, 8, 0, 0, 0, 0, 0]; // version, pad, length 8, no fields present
+        let mut wifi = vec![0x88, 0x01, 0, 0]; // QoS data, ToDS
+        wifi.extend_from_slice(&[0x11; 18]); // addresses
+        wifi.extend_from_slice(&[0, 0]); // sequence control
+        wifi.extend_from_slice(&[0, 0]); // QoS control
+        wifi.extend_from_slice(&[0xaa, 0xaa, 0x03, 0, 0, 0, 0x08, 0x00]);
+        let frame = [&radiotap[..], &wifi, &ipv4_udp()].concat();
+        assert_udp(IEEE802_11_RADIOTAP, &frame, false);
+
+        // The same frame with the Protected bit set cannot be decoded.
+        let mut protected = frame.clone();
+        protected[radiotap.len() + 1] |= 0x40;
+        assert!(decode_packet(IEEE802_11_RADIOTAP, &protected).is_none());
+    }
+
+    #[test]
+    fn test_stacked_vlan_and_mpls() {
+        let mac = [0u8; 12];
+        // Four VLAN tags: more than etherparse keeps on its own.
+        let mut qinq = mac.to_vec();
+        qinq.extend_from_slice(&[0x88, 0xa8, 0, 10, 0x81, 0x00, 0, 20, 0x81, 0x00, 0, 30, 0x81, 0x00, 0, 40, 0x08, 0x00]);
+        assert_udp(Linktype::ETHERNET, &with_prefix(&qinq, &ipv4_udp()), false);
+
+        // VLAN carrying a two-label MPLS stack.
+        let mut mpls = mac.to_vec();
+        mpls.extend_from_slice(&[0x81, 0x00, 0, 10, 0x88, 0x47]);
+        mpls.extend_from_slice(&[0x00, 0x01, 0x00, 0x40]); // label 16, not bottom
+        mpls.extend_from_slice(&[0x00, 0x01, 0x11, 0x40]); // label 17, bottom of stack
+        let frame = with_prefix(&mpls, &ipv6_udp());
+        let headers = decode_packet(Linktype::ETHERNET, &frame).unwrap();
+        assert!(headers.link.is_some());
+        assert!(matches!(headers.net, Some(NetHeaders::Ipv6(..))));
+    }
+}
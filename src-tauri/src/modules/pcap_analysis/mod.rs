@@ -0,0 +1,942 @@
+mod anomaly;
+mod arp;
+mod capture;
+mod compare;
+mod detail;
+mod dhcp;
+mod dns;
+mod filter;
+mod flows;
+mod follow;
+mod frag;
+mod http;
+mod icmp;
+mod index;
+mod iograph;
+mod link;
+mod quic;
+mod reader;
+mod sanitize;
+mod tcp;
+mod tls;
+mod voip;
+mod writer;
+
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use etherparse::{NetHeaders, TransportHeader};
+use tauri::Emitter;
+use tokio::sync::oneshot;
+
+use crate::modules::utils::{
+    PcapAnalysisResult, PcapCaptureOptions, PcapCaptureStats, PcapComparison, PcapIoGraph, PcapIoGraphOptions, PcapIssue,
+    PcapPacketDetail, PcapPacketPage, PcapProgress, PcapSanitizeOptions, PcapSanitizeReport, PcapStream,
+};
+use filter::DisplayFilter;
+use index::PacketIndex;
+use link::decode_packet;
+use reader::{CaptureReader, CapturedPacket};
+
+// Progress is emitted at most this often so multi-GB files don't flood the frontend.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A running analysis that `cancel_pcap_analysis` can stop.
+#[derive(Default)]
+struct PcapJob {
+    cancel: AtomicBool,
+    // (finished, canceller waiting for the partial result)
+    waiter: Mutex<(bool, Option<oneshot::Sender<PcapAnalysisResult>>)>,
+}
+
+static PCAP_JOBS: LazyLock<Mutex<HashMap<String, Arc<PcapJob>>>> = LazyLock::new(Default::default);
+
+// Packet indexes of the most recently analyzed captures, oldest first, for the
+// packet list, packet detail and follow-stream commands.
+const MAX_INDEXES: usize = 4;
+type IndexCache = Vec<(String, Arc<PacketIndex>)>;
+static PCAP_INDEXES: LazyLock<Mutex<IndexCache>> = LazyLock::new(Default::default);
+
+/// Analyzes a capture, emitting `pcap_progress` events. `job_id` defaults to the file path.
+/// With a display `filter`, only matching packets are analyzed.
+#[tauri::command]
+pub async fn analyze_pcap_file(
+    app: tauri::AppHandle, file_path: String, job_id: Option<String>, flow_sort: Option<String>, filter: Option<String>,
+) -> Result<PcapAnalysisResult, String> {
+    let filter = parse_filter(filter)?;
+    let flow_sort = flow_sort.as_deref().map(flows::FlowSort::parse).transpose()?;
+    let job_id = job_id.unwrap_or_else(|| file_path.clone());
+    let id = job_id.clone();
+    run_job(job_id, move |cancel| {
+        let (mut result, index) = run_analysis(&file_path, filter, cancel, |progress| {
+            let _ = app.emit("pcap_progress", PcapProgress { job_id: id.clone(), ..progress });
+        })?;
+        store_index(file_path, index)?;
+        if let Some(key) = flow_sort {
+            flows::sort_flows(&mut result.flows, key);
+        }
+        Ok(result)
+    }).await
+}
+
+/// Captures live traffic from `interface` (Linux only) into a pcapng file at `output_path`,
+/// running it through the same analysis as `analyze_pcap_file` and emitting
+/// `pcap_capture_stats` events about once a second. Stop it with `cancel_pcap_analysis`;
+/// `job_id` defaults to "capture:<interface>". The saved file can then be browsed with
+/// the packet list, packet detail and follow-stream commands.
+#[tauri::command]
+pub async fn start_pcap_capture(
+    app: tauri::AppHandle, interface: String, output_path: String, options: Option<PcapCaptureOptions>, job_id: Option<String>,
+) -> Result<PcapAnalysisResult, String> {
+    let options = options.unwrap_or_default();
+    let job_id = job_id.unwrap_or_else(|| format!("capture:{}", interface));
+    let id = job_id.clone();
+    run_job(job_id, move |cancel| {
+        let (result, index) = capture::run_capture(&interface, &output_path, &options, cancel, |stats| {
+            let _ = app.emit("pcap_capture_stats", PcapCaptureStats { job_id: id.clone(), ..stats });
+        })?;
+        store_index(output_path, index)?;
+        Ok(result)
+    }).await
+}
+
+/// Registers `job_id` so `cancel_pcap_analysis` can stop it, runs `work` on the blocking
+/// pool, and hands the result to a waiting canceller as well as returning it.
+async fn run_job<F>(job_id: String, work: F) -> Result<PcapAnalysisResult, String>
+where
+    F: FnOnce(&AtomicBool) -> Result<PcapAnalysisResult, String> + Send + 'static,
+{
+    let job = Arc::new(PcapJob::default());
+    {
+        let mut jobs = PCAP_JOBS.lock().map_err(|e| e.to_string())?;
+        if jobs.contains_key(&job_id) {
+            return Err(format!("Analysis '{}' is already running", job_id));
+        }
+        jobs.insert(job_id.clone(), job.clone());
+    }
+
+    let running = job.clone();
+    let res = tokio::task::spawn_blocking(move || work(&running.cancel)).await;
+
+    if let Ok(mut jobs) = PCAP_JOBS.lock() { jobs.remove(&job_id); }
+    let res = match res {
+        Ok(inner_res) => inner_res,
+        Err(e) => Err(e.to_string())
+    };
+
+    let mut waiter = job.waiter.lock().map_err(|e| e.to_string())?;
+    waiter.0 = true;
+    if let (Some(tx), Ok(result)) = (waiter.1.take(), &res) {
+        let _ = tx.send(result.clone());
+    }
+    res
+}
+
+/// Stops a running analysis and returns what it had gathered up to that point.
+#[tauri::command]
+pub async fn cancel_pcap_analysis(job_id: String) -> Result<PcapAnalysisResult, String> {
+    let job = PCAP_JOBS.lock().map_err(|e| e.to_string())?
+        .get(&job_id).cloned()
+        .ok_or_else(|| format!("No running analysis with job ID '{}'", job_id))?;
+
+    let (tx, rx) = oneshot::channel();
+    {
+        let mut waiter = job.waiter.lock().map_err(|e| e.to_string())?;
+        if waiter.0 {
+            return Err(format!("Analysis '{}' has already finished", job_id));
+        }
+        waiter.1 = Some(tx);
+    }
+    job.cancel.store(true, Ordering::Relaxed);
+    rx.await.map_err(|_| format!("Analysis '{}' failed before it could be cancelled", job_id))
+}
+
+/// A page of packet summaries from a capture analyzed earlier. `offset` counts packets,
+/// or only those matching `filter` when one is given.
+#[tauri::command]
+pub async fn get_pcap_packets(file_path: String, offset: usize, limit: usize, filter: Option<String>) -> Result<PcapPacketPage, String> {
+    let filter = parse_filter(filter)?;
+    let index = find_index(&file_path)?;
+    tokio::task::spawn_blocking(move || detail::packet_page(&index, &file_path, offset, limit, filter.as_ref()))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// The decoded header tree and hex dump of packet `number` (1-based).
+#[tauri::command]
+pub async fn get_pcap_packet_detail(file_path: String, number: usize) -> Result<PcapPacketDetail, String> {
+    let index = find_index(&file_path)?;
+    tokio::task::spawn_blocking(move || detail::packet_detail(&index, &file_path, number))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Reassembles both directions of the TCP or UDP conversation containing packet `number`,
+/// as "ascii" (the default) or "hex".
+#[tauri::command]
+pub async fn follow_pcap_stream(file_path: String, number: usize, format: Option<String>) -> Result<PcapStream, String> {
+    let index = find_index(&file_path)?;
+    let format = format.unwrap_or_else(|| "ascii".into());
+    tokio::task::spawn_blocking(move || follow::follow_stream(&index, &file_path, number, &format))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Packets/s, bits/s, retransmissions, resets, DNS queries and new flows per interval
+/// for a capture analyzed earlier, or for one of its flows, with intervals above
+/// `line_rate_mbps` reported as microbursts.
+#[tauri::command]
+pub async fn get_pcap_io_graph(file_path: String, options: Option<PcapIoGraphOptions>) -> Result<PcapIoGraph, String> {
+    let options = options.unwrap_or_default();
+    let index = find_index(&file_path)?;
+    tokio::task::spawn_blocking(move || iograph::io_graph(&index, &options))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Correlates two captures of the same traffic taken at different points (e.g. either
+/// side of a firewall) and reports loss, NAT, DSCP/TTL changes and one-way latency.
+#[tauri::command]
+pub async fn compare_pcap_files(file_a: String, file_b: String) -> Result<PcapComparison, String> {
+    tokio::task::spawn_blocking(move || compare::compare_captures(&file_a, &file_b))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Writes an anonymized copy of a capture for sharing with vendors, in the same format
+/// and with the same link types.
+#[tauri::command]
+pub async fn sanitize_pcap_file(
+    input_path: String, output_path: String, options: Option<PcapSanitizeOptions>,
+) -> Result<PcapSanitizeReport, String> {
+    let options = options.unwrap_or_default();
+    tokio::task::spawn_blocking(move || sanitize::sanitize_capture(&input_path, &output_path, options))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// A blank filter is the same as none.
+fn parse_filter(filter: Option<String>) -> Result<Option<DisplayFilter>, String> {
+    match filter.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(text) => DisplayFilter::parse(text).map(Some).map_err(|e| format!("Invalid display filter: {}", e)),
+    }
+}
+
+fn store_index(file_path: String, mut index: PacketIndex) -> Result<(), String> {
+    index.seal(&file_path);
+    let mut indexes = PCAP_INDEXES.lock().map_err(|e| e.to_string())?;
+    indexes.retain(|(path, _)| *path != file_path);
+    indexes.push((file_path, Arc::new(index)));
+    if indexes.len() > MAX_INDEXES { indexes.remove(0); }
+    Ok(())
+}
+
+fn find_index(file_path: &str) -> Result<Arc<PacketIndex>, String> {
+    PCAP_INDEXES.lock().map_err(|e| e.to_string())?
+        .iter()
+        .find(|(path, _)| path == file_path)
+        .map(|(_, index)| index.clone())
+        .ok_or_else(|| format!("{} has not been analyzed yet", file_path))
+}
+
+/// Reads the capture until it ends or `cancel` is set; a cancelled run still returns
+/// the results for the packets read so far, marked `cancelled`, along with the index
+/// of those packets.
+fn run_analysis<F>(file_path: &str, filter: Option<DisplayFilter>, cancel: &AtomicBool, mut on_progress: F) -> Result<(PcapAnalysisResult, PacketIndex), String>
+where
+    F: FnMut(PcapProgress),
+{
+    let total_bytes = std::fs::metadata(file_path).map_err(|e| e.to_string())?.len();
+    let mut reader = CaptureReader::open(file_path)?;
+    let mut analyzer = PcapAnalyzer { filter, ..Default::default() };
+    let mut last_report = Instant::now();
+    let mut cancelled = false;
+    let mut bytes_read = 0;
+
+    let progress = |analyzer: &PcapAnalyzer, bytes_read: u64| PcapProgress {
+        job_id: String::new(),
+        bytes_read,
+        total_bytes,
+        packets: analyzer.index.len(),
+        flagged_packets: analyzer.flagged_packets,
+    };
+
+    reader.read_packets(|pkt| {
+        analyzer.process_packet(pkt);
+        bytes_read = pkt.offset;
+        // Checking the clock on every packet is measurable on big files.
+        if analyzer.index.len().is_multiple_of(1024) {
+            if cancel.load(Ordering::Relaxed) {
+                cancelled = true;
+                return ControlFlow::Break(());
+            }
+            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                last_report = Instant::now();
+                on_progress(progress(&analyzer, bytes_read));
+            }
+        }
+        ControlFlow::Continue(())
+    })?;
+    on_progress(progress(&analyzer, if cancelled { bytes_read } else { total_bytes }));
+
+    let index = std::mem::take(&mut analyzer.index);
+    let mut result = analyzer.finish();
+    result.cancelled = cancelled;
+    Ok((result, index))
+}
+
+/// Accumulates statistics packet by packet; pcap and pcapng input both feed `process_packet`.
+#[derive(Default)]
+struct PcapAnalyzer {
+    packet_count: usize,
+    start_ts: f64,
+    end_ts: f64,
+    protocol_counts: HashMap<String, usize>,
+    flows: flows::FlowTable,
+    tcp: tcp::TcpAnalyzer,
+    tls: tls::TlsAnalyzer,
+    quic: quic::QuicAnalyzer,
+    http: http::HttpAnalyzer,
+    dns: dns::DnsAnalyzer,
+    voip: voip::VoipAnalyzer,
+    dhcp: dhcp::DhcpAnalyzer,
+    arp: arp::ArpAnalyzer,
+    icmp: icmp::IcmpAnalyzer,
+    anomaly: anomaly::AnomalyDetector,
+
+    service_hits: ServiceHits,
+    frag: frag::FragmentReassembler,
+    index: PacketIndex,
+    filter: Option<DisplayFilter>,
+    filtered_out: usize,
+    // Packets that tripped any per-packet check; reported as the running issue count.
+    flagged_packets: usize,
+}
+
+/// Counters for the payload-level security checks in `check_tcp_services`.
+#[derive(Default)]
+struct ServiceHits {
+    suspicious_ports: usize,
+    cleartext_logins: usize,
+}
+
+impl ServiceHits {
+    fn total(&self) -> usize {
+        self.suspicious_ports + self.cleartext_logins
+    }
+}
+
+impl PcapAnalyzer {
+    fn process_packet(&mut self, packet: &CapturedPacket) {
+        let data = packet.data;
+        let decoded = decode_packet(packet.linktype, data);
+        // Every packet is indexed, so packet numbers and streams match the file.
+        self.index.push(packet);
+        if self.filter.as_ref().is_some_and(|f| !f.matches(decoded.as_ref(), data.len())) {
+            self.filtered_out += 1;
+            self.index.mark(index::FILTERED_OUT);
+            if let Some((protocol, src, dst)) = decoded.as_ref().and_then(conversation) {
+                self.index.set_stream(protocol, &src, &dst);
+            }
+            return;
+        }
+
+        let ts = packet.ts;
+        if self.packet_count == 0 { self.start_ts = ts; }
+        self.end_ts = ts;
+        self.packet_count += 1;
+        let pkt_len = data.len() as u64;
+
+        let headers = match decoded {
+            Some(headers) => headers,
+            None => {
+                *self.protocol_counts.entry("Malformed/Unknown".to_string()).or_insert(0) += 1;
+                return;
+            }
+        };
+
+        if self.anomaly.process(&headers, ts) {
+            self.flagged_packets += 1;
+        }
+
+        let mut s_ip = "0.0.0.0".to_string();
+        let mut d_ip = "0.0.0.0".to_string();
+        let mut fragment = None;
+
+        // Network Layer
+        if let Some(ref net) = headers.net {
+            match net {
+                NetHeaders::Ipv4(ipv4, _) => {
+                    s_ip = format!("{}", std::net::Ipv4Addr::from(ipv4.source));
+                    d_ip = format!("{}", std::net::Ipv4Addr::from(ipv4.destination));
+                    *self.protocol_counts.entry("IPv4".to_string()).or_insert(0) += 1;
+
+                    if ipv4.is_fragmenting_payload() {
+                        fragment = Some(frag::Fragment {
+                            id: ipv4.identification as u32,
+                            offset: ipv4.fragment_offset.byte_offset() as u32,
+                            more: ipv4.more_fragments,
+                            protocol: ipv4.protocol.0,
+                        });
+                    }
+                },
+                NetHeaders::Ipv6(ipv6, exts) => {
+                    s_ip = format!("{}", std::net::Ipv6Addr::from(ipv6.source));
+                    d_ip = format!("{}", std::net::Ipv6Addr::from(ipv6.destination));
+                    *self.protocol_counts.entry("IPv6".to_string()).or_insert(0) += 1;
+
+                    fragment = exts.fragment.as_ref()
+                        .map(frag::Fragment::from_ipv6)
+                        .filter(frag::Fragment::is_fragment);
+                },
+                NetHeaders::Arp(arp) => {
+                    *self.protocol_counts.entry("ARP".to_string()).or_insert(0) += 1;
+                    if let Ok(arp) = arp.try_eth_ipv4() {
+                        self.arp.process(&arp, ts);
+                    }
+                },
+            }
+        }
+
+        // Fragments are held back until the whole datagram is in, then analyzed as one packet.
+        if let Some(fragment) = fragment {
+            *self.protocol_counts.entry("IP Fragment".to_string()).or_insert(0) += 1;
+            let Some(dgram) = self.frag.process(&s_ip, &d_ip, fragment, headers.payload.slice(), pkt_len, ts) else { return };
+            if let Some((transport, payload)) = frag::parse_transport(dgram.protocol, &dgram.data) {
+                self.process_transport(&transport, payload, &s_ip, &d_ip, dgram.wire_bytes, ts);
+            }
+            return;
+        }
+
+        if let Some(ref transport) = headers.transport {
+            self.process_transport(transport, headers.payload.slice(), &s_ip, &d_ip, pkt_len, ts);
+        }
+    }
+
+    fn process_transport(&mut self, transport: &TransportHeader, payload: &[u8], s_ip: &str, d_ip: &str, pkt_len: u64, ts: f64) {
+        match transport {
+            TransportHeader::Tcp(tcp) => {
+                let s_port = tcp.source_port;
+                let d_port = tcp.destination_port;
+                *self.protocol_counts.entry("TCP".to_string()).or_insert(0) += 1;
+
+                let (src_ep, dst_ep) = (endpoint(s_ip, s_port), endpoint(d_ip, d_port));
+                let events = self.tcp.process_segment(&src_ep, &dst_ep, tcp, payload.len() as u32, ts);
+                let new_flow = self.flows.record("TCP", &src_ep, &dst_ep, pkt_len, Some((tcp, &events)), ts);
+                self.index.set_stream("TCP", &src_ep, &dst_ep);
+                if new_flow { self.index.mark(index::NEW_FLOW); }
+                if tcp.rst { self.index.mark(index::RESET); }
+                if tcp.syn && !tcp.ack { self.quic.note_tcp_syn(s_ip, d_ip, d_port, ts); }
+                if events.iter().any(|e| e.is_retransmission()) { self.index.mark(index::RETRANSMISSION); }
+
+                // Detect Services
+                let hits_before = self.service_hits.total() + self.http.findings();
+                check_tcp_services(d_port, payload, &mut self.service_hits);
+                if !payload.is_empty() {
+                    self.tls.process_segment(&src_ep, &dst_ep, tcp, payload, ts);
+                }
+                if !payload.is_empty() || tcp.syn || tcp.fin || tcp.rst {
+                    self.http.process_segment(&src_ep, &dst_ep, tcp, payload, ts);
+                }
+                if !events.is_empty() || self.service_hits.total() + self.http.findings() > hits_before {
+                    self.flagged_packets += 1;
+                }
+                // DNS over TCP prefixes each message with its length.
+                let queries_before = self.dns.queries();
+                if (d_port == 53 || s_port == 53) && payload.len() > 2 {
+                    *self.protocol_counts.entry("DNS".to_string()).or_insert(0) += 1;
+                    for message in dns::tcp_messages(payload) {
+                        self.dns.process_message(&src_ep, s_ip, &dst_ep, d_ip, message, ts);
+                    }
+                }
+                if self.dns.queries() > queries_before { self.index.mark(index::DNS_QUERY); }
+                if (d_port == 5060 || s_port == 5060) && !payload.is_empty() {
+                    self.voip.process_sip(&src_ep, &dst_ep, payload, ts);
+                }
+            },
+            TransportHeader::Udp(udp) => {
+                let s_port = udp.source_port;
+                let d_port = udp.destination_port;
+                *self.protocol_counts.entry("UDP".to_string()).or_insert(0) += 1;
+                let (src_ep, dst_ep) = (endpoint(s_ip, s_port), endpoint(d_ip, d_port));
+                if self.flows.record("UDP", &src_ep, &dst_ep, pkt_len, None, ts) { self.index.mark(index::NEW_FLOW); }
+                self.index.set_stream("UDP", &src_ep, &dst_ep);
+
+                if d_port == 53 || s_port == 53 {
+                    *self.protocol_counts.entry("DNS".to_string()).or_insert(0) += 1;
+                    let queries_before = self.dns.queries();
+                    self.dns.process_message(&src_ep, s_ip, &dst_ep, d_ip, payload, ts);
+                    if self.dns.queries() > queries_before { self.index.mark(index::DNS_QUERY); }
+                } else if matches!((s_port, d_port), (67, 68) | (68, 67)) {
+                    *self.protocol_counts.entry("DHCP".to_string()).or_insert(0) += 1;
+                    self.dhcp.process_message(s_ip, payload, ts);
+                } else if self.quic.process_datagram(&src_ep, &dst_ep, payload, pkt_len, ts) {
+                    *self.protocol_counts.entry("QUIC".to_string()).or_insert(0) += 1;
+                } else {
+                    self.voip.process_udp(&src_ep, &dst_ep, payload, ts);
+                }
+            },
+            TransportHeader::Icmpv4(icmp) => {
+                *self.protocol_counts.entry("ICMP".to_string()).or_insert(0) += 1;
+                if self.icmp.process_v4(s_ip, &icmp.icmp_type, payload, ts) { self.flagged_packets += 1; }
+            },
+            TransportHeader::Icmpv6(icmp) => {
+                *self.protocol_counts.entry("ICMPv6".to_string()).or_insert(0) += 1;
+                if self.icmp.process_v6(s_ip, &icmp.icmp_type, payload, ts) { self.flagged_packets += 1; }
+            },
+        }
+    }
+
+    fn finish(self) -> PcapAnalysisResult {
+        let mut issues = Vec::new();
+
+        let tcp_stats = self.tcp.finish(&mut issues);
+
+        let hits = &self.service_hits;
+        if hits.suspicious_ports > 0 {
+            issues.push(PcapIssue {
+                 severity: "critical".into(),
+                 title: "Suspicious Port Activity".into(),
+                 description: format!("{} packets involving high-risk ports.", hits.suspicious_ports),
+                 timestamp: None,
+            });
+        }
+
+        if hits.cleartext_logins > 0 {
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "Cleartext Credentials".into(),
+                description: format!("Found {} plaintext FTP/POP3/IMAP logins.", hits.cleartext_logins),
+                timestamp: None,
+            });
+        }
+
+        let tls_sessions = self.tls.finish(&mut issues);
+        let quic = self.quic.finish(&mut issues);
+        let http = self.http.finish(&mut issues);
+        let dns = self.dns.finish(&mut issues);
+        let voip_calls = self.voip.finish(&mut issues);
+        let fragmentation = self.frag.finish(self.end_ts, &mut issues);
+        let dhcp = self.dhcp.finish(&mut issues);
+        let arp = self.arp.finish(&mut issues);
+        let icmp_errors = self.icmp.finish(&mut issues);
+        self.anomaly.finish(&mut issues);
+
+        let (flows, conversations) = self.flows.finish(self.end_ts);
+        let top_talkers = conversations.iter().take(5).map(|c| format!("{} <-> {} ({})", c.source, c.destination, format_bytes(c.bytes))).collect();
+        let duration_sec = if self.end_ts > self.start_ts { self.end_ts - self.start_ts } else { 0.0 };
+
+        PcapAnalysisResult {
+            packet_count: self.packet_count, filtered_out: self.filtered_out, duration_sec, issues, top_talkers, conversations, flows,
+            protocol_distribution: self.protocol_counts, tcp_stats,
+            tls_sessions, quic, http, dns, voip_calls, fragmentation, dhcp, arp, icmp_errors, cancelled: false,
+        }
+    }
+}
+
+fn check_tcp_services(dst_port: u16, payload: &[u8], hits: &mut ServiceHits) {
+    let bad_ports = [21, 23, 4444, 31337, 6667];
+    if bad_ports.contains(&dst_port) { hits.suspicious_ports += 1; }
+
+    let login_ports = [21, 110, 143];
+    if login_ports.contains(&dst_port) && is_cleartext_login(payload) { hits.cleartext_logins += 1; }
+}
+
+/// FTP/POP3 `PASS` and IMAP `LOGIN` commands carry the password in the clear.
+fn is_cleartext_login(payload: &[u8]) -> bool {
+    let line = String::from_utf8_lossy(&payload[..payload.len().min(64)]).to_ascii_uppercase();
+    line.starts_with("PASS ") || line.split_whitespace().nth(1) == Some("LOGIN")
+}
+
+fn endpoint(ip: &str, port: u16) -> String {
+    if ip.contains(':') { format!("[{}]:{}", ip, port) } else { format!("{}:{}", ip, port) }
+}
+
+/// The TCP/UDP conversation of an unfragmented packet, as the index keys streams.
+fn conversation(headers: &etherparse::PacketHeaders) -> Option<(&'static str, String, String)> {
+    let (s_ip, d_ip) = match &headers.net {
+        Some(NetHeaders::Ipv4(ip, _)) => (std::net::Ipv4Addr::from(ip.source).to_string(), std::net::Ipv4Addr::from(ip.destination).to_string()),
+        Some(NetHeaders::Ipv6(ip, _)) => (std::net::Ipv6Addr::from(ip.source).to_string(), std::net::Ipv6Addr::from(ip.destination).to_string()),
+        _ => return None,
+    };
+    match &headers.transport {
+        Some(TransportHeader::Tcp(t)) => Some(("TCP", endpoint(&s_ip, t.source_port), endpoint(&d_ip, t.destination_port))),
+        Some(TransportHeader::Udp(u)) => Some(("UDP", endpoint(&s_ip, u.source_port), endpoint(&d_ip, u.destination_port))),
+        _ => None,
+    }
+}
+
+fn format_bytes(b: u64) -> String {
+    if b > 1_000_000 { format!("{:.1} MB", b as f64/1e6) } else { format!("{} B", b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::PacketBuilder;
+
+    const CLIENT: [u8; 4] = [10, 0, 0, 5];
+    const SERVER: [u8; 4] = [93, 184, 216, 34];
+
+    fn tcp_frame(src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16, seq: u32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+            .ipv4(src, dst, 64)
+            .tcp(sport, dport, seq, 64240)
+            .ack(1)
+            .psh()
+            .write(&mut frame, payload)
+            .unwrap();
+        frame
+    }
+
+    /// Runs the full analysis on `frames` written to a temporary pcap.
+    fn analyze_frames(name: &str, frames: &[Vec<u8>]) -> PcapAnalysisResult {
+        let path = write_pcap(name, frames);
+        let res = run_analysis(&path.to_string_lossy(), None, &AtomicBool::new(false), |_| {});
+        std::fs::remove_file(&path).ok();
+        res.unwrap().0
+    }
+
+    /// Ethernet frames 1ms apart in a microsecond legacy pcap.
+    fn write_pcap(name: &str, frames: &[Vec<u8>]) -> std::path::PathBuf {
+        let timed: Vec<(u64, Vec<u8>)> = frames.iter().enumerate().map(|(i, f)| (i as u64 * 1000, f.clone())).collect();
+        write_timed_pcap(name, &timed)
+    }
+
+    /// Ethernet frames at the given microsecond offsets from a fixed start time.
+    fn write_timed_pcap(name: &str, frames: &[(u64, Vec<u8>)]) -> std::path::PathBuf {
+        let mut file = Vec::new();
+        file.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        file.extend_from_slice(&2u16.to_le_bytes());
+        file.extend_from_slice(&4u16.to_le_bytes());
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&65535u32.to_le_bytes());
+        file.extend_from_slice(&1u32.to_le_bytes());
+        for (micros, frame) in frames {
+            file.extend_from_slice(&(1_700_000_000 + (micros / 1_000_000) as u32).to_le_bytes());
+            file.extend_from_slice(&((micros % 1_000_000) as u32).to_le_bytes());
+            file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            file.extend_from_slice(frame);
+        }
+
+        let path = std::env::temp_dir().join(format!("trubleshoota_{}_{}.pcap", std::process::id(), name));
+        std::fs::write(&path, &file).unwrap();
+        path
+    }
+
+    fn issue<'a>(res: &'a PcapAnalysisResult, title: &str) -> Option<&'a PcapIssue> {
+        res.issues.iter().find(|i| i.title == title)
+    }
+
+    fn hello(handshake_type: u8, version: [u8; 2], cipher: u16) -> Vec<u8> {
+        let mut body = version.to_vec();
+        body.extend_from_slice(&[0; 32]); // random
+        body.push(0); // session id
+        if handshake_type == 1 {
+            body.extend_from_slice(&2u16.to_be_bytes());
+        }
+        body.extend_from_slice(&cipher.to_be_bytes());
+        if handshake_type == 1 {
+            body.extend_from_slice(&[1, 0]);
+        } else {
+            body.push(0);
+        }
+        let mut rec = vec![22, 3, 1];
+        rec.extend_from_slice(&((body.len() + 4) as u16).to_be_bytes());
+        rec.push(handshake_type);
+        rec.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        rec.extend_from_slice(&body);
+        rec
+    }
+
+    #[test]
+    fn test_basic_auth_detected() {
+        let req = b"GET /admin HTTP/1.1\r\nHost: router\r\nAuthorization: Basic YWRtaW46YWRtaW4=\r\n\r\n";
+        // The header is split across two segments, on a non-standard port.
+        let frames = [
+            tcp_frame(CLIENT, SERVER, 50000, 8080, 1, &req[..40]),
+            tcp_frame(CLIENT, SERVER, 50000, 8080, 41, &req[40..]),
+        ];
+        let res = analyze_frames("basic", &frames);
+        let found = issue(&res, "Cleartext HTTP Authentication").expect("Basic Auth should be flagged");
+        assert!(found.description.contains("1 request(s) sent HTTP Basic credentials unencrypted to router"));
+        assert_eq!(res.http.len(), 1);
+        assert_eq!(res.http[0].uri, "/admin");
+    }
+
+    #[test]
+    fn test_ftp_password_detected() {
+        let frames = [
+            tcp_frame(CLIENT, SERVER, 50001, 21, 1, b"USER anonymous\r\n"),
+            tcp_frame(CLIENT, SERVER, 50001, 21, 17, b"PASS hunter2\r\n"),
+        ];
+        let res = analyze_frames("ftp", &frames);
+        let found = issue(&res, "Cleartext Credentials").expect("FTP PASS should be flagged");
+        assert!(found.description.contains("1 plaintext"));
+    }
+
+    #[test]
+    fn test_deprecated_tls_detected() {
+        let frames = [
+            tcp_frame(CLIENT, SERVER, 50002, 8443, 1, &hello(1, [3, 1], 0x000A)),
+            tcp_frame(SERVER, CLIENT, 8443, 50002, 1, &hello(2, [3, 1], 0x000A)),
+        ];
+        let res = analyze_frames("oldtls", &frames);
+        let found = issue(&res, "Deprecated TLS").expect("TLS 1.0 should be flagged on any port");
+        assert!(found.description.starts_with("1 TLS 1.0 handshake(s) with 93.184.216.34:8443"));
+        assert!(issue(&res, "Weak TLS Cipher").is_some());
+
+        assert_eq!(res.tls_sessions.len(), 1);
+        assert_eq!(res.tls_sessions[0].negotiated_version.as_deref(), Some("TLS 1.0"));
+        assert_eq!(res.tls_sessions[0].negotiated_cipher.as_deref(), Some("TLS_RSA_WITH_3DES_EDE_CBC_SHA"));
+    }
+
+    #[test]
+    fn test_modern_tls_not_flagged() {
+        // TLS 1.2+ clients still send a TLS 1.0 record header.
+        let frames = [
+            tcp_frame(CLIENT, SERVER, 50003, 443, 1, &hello(1, [3, 3], 0xC02F)),
+            tcp_frame(SERVER, CLIENT, 443, 50003, 1, &hello(2, [3, 3], 0xC02F)),
+        ];
+        let res = analyze_frames("newtls", &frames);
+        assert!(issue(&res, "Deprecated TLS").is_none());
+        assert!(issue(&res, "Weak TLS Cipher").is_none());
+        assert!(issue(&res, "Cleartext Credentials").is_none());
+    }
+
+    #[test]
+    fn test_cancel_returns_partial_results() {
+        let frames: Vec<Vec<u8>> = (0..3000u32)
+            .map(|i| tcp_frame(CLIENT, SERVER, 50003, 443, 1 + i * 10, &[0; 10]))
+            .collect();
+        let path = write_pcap("cancel", &frames);
+        let path = path.to_string_lossy();
+        let total = std::fs::metadata(&*path).unwrap().len();
+
+        let mut reports = Vec::new();
+        let full = run_analysis(&path, None, &AtomicBool::new(false), |p| reports.push(p)).unwrap().0;
+        assert!(!full.cancelled);
+        assert_eq!(full.packet_count, 3000);
+        let last = reports.last().unwrap();
+        assert_eq!((last.bytes_read, last.total_bytes, last.packets), (total, total, 3000));
+
+        // Cancellation is checked every 1024 packets.
+        let mut reports = Vec::new();
+        let partial = run_analysis(&path, None, &AtomicBool::new(true), |p| reports.push(p)).unwrap().0;
+        std::fs::remove_file(&*path).ok();
+        assert!(partial.cancelled);
+        assert_eq!(partial.packet_count, 1024);
+        assert_eq!(partial.flows[0].packets_to_server, 1024);
+        let last = reports.last().unwrap();
+        assert!(last.bytes_read > 0 && last.bytes_read < total);
+    }
+
+    /// A DNS query for example.com with 600 bytes of EDNS padding, split into IP fragments.
+    fn fragmented_dns_frames(v6: bool, chunk: usize) -> Vec<Vec<u8>> {
+        let mut dns = b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x01\x07example\x03com\x00\x00\x01\x00\x01".to_vec();
+        dns.extend_from_slice(&[0, 0x00, 0x29, 0x04, 0xd0, 0, 0, 0, 0, 0x02, 0x5c, 0x00, 0x0c, 0x02, 0x58]);
+        dns.extend_from_slice(&[0; 600]);
+        let mut udp = vec![0xc3, 0x50, 0x00, 0x35];
+        udp.extend_from_slice(&((dns.len() + 8) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(&dns);
+
+        udp.chunks(chunk).enumerate().map(|(i, piece)| {
+            let offset = (i * chunk) as u16;
+            let more = (i + 1) * chunk < udp.len();
+            let mut frame = vec![2, 0, 0, 0, 0, 2, 2, 0, 0, 0, 0, 1];
+            if v6 {
+                frame.extend_from_slice(&[0x86, 0xdd, 0x60, 0, 0, 0]);
+                frame.extend_from_slice(&((piece.len() + 8) as u16).to_be_bytes());
+                frame.extend_from_slice(&[44, 64]); // next header: Fragment
+                frame.extend_from_slice(&[0xfd; 16]);
+                frame.extend_from_slice(&[0xfe; 16]);
+                frame.extend_from_slice(&[17, 0]);
+                frame.extend_from_slice(&(offset | more as u16).to_be_bytes());
+                frame.extend_from_slice(&0xabcdu32.to_be_bytes());
+            } else {
+                frame.extend_from_slice(&[0x08, 0x00, 0x45, 0]);
+                frame.extend_from_slice(&((piece.len() + 20) as u16).to_be_bytes());
+                frame.extend_from_slice(&[0xab, 0xcd]);
+                frame.extend_from_slice(&((offset / 8) | ((more as u16) << 13)).to_be_bytes());
+                frame.extend_from_slice(&[64, 17, 0, 0]);
+                frame.extend_from_slice(&CLIENT);
+                frame.extend_from_slice(&SERVER);
+            }
+            frame.extend_from_slice(piece);
+            frame
+        }).collect()
+    }
+
+    #[test]
+    fn test_fragmented_datagrams_reassembled() {
+        for v6 in [false, true] {
+            let res = analyze_frames(if v6 { "frag6" } else { "frag4" }, &fragmented_dns_frames(v6, 256));
+            assert_eq!(res.fragmentation.fragments, 3, "v6={}", v6);
+            assert_eq!(res.fragmentation.reassembled, 1, "v6={}", v6);
+            assert_eq!(res.dns.queries, 1, "DNS should see the reassembled query (v6={})", v6);
+            assert_eq!(res.flows.len(), 1);
+            assert!(issue(&res, "IP Fragmentation").is_some());
+        }
+
+        // Last fragment lost.
+        let mut frames = fragmented_dns_frames(false, 256);
+        frames.pop();
+        let res = analyze_frames("fraglost", &frames);
+        assert_eq!(res.fragmentation.incomplete, 1);
+        assert_eq!(res.dns.queries, 0);
+        assert!(issue(&res, "Incomplete Fragmented Datagrams").is_some());
+    }
+
+    #[test]
+    fn test_packet_index_detail_and_follow() {
+        let req = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let resp = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi";
+        let mut syn = Vec::new();
+        PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+            .ipv4(CLIENT, SERVER, 64)
+            .tcp(50010, 80, 0, 64240)
+            .syn()
+            .write(&mut syn, &[])
+            .unwrap();
+        let frames = vec![
+            syn,
+            tcp_frame(CLIENT, SERVER, 50010, 80, 1 + 20, &req[20..]), // arrives before the first half
+            tcp_frame(CLIENT, SERVER, 50010, 80, 1, &req[..20]),
+            tcp_frame(CLIENT, SERVER, 50010, 80, 1, &req[..20]), // retransmission
+            tcp_frame(SERVER, CLIENT, 80, 50010, 1, resp),
+            tcp_frame(CLIENT, SERVER, 50011, 80, 1, b"other flow"),
+        ];
+        let path = write_pcap("follow", &frames);
+        let path = path.to_string_lossy().to_string();
+        let (_, mut index) = run_analysis(&path, None, &AtomicBool::new(false), |_| {}).unwrap();
+        index.seal(&path);
+
+        let page = detail::packet_page(&index, &path, 4, 10, None).unwrap();
+        assert_eq!(page.total, 6);
+        assert_eq!(page.packets.iter().map(|p| p.number).collect::<Vec<_>>(), vec![5, 6]);
+        assert_eq!(page.packets[0].protocol, "HTTP");
+        assert_eq!(page.packets[0].info, "HTTP/1.1 200 OK");
+        assert_eq!(page.packets[0].source, "93.184.216.34");
+
+        let pkt = detail::packet_detail(&index, &path, 5).unwrap();
+        let names: Vec<&str> = pkt.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Frame", "Ethernet II", "Internet Protocol Version 4", "Transmission Control Protocol", "HTTP"]);
+        assert!(pkt.layers[4].fields.iter().any(|f| f.name == "Content-Length" && f.value == "2"));
+        assert!(pkt.hex_dump.starts_with("0000  02 00 00 00 00 02 02 00  00 00 00 01 08 00 45 00"));
+        assert!(detail::packet_detail(&index, &path, 7).is_err());
+
+        // Filtered paging counts matching packets but keeps the file's numbering.
+        let http = DisplayFilter::parse("http").unwrap();
+        let page = detail::packet_page(&index, &path, 1, 10, Some(&http)).unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.packets.iter().map(|p| p.number).collect::<Vec<_>>(), vec![4, 5]);
+
+        let stream = follow::follow_stream(&index, &path, 2, "ascii").unwrap();
+        assert_eq!((stream.client.as_str(), stream.server.as_str()), ("10.0.0.5:50010", "93.184.216.34:80"));
+        assert_eq!(stream.segments.len(), 2);
+        assert!(stream.segments[0].from_client);
+        assert_eq!(stream.segments[0].data.as_bytes(), req);
+        assert_eq!(stream.segments[1].data.as_bytes(), resp);
+        assert_eq!((stream.bytes_to_server, stream.bytes_to_client), (req.len() as u64, resp.len() as u64));
+
+        let hex = follow::follow_stream(&index, &path, 6, "hex").unwrap();
+        assert_eq!(hex.segments.len(), 1);
+        assert!(hex.segments[0].data.ends_with("other flow\n"));
+        assert!(follow::follow_stream(&index, &path, 6, "base64").is_err());
+
+        // A rewritten file is refused rather than misread.
+        std::fs::write(&path, b"not a capture").unwrap();
+        assert!(detail::packet_page(&index, &path, 0, 10, None).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_display_filter_limits_analysis() {
+        let req = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let resp = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let frames = vec![
+            tcp_frame(CLIENT, SERVER, 50010, 80, 1, req),
+            tcp_frame(SERVER, CLIENT, 80, 50010, 1, resp),
+            tcp_frame(CLIENT, [10, 0, 0, 9], 50020, 443, 1, b"not for us"),
+        ];
+        let path = write_pcap("filtered", &frames);
+        let path = path.to_string_lossy().to_string();
+        let filter = DisplayFilter::parse("ip.addr == 93.184.216.34 && tcp.port == 80").unwrap();
+        let (res, mut index) = run_analysis(&path, Some(filter), &AtomicBool::new(false), |_| {}).unwrap();
+        assert_eq!((res.packet_count, res.filtered_out), (2, 1));
+        assert_eq!(res.flows.len(), 1);
+        assert_eq!(res.http.len(), 1);
+
+        // Filtered-out packets are still indexed and can be followed.
+        index.seal(&path);
+        assert_eq!(index.len(), 3);
+        let stream = follow::follow_stream(&index, &path, 3, "ascii").unwrap();
+        assert_eq!(stream.client, "10.0.0.5:50020");
+        std::fs::remove_file(&path).ok();
+
+        assert!(parse_filter(Some("  ".into())).unwrap().is_none());
+        assert_eq!(parse_filter(Some("tcp.port ==".into())).err().unwrap(), "Invalid display filter: Expected a value after 'tcp.port' at position 12");
+    }
+
+    #[test]
+    fn test_compare_captures_across_nat() {
+        let public = [203, 0, 113, 1];
+        let frame = |src, dst, ttl, dscp, id, sport, dport, seq, payload: &[u8]| {
+            let mut ip = etherparse::Ipv4Header::new(0, ttl, etherparse::IpNumber::TCP, src, dst).unwrap();
+            ip.identification = id;
+            ip.dscp = etherparse::IpDscp::try_new(dscp).unwrap();
+            let mut out = Vec::new();
+            PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+                .ip(etherparse::IpHeaders::Ipv4(ip, Default::default()))
+                .tcp(sport, dport, seq, 64240)
+                .ack(1)
+                .write(&mut out, payload)
+                .unwrap();
+            out
+        };
+        // Inside the NAT, with B's clock 5 s ahead and 2 ms of delay each way.
+        let inside = write_timed_pcap("cmp_a", &[
+            (0, frame(CLIENT, SERVER, 64, 46, 1, 50000, 443, 1, b"hello")),
+            (10_000, frame(CLIENT, SERVER, 64, 46, 2, 50000, 443, 6, b"world")), // lost
+            (20_000, frame(CLIENT, SERVER, 64, 46, 3, 50000, 443, 11, b"again")),
+            (30_000, frame(SERVER, CLIENT, 63, 0, 7, 443, 50000, 1, b"reply")),
+            (40_000, tcp_frame(CLIENT, [10, 0, 0, 1], 50001, 22, 1, b"local")),
+        ]);
+        let outside = write_timed_pcap("cmp_b", &[
+            (5_002_000, frame(public, SERVER, 63, 0, 1, 40000, 443, 1, b"hello")),
+            (5_022_000, frame(public, SERVER, 63, 0, 3, 40000, 443, 11, b"again")),
+            (5_028_000, frame(SERVER, public, 64, 0, 7, 443, 40000, 1, b"reply")),
+        ]);
+        let res = compare::compare_captures(&inside.to_string_lossy(), &outside.to_string_lossy()).unwrap();
+        std::fs::remove_file(&inside).ok();
+        std::fs::remove_file(&outside).ok();
+
+        assert_eq!((res.packets_a, res.packets_b, res.matched), (5, 3, 3));
+        assert!((res.clock_offset_ms.unwrap() - 5000.0).abs() < 0.01);
+
+        let out = &res.flows[0];
+        assert_eq!(out.direction, "a_to_b");
+        assert_eq!((out.source.as_str(), out.translated_source.as_str()), ("10.0.0.5:50000", "203.0.113.1:40000"));
+        assert_eq!((out.packets_sent, out.packets_received, out.dropped), (3, 2, 1));
+        assert_eq!(out.ttl_decrement, Some(1));
+        assert_eq!((out.dscp_sent, out.dscp_received, out.dscp_remarked), (Some(46), Some(0), 2));
+        assert!((out.avg_latency_ms.unwrap() - 2.0).abs() < 0.01);
+
+        let back = res.flows.iter().find(|f| f.direction == "b_to_a").unwrap();
+        assert_eq!((back.source.as_str(), back.destination.as_str()), ("93.184.216.34:443", "203.0.113.1:40000"));
+        assert!((back.min_latency_ms.unwrap() - 2.0).abs() < 0.01);
+        assert_eq!(res.flows.iter().filter(|f| f.direction == "a_only").count(), 1);
+
+        assert_eq!(res.nat_translations.len(), 1);
+        assert_eq!((res.nat_translations[0].seen_in_a.as_str(), res.nat_translations[0].flows), ("10.0.0.5:50000", 2));
+        let titles: Vec<&str> = res.issues.iter().map(|i| i.title.as_str()).collect();
+        assert_eq!(titles, ["Packets Dropped in Transit", "Flows Seen on One Side Only", "DSCP Remarked", "NAT Detected"]);
+        assert_eq!(res.issues[0].severity, "critical");
+    }
+}
@@ -0,0 +1,603 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use ring::aead::{self, quic, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::hkdf;
+
+use crate::modules::utils::{PcapIssue, QuicConnection, QuicSummary};
+use super::tls::{self, Reader};
+
+// Clients send their ClientHello in the first few Initials; anything larger is not one.
+const MAX_CRYPTO_BUFFER: usize = 64 * 1024;
+const MAX_CONNECTIONS: usize = 10_000;
+const MAX_TCP_SYNS: usize = 100_000;
+// How long after its last Initial a TCP connection to the same server counts as the fallback.
+const FALLBACK_WINDOW: f64 = 10.0;
+const MAX_LISTED: usize = 5;
+
+const VERSION_1: u32 = 0x0000_0001;
+const VERSION_2: u32 = 0x6b33_43cf;
+const DRAFT_29: u32 = 0xff00_001d;
+
+// RFC 9001 5.2, RFC 9369 3.3.1, draft-ietf-quic-tls-29 5.2.
+const SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad, 0xcc, 0xbb, 0x7f, 0x0a,
+];
+const SALT_V2: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb, 0xf9, 0xbd, 0x2e, 0xd9,
+];
+const SALT_DRAFT_29: [u8; 20] = [
+    0xaf, 0xbf, 0xec, 0x28, 0x99, 0x93, 0xd2, 0x4c, 0x9e, 0x97, 0x86, 0xf1, 0x9c, 0x61, 0x11, 0xe0, 0x43, 0x90, 0xa8, 0x99,
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum PacketType {
+    Initial,
+    ZeroRtt,
+    Handshake,
+    Retry,
+}
+
+fn version_name(version: u32) -> Option<String> {
+    match version {
+        VERSION_1 => Some("QUIC v1".into()),
+        VERSION_2 => Some("QUIC v2".into()),
+        v if v >> 8 == 0xff0000 => Some(format!("draft-{}", v & 0xff)),
+        _ => None,
+    }
+}
+
+/// Long header packet types are numbered differently in v2 (RFC 9369 3.2).
+fn packet_type(version: u32, bits: u8) -> PacketType {
+    let bits = if version == VERSION_2 { bits.wrapping_sub(1) & 3 } else { bits };
+    match bits {
+        0 => PacketType::Initial,
+        1 => PacketType::ZeroRtt,
+        2 => PacketType::Handshake,
+        _ => PacketType::Retry,
+    }
+}
+
+struct Len(usize);
+
+impl hkdf::KeyType for Len {
+    fn len(&self) -> usize { self.0 }
+}
+
+/// HKDF-Expand-Label from TLS 1.3 with an empty context.
+fn expand_label(prk: &hkdf::Prk, label: &[u8], len: usize) -> Option<Vec<u8>> {
+    let full_label = [b"tls13 ".as_slice(), label].concat();
+    let out_len = (len as u16).to_be_bytes();
+    let info = [&out_len[..], &[full_label.len() as u8], &full_label, &[0u8]];
+    let mut out = vec![0u8; len];
+    prk.expand(&info, Len(len)).ok()?.fill(&mut out).ok()?;
+    Some(out)
+}
+
+/// The client's Initial packet protection key, IV and header protection key,
+/// derived from the Destination Connection ID of its first Initial.
+fn client_initial_secrets(version: u32, dcid: &[u8]) -> Option<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let (salt, prefix): (&[u8], &[u8]) = match version {
+        VERSION_1 => (&SALT_V1, b"quic"),
+        VERSION_2 => (&SALT_V2, b"quicv2"),
+        DRAFT_29 => (&SALT_DRAFT_29, b"quic"),
+        _ => return None,
+    };
+    let initial = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(dcid);
+    let client = hkdf::Prk::new_less_safe(hkdf::HKDF_SHA256, &expand_label(&initial, b"client in", 32)?);
+    let label = |name: &str| [prefix, b" ", name.as_bytes()].concat();
+    Some((
+        expand_label(&client, &label("key"), 16)?,
+        expand_label(&client, &label("iv"), 12)?,
+        expand_label(&client, &label("hp"), 16)?,
+    ))
+}
+
+struct InitialKeys {
+    key: LessSafeKey,
+    iv: [u8; 12],
+    hp: quic::HeaderProtectionKey,
+}
+
+impl InitialKeys {
+    fn client(version: u32, dcid: &[u8]) -> Option<Self> {
+        let (key, iv, hp) = client_initial_secrets(version, dcid)?;
+        Some(InitialKeys {
+            key: LessSafeKey::new(UnboundKey::new(&aead::AES_128_GCM, &key).ok()?),
+            iv: iv.try_into().ok()?,
+            hp: quic::HeaderProtectionKey::new(&quic::AES_128, &hp).ok()?,
+        })
+    }
+
+    /// Removes header protection and decrypts one Initial packet (RFC 9001 5.3-5.4).
+    /// `pn_offset` is where the packet number starts.
+    fn open(&self, packet: &[u8], pn_offset: usize) -> Option<Vec<u8>> {
+        let mask = self.hp.new_mask(packet.get(pn_offset + 4..pn_offset + 20)?).ok()?;
+        let first = packet[0] ^ (mask[0] & 0x0f);
+        let pn_len = (first & 0x03) as usize + 1;
+        let mut header = packet.get(..pn_offset + pn_len)?.to_vec();
+        header[0] = first;
+        let mut pn = 0u64;
+        for i in 0..pn_len {
+            header[pn_offset + i] ^= mask[1 + i];
+            pn = pn << 8 | header[pn_offset + i] as u64;
+        }
+        // Initial packet numbers start at zero, so the truncated number is the full one.
+        let mut nonce = self.iv;
+        for (n, p) in nonce[4..].iter_mut().zip(pn.to_be_bytes()) {
+            *n ^= p;
+        }
+        let mut payload = packet[pn_offset + pn_len..].to_vec();
+        let plain = self.key.open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(&header), &mut payload).ok()?;
+        Some(plain.to_vec())
+    }
+}
+
+fn varint(r: &mut Reader) -> Option<u64> {
+    let first = r.u8()?;
+    let len = 1usize << (first >> 6);
+    let mut value = (first & 0x3f) as u64;
+    for &b in r.take(len - 1)? {
+        value = value << 8 | b as u64;
+    }
+    Some(value)
+}
+
+/// CRYPTO frame data from a decrypted Initial, by stream offset.
+fn crypto_frames(plain: &[u8]) -> Vec<(u64, &[u8])> {
+    let mut frames = Vec::new();
+    let mut r = Reader::new(plain);
+    while let Some(frame_type) = varint(&mut r) {
+        let parsed = match frame_type {
+            0x00 | 0x01 => Some(()), // PADDING, PING
+            0x02 | 0x03 => (|| {
+                varint(&mut r)?; // largest acknowledged
+                varint(&mut r)?; // delay
+                let ranges = varint(&mut r)?;
+                varint(&mut r)?; // first range
+                for _ in 0..ranges {
+                    varint(&mut r)?;
+                    varint(&mut r)?;
+                }
+                if frame_type == 0x03 {
+                    for _ in 0..3 { varint(&mut r)?; }
+                }
+                Some(())
+            })(),
+            0x06 => (|| {
+                let offset = varint(&mut r)?;
+                let len = varint(&mut r)? as usize;
+                frames.push((offset, r.take(len)?));
+                Some(())
+            })(),
+            // CONNECTION_CLOSE ends the packet's useful content; nothing else belongs in an Initial.
+            _ => None,
+        };
+        if parsed.is_none() {
+            break;
+        }
+    }
+    frames
+}
+
+struct Connection {
+    info: QuicConnection,
+    version: u32,
+    client_ip: String,
+    server_ip: String,
+    // DCID the client's Initials are protected with; replaced by a Retry
+    keys_dcid: Vec<u8>,
+    keys: Option<InitialKeys>,
+    crypto: BTreeMap<u64, Vec<u8>>,
+    crypto_bytes: usize,
+    hello_done: bool,
+}
+
+impl Connection {
+    fn add_crypto(&mut self, offset: u64, data: &[u8]) {
+        if self.hello_done || data.is_empty() { return; }
+        self.crypto_bytes += data.len();
+        if self.crypto_bytes > MAX_CRYPTO_BUFFER {
+            self.hello_done = true;
+            return;
+        }
+        self.crypto.entry(offset).or_insert_with(|| data.to_vec());
+
+        let mut stream = Vec::new();
+        for (&offset, data) in &self.crypto {
+            let offset = offset as usize;
+            if offset > stream.len() { break; }
+            if offset + data.len() > stream.len() {
+                stream.extend_from_slice(&data[stream.len() - offset..]);
+            }
+        }
+        if stream.len() < 4 { return; }
+        let msg_len = u32::from_be_bytes([0, stream[1], stream[2], stream[3]]) as usize;
+        if stream.len() < 4 + msg_len { return; }
+        self.hello_done = true;
+        if stream[0] != tls::CLIENT_HELLO { return; }
+        if let Some(hello) = tls::parse_client_hello(&stream[4..4 + msg_len]) {
+            self.info.sni = hello.sni;
+            self.info.alpn_offered = hello.alpn;
+        }
+        self.crypto.clear();
+    }
+}
+
+/// Identifies QUIC by its long header, groups packets into connections by
+/// connection ID, and decrypts client Initials (their keys derive from public
+/// values) to read the SNI and ALPN of the ClientHello. Clients whose Initials go
+/// unanswered and then open TCP/443 to the same server are reported as QUIC being
+/// blocked.
+#[derive(Default)]
+pub struct QuicAnalyzer {
+    summary: QuicSummary,
+    connections: Vec<Connection>,
+    by_cid: HashMap<Vec<u8>, usize>,
+    // lengths of the connection IDs in `by_cid`, to find short-header DCIDs
+    cid_lengths: BTreeSet<usize>,
+    by_endpoints: HashMap<(String, String), usize>,
+    // TCP/443 SYNs: (client IP, server IP) -> times
+    tcp_syns: HashMap<(String, String), Vec<f64>>,
+    tcp_syn_count: usize,
+}
+
+impl QuicAnalyzer {
+    /// `src` and `dst` are `ip:port` endpoints. Returns true if the datagram was QUIC.
+    pub fn process_datagram(&mut self, src: &str, dst: &str, data: &[u8], pkt_len: u64, ts: f64) -> bool {
+        let Some(&first) = data.first() else { return false };
+        if first & 0x80 == 0 {
+            let Some(idx) = self.find_short_header(src, dst, data) else { return false };
+            self.summary.packets += 1;
+            self.count(idx, src, pkt_len, ts);
+            return true;
+        }
+
+        let mut at = 0;
+        let mut conn_idx = None;
+        while at < data.len() {
+            let packet = &data[at..];
+            let Some((end, idx)) = self.process_long_header(src, dst, packet, ts) else { break };
+            conn_idx = conn_idx.or(idx);
+            at += end;
+        }
+        let Some(idx) = conn_idx else { return at > 0 };
+        self.summary.packets += 1;
+        self.count(idx, src, pkt_len, ts);
+        true
+    }
+
+    /// Handles one long header packet of a datagram. Returns the packet's length and
+    /// its connection, or None if this isn't a QUIC packet.
+    fn process_long_header(&mut self, src: &str, dst: &str, packet: &[u8], ts: f64) -> Option<(usize, Option<usize>)> {
+        let mut r = Reader::new(packet);
+        let first = r.u8()?;
+        let version = u32::from_be_bytes(r.take(4)?.try_into().ok()?);
+        let dcid = r.vec8()?;
+        let scid = r.vec8()?;
+        if first & 0x80 == 0 || dcid.len() > 20 || scid.len() > 20 {
+            return None;
+        }
+        if version == 0 {
+            // Version Negotiation only counts once the connection it answers is known.
+            let idx = *self.by_cid.get(dcid)?;
+            self.summary.version_negotiations += 1;
+            return Some((packet.len(), Some(idx)));
+        }
+        let name = version_name(version)?;
+        // Fixed bit (RFC 9000 17.2); QUIC bit greasing (RFC 9287) is rare enough to ignore.
+        if first & 0x40 == 0 {
+            return None;
+        }
+        let kind = packet_type(version, (first >> 4) & 0x03);
+        let (pn_offset, end) = match kind {
+            PacketType::Retry => (0, packet.len()),
+            _ => {
+                if kind == PacketType::Initial {
+                    let token_len = varint(&mut r)? as usize;
+                    r.skip(token_len)?;
+                }
+                let len = varint(&mut r)? as usize;
+                let pn_offset = r.position();
+                (pn_offset, pn_offset.checked_add(len).filter(|&e| e <= packet.len())?)
+            },
+        };
+        *self.summary.versions.entry(name).or_insert(0) += 1;
+
+        // Clients may use zero-length connection IDs, leaving only the endpoints to go by.
+        let known = self.by_cid.get(dcid)
+            .or_else(|| self.by_endpoints.get(&(dst.to_string(), src.to_string())))
+            .or_else(|| self.by_endpoints.get(&(src.to_string(), dst.to_string())))
+            .copied();
+        let idx = match known {
+            Some(idx) => idx,
+            None if kind == PacketType::Initial && self.connections.len() < MAX_CONNECTIONS => {
+                self.start_connection(src, dst, version, dcid, scid, ts)
+            },
+            None => return Some((end, None)),
+        };
+        let conn = &mut self.connections[idx];
+        if src == conn.info.client {
+            if kind == PacketType::Initial {
+                conn.info.initials += 1;
+                if !conn.hello_done {
+                    if conn.keys.is_none() {
+                        conn.keys = InitialKeys::client(conn.version, &conn.keys_dcid);
+                    }
+                    let plain = conn.keys.as_ref().and_then(|keys| keys.open(&packet[..end], pn_offset));
+                    for (offset, data) in plain.as_deref().map(crypto_frames).unwrap_or_default() {
+                        conn.add_crypto(offset, data);
+                    }
+                }
+            }
+        } else if !scid.is_empty() {
+            // The server's packets carry the connection ID the client switches to.
+            if conn.info.server_cid.is_none() {
+                conn.info.server_cid = Some(hex(scid));
+            }
+            if kind == PacketType::Retry {
+                conn.keys_dcid = scid.to_vec();
+                conn.keys = None;
+            }
+            if self.by_cid.len() < MAX_CONNECTIONS * 4 {
+                self.by_cid.insert(scid.to_vec(), idx);
+                self.cid_lengths.insert(scid.len());
+            }
+        }
+        Some((end, Some(idx)))
+    }
+
+    fn start_connection(&mut self, src: &str, dst: &str, version: u32, dcid: &[u8], scid: &[u8], ts: f64) -> usize {
+        let idx = self.connections.len();
+        self.connections.push(Connection {
+            info: QuicConnection {
+                client: src.to_string(),
+                server: dst.to_string(),
+                version: version_name(version).unwrap_or_default(),
+                original_dcid: hex(dcid),
+                client_cid: hex(scid),
+                first_seen: ts,
+                last_seen: ts,
+                ..Default::default()
+            },
+            version,
+            client_ip: host_of(src).to_string(),
+            server_ip: host_of(dst).to_string(),
+            keys_dcid: dcid.to_vec(),
+            keys: None,
+            crypto: BTreeMap::new(),
+            crypto_bytes: 0,
+            hello_done: false,
+        });
+        self.by_cid.insert(dcid.to_vec(), idx);
+        if !scid.is_empty() {
+            self.by_cid.insert(scid.to_vec(), idx);
+            self.cid_lengths.insert(scid.len());
+        }
+        self.cid_lengths.insert(dcid.len());
+        self.by_endpoints.insert((src.to_string(), dst.to_string()), idx);
+        idx
+    }
+
+    /// Short headers don't say how long the DCID is, so try the lengths in use,
+    /// then fall back to the endpoints for zero-length IDs.
+    fn find_short_header(&self, src: &str, dst: &str, data: &[u8]) -> Option<usize> {
+        if data[0] & 0x40 == 0 {
+            return None;
+        }
+        self.cid_lengths.iter()
+            .filter(|&&len| len > 0)
+            .find_map(|&len| data.get(1..1 + len).and_then(|dcid| self.by_cid.get(dcid)))
+            .or_else(|| self.by_endpoints.get(&(src.to_string(), dst.to_string())))
+            .or_else(|| self.by_endpoints.get(&(dst.to_string(), src.to_string())))
+            .copied()
+    }
+
+    fn count(&mut self, idx: usize, src: &str, pkt_len: u64, ts: f64) {
+        let info = &mut self.connections[idx].info;
+        info.last_seen = ts;
+        if src == info.client {
+            info.packets_to_server += 1;
+            info.bytes_to_server += pkt_len;
+        } else {
+            info.packets_to_client += 1;
+            info.bytes_to_client += pkt_len;
+            info.answered = true;
+        }
+    }
+
+    /// Records a TCP SYN so QUIC connections left unanswered can be matched to
+    /// the client falling back to TCP.
+    pub fn note_tcp_syn(&mut self, src_ip: &str, dst_ip: &str, dst_port: u16, ts: f64) {
+        if dst_port != 443 || self.tcp_syn_count >= MAX_TCP_SYNS || self.connections.is_empty() {
+            return;
+        }
+        self.tcp_syn_count += 1;
+        self.tcp_syns.entry((src_ip.to_string(), dst_ip.to_string())).or_default().push(ts);
+    }
+
+    pub fn finish(self, issues: &mut Vec<PcapIssue>) -> QuicSummary {
+        let mut summary = self.summary;
+        let tcp_syns = self.tcp_syns;
+        let mut blocked = Vec::new();
+        for conn in self.connections {
+            let mut info = conn.info;
+            if info.alpn_offered.iter().any(|a| a == "h3" || a.starts_with("h3-")) {
+                summary.http3_connections += 1;
+            }
+            if !info.answered && info.initials > 0 {
+                summary.blocked_connections += 1;
+                info.tcp_fallback = tcp_syns.get(&(conn.client_ip, conn.server_ip)).is_some_and(|times| {
+                    times.iter().any(|&t| t >= info.first_seen && t <= info.last_seen + FALLBACK_WINDOW)
+                });
+                blocked.push(summary.connections.len());
+            }
+            summary.connections.push(info);
+        }
+
+        if !blocked.is_empty() {
+            let fallbacks = blocked.iter().filter(|&&i| summary.connections[i].tcp_fallback).count();
+            let servers: Vec<String> = blocked.iter().take(MAX_LISTED).map(|&i| {
+                let c = &summary.connections[i];
+                match &c.sni {
+                    Some(sni) => format!("{} ({})", c.server, sni),
+                    None => c.server.clone(),
+                }
+            }).collect();
+            let more = blocked.len().saturating_sub(MAX_LISTED);
+            issues.push(PcapIssue {
+                severity: if fallbacks > 0 { "warn".into() } else { "info".into() },
+                title: "QUIC Blocked".into(),
+                description: format!(
+                    "{} QUIC connection(s) got no answer to their Initial packets{}: {}{}. Something on the path drops UDP/443, so HTTP/3 is unusable and clients pay a fallback delay.",
+                    blocked.len(),
+                    if fallbacks > 0 { format!(", {} then fell back to TCP/443", fallbacks) } else { String::new() },
+                    servers.join(", "),
+                    if more > 0 { format!(" (+{} more)", more) } else { String::new() },
+                ),
+                timestamp: Some(summary.connections[blocked[0]].first_seen),
+            });
+        }
+        summary
+    }
+}
+
+fn host_of(endpoint: &str) -> &str {
+    endpoint.rsplit_once(':').map_or(endpoint, |(host, _)| host.trim_start_matches('[').trim_end_matches(']'))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DCID: [u8; 8] = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+
+    fn varint2(v: usize) -> [u8; 2] {
+        (0x4000 | v as u16).to_be_bytes()
+    }
+
+    fn long_header(first: u8, dcid: &[u8], scid: &[u8], len: usize) -> Vec<u8> {
+        let mut header = vec![first];
+        header.extend_from_slice(&VERSION_1.to_be_bytes());
+        header.push(dcid.len() as u8);
+        header.extend_from_slice(dcid);
+        header.push(scid.len() as u8);
+        header.extend_from_slice(scid);
+        header.push(0); // token length
+        header.extend_from_slice(&varint2(len));
+        header
+    }
+
+    /// A client Initial carrying the given CRYPTO frames, protected as a client would.
+    fn client_initial(dcid: &[u8], scid: &[u8], pn: u8, crypto: &[(usize, &[u8])]) -> Vec<u8> {
+        let (key, iv, hp) = client_initial_secrets(VERSION_1, dcid).unwrap();
+        let mut frames = Vec::new();
+        for (offset, data) in crypto {
+            frames.push(0x06);
+            frames.extend_from_slice(&varint2(*offset));
+            frames.extend_from_slice(&varint2(data.len()));
+            frames.extend_from_slice(data);
+        }
+        frames.resize(1100, 0);
+
+        let mut packet = long_header(0xc0, dcid, scid, 1 + frames.len() + 16);
+        let pn_offset = packet.len();
+        packet.push(pn);
+        let mut nonce: [u8; 12] = iv.try_into().unwrap();
+        nonce[11] ^= pn;
+        let key = LessSafeKey::new(UnboundKey::new(&aead::AES_128_GCM, &key).unwrap());
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(packet.clone()), &mut frames).unwrap();
+        packet.extend_from_slice(&frames);
+
+        let hp = quic::HeaderProtectionKey::new(&quic::AES_128, &hp).unwrap();
+        let mask = hp.new_mask(&packet[pn_offset + 4..pn_offset + 20]).unwrap();
+        packet[0] ^= mask[0] & 0x0f;
+        packet[pn_offset] ^= mask[1];
+        packet
+    }
+
+    fn client_hello(sni: &str, alpn: &str) -> Vec<u8> {
+        let mut server_name = vec![0, (sni.len() + 3) as u8, 0, 0, sni.len() as u8];
+        server_name.extend_from_slice(sni.as_bytes());
+        let mut protocols = vec![0, 16, 0, (alpn.len() + 3) as u8, 0, (alpn.len() + 1) as u8, alpn.len() as u8];
+        protocols.extend_from_slice(alpn.as_bytes());
+        let mut extensions = vec![0, 0, 0, server_name.len() as u8];
+        extensions.extend_from_slice(&server_name);
+        extensions.extend_from_slice(&protocols);
+
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&[0; 32]);
+        body.extend_from_slice(&[0, 0, 2, 0x13, 0x01, 1, 0]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+        let mut msg = vec![tls::CLIENT_HELLO, 0, 0, body.len() as u8];
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    #[test]
+    fn test_initial_secrets_match_rfc9001() {
+        let (key, iv, hp) = client_initial_secrets(VERSION_1, &DCID).unwrap();
+        assert_eq!(hex(&key), "1f369613dd76d5467730efcbe3b1a22d");
+        assert_eq!(hex(&iv), "fa044b2f42a3fd3b46fb255c");
+        assert_eq!(hex(&hp), "9f50449e04a0e810283a1e9933adedd2");
+    }
+
+    #[test]
+    fn test_connections_sni_and_blocking() {
+        let (client, server) = ("10.0.0.5:50000", "142.250.1.1:443");
+        let (client_cid, server_cid) = ([0xc1; 8], [0x5e; 8]);
+        let mut quic = QuicAnalyzer::default();
+
+        // The ClientHello spans two Initials, second half first.
+        let hello = client_hello("www.example.com", "h3");
+        let (head, tail) = hello.split_at(30);
+        let second = client_initial(&DCID, &client_cid, 1, &[(30, tail)]);
+        assert!(quic.process_datagram(client, server, &second, 1250, 1.0));
+        let first = client_initial(&DCID, &client_cid, 0, &[(0, head)]);
+        assert!(quic.process_datagram(client, server, &first, 1250, 1.0));
+
+        let mut reply = long_header(0xc0, &client_cid, &server_cid, 40);
+        reply.extend_from_slice(&[0xaa; 40]);
+        assert!(quic.process_datagram(server, client, &reply, 1250, 1.03));
+        let mut short = vec![0x40];
+        short.extend_from_slice(&server_cid);
+        short.extend_from_slice(&[0xbb; 30]);
+        assert!(quic.process_datagram(client, server, &short, 80, 1.05));
+
+        // Initials to a second server go unanswered, and the client tries TCP instead.
+        for ts in [2.0, 2.3] {
+            let initial = client_initial(&[0xd2; 8], &[0xc2; 8], 0, &[(0, &client_hello("blocked.example", "h3"))]);
+            quic.process_datagram("10.0.0.5:50001", "203.0.113.9:443", &initial, 1250, ts);
+        }
+        quic.note_tcp_syn("10.0.0.5", "203.0.113.9", 443, 2.6);
+
+        let mut not_quic = vec![0xc0, 0, 0, 0, 7, 0, 0];
+        not_quic.extend_from_slice(&[0; 20]);
+        assert!(!quic.process_datagram("10.0.0.5:5000", "10.0.0.9:5000", &not_quic, 60, 3.0));
+        assert!(!quic.process_datagram("10.0.0.5:5000", "10.0.0.9:5000", &[0x40, 1, 2, 3], 60, 3.0));
+
+        let mut issues = Vec::new();
+        let summary = quic.finish(&mut issues);
+        assert_eq!(summary.packets, 6);
+        assert_eq!(summary.versions["QUIC v1"], 5);
+        assert_eq!((summary.http3_connections, summary.blocked_connections), (2, 1));
+        assert_eq!(summary.connections.len(), 2);
+
+        let conn = &summary.connections[0];
+        assert_eq!(conn.sni.as_deref(), Some("www.example.com"));
+        assert_eq!(conn.alpn_offered, vec!["h3"]);
+        assert_eq!((conn.original_dcid.as_str(), conn.server_cid.as_deref()), ("8394c8f03e515708", Some("5e5e5e5e5e5e5e5e")));
+        assert_eq!((conn.initials, conn.packets_to_server, conn.packets_to_client), (2, 3, 1));
+        assert!(conn.answered && !conn.tcp_fallback);
+
+        let blocked = &summary.connections[1];
+        assert_eq!(blocked.sni.as_deref(), Some("blocked.example"));
+        assert!(!blocked.answered && blocked.tcp_fallback);
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].title.as_str(), issues[0].severity.as_str()), ("QUIC Blocked", "warn"));
+        assert!(issues[0].description.contains("1 then fell back to TCP/443: 203.0.113.9:443 (blocked.example)"));
+    }
+}
@@ -0,0 +1,276 @@
+use pcap_parser::*;
+use pcap_parser::traits::PcapReaderIterator;
+use std::fs::File;
+use std::ops::ControlFlow;
+
+// Large enough for TSO/GRO frames captured with the default 256K snaplen.
+const READER_CAPACITY: usize = 1 << 20;
+
+/// One captured frame, normalised across legacy pcap and pcapng.
+pub struct CapturedPacket<'a> {
+    /// Byte offset of the packet's block within the file.
+    pub offset: u64,
+    /// Byte offset of `data` within the file.
+    pub data_offset: u64,
+    pub ts: f64,
+    pub linktype: Linktype,
+    pub data: &'a [u8],
+    /// Length on the wire; more than `data.len()` when the capture was truncated.
+    pub orig_len: u32,
+}
+
+struct InterfaceInfo {
+    linktype: Linktype,
+    ts_resolution: u64,
+    ts_offset: i64,
+}
+
+/// Reads packets from either a legacy pcap or a pcapng file.
+///
+/// pcapng sections can describe several interfaces, each with its own link type and
+/// timestamp resolution (`if_tsresol`), so those are tracked per section and applied
+/// to every Enhanced/Simple Packet Block.
+pub struct CaptureReader {
+    inner: Box<dyn PcapReaderIterator + Send>,
+    interfaces: Vec<InterfaceInfo>,
+    legacy_linktype: Linktype,
+    legacy_nanos: bool,
+    last_ts: f64,
+    position: u64,
+}
+
+impl CaptureReader {
+    pub fn open(file_path: &str) -> Result<Self, String> {
+        let file = File::open(file_path).map_err(|e| e.to_string())?;
+        let inner = create_reader(READER_CAPACITY, file).map_err(|e| e.to_string())?;
+
+        Ok(CaptureReader {
+            inner,
+            interfaces: Vec::new(),
+            legacy_linktype: Linktype::ETHERNET,
+            legacy_nanos: false,
+            last_ts: 0.0,
+            position: 0,
+        })
+    }
+
+    /// Walks the capture, handing every packet to `on_packet` in file order until the
+    /// callback breaks or the file ends.
+    pub fn read_packets<F>(&mut self, mut on_packet: F) -> Result<(), String>
+    where
+        F: FnMut(&CapturedPacket) -> ControlFlow<()>,
+    {
+        loop {
+            match self.inner.next() {
+                Ok((offset, block)) => {
+                    let packet = match block {
+                        PcapBlockOwned::LegacyHeader(hdr) => {
+                            self.legacy_linktype = hdr.network;
+                            self.legacy_nanos = hdr.is_nanosecond_precision();
+                            None
+                        },
+                        PcapBlockOwned::Legacy(pkt) => {
+                            let frac = if self.legacy_nanos { 1_000_000_000.0 } else { 1_000_000.0 };
+                            let ts = pkt.ts_sec as f64 + (pkt.ts_usec as f64 / frac);
+                            Some(CapturedPacket {
+                                offset: self.position,
+                                data_offset: self.position + 16, // ts_sec, ts_usec, caplen, len
+                                ts,
+                                linktype: self.legacy_linktype,
+                                data: pkt.data,
+                                orig_len: pkt.origlen,
+                            })
+                        },
+                        PcapBlockOwned::NG(Block::SectionHeader(_)) => {
+                            // Interface IDs are scoped to their section.
+                            self.interfaces.clear();
+                            None
+                        },
+                        PcapBlockOwned::NG(Block::InterfaceDescription(idb)) => {
+                            self.interfaces.push(InterfaceInfo {
+                                linktype: idb.linktype,
+                                ts_resolution: idb.ts_resolution().unwrap_or(1_000_000),
+                                ts_offset: idb.ts_offset(),
+                            });
+                            None
+                        },
+                        PcapBlockOwned::NG(Block::EnhancedPacket(epb)) => {
+                            let iface = self.interfaces.get(epb.if_id as usize)
+                                .ok_or_else(|| format!("Packet references unknown interface {}", epb.if_id))?;
+                            let raw_ts = ((epb.ts_high as u64) << 32) | epb.ts_low as u64;
+                            let ts = (raw_ts / iface.ts_resolution) as f64
+                                + (raw_ts % iface.ts_resolution) as f64 / iface.ts_resolution as f64
+                                + iface.ts_offset as f64;
+                            Some(CapturedPacket {
+                                offset: self.position,
+                                data_offset: self.position + 28, // type, length, if_id, ts, caplen, len
+                                ts,
+                                linktype: iface.linktype,
+                                // `data` carries block padding; only the first `caplen` bytes are the frame.
+                                data: &epb.data[..(epb.caplen as usize).min(epb.data.len())],
+                                orig_len: epb.origlen,
+                            })
+                        },
+                        PcapBlockOwned::NG(Block::SimplePacket(spb)) => {
+                            // SPBs always belong to the first interface and carry no timestamp,
+                            // so they inherit the time of the previous packet.
+                            let iface = self.interfaces.first()
+                                .ok_or("Simple Packet Block before any Interface Description Block")?;
+                            Some(CapturedPacket {
+                                offset: self.position,
+                                data_offset: self.position + 12, // type, length, original length
+                                ts: self.last_ts,
+                                linktype: iface.linktype,
+                                data: &spb.data[..(spb.origlen as usize).min(spb.data.len())],
+                                orig_len: spb.origlen,
+                            })
+                        },
+                        _ => None,
+                    };
+
+                    let flow = match packet {
+                        Some(pkt) => {
+                            self.last_ts = pkt.ts;
+                            on_packet(&pkt)
+                        },
+                        None => ControlFlow::Continue(()),
+                    };
+                    self.inner.consume(offset);
+                    self.position += offset as u64;
+                    if flow.is_break() { break; }
+                },
+                Err(PcapError::Eof) => break,
+                // A capture cut off mid-write still has useful packets before the tear.
+                Err(PcapError::UnexpectedEof) => break,
+                Err(PcapError::Incomplete(_)) => {
+                    self.inner.refill().map_err(|e| e.to_string())?;
+                },
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let mut body = body.to_vec();
+        while !body.len().is_multiple_of(4) { body.push(0); }
+        let len = (body.len() + 12) as u32;
+        let mut out = Vec::new();
+        out.extend_from_slice(&block_type.to_le_bytes());
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&body);
+        out.extend_from_slice(&len.to_le_bytes());
+        out
+    }
+
+    fn idb(linktype: u16, tsresol: Option<u8>) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&linktype.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&65535u32.to_le_bytes());
+        if let Some(res) = tsresol {
+            body.extend_from_slice(&9u16.to_le_bytes()); // if_tsresol
+            body.extend_from_slice(&1u16.to_le_bytes());
+            body.extend_from_slice(&[res, 0, 0, 0]);
+            body.extend_from_slice(&[0, 0, 0, 0]); // opt_endofopt
+        }
+        ng_block(0x0000_0001, &body)
+    }
+
+    fn epb(if_id: u32, ts: u64, data: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&if_id.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        ng_block(0x0000_0006, &body)
+    }
+
+    fn spb(data: &[u8]) -> Vec<u8> {
+        let mut body = (data.len() as u32).to_le_bytes().to_vec();
+        body.extend_from_slice(data);
+        ng_block(0x0000_0003, &body)
+    }
+
+    fn write_temp(name: &str, bytes: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("trubleshoota_{}_{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn collect(path: &str) -> Vec<(f64, Linktype, Vec<u8>, u64)> {
+        let mut reader = CaptureReader::open(path).unwrap();
+        let file = std::fs::read(path).unwrap();
+        let mut out = Vec::new();
+        reader.read_packets(|p| {
+            let at = p.data_offset as usize;
+            assert_eq!(&file[at..at + p.data.len()], p.data, "data_offset must point at the frame");
+            out.push((p.ts, p.linktype, p.data.to_vec(), p.offset));
+            ControlFlow::Continue(())
+        }).unwrap();
+        std::fs::remove_file(path).ok();
+        out
+    }
+
+    #[test]
+    fn test_pcapng_multiple_interfaces() {
+        let mut shb_body = Vec::new();
+        shb_body.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+        shb_body.extend_from_slice(&1u16.to_le_bytes());
+        shb_body.extend_from_slice(&0u16.to_le_bytes());
+        shb_body.extend_from_slice(&(-1i64).to_le_bytes());
+
+        let mut file = ng_block(0x0A0D_0D0A, &shb_body);
+        file.extend(idb(1, None));      // Ethernet, default microseconds
+        file.extend(idb(101, Some(9))); // Raw IP, nanoseconds
+        file.extend(epb(0, 1_500_000, &[0xAA; 14]));
+        file.extend(epb(1, 2_250_000_000, &[0x45; 21])); // odd length exercises block padding
+        file.extend(spb(&[0xBB; 20]));
+
+        let pkts = collect(&write_temp("multi.pcapng", &file));
+        assert_eq!(pkts.len(), 3);
+
+        assert!((pkts[0].0 - 1.5).abs() < 1e-9);
+        assert_eq!(pkts[0].1, Linktype::ETHERNET);
+        assert_eq!(pkts[0].2.len(), 14);
+        assert_eq!(pkts[0].3, 28 + 20 + 32); // after the SHB and both IDBs
+
+        assert!((pkts[1].0 - 2.25).abs() < 1e-9);
+        assert_eq!(pkts[1].1, Linktype::RAW);
+        assert_eq!(pkts[1].2.len(), 21);
+
+        // Simple Packet Block: first interface, previous timestamp.
+        assert_eq!(pkts[2].1, Linktype::ETHERNET);
+        assert!((pkts[2].0 - 2.25).abs() < 1e-9);
+        assert_eq!(pkts[2].2.len(), 20);
+        assert_eq!(pkts[2].3, pkts[1].3 + 32 + 24);
+    }
+
+    #[test]
+    fn test_legacy_nanosecond_pcap() {
+        let mut file = Vec::new();
+        file.extend_from_slice(&0xa1b2_3c4du32.to_le_bytes());
+        file.extend_from_slice(&2u16.to_le_bytes());
+        file.extend_from_slice(&4u16.to_le_bytes());
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&65535u32.to_le_bytes());
+        file.extend_from_slice(&228u32.to_le_bytes()); // LINKTYPE_IPV4
+        file.extend_from_slice(&10u32.to_le_bytes());
+        file.extend_from_slice(&500_000_000u32.to_le_bytes());
+        file.extend_from_slice(&4u32.to_le_bytes());
+        file.extend_from_slice(&4u32.to_le_bytes());
+        file.extend_from_slice(&[1, 2, 3, 4]);
+
+        let pkts = collect(&write_temp("nsec.pcap", &file));
+        assert_eq!(pkts.len(), 1);
+        assert!((pkts[0].0 - 10.5).abs() < 1e-9);
+        assert_eq!(pkts[0].1, Linktype::IPV4);
+    }
+}
@@ -0,0 +1,656 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::net::IpAddr;
+use std::ops::ControlFlow;
+use etherparse::{EtherType, Icmpv4Type, LaxPacketHeaders, NetHeaders, TransportHeader};
+use hmac::{Hmac, Mac};
+use pcap_parser::Linktype;
+use sha2::{Digest, Sha256};
+
+use crate::modules::utils::{PcapSanitizeOptions, PcapSanitizeReport};
+use super::frag;
+use super::link::{self, IEEE802_11, IEEE802_11_RADIOTAP};
+use super::reader::CaptureReader;
+use super::writer::CaptureWriter;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const PCAPNG_MAGIC: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+const LABEL_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Writes a copy of `input_path` that is safe to hand to a third party: IP addresses
+/// anonymized prefix-preservingly (Crypto-PAn construction, with HMAC-SHA256 as the
+/// PRF), MACs replaced, payloads zeroed or truncated and DNS names hashed. The output
+/// has the same format and link types as the input, and checksums are recomputed.
+///
+/// The same `key` gives the same mapping across files; without one a random key is used.
+pub fn sanitize_capture(input_path: &str, output_path: &str, options: PcapSanitizeOptions) -> Result<PcapSanitizeReport, String> {
+    if !matches!(options.payload.as_str(), "keep" | "zero" | "truncate") {
+        return Err(format!("Unknown payload mode '{}'", options.payload));
+    }
+    let same_file = std::fs::canonicalize(input_path).ok()
+        .is_some_and(|input| std::fs::canonicalize(output_path).ok() == Some(input));
+    if same_file {
+        return Err("The sanitized capture must be written to a different file".into());
+    }
+
+    let mut magic = [0u8; 4];
+    File::open(input_path).and_then(|mut f| f.read_exact(&mut magic)).map_err(|e| e.to_string())?;
+    let pcapng = magic == PCAPNG_MAGIC;
+
+    let mut reader = CaptureReader::open(input_path)?;
+    let mut writer = CaptureWriter::create(output_path, pcapng)?;
+    let mut sanitizer = Sanitizer::new(options);
+    let mut write_error = None;
+
+    reader.read_packets(|pkt| {
+        let mut frame = pkt.data.to_vec();
+        sanitizer.sanitize(pkt.linktype, &mut frame);
+        match writer.write(pkt.linktype, pkt.ts, &frame, pkt.orig_len) {
+            Ok(_) => ControlFlow::Continue(()),
+            Err(e) => {
+                write_error = Some(e.to_string());
+                ControlFlow::Break(())
+            },
+        }
+    })?;
+    if let Some(e) = write_error { return Err(e); }
+    writer.finish().map_err(|e| e.to_string())?;
+
+    Ok(PcapSanitizeReport {
+        output_path: output_path.to_string(),
+        format: if pcapng { "pcapng".into() } else { "pcap".into() },
+        packets: sanitizer.packets,
+        addresses_anonymized: sanitizer.ips.len(),
+        macs_rewritten: sanitizer.macs.len(),
+        dns_names_hashed: sanitizer.dns_names,
+        payload_bytes_removed: sanitizer.payload_bytes_removed,
+        undecoded_packets: sanitizer.undecoded,
+    })
+}
+
+struct Sanitizer {
+    options: PcapSanitizeOptions,
+    key: [u8; 32],
+    ips: HashMap<IpAddr, IpAddr>,
+    macs: HashMap<[u8; 6], [u8; 6]>,
+    packets: usize,
+    dns_names: usize,
+    payload_bytes_removed: u64,
+    undecoded: usize,
+}
+
+impl Sanitizer {
+    fn new(options: PcapSanitizeOptions) -> Self {
+        let key = match &options.key {
+            Some(passphrase) => Sha256::digest(passphrase.as_bytes()).into(),
+            None => rand::random(),
+        };
+        Sanitizer {
+            options,
+            key,
+            ips: HashMap::new(),
+            macs: HashMap::new(),
+            packets: 0,
+            dns_names: 0,
+            payload_bytes_removed: 0,
+            undecoded: 0,
+        }
+    }
+
+    /// Rewrites one frame in place; it may come back shorter when payloads are truncated.
+    fn sanitize(&mut self, linktype: Linktype, frame: &mut Vec<u8>) {
+        self.packets += 1;
+        if self.options.rewrite_macs {
+            self.rewrite_link_addresses(linktype, frame);
+        }
+        match link::network_layer(linktype, frame) {
+            Some((0x0806, off)) => {
+                if !self.sanitize_arp(&mut frame[off..]) {
+                    self.scrub(frame, off);
+                }
+            },
+            Some((ether_type @ (0x0800 | 0x86dd), off)) => {
+                if let Some(end) = self.sanitize_ip(ether_type, frame, off) {
+                    frame.truncate(end);
+                }
+            },
+            // Anything we can't rewrite may still carry addresses or data.
+            Some((_, off)) => self.scrub(frame, off),
+            None => {
+                let off = link_header_len(linktype, frame);
+                self.scrub(frame, off);
+            },
+        }
+    }
+
+    /// Zeroes everything from `off` on, keeping only the (already rewritten) link header.
+    fn scrub(&mut self, frame: &mut [u8], off: usize) {
+        self.undecoded += 1;
+        if let Some(rest) = frame.get_mut(off..) {
+            self.payload_bytes_removed += rest.len() as u64;
+            rest.fill(0);
+        }
+    }
+
+    fn rewrite_link_addresses(&mut self, linktype: Linktype, frame: &mut [u8]) {
+        let (base, addresses): (usize, &[usize]) = match linktype {
+            Linktype::ETHERNET => (0, &[0, 6]),
+            // Cooked headers hold only the sender's address, and only if it is a MAC.
+            Linktype::LINUX_SLL if frame.get(4..6) == Some(&[0, 6]) => (0, &[6]),
+            Linktype::LINUX_SLL2 if frame.get(11) == Some(&6) => (0, &[12]),
+            IEEE802_11 | IEEE802_11_RADIOTAP => {
+                let Some(base) = ieee80211_start(linktype, frame) else { return };
+                let (Some(&fc), Some(&flags)) = (frame.get(base), frame.get(base + 1)) else { return };
+                let addresses: &[usize] = match (fc >> 2) & 0x3 {
+                    // Control frames: ACK and CTS carry the receiver only.
+                    1 if matches!(fc >> 4, 12 | 13) => &[4],
+                    1 => &[4, 10],
+                    2 if flags & 0x03 == 0x03 => &[4, 10, 16, 24],
+                    _ => &[4, 10, 16],
+                };
+                (base, addresses)
+            },
+            _ => return,
+        };
+        for &at in addresses {
+            self.rewrite_mac(frame, base + at);
+        }
+    }
+
+    /// Replaces the MAC at `at` with a keyed hash, keeping broadcast as is and the
+    /// multicast bit as it was, and marking the result locally administered.
+    fn rewrite_mac(&mut self, frame: &mut [u8], at: usize) {
+        let Some(slot) = frame.get_mut(at..at + 6) else { return };
+        let mac: [u8; 6] = slot.try_into().unwrap_or_default();
+        if mac == [0xff; 6] || mac == [0; 6] { return; }
+        let key = self.key;
+        let new = *self.macs.entry(mac).or_insert_with(|| {
+            let hash = prf(&key, &[b"mac".as_slice(), &mac]);
+            let mut new = [0u8; 6];
+            new.copy_from_slice(&hash[..6]);
+            new[0] = (new[0] & 0xfc) | 0x02 | (mac[0] & 0x01);
+            new
+        });
+        slot.copy_from_slice(&new);
+    }
+
+    /// Ethernet/IPv4 ARP: sender and target addresses. False for any other format.
+    fn sanitize_arp(&mut self, arp: &mut [u8]) -> bool {
+        if arp.len() < 28 || arp[4] != 6 || arp[5] != 4 { return false; }
+        if self.options.rewrite_macs {
+            self.rewrite_mac(arp, 8);
+            self.rewrite_mac(arp, 18);
+        }
+        self.rewrite_ipv4_at(arp, 14);
+        self.rewrite_ipv4_at(arp, 24);
+        true
+    }
+
+    /// Sanitizes an IP packet starting at `off`. Returns the new frame length when the
+    /// payload was truncated. Decoding is lax so that packets cut short by the capture
+    /// snaplen, whose IP lengths promise more than was captured, are still rewritten.
+    fn sanitize_ip(&mut self, ether_type: u16, frame: &mut [u8], off: usize) -> Option<usize> {
+        let headers = LaxPacketHeaders::from_ether_type(EtherType(ether_type), &frame[off..]);
+        let (mut net, mut transport, payload_len) = (headers.net.clone(), headers.transport.clone(), headers.payload.slice().len());
+        let ip_len = match &net {
+            Some(NetHeaders::Ipv4(ip, exts)) => ip.header_len() + exts.header_len(),
+            Some(NetHeaders::Ipv6(ip, exts)) => {
+                // Leave the payload of non-first fragments alone as transport headers.
+                if exts.fragment.as_ref().map(frag::Fragment::from_ipv6).is_some_and(|f| f.is_fragment()) {
+                    transport = None;
+                }
+                ip.header_len() + exts.header_len()
+            },
+            // Cut short inside the IP header.
+            _ => {
+                self.scrub(frame, off);
+                return None;
+            },
+        };
+        if matches!(&net, Some(NetHeaders::Ipv4(ip, _)) if ip.is_fragmenting_payload()) {
+            transport = None;
+        }
+
+        let transport_len = match &transport {
+            Some(TransportHeader::Tcp(t)) => t.header_len(),
+            Some(TransportHeader::Udp(_)) => 8,
+            Some(TransportHeader::Icmpv4(i)) => i.header_len(),
+            Some(TransportHeader::Icmpv6(i)) => i.header_len(),
+            None => 0,
+        };
+        // Fragments and unknown protocols: everything after the IP header is payload.
+        let payload_start = off + ip_len + transport_len;
+        let payload_end = if transport.is_some() { payload_start + payload_len } else { off + ip_len + payload_len };
+
+        match &mut net {
+            Some(NetHeaders::Ipv4(ip, _)) => {
+                ip.source = self.anonymize_v4(ip.source);
+                ip.destination = self.anonymize_v4(ip.destination);
+                ip.header_checksum = ip.calc_header_checksum();
+                let bytes = ip.to_bytes();
+                frame[off..off + bytes.len()].copy_from_slice(&bytes);
+            },
+            Some(NetHeaders::Ipv6(ip, _)) => {
+                ip.source = self.anonymize_v6(ip.source);
+                ip.destination = self.anonymize_v6(ip.destination);
+                frame[off..off + 40].copy_from_slice(&ip.to_bytes());
+            },
+            _ => {},
+        }
+
+        let ports = match &transport {
+            Some(TransportHeader::Tcp(t)) => Some((t.source_port, t.destination_port)),
+            Some(TransportHeader::Udp(u)) => Some((u.source_port, u.destination_port)),
+            _ => None,
+        };
+        let is_dns = self.options.hash_dns_names && ports.is_some_and(|(s, d)| s == 53 || d == 53);
+        let payload = &mut frame[payload_start..payload_end];
+        let mut keep_until = None;
+        if is_dns {
+            let is_tcp = matches!(transport, Some(TransportHeader::Tcp(_)));
+            let msg = if is_tcp { payload.get_mut(2..).unwrap_or_default() } else { payload };
+            if self.sanitize_dns(msg).is_some() {
+                self.dns_names += 1;
+            } else {
+                // Not a whole message (e.g. a TCP continuation): don't let names through.
+                self.payload_bytes_removed += msg.len() as u64;
+                msg.fill(0);
+            }
+        } else {
+            match self.options.payload.as_str() {
+                "keep" => self.sanitize_embedded_ip(&transport, payload),
+                "zero" => {
+                    self.payload_bytes_removed += payload.len() as u64;
+                    payload.fill(0);
+                },
+                _ => {
+                    // Like a capture snaplen: the wire length stays, the bytes go. Checksums
+                    // are computed as if the removed bytes were zero.
+                    let keep = self.options.payload_bytes.min(payload.len());
+                    self.payload_bytes_removed += (payload.len() - keep) as u64;
+                    payload[keep..].fill(0);
+                    keep_until = Some(payload_start + keep);
+                },
+            }
+        }
+
+        self.update_transport(&net, transport.as_mut(), &mut frame[off + ip_len..payload_end], transport_len);
+        keep_until
+    }
+
+    /// Recomputes the transport checksum over the rewritten addresses and payload.
+    fn update_transport(&mut self, net: &Option<NetHeaders>, transport: Option<&mut TransportHeader>, segment: &mut [u8], header_len: usize) {
+        let Some(transport) = transport else { return };
+        let (header, payload) = segment.split_at_mut(header_len);
+        match (net, transport) {
+            (Some(NetHeaders::Ipv4(ip, _)), TransportHeader::Tcp(t)) => {
+                t.checksum = t.calc_checksum_ipv4(ip, payload).unwrap_or(t.checksum);
+                header.copy_from_slice(&t.to_bytes());
+            },
+            (Some(NetHeaders::Ipv6(ip, _)), TransportHeader::Tcp(t)) => {
+                t.checksum = t.calc_checksum_ipv6(ip, payload).unwrap_or(t.checksum);
+                header.copy_from_slice(&t.to_bytes());
+            },
+            // A zero UDP checksum over IPv4 means "none" and stays that way.
+            (Some(NetHeaders::Ipv4(ip, _)), TransportHeader::Udp(u)) if u.checksum != 0 => {
+                u.checksum = u.calc_checksum_ipv4(ip, payload).unwrap_or(u.checksum);
+                header.copy_from_slice(&u.to_bytes());
+            },
+            (Some(NetHeaders::Ipv6(ip, _)), TransportHeader::Udp(u)) => {
+                u.checksum = u.calc_checksum_ipv6(ip, payload).unwrap_or(u.checksum);
+                header.copy_from_slice(&u.to_bytes());
+            },
+            (_, TransportHeader::Icmpv4(i)) => {
+                if let Icmpv4Type::Redirect(redirect) = &mut i.icmp_type {
+                    redirect.gateway_internet_address = self.anonymize_v4(redirect.gateway_internet_address);
+                }
+                i.update_checksum(payload);
+                header.copy_from_slice(&i.to_bytes());
+            },
+            (Some(NetHeaders::Ipv6(ip, _)), TransportHeader::Icmpv6(i)) => {
+                // Only fails for payloads too long for an IPv6 packet.
+                let _ = i.update_checksum(ip.source, ip.destination, payload);
+                header.copy_from_slice(&i.to_bytes());
+            },
+            _ => {},
+        }
+    }
+
+    /// ICMP errors quote the offending packet's IP header; anonymize its addresses too.
+    fn sanitize_embedded_ip(&mut self, transport: &Option<TransportHeader>, payload: &mut [u8]) {
+        let is_error = match transport {
+            Some(TransportHeader::Icmpv4(i)) => matches!(i.to_bytes()[0], 3 | 4 | 5 | 11 | 12),
+            Some(TransportHeader::Icmpv6(i)) => matches!(i.to_bytes()[0], 1..=4),
+            _ => false,
+        };
+        if !is_error { return; }
+        match payload.first().map(|b| b >> 4) {
+            Some(4) if payload.len() >= 20 => {
+                self.rewrite_ipv4_at(payload, 12);
+                self.rewrite_ipv4_at(payload, 16);
+                let ihl = ((payload[0] & 0x0f) as usize * 4).clamp(20, payload.len());
+                payload[10..12].fill(0);
+                let sum = etherparse::checksum::Sum16BitWords::new().add_slice(&payload[..ihl]).ones_complement().to_be();
+                payload[10..12].copy_from_slice(&sum.to_be_bytes());
+            },
+            Some(6) if payload.len() >= 40 => {
+                for at in [8, 24] {
+                    let addr: [u8; 16] = payload[at..at + 16].try_into().unwrap_or_default();
+                    payload[at..at + 16].copy_from_slice(&self.anonymize_v6(addr));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Hashes the names and anonymizes the addresses of a DNS message in place. Record
+    /// data we can't rewrite (TXT, EDNS options, ...) is zeroed.
+    fn sanitize_dns(&mut self, msg: &mut [u8]) -> Option<()> {
+        if msg.len() < 12 { return None; }
+        let count = |msg: &[u8], at: usize| u16::from_be_bytes([msg[at], msg[at + 1]]) as usize;
+        let questions = count(msg, 4);
+        let records = count(msg, 6) + count(msg, 8) + count(msg, 10);
+
+        let mut pos = 12;
+        for _ in 0..questions {
+            pos = self.hash_name(msg, pos)? + 4;
+        }
+        for _ in 0..records {
+            pos = self.hash_name(msg, pos)?;
+            let header = msg.get(pos..pos + 10)?;
+            let rtype = u16::from_be_bytes([header[0], header[1]]);
+            let rdlen = u16::from_be_bytes([header[8], header[9]]) as usize;
+            let (start, end) = (pos + 10, pos + 10 + rdlen);
+            if end > msg.len() { return None; }
+            match rtype {
+                1 if rdlen == 4 => self.rewrite_ipv4_at(msg, start),
+                28 if rdlen == 16 => {
+                    let addr: [u8; 16] = msg[start..end].try_into().ok()?;
+                    msg[start..end].copy_from_slice(&self.anonymize_v6(addr));
+                },
+                // NS, CNAME, PTR
+                2 | 5 | 12 => { self.hash_name(msg, start)?; },
+                15 => { self.hash_name(msg, start + 2)?; }, // MX: preference, exchange
+                6 => { // SOA: primary server, mailbox
+                    let next = self.hash_name(msg, start)?;
+                    self.hash_name(msg, next)?;
+                },
+                _ => msg[start..end].fill(0),
+            }
+            pos = end;
+        }
+        Some(())
+    }
+
+    /// Replaces each label written out in full with a keyed hash of the same length, so
+    /// compression pointers stay valid. Pointers aren't followed: the labels they point
+    /// at are rewritten where they are written out. A name's last label (usually the TLD)
+    /// is kept. Returns the offset just past the name.
+    fn hash_name(&mut self, msg: &mut [u8], mut pos: usize) -> Option<usize> {
+        loop {
+            let len = *msg.get(pos)? as usize;
+            if len == 0 { return Some(pos + 1); }
+            if len & 0xc0 == 0xc0 {
+                msg.get(pos + 1)?;
+                return Some(pos + 2);
+            }
+            let label = msg.get(pos + 1..pos + 1 + len)?.to_ascii_lowercase();
+            if msg.get(pos + 1 + len) != Some(&0) {
+                let hash = prf(&self.key, &[b"dns".as_slice(), &label]);
+                for (i, b) in msg[pos + 1..pos + 1 + len].iter_mut().enumerate() {
+                    *b = LABEL_CHARS[(hash[i % hash.len()] as usize + i / hash.len()) % LABEL_CHARS.len()];
+                }
+            }
+            pos += 1 + len;
+        }
+    }
+
+    fn rewrite_ipv4_at(&mut self, data: &mut [u8], at: usize) {
+        let Some(slot) = data.get_mut(at..at + 4) else { return };
+        let addr: [u8; 4] = slot.try_into().unwrap_or_default();
+        let new = self.anonymize_v4(addr);
+        data[at..at + 4].copy_from_slice(&new);
+    }
+
+    fn anonymize_v4(&mut self, addr: [u8; 4]) -> [u8; 4] {
+        match self.anonymize(IpAddr::from(addr)) {
+            IpAddr::V4(v4) => v4.octets(),
+            IpAddr::V6(_) => addr,
+        }
+    }
+
+    fn anonymize_v6(&mut self, addr: [u8; 16]) -> [u8; 16] {
+        match self.anonymize(IpAddr::from(addr)) {
+            IpAddr::V6(v6) => v6.octets(),
+            IpAddr::V4(_) => addr,
+        }
+    }
+
+    /// Unspecified, loopback, broadcast and multicast addresses carry no customer data
+    /// and are kept so the capture still reads naturally.
+    fn anonymize(&mut self, ip: IpAddr) -> IpAddr {
+        let special = ip.is_unspecified() || ip.is_loopback() || ip.is_multicast()
+            || matches!(ip, IpAddr::V4(v4) if v4.is_broadcast());
+        if !self.options.anonymize_ips || special { return ip; }
+        let key = self.key;
+        *self.ips.entry(ip).or_insert_with(|| match ip {
+            IpAddr::V4(v4) => {
+                let out: [u8; 4] = prefix_preserving(&key, &v4.octets()).try_into().unwrap_or_default();
+                IpAddr::from(out)
+            },
+            IpAddr::V6(v6) => {
+                let out: [u8; 16] = prefix_preserving(&key, &v6.octets()).try_into().unwrap_or_default();
+                IpAddr::from(out)
+            },
+        })
+    }
+}
+
+/// Where the 802.11 header starts, past any radiotap header.
+fn ieee80211_start(linktype: Linktype, frame: &[u8]) -> Option<usize> {
+    if linktype == IEEE802_11_RADIOTAP {
+        frame.get(2..4).map(|len| u16::from_le_bytes([len[0], len[1]]) as usize)
+    } else {
+        Some(0)
+    }
+}
+
+/// The length of the link header of a frame whose network layer couldn't be found. For
+/// 802.11 management and protected frames that is the MAC header, so the frame type and
+/// the (rewritten) addresses survive while the body is zeroed.
+fn link_header_len(linktype: Linktype, frame: &[u8]) -> usize {
+    match linktype {
+        Linktype::ETHERNET => 14,
+        Linktype::LINUX_SLL => 16,
+        Linktype::LINUX_SLL2 => 20,
+        Linktype::NULL | Linktype::LOOP => 4,
+        IEEE802_11 | IEEE802_11_RADIOTAP => {
+            let Some(base) = ieee80211_start(linktype, frame) else { return 0 };
+            let (Some(&fc), Some(&flags)) = (frame.get(base), frame.get(base + 1)) else { return 0 };
+            match (fc >> 2) & 0x3 {
+                // Control frames are all header.
+                1 => frame.len(),
+                2 => {
+                    let qos = fc & 0x80 != 0;
+                    base + 24
+                        + if flags & 0x03 == 0x03 { 6 } else { 0 }
+                        + if qos { 2 } else { 0 }
+                        + if qos && flags & 0x80 != 0 { 4 } else { 0 }
+                },
+                _ => base + 24,
+            }
+        },
+        _ => 0,
+    }
+}
+
+/// Crypto-PAn: bit `i` of the output is bit `i` of the input flipped by a PRF of the
+/// first `i` input bits, so addresses sharing a k-bit prefix still share one afterwards.
+fn prefix_preserving(key: &[u8; 32], addr: &[u8]) -> Vec<u8> {
+    let mut out = addr.to_vec();
+    let mut prefix = vec![0u8; addr.len()];
+    for i in 0..addr.len() * 8 {
+        let (byte, bit) = (i / 8, 7 - i % 8);
+        let flip = prf(key, &[&[addr.len() as u8, i as u8], &prefix])[0] >> 7;
+        out[byte] ^= flip << bit;
+        prefix[byte] |= addr[byte] & (1 << bit);
+    }
+    out
+}
+
+fn prf(key: &[u8; 32], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::{PacketBuilder, PacketHeaders};
+
+    fn options(payload: &str) -> PcapSanitizeOptions {
+        PcapSanitizeOptions { key: Some("test key".into()), payload: payload.into(), ..Default::default() }
+    }
+
+    fn dns_response() -> Vec<u8> {
+        let mut msg = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0];
+        msg.extend_from_slice(b"\x04mail\x07example\x03com\x00\x00\x01\x00\x01");
+        // Answer: pointer to the question name, A 10.1.2.3
+        msg.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 1, 2, 3]);
+        let mut frame = Vec::new();
+        PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+            .ipv4([10, 0, 0, 53], [10, 0, 0, 5], 64)
+            .udp(53, 40000)
+            .write(&mut frame, &msg)
+            .unwrap();
+        frame
+    }
+
+    #[test]
+    fn test_prefix_preserving() {
+        let key = [7u8; 32];
+        let a = prefix_preserving(&key, &[10, 1, 2, 3]);
+        let b = prefix_preserving(&key, &[10, 1, 2, 200]);
+        let c = prefix_preserving(&key, &[11, 1, 2, 3]);
+        assert_ne!(a, vec![10, 1, 2, 3]);
+        assert_eq!(a[..3], b[..3]); // shared /24 survives
+        assert_ne!(a[3], b[3]);
+        assert_eq!(a[0] >> 1, c[0] >> 1); // 10 and 11 share 7 bits
+        assert_ne!(a[0], c[0]);
+        assert_eq!(a, prefix_preserving(&key, &[10, 1, 2, 3]));
+    }
+
+    #[test]
+    fn test_sanitize_dns_frame() {
+        let mut sanitizer = Sanitizer::new(options("zero"));
+        let mut frame = dns_response();
+        sanitizer.sanitize(Linktype::ETHERNET, &mut frame);
+
+        let headers = PacketHeaders::from_ethernet_slice(&frame).unwrap();
+        let Some(etherparse::LinkHeader::Ethernet2(eth)) = &headers.link else { panic!() };
+        assert_ne!(eth.source, [2, 0, 0, 0, 0, 1]);
+        assert_eq!(eth.source[0] & 0x03, 0x02); // locally administered unicast
+
+        let Some(NetHeaders::Ipv4(ip, _)) = &headers.net else { panic!() };
+        assert_ne!(ip.source, [10, 0, 0, 53]);
+        assert_eq!(ip.source[..3], ip.destination[..3]);
+        assert_eq!(ip.header_checksum, ip.calc_header_checksum());
+        let Some(TransportHeader::Udp(udp)) = &headers.transport else { panic!() };
+        let payload = headers.payload.slice();
+        assert_eq!(udp.checksum, udp.calc_checksum_ipv4(ip, payload).unwrap());
+
+        // DNS is hashed rather than zeroed; lengths, the TLD and the pointer survive.
+        let msg = super::super::dns::parse_message(payload).unwrap();
+        let name = &msg.questions[0].name;
+        assert_eq!(name.len(), "mail.example.com".len());
+        assert!(name.ends_with(".com") && !name.contains("example"));
+        assert_ne!(msg.answers[0].data, "10.1.2.3");
+        assert_eq!(msg.answers[0].data, std::net::Ipv4Addr::from(sanitizer.anonymize_v4([10, 1, 2, 3])).to_string());
+        assert_eq!(sanitizer.dns_names, 1);
+    }
+
+    #[test]
+    fn test_sanitize_snaplen_cut_and_unknown_frames() {
+        let mut sanitizer = Sanitizer::new(options("zero"));
+        let mut full = Vec::new();
+        PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+            .ipv4([192, 168, 1, 10], [93, 184, 216, 34], 64)
+            .tcp(50000, 80, 1, 64240)
+            .write(&mut full, b"GET /secret HTTP/1.1\r\n\r\n")
+            .unwrap();
+
+        // Snaplen 60: the IPv4 total length promises more than was captured.
+        let mut cut = full[..60].to_vec();
+        sanitizer.sanitize(Linktype::ETHERNET, &mut cut);
+        assert_eq!(cut.len(), 60);
+        assert!(!cut.windows(4).any(|w| w == [192, 168, 1, 10] || w == [93, 184, 216, 34]));
+        assert!(cut[54..].iter().all(|&b| b == 0));
+        assert_eq!(&cut[34..36], &50000u16.to_be_bytes()); // TCP header kept
+        assert_eq!(sanitizer.undecoded, 0);
+
+        // Cut inside the IP header: everything past Ethernet goes.
+        let mut stub = full[..30].to_vec();
+        sanitizer.sanitize(Linktype::ETHERNET, &mut stub);
+        assert!(stub[14..].iter().all(|&b| b == 0));
+
+        // A non-IP EtherType (LLDP) keeps only its Ethernet header.
+        let mut lldp = vec![0x01, 0x80, 0xc2, 0, 0, 0x0e, 2, 0, 0, 0, 0, 1, 0x88, 0xcc];
+        lldp.extend_from_slice(b"\x02\x07\x04switch-01");
+        sanitizer.sanitize(Linktype::ETHERNET, &mut lldp);
+        assert_eq!(&lldp[12..14], &[0x88, 0xcc]);
+        assert!(lldp[14..].iter().all(|&b| b == 0));
+        assert_eq!(sanitizer.undecoded, 2);
+    }
+
+    #[test]
+    fn test_sanitize_capture_truncates_payloads() {
+        let mut tcp = Vec::new();
+        PacketBuilder::ethernet2([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2])
+            .ipv4([192, 168, 1, 10], [93, 184, 216, 34], 64)
+            .tcp(50000, 80, 1, 64240)
+            .write(&mut tcp, b"GET /secret HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut input = Vec::new();
+        input.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        input.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 0, 1, 0, 0, 0]);
+        for (i, frame) in [tcp.clone(), dns_response()].iter().enumerate() {
+            input.extend_from_slice(&(1_700_000_000u32 + i as u32).to_le_bytes());
+            input.extend_from_slice(&250u32.to_le_bytes());
+            input.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            input.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            input.extend_from_slice(frame);
+        }
+        let dir = std::env::temp_dir();
+        let (src, dst) = (dir.join(format!("trubleshoota_{}_raw.pcap", std::process::id())), dir.join(format!("trubleshoota_{}_clean.pcap", std::process::id())));
+        std::fs::write(&src, &input).unwrap();
+
+        let report = sanitize_capture(&src.to_string_lossy(), &dst.to_string_lossy(), options("truncate")).unwrap();
+        assert_eq!((report.format.as_str(), report.packets, report.undecoded_packets), ("pcap", 2, 0));
+        assert_eq!(report.payload_bytes_removed, 24);
+        assert_eq!(report.dns_names_hashed, 1);
+        assert!(sanitize_capture(&src.to_string_lossy(), &src.to_string_lossy(), options("keep")).is_err());
+        assert!(sanitize_capture(&src.to_string_lossy(), &dst.to_string_lossy(), options("shred")).is_err());
+
+        let mut packets = Vec::new();
+        CaptureReader::open(&dst.to_string_lossy()).unwrap().read_packets(|p| {
+            packets.push((p.ts, p.data.to_vec(), p.orig_len, p.linktype));
+            ControlFlow::Continue(())
+        }).unwrap();
+        std::fs::remove_file(&src).ok();
+        std::fs::remove_file(&dst).ok();
+
+        assert_eq!(packets.len(), 2);
+        let (ts, data, orig_len, linktype) = &packets[0];
+        assert!((ts - 1_700_000_000.000_25).abs() < 1e-6);
+        assert_eq!(*linktype, Linktype::ETHERNET);
+        assert_eq!(data.len(), tcp.len() - 24);
+        assert_eq!(*orig_len as usize, tcp.len());
+        assert!(!data.windows(6).any(|w| w == b"secret"));
+        assert!(!data.windows(4).any(|w| w == [192, 168, 1, 10]));
+    }
+}
@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use etherparse::{TcpHeader, TcpOptionElement};
+
+use crate::modules::utils::{PcapIssue, TcpAnalysisStats};
+
+// Wireshark's defaults: a fast retransmission follows the third duplicate ACK within 20ms,
+// and without a handshake RTT anything re-sent within 3ms is treated as reordering.
+const FAST_RETRANS_WINDOW: f64 = 0.02;
+const DEFAULT_OOO_THRESHOLD: f64 = 0.003;
+
+/// Classification of a single segment, modelled on Wireshark's `tcp.analysis.*` flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpEvent {
+    Retransmission,
+    FastRetransmission,
+    SpuriousRetransmission,
+    OutOfOrder,
+    DuplicateAck,
+    LostSegment,
+    WindowFull,
+    ZeroWindow,
+    ZeroWindowProbe,
+    KeepAlive,
+}
+
+impl TcpEvent {
+    /// Any kind of retransmission, including fast and spurious ones.
+    pub fn is_retransmission(self) -> bool {
+        matches!(self, TcpEvent::Retransmission | TcpEvent::FastRetransmission | TcpEvent::SpuriousRetransmission)
+    }
+}
+
+/// What one side of a connection has sent so far.
+#[derive(Default)]
+struct TcpDirection {
+    next_seq: Option<u32>,
+    last_ack: Option<u32>,
+    last_ack_ts: f64,
+    dup_acks: u32,
+    // Latest advertised window, already scaled. `None` while the scale factor is unknown.
+    window: Option<u32>,
+    raw_window: Option<u16>,
+    last_seg_ts: f64,
+    syn_seen: bool,
+    isn: Option<u32>,
+    win_scale: Option<u8>,
+}
+
+#[derive(Default)]
+struct TcpFlow {
+    dirs: [TcpDirection; 2],
+    syn_ts: Option<f64>,
+    handshake_rtt: Option<f64>,
+}
+
+impl TcpFlow {
+    /// RFC 7323: scaling is in effect only if both SYNs carried the option. Until both SYNs
+    /// have been seen (capture started mid-connection) the shift is unknown.
+    fn window_shift(&self, dir: usize) -> Option<u8> {
+        let (this, other) = (&self.dirs[dir], &self.dirs[1 - dir]);
+        if !(this.syn_seen && other.syn_seen) { return None; }
+        match (this.win_scale, other.win_scale) {
+            (Some(shift), Some(_)) => Some(shift.min(14)),
+            _ => Some(0),
+        }
+    }
+}
+
+/// Per-connection sequence tracking for both directions of every TCP flow.
+#[derive(Default)]
+pub struct TcpAnalyzer {
+    flows: HashMap<String, TcpFlow>,
+    // Handshake RTTs of connections whose 4-tuple was later reused.
+    reused_rtts: Vec<f64>,
+    stats: TcpAnalysisStats,
+}
+
+impl TcpAnalyzer {
+    pub fn process_segment(&mut self, src: &str, dst: &str, tcp: &TcpHeader, seglen: u32, ts: f64) -> Vec<TcpEvent> {
+        // Both directions share one flow; `d` is the sender's side, `r` the receiver's.
+        let (key, d) = if src < dst { (format!("{} <-> {}", src, dst), 0) } else { (format!("{} <-> {}", dst, src), 1) };
+        let r = 1 - d;
+        let flow = self.flows.entry(key).or_default();
+        let seq = tcp.sequence_number;
+        // A SYN with a new ISN on a known 4-tuple starts a new connection reusing the ports;
+        // its sequence numbers have nothing to do with the old one's.
+        if tcp.syn && !tcp.ack && flow.dirs.iter().any(|dir| dir.next_seq.is_some())
+            && flow.dirs[d].isn != Some(seq) {
+            self.reused_rtts.extend(std::mem::take(flow).handshake_rtt);
+        }
+        let ack = tcp.acknowledgment_number;
+        let control = tcp.syn || tcp.fin || tcp.rst;
+        let mut events = Vec::new();
+
+        if tcp.rst { self.stats.resets += 1; }
+
+        if tcp.syn {
+            let sender = &mut flow.dirs[d];
+            sender.syn_seen = true;
+            sender.isn = Some(seq);
+            sender.win_scale = tcp.options_iterator().find_map(|opt| match opt {
+                Ok(TcpOptionElement::WindowScale(shift)) => Some(shift),
+                _ => None,
+            });
+            if !tcp.ack {
+                flow.syn_ts = Some(ts);
+            } else if let Some(syn_ts) = flow.syn_ts.take() {
+                let rtt = ts - syn_ts;
+                if rtt > 0.0 && rtt < 10.0 { flow.handshake_rtt = Some(rtt); }
+            }
+        }
+
+        // Windows in SYN segments are never scaled.
+        let window = if tcp.syn || tcp.window_size == 0 {
+            Some(tcp.window_size as u32)
+        } else {
+            flow.window_shift(d).map(|shift| (tcp.window_size as u32) << shift)
+        };
+
+        let sender = &flow.dirs[d];
+        let receiver = &flow.dirs[r];
+        let seq_end = seq.wrapping_add(seglen);
+
+        let zero_window_probe = seglen == 1 && sender.next_seq == Some(seq) && receiver.window == Some(0);
+        let keep_alive = seglen <= 1 && !control
+            && sender.next_seq.is_some_and(|next| seq == next.wrapping_sub(1));
+
+        if zero_window_probe {
+            events.push(TcpEvent::ZeroWindowProbe);
+        } else {
+            if tcp.window_size == 0 && !control {
+                events.push(TcpEvent::ZeroWindow);
+            }
+            if !tcp.rst && sender.next_seq.is_some_and(|next| seq_gt(seq, next)) {
+                events.push(TcpEvent::LostSegment);
+            }
+            if keep_alive {
+                events.push(TcpEvent::KeepAlive);
+            }
+            if seglen > 0 && !control {
+                if let (Some(acked), Some(win)) = (receiver.last_ack, receiver.window) {
+                    if seq_end == acked.wrapping_add(win) {
+                        events.push(TcpEvent::WindowFull);
+                    }
+                }
+            }
+            if tcp.ack && seglen == 0 && !control
+                && sender.next_seq == Some(seq)
+                && sender.last_ack == Some(ack)
+                && sender.raw_window == Some(tcp.window_size)
+            {
+                events.push(TcpEvent::DuplicateAck);
+            }
+
+            let consumes_seq = seglen > 0 || tcp.syn || tcp.fin;
+            let behind = sender.next_seq.is_some_and(|next| seq_lt(seq, next));
+            if consumes_seq && behind && !keep_alive {
+                let threshold = flow.handshake_rtt.unwrap_or(DEFAULT_OOO_THRESHOLD);
+                let event = if seglen > 0 && receiver.last_ack.is_some_and(|acked| !seq_gt(seq_end, acked)) {
+                    TcpEvent::SpuriousRetransmission
+                } else if receiver.dup_acks >= 2 && receiver.last_ack == Some(seq)
+                    && ts - receiver.last_ack_ts < FAST_RETRANS_WINDOW
+                {
+                    TcpEvent::FastRetransmission
+                } else if ts - sender.last_seg_ts < threshold && sender.next_seq != Some(seq_end) {
+                    TcpEvent::OutOfOrder
+                } else {
+                    TcpEvent::Retransmission
+                };
+                events.push(event);
+            }
+        }
+
+        // --- Update the sender's state ---
+        let sender = &mut flow.dirs[d];
+        let advance = seglen + tcp.syn as u32 + tcp.fin as u32;
+        let end = seq.wrapping_add(advance);
+        if sender.next_seq.is_none_or(|next| seq_gt(end, next)) && !tcp.rst {
+            sender.next_seq = Some(end);
+        }
+        if tcp.ack {
+            if events.contains(&TcpEvent::DuplicateAck) {
+                sender.dup_acks += 1;
+                sender.last_ack_ts = ts;
+            } else if sender.last_ack != Some(ack) {
+                sender.dup_acks = 0;
+                sender.last_ack = Some(ack);
+                sender.last_ack_ts = ts;
+            }
+        }
+        sender.window = window;
+        sender.raw_window = Some(tcp.window_size);
+        sender.last_seg_ts = ts;
+
+        for event in &events {
+            let stats = &mut self.stats;
+            match event {
+                TcpEvent::Retransmission => stats.retransmissions += 1,
+                TcpEvent::FastRetransmission => { stats.retransmissions += 1; stats.fast_retransmissions += 1; },
+                TcpEvent::SpuriousRetransmission => { stats.retransmissions += 1; stats.spurious_retransmissions += 1; },
+                TcpEvent::OutOfOrder => stats.out_of_order += 1,
+                TcpEvent::DuplicateAck => stats.duplicate_acks += 1,
+                TcpEvent::LostSegment => stats.lost_segments += 1,
+                TcpEvent::WindowFull => stats.window_full += 1,
+                TcpEvent::ZeroWindow => stats.zero_window += 1,
+                TcpEvent::ZeroWindowProbe => stats.zero_window_probes += 1,
+                TcpEvent::KeepAlive => stats.keep_alives += 1,
+            }
+        }
+        events
+    }
+
+    pub fn finish(mut self, issues: &mut Vec<PcapIssue>) -> TcpAnalysisStats {
+        let mut rtts: Vec<f64> = self.flows.values().filter_map(|f| f.handshake_rtt).collect();
+        rtts.append(&mut self.reused_rtts);
+        if !rtts.is_empty() {
+            self.stats.avg_rtt_ms = Some(rtts.iter().sum::<f64>() / rtts.len() as f64 * 1000.0);
+        }
+
+        let stats = &self.stats;
+        if stats.retransmissions > 10 {
+            issues.push(PcapIssue {
+                severity: if stats.retransmissions > 100 { "critical".into() } else { "warn".into() },
+                title: "TCP Retransmissions".into(),
+                description: format!(
+                    "Detected {} TCP retransmissions ({} fast, {} spurious).",
+                    stats.retransmissions, stats.fast_retransmissions, stats.spurious_retransmissions
+                ),
+                timestamp: None,
+            });
+        }
+
+        if stats.lost_segments > 0 {
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "TCP Segment Loss".into(),
+                description: format!(
+                    "{} gaps in sequence space (previous segment not captured), {} duplicate ACKs, {} out-of-order segments.",
+                    stats.lost_segments, stats.duplicate_acks, stats.out_of_order
+                ),
+                timestamp: None,
+            });
+        }
+
+        if stats.zero_window > 0 {
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "TCP Zero Window".into(),
+                description: format!(
+                    "Detected {} Zero Window occurrences and {} zero-window probes.",
+                    stats.zero_window, stats.zero_window_probes
+                ),
+                timestamp: None,
+            });
+        }
+
+        if stats.window_full > 0 {
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "TCP Window Full".into(),
+                description: format!("{} segments filled the receiver's advertised window.", stats.window_full),
+                timestamp: None,
+            });
+        }
+
+        self.stats
+    }
+}
+
+/// Sequence-space comparisons that survive 32-bit wraparound.
+pub(super) fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+pub(super) fn seq_gt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C: &str = "10.0.0.5:50000";
+    const S: &str = "10.0.0.9:80";
+
+    fn seg(seq: u32, ack: u32, window: u16) -> TcpHeader {
+        let mut tcp = TcpHeader::new(0, 0, seq, window);
+        tcp.ack = true;
+        tcp.acknowledgment_number = ack;
+        tcp
+    }
+
+    fn syn(seq: u32, ack: Option<u32>, window: u16, scale: Option<u8>) -> TcpHeader {
+        let mut tcp = TcpHeader::new(0, 0, seq, window);
+        tcp.syn = true;
+        if let Some(ack) = ack {
+            tcp.ack = true;
+            tcp.acknowledgment_number = ack;
+        }
+        if let Some(shift) = scale {
+            tcp.set_options(&[TcpOptionElement::WindowScale(shift)]).unwrap();
+        }
+        tcp
+    }
+
+    /// Three-way handshake: client ISN 1000, server ISN 5000.
+    fn handshake(tcp: &mut TcpAnalyzer, client_scale: Option<u8>, server_scale: Option<u8>) {
+        tcp.process_segment(C, S, &syn(1000, None, 64240, client_scale), 0, 0.0);
+        tcp.process_segment(S, C, &syn(5000, Some(1001), 65535, server_scale), 0, 0.010);
+        tcp.process_segment(C, S, &seg(1001, 5001, 502), 0, 0.020);
+    }
+
+    #[test]
+    fn test_retransmission_kinds() {
+        let mut tcp = TcpAnalyzer::default();
+        handshake(&mut tcp, None, None);
+
+        assert!(tcp.process_segment(C, S, &seg(1001, 5001, 502), 100, 1.0).is_empty());
+        // Segment at 1101 never captured; 1201 arrives first.
+        assert_eq!(tcp.process_segment(C, S, &seg(1201, 5001, 502), 100, 1.001), vec![TcpEvent::LostSegment]);
+        // The missing one shows up 1ms later: reordering, not a resend.
+        assert_eq!(tcp.process_segment(C, S, &seg(1101, 5001, 502), 100, 1.002), vec![TcpEvent::OutOfOrder]);
+
+        // Server acknowledges everything, then the client resends the first segment anyway.
+        tcp.process_segment(S, C, &seg(5001, 1301, 65535), 0, 1.05);
+        assert_eq!(tcp.process_segment(C, S, &seg(1001, 5001, 502), 100, 1.5), vec![TcpEvent::SpuriousRetransmission]);
+
+        // Unacknowledged data resent after an RTO.
+        tcp.process_segment(C, S, &seg(1301, 5001, 502), 100, 2.0);
+        assert_eq!(tcp.process_segment(C, S, &seg(1301, 5001, 502), 100, 2.5), vec![TcpEvent::Retransmission]);
+
+        let stats = tcp.finish(&mut Vec::new());
+        assert_eq!(stats.retransmissions, 2);
+        assert_eq!(stats.spurious_retransmissions, 1);
+        assert_eq!(stats.out_of_order, 1);
+        assert_eq!(stats.lost_segments, 1);
+        assert!((stats.avg_rtt_ms.unwrap() - 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_dup_acks_and_fast_retransmission() {
+        let mut tcp = TcpAnalyzer::default();
+        handshake(&mut tcp, None, None);
+
+        // Server sends to the client (the reverse direction), 1300 bytes per segment.
+        tcp.process_segment(S, C, &seg(5001, 1001, 65535), 1300, 1.0);
+        tcp.process_segment(C, S, &seg(1001, 6301, 502), 0, 1.01);
+        // 6301 lost; client keeps acking 6301 while later segments arrive.
+        assert_eq!(tcp.process_segment(S, C, &seg(7601, 1001, 65535), 1300, 1.011), vec![TcpEvent::LostSegment]);
+        for i in 0..3 {
+            let ts = 1.012 + i as f64 * 0.001;
+            assert_eq!(tcp.process_segment(C, S, &seg(1001, 6301, 502), 0, ts), vec![TcpEvent::DuplicateAck]);
+        }
+        assert_eq!(tcp.process_segment(S, C, &seg(6301, 1001, 65535), 1300, 1.016), vec![TcpEvent::FastRetransmission]);
+
+        let stats = tcp.finish(&mut Vec::new());
+        assert_eq!(stats.duplicate_acks, 3);
+        assert_eq!(stats.fast_retransmissions, 1);
+        assert_eq!(stats.retransmissions, 1);
+    }
+
+    #[test]
+    fn test_scaled_window_full_and_zero_window() {
+        let mut tcp = TcpAnalyzer::default();
+        // Server advertises 256 << 4 = 4096 bytes once the handshake completes.
+        handshake(&mut tcp, Some(7), Some(4));
+        tcp.process_segment(S, C, &seg(5001, 1001, 256), 0, 0.03);
+
+        assert!(tcp.process_segment(C, S, &seg(1001, 5001, 502), 2048, 1.0).is_empty());
+        assert_eq!(tcp.process_segment(C, S, &seg(3049, 5001, 502), 2048, 1.001), vec![TcpEvent::WindowFull]);
+
+        assert_eq!(tcp.process_segment(S, C, &seg(5001, 5097, 0), 0, 1.1), vec![TcpEvent::ZeroWindow]);
+        assert_eq!(tcp.process_segment(C, S, &seg(5097, 5001, 502), 1, 1.3), vec![TcpEvent::ZeroWindowProbe]);
+
+        // Keep-alive: one byte behind next_seq, no new data.
+        tcp.process_segment(S, C, &seg(5001, 5098, 256), 0, 2.0);
+        assert_eq!(tcp.process_segment(C, S, &seg(5097, 5001, 502), 0, 60.0), vec![TcpEvent::KeepAlive]);
+
+        let mut issues = Vec::new();
+        let stats = tcp.finish(&mut issues);
+        assert_eq!((stats.window_full, stats.zero_window, stats.zero_window_probes, stats.keep_alives), (1, 1, 1, 1));
+        assert_eq!(stats.retransmissions, 0);
+        assert!(issues.iter().any(|i| i.title == "TCP Window Full"));
+    }
+
+    #[test]
+    fn test_port_reuse_resets_state() {
+        let mut tcp = TcpAnalyzer::default();
+        // A retransmitted SYN keeps the connection.
+        tcp.process_segment(C, S, &syn(1000, None, 64240, None), 0, 0.0);
+        tcp.process_segment(C, S, &syn(1000, None, 64240, None), 0, 1.0);
+        tcp.process_segment(S, C, &syn(5000, Some(1001), 65535, None), 0, 1.01);
+        tcp.process_segment(C, S, &seg(1001, 5001, 502), 0, 1.02);
+        assert!(tcp.process_segment(C, S, &seg(1001, 5001, 502), 100, 1.1).is_empty());
+
+        // Same ports, new ISNs: nothing is compared against the old connection.
+        assert!(tcp.process_segment(C, S, &syn(90_000, None, 64240, None), 0, 10.0).is_empty());
+        assert!(tcp.process_segment(S, C, &syn(40_000, Some(90_001), 65535, None), 0, 10.03).is_empty());
+        assert!(tcp.process_segment(C, S, &seg(90_001, 40_001, 502), 100, 10.04).is_empty());
+        assert!(tcp.process_segment(S, C, &seg(40_001, 90_101, 65535), 0, 10.05).is_empty());
+
+        let stats = tcp.finish(&mut Vec::new());
+        // Only the retransmitted SYN.
+        assert_eq!((stats.retransmissions, stats.out_of_order, stats.lost_segments), (1, 0, 0));
+        // Both handshakes count towards the average: (10 + 30) / 2.
+        assert!((stats.avg_rtt_ms.unwrap() - 20.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sequence_wraparound() {
+        let mut tcp = TcpAnalyzer::default();
+        tcp.process_segment(C, S, &seg(u32::MAX - 99, 1, 502), 100, 0.0);
+        assert!(tcp.process_segment(C, S, &seg(0, 1, 502), 100, 0.1).is_empty());
+        assert_eq!(tcp.process_segment(C, S, &seg(u32::MAX - 99, 1, 502), 100, 0.5), vec![TcpEvent::Retransmission]);
+    }
+}
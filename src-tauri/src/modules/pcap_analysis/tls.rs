@@ -0,0 +1,595 @@
+use std::collections::{BTreeMap, HashMap};
+use etherparse::TcpHeader;
+
+use crate::modules::utils::{PcapIssue, TlsSession};
+
+// Enough for a ClientHello carrying post-quantum key shares plus the ServerHello flight.
+const MAX_HANDSHAKE_BUFFER: usize = 64 * 1024;
+
+const HANDSHAKE: u8 = 22;
+pub(crate) const CLIENT_HELLO: u8 = 1;
+const SERVER_HELLO: u8 = 2;
+
+const EXT_SERVER_NAME: u16 = 0;
+const EXT_SUPPORTED_GROUPS: u16 = 10;
+const EXT_EC_POINT_FORMATS: u16 = 11;
+const EXT_ALPN: u16 = 16;
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+
+/// Fields pulled out of a ClientHello.
+#[derive(Debug, Default)]
+pub struct ClientHello {
+    pub version: u16,
+    pub cipher_suites: Vec<u16>,
+    pub extensions: Vec<u16>,
+    pub sni: Option<String>,
+    pub alpn: Vec<String>,
+    pub supported_versions: Vec<u16>,
+    pub groups: Vec<u16>,
+    pub ec_point_formats: Vec<u8>,
+}
+
+/// Fields pulled out of a ServerHello.
+#[derive(Debug, Default)]
+pub struct ServerHello {
+    pub version: u16,
+    pub cipher_suite: u16,
+    pub extensions: Vec<u16>,
+    pub alpn: Option<String>,
+    pub selected_version: Option<u16>,
+}
+
+impl ClientHello {
+    pub fn ja3_string(&self) -> String {
+        format!(
+            "{},{},{},{},{}",
+            self.version,
+            join_dec(self.cipher_suites.iter().copied().filter(|v| !is_grease(*v))),
+            join_dec(self.extensions.iter().copied().filter(|v| !is_grease(*v))),
+            join_dec(self.groups.iter().copied().filter(|v| !is_grease(*v))),
+            join_dec(self.ec_point_formats.iter().map(|v| *v as u16)),
+        )
+    }
+
+    /// Versions the client is willing to speak, highest first.
+    pub fn offered_versions(&self) -> Vec<u16> {
+        let mut versions: Vec<u16> = if self.supported_versions.is_empty() {
+            // Pre-1.3 clients accept anything up to legacy_version.
+            [0x0300, 0x0301, 0x0302, 0x0303].into_iter().filter(|v| *v <= self.version).collect()
+        } else {
+            self.supported_versions.iter().copied().filter(|v| !is_grease(*v)).collect()
+        };
+        versions.sort_unstable_by(|a, b| b.cmp(a));
+        versions
+    }
+}
+
+impl ServerHello {
+    pub fn ja3s_string(&self) -> String {
+        format!(
+            "{},{},{}",
+            self.version,
+            self.cipher_suite,
+            join_dec(self.extensions.iter().copied()),
+        )
+    }
+
+    pub fn negotiated_version(&self) -> u16 {
+        self.selected_version.unwrap_or(self.version)
+    }
+}
+
+/// Reassembles one direction of a TCP stream until its first handshake message is complete.
+#[derive(Default)]
+struct HandshakeStream {
+    next_seq: Option<u32>,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl HandshakeStream {
+    fn push(&mut self, seq: u32, payload: &[u8]) {
+        if self.done || payload.is_empty() { return; }
+        match self.next_seq {
+            // Retransmissions and out-of-order segments are skipped; the next in-order
+            // segment fills the gap.
+            Some(expected) if expected != seq => return,
+            None if !looks_like_handshake(payload) => {
+                self.done = true;
+                return;
+            },
+            _ => {}
+        }
+        self.next_seq = Some(seq.wrapping_add(payload.len() as u32));
+        self.buf.extend_from_slice(payload);
+        if self.buf.len() > MAX_HANDSHAKE_BUFFER { self.done = true; }
+    }
+}
+
+struct TlsFlow {
+    session_idx: usize,
+    client: String,
+    hello_seq: u32, // where the ClientHello started
+    to_server: HandshakeStream,
+    to_client: HandshakeStream,
+}
+
+/// Tracks TLS handshakes on every TCP flow, regardless of port.
+#[derive(Default)]
+pub struct TlsAnalyzer {
+    flows: HashMap<String, TlsFlow>,
+    sessions: Vec<TlsSession>,
+}
+
+impl TlsAnalyzer {
+    /// `src` and `dst` are `ip:port` endpoints of the segment.
+    pub fn process_segment(&mut self, src: &str, dst: &str, tcp: &TcpHeader, payload: &[u8], ts: f64) {
+        let key = if src < dst { format!("{} <-> {}", src, dst) } else { format!("{} <-> {}", dst, src) };
+
+        // A ClientHello far from the first one is a new connection reusing the ports; a
+        // retransmission, or the second ClientHello after a HelloRetryRequest, is close by.
+        let is_client_hello = looks_like_handshake(payload) && payload[5] == CLIENT_HELLO;
+        let reused = is_client_hello && self.flows.get(&key).is_some_and(|flow| {
+            flow.client != src || tcp.sequence_number.wrapping_sub(flow.hello_seq) as usize >= MAX_HANDSHAKE_BUFFER
+        });
+        if reused {
+            self.flows.remove(&key);
+        }
+
+        if !self.flows.contains_key(&key) {
+            // Only the client speaks first in TLS, so its ClientHello opens the session.
+            if !is_client_hello { return; }
+            self.sessions.push(TlsSession {
+                client: src.to_string(),
+                server: dst.to_string(),
+                timestamp: ts,
+                ..Default::default()
+            });
+            self.flows.insert(key.clone(), TlsFlow {
+                session_idx: self.sessions.len() - 1,
+                client: src.to_string(),
+                hello_seq: tcp.sequence_number,
+                to_server: HandshakeStream::default(),
+                to_client: HandshakeStream::default(),
+            });
+        }
+        let Some(flow) = self.flows.get_mut(&key) else { return };
+
+        let session = &mut self.sessions[flow.session_idx];
+        if flow.client == src {
+            flow.to_server.push(tcp.sequence_number, payload);
+            if flow.to_server.done { return; }
+            match first_handshake_message(&flow.to_server.buf) {
+                Some((CLIENT_HELLO, body)) => {
+                    if let Some(hello) = parse_client_hello(&body) {
+                        apply_client_hello(session, &hello);
+                    }
+                    flow.to_server.done = true;
+                },
+                Some(_) => flow.to_server.done = true,
+                None => {}
+            }
+        } else {
+            flow.to_client.push(tcp.sequence_number, payload);
+            if flow.to_client.done { return; }
+            match first_handshake_message(&flow.to_client.buf) {
+                Some((SERVER_HELLO, body)) => {
+                    if let Some(hello) = parse_server_hello(&body) {
+                        apply_server_hello(session, &hello);
+                    }
+                    flow.to_client.done = true;
+                },
+                Some(_) => flow.to_client.done = true,
+                None => {}
+            }
+        }
+    }
+
+    pub fn finish(self, issues: &mut Vec<PcapIssue>) -> Vec<TlsSession> {
+        let mut deprecated: BTreeMap<String, (usize, f64, Vec<String>)> = BTreeMap::new();
+        let mut weak: BTreeMap<String, (usize, f64, Vec<String>)> = BTreeMap::new();
+
+        for s in &self.sessions {
+            let target = s.sni.clone().unwrap_or_else(|| s.server.clone());
+            // Without a ServerHello, fall back to the best version the client offered.
+            let version = s.negotiated_version.clone().or_else(|| s.offered_versions.first().cloned());
+            if let Some(v) = version.filter(|v| is_deprecated_version_name(v)) {
+                let entry = deprecated.entry(v).or_insert((0, s.timestamp, Vec::new()));
+                entry.0 += 1;
+                if !entry.2.contains(&target) { entry.2.push(target.clone()); }
+            }
+            if let Some(c) = s.negotiated_cipher.clone().filter(|c| is_weak_cipher_name(c)) {
+                let entry = weak.entry(c).or_insert((0, s.timestamp, Vec::new()));
+                entry.0 += 1;
+                if !entry.2.contains(&target) { entry.2.push(target); }
+            }
+        }
+
+        for (version, (count, first_ts, targets)) in deprecated {
+            issues.push(PcapIssue {
+                severity: if version == "SSL 3.0" { "critical".into() } else { "warn".into() },
+                title: "Deprecated TLS".into(),
+                description: format!("{} {} handshake(s) with {}.", count, version, summarize_targets(&targets)),
+                timestamp: Some(first_ts),
+            });
+        }
+        for (cipher, (count, first_ts, targets)) in weak {
+            issues.push(PcapIssue {
+                severity: "critical".into(),
+                title: "Weak TLS Cipher".into(),
+                description: format!("{} session(s) negotiated {} with {}.", count, cipher, summarize_targets(&targets)),
+                timestamp: Some(first_ts),
+            });
+        }
+
+        self.sessions
+    }
+}
+
+fn apply_client_hello(session: &mut TlsSession, hello: &ClientHello) {
+    let ja3 = hello.ja3_string();
+    session.sni = hello.sni.clone();
+    session.offered_versions = hello.offered_versions().into_iter().map(version_name).collect();
+    session.offered_ciphers = hello.cipher_suites.iter().copied().filter(|v| !is_grease(*v)).map(cipher_suite_name).collect();
+    session.alpn_offered = hello.alpn.clone();
+    session.ja3_hash = Some(format!("{:x}", md5::compute(ja3.as_bytes())));
+    session.ja3 = Some(ja3);
+}
+
+fn apply_server_hello(session: &mut TlsSession, hello: &ServerHello) {
+    let ja3s = hello.ja3s_string();
+    session.negotiated_version = Some(version_name(hello.negotiated_version()));
+    session.negotiated_cipher = Some(cipher_suite_name(hello.cipher_suite));
+    session.alpn_selected = hello.alpn.clone();
+    session.ja3s_hash = Some(format!("{:x}", md5::compute(ja3s.as_bytes())));
+    session.ja3s = Some(ja3s);
+}
+
+fn summarize_targets(targets: &[String]) -> String {
+    let mut shown = targets.iter().take(3).cloned().collect::<Vec<_>>().join(", ");
+    if targets.len() > 3 { shown.push_str(&format!(" and {} more", targets.len() - 3)); }
+    shown
+}
+
+fn looks_like_handshake(payload: &[u8]) -> bool {
+    payload.len() >= 6 && payload[0] == HANDSHAKE && payload[1] == 3 && payload[2] <= 4
+}
+
+/// Joins handshake record fragments and returns the first complete message, or `None`
+/// while more data is needed. A non-handshake record ends the search with type 0.
+fn first_handshake_message(buf: &[u8]) -> Option<(u8, Vec<u8>)> {
+    let mut msg = Vec::new();
+    let mut pos = 0;
+    loop {
+        if msg.len() >= 4 {
+            let msg_len = u32::from_be_bytes([0, msg[1], msg[2], msg[3]]) as usize;
+            if msg.len() >= 4 + msg_len {
+                return Some((msg[0], msg[4..4 + msg_len].to_vec()));
+            }
+        }
+        let hdr = buf.get(pos..pos + 5)?;
+        if hdr[0] != HANDSHAKE { return Some((0, Vec::new())); }
+        let len = u16::from_be_bytes([hdr[3], hdr[4]]) as usize;
+        msg.extend_from_slice(buf.get(pos + 5..pos + 5 + len)?);
+        pos += 5 + len;
+    }
+}
+
+pub(crate) fn parse_client_hello(body: &[u8]) -> Option<ClientHello> {
+    let mut r = Reader::new(body);
+    let mut hello = ClientHello { version: r.u16()?, ..Default::default() };
+    r.skip(32)?; // random
+    r.vec8()?; // session id
+    let mut suites = Reader::new(r.vec16()?);
+    while let Some(s) = suites.u16() { hello.cipher_suites.push(s); }
+    r.vec8()?; // compression methods
+
+    if let Some(ext_len) = r.u16() {
+        let mut exts = Reader::new(r.take(ext_len as usize)?);
+        while let (Some(ext_type), Some(len)) = (exts.u16(), exts.u16()) {
+            let data = exts.take(len as usize)?;
+            hello.extensions.push(ext_type);
+            let mut d = Reader::new(data);
+            match ext_type {
+                EXT_SERVER_NAME => {
+                    let mut list = Reader::new(d.vec16()?);
+                    while let Some(name_type) = list.u8() {
+                        let name = list.vec16()?;
+                        if name_type == 0 {
+                            hello.sni = Some(String::from_utf8_lossy(name).to_string());
+                        }
+                    }
+                },
+                EXT_SUPPORTED_GROUPS => {
+                    let mut list = Reader::new(d.vec16()?);
+                    while let Some(g) = list.u16() { hello.groups.push(g); }
+                },
+                EXT_EC_POINT_FORMATS => {
+                    hello.ec_point_formats.extend_from_slice(d.vec8()?);
+                },
+                EXT_ALPN => hello.alpn = parse_alpn_list(&mut d)?,
+                EXT_SUPPORTED_VERSIONS => {
+                    let mut list = Reader::new(d.vec8()?);
+                    while let Some(v) = list.u16() { hello.supported_versions.push(v); }
+                },
+                _ => {}
+            }
+        }
+    }
+    Some(hello)
+}
+
+fn parse_server_hello(body: &[u8]) -> Option<ServerHello> {
+    let mut r = Reader::new(body);
+    let mut hello = ServerHello { version: r.u16()?, ..Default::default() };
+    r.skip(32)?;
+    r.vec8()?; // session id
+    hello.cipher_suite = r.u16()?;
+    r.u8()?; // compression method
+
+    if let Some(ext_len) = r.u16() {
+        let mut exts = Reader::new(r.take(ext_len as usize)?);
+        while let (Some(ext_type), Some(len)) = (exts.u16(), exts.u16()) {
+            let mut d = Reader::new(exts.take(len as usize)?);
+            hello.extensions.push(ext_type);
+            match ext_type {
+                EXT_ALPN => hello.alpn = parse_alpn_list(&mut d)?.into_iter().next(),
+                EXT_SUPPORTED_VERSIONS => hello.selected_version = d.u16(),
+                _ => {}
+            }
+        }
+    }
+    Some(hello)
+}
+
+fn parse_alpn_list(d: &mut Reader) -> Option<Vec<String>> {
+    let mut list = Reader::new(d.vec16()?);
+    let mut out = Vec::new();
+    while let Some(name) = list.vec8() {
+        out.push(String::from_utf8_lossy(name).to_string());
+    }
+    Some(out)
+}
+
+/// Minimal big-endian cursor over handshake bytes.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self { Reader { data, pos: 0 } }
+
+    pub(crate) fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let out = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(out)
+    }
+
+    pub(crate) fn position(&self) -> usize { self.pos }
+
+    pub(crate) fn skip(&mut self, n: usize) -> Option<()> { self.take(n).map(|_| ()) }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> { self.take(1).map(|b| b[0]) }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> { self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]])) }
+
+    /// A vector with a one-byte length prefix.
+    pub(crate) fn vec8(&mut self) -> Option<&'a [u8]> {
+        let n = self.u8()? as usize;
+        self.take(n)
+    }
+
+    /// A vector with a two-byte length prefix.
+    pub(crate) fn vec16(&mut self) -> Option<&'a [u8]> {
+        let n = self.u16()? as usize;
+        self.take(n)
+    }
+}
+
+fn join_dec(values: impl Iterator<Item = u16>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join("-")
+}
+
+/// GREASE values (RFC 8701) are random filler and excluded from fingerprints.
+fn is_grease(v: u16) -> bool {
+    (v & 0x0f0f) == 0x0a0a && (v >> 8) == (v & 0xff)
+}
+
+pub fn version_name(v: u16) -> String {
+    match v {
+        0x0300 => "SSL 3.0".into(),
+        0x0301 => "TLS 1.0".into(),
+        0x0302 => "TLS 1.1".into(),
+        0x0303 => "TLS 1.2".into(),
+        0x0304 => "TLS 1.3".into(),
+        0x7f00..=0x7fff => format!("TLS 1.3 (draft {})", v & 0xff),
+        _ => format!("0x{:04X}", v),
+    }
+}
+
+fn is_deprecated_version_name(name: &str) -> bool {
+    matches!(name, "SSL 3.0" | "TLS 1.0" | "TLS 1.1")
+}
+
+fn is_weak_cipher_name(name: &str) -> bool {
+    ["NULL", "EXPORT", "RC4", "DES", "anon", "MD5"].iter().any(|w| name.contains(w))
+}
+
+pub fn cipher_suite_name(id: u16) -> String {
+    let name = match id {
+        0x0000 => "TLS_NULL_WITH_NULL_NULL",
+        0x0001 => "TLS_RSA_WITH_NULL_MD5",
+        0x0002 => "TLS_RSA_WITH_NULL_SHA",
+        0x0003 => "TLS_RSA_EXPORT_WITH_RC4_40_MD5",
+        0x0004 => "TLS_RSA_WITH_RC4_128_MD5",
+        0x0005 => "TLS_RSA_WITH_RC4_128_SHA",
+        0x0006 => "TLS_RSA_EXPORT_WITH_RC2_CBC_40_MD5",
+        0x0008 => "TLS_RSA_EXPORT_WITH_DES40_CBC_SHA",
+        0x0009 => "TLS_RSA_WITH_DES_CBC_SHA",
+        0x000A => "TLS_RSA_WITH_3DES_EDE_CBC_SHA",
+        0x0011 => "TLS_DHE_DSS_EXPORT_WITH_DES40_CBC_SHA",
+        0x0012 => "TLS_DHE_DSS_WITH_DES_CBC_SHA",
+        0x0013 => "TLS_DHE_DSS_WITH_3DES_EDE_CBC_SHA",
+        0x0014 => "TLS_DHE_RSA_EXPORT_WITH_DES40_CBC_SHA",
+        0x0015 => "TLS_DHE_RSA_WITH_DES_CBC_SHA",
+        0x0016 => "TLS_DHE_RSA_WITH_3DES_EDE_CBC_SHA",
+        0x0017 => "TLS_DH_anon_EXPORT_WITH_RC4_40_MD5",
+        0x0018 => "TLS_DH_anon_WITH_RC4_128_MD5",
+        0x001A => "TLS_DH_anon_WITH_DES_CBC_SHA",
+        0x001B => "TLS_DH_anon_WITH_3DES_EDE_CBC_SHA",
+        0x002F => "TLS_RSA_WITH_AES_128_CBC_SHA",
+        0x0033 => "TLS_DHE_RSA_WITH_AES_128_CBC_SHA",
+        0x0034 => "TLS_DH_anon_WITH_AES_128_CBC_SHA",
+        0x0035 => "TLS_RSA_WITH_AES_256_CBC_SHA",
+        0x0039 => "TLS_DHE_RSA_WITH_AES_256_CBC_SHA",
+        0x003A => "TLS_DH_anon_WITH_AES_256_CBC_SHA",
+        0x003B => "TLS_RSA_WITH_NULL_SHA256",
+        0x003C => "TLS_RSA_WITH_AES_128_CBC_SHA256",
+        0x003D => "TLS_RSA_WITH_AES_256_CBC_SHA256",
+        0x009C => "TLS_RSA_WITH_AES_128_GCM_SHA256",
+        0x009D => "TLS_RSA_WITH_AES_256_GCM_SHA384",
+        0x009E => "TLS_DHE_RSA_WITH_AES_128_GCM_SHA256",
+        0x009F => "TLS_DHE_RSA_WITH_AES_256_GCM_SHA384",
+        0x1301 => "TLS_AES_128_GCM_SHA256",
+        0x1302 => "TLS_AES_256_GCM_SHA384",
+        0x1303 => "TLS_CHACHA20_POLY1305_SHA256",
+        0xC007 => "TLS_ECDHE_ECDSA_WITH_RC4_128_SHA",
+        0xC008 => "TLS_ECDHE_ECDSA_WITH_3DES_EDE_CBC_SHA",
+        0xC009 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA",
+        0xC00A => "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA",
+        0xC011 => "TLS_ECDHE_RSA_WITH_RC4_128_SHA",
+        0xC012 => "TLS_ECDHE_RSA_WITH_3DES_EDE_CBC_SHA",
+        0xC013 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA",
+        0xC014 => "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA",
+        0xC023 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA256",
+        0xC024 => "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA384",
+        0xC027 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA256",
+        0xC028 => "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA384",
+        0xC02B => "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        0xC02C => "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+        0xC02F => "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        0xC030 => "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        0xCCA8 => "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xCCA9 => "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+        0x00FF => "TLS_EMPTY_RENEGOTIATION_INFO_SCSV",
+        0x5600 => "TLS_FALLBACK_SCSV",
+        _ => return format!("0x{:04X}", id),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ext(ext_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut out = ext_type.to_be_bytes().to_vec();
+        out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn client_hello_body() -> Vec<u8> {
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&[7; 32]);
+        body.push(0);
+        body.extend_from_slice(&[0, 6, 0x1a, 0x1a, 0x13, 0x01, 0xc0, 0x2f]); // GREASE, two suites
+        body.extend_from_slice(&[1, 0]);
+
+        let mut exts = ext(0x2a2a, &[]);
+        exts.extend(ext(EXT_SERVER_NAME, &[0, 14, 0, 0, 11, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm']));
+        exts.extend(ext(EXT_SUPPORTED_GROUPS, &[0, 4, 0x3a, 0x3a, 0, 29]));
+        exts.extend(ext(EXT_EC_POINT_FORMATS, &[1, 0]));
+        exts.extend(ext(EXT_ALPN, &[0, 12, 2, b'h', b'2', 8, b'h', b't', b't', b'p', b'/', b'1', b'.', b'1']));
+        exts.extend(ext(EXT_SUPPORTED_VERSIONS, &[4, 3, 4, 3, 3]));
+        body.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+        body.extend(exts);
+        body
+    }
+
+    fn handshake(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut msg = vec![msg_type];
+        msg.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        msg.extend_from_slice(body);
+        msg
+    }
+
+    fn record(fragment: &[u8]) -> Vec<u8> {
+        let mut rec = vec![HANDSHAKE, 3, 1];
+        rec.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+        rec.extend_from_slice(fragment);
+        rec
+    }
+
+    fn tcp(seq: u32) -> TcpHeader {
+        TcpHeader::new(50000, 443, seq, 64240)
+    }
+
+    #[test]
+    fn test_client_hello_fields_and_ja3() {
+        let hello = parse_client_hello(&client_hello_body()).unwrap();
+        assert_eq!(hello.sni.as_deref(), Some("example.com"));
+        assert_eq!(hello.alpn, vec!["h2", "http/1.1"]);
+        assert_eq!(hello.offered_versions(), vec![0x0304, 0x0303]);
+        // GREASE cipher, extension and group are all dropped from the fingerprint.
+        assert_eq!(hello.ja3_string(), "771,4865-49199,0-10-11-16-43,29,0");
+    }
+
+    #[test]
+    fn test_handshake_split_across_records_and_segments() {
+        let msg = handshake(CLIENT_HELLO, &client_hello_body());
+        let (a, b) = msg.split_at(40);
+        let mut stream = record(a);
+        stream.extend(record(b));
+        let (seg1, seg2) = stream.split_at(30);
+
+        let mut tls = TlsAnalyzer::default();
+        tls.process_segment("10.0.0.5:50000", "1.1.1.1:443", &tcp(100), seg1, 1.0);
+        tls.process_segment("10.0.0.5:50000", "1.1.1.1:443", &tcp(100), seg1, 1.1); // retransmission
+        assert!(tls.sessions[0].sni.is_none());
+        tls.process_segment("10.0.0.5:50000", "1.1.1.1:443", &tcp(130), seg2, 1.2);
+
+        let mut server_body = vec![3, 3];
+        server_body.extend_from_slice(&[9; 32]);
+        server_body.extend_from_slice(&[0, 0x13, 0x01, 0]);
+        let exts = ext(EXT_SUPPORTED_VERSIONS, &[3, 4]);
+        server_body.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+        server_body.extend(exts);
+        let server = record(&handshake(SERVER_HELLO, &server_body));
+        tls.process_segment("1.1.1.1:443", "10.0.0.5:50000", &tcp(9000), &server, 1.3);
+
+        let mut issues = Vec::new();
+        let sessions = tls.finish(&mut issues);
+        assert_eq!(sessions.len(), 1);
+        let s = &sessions[0];
+        assert_eq!(s.client, "10.0.0.5:50000");
+        assert_eq!(s.sni.as_deref(), Some("example.com"));
+        assert_eq!(s.negotiated_version.as_deref(), Some("TLS 1.3"));
+        assert_eq!(s.negotiated_cipher.as_deref(), Some("TLS_AES_128_GCM_SHA256"));
+        assert_eq!(s.ja3s.as_deref(), Some("771,4865,43"));
+        assert!(issues.is_empty());
+    }
+
+    #[test]
+    fn test_port_reuse_starts_new_session() {
+        let hello = record(&handshake(CLIENT_HELLO, &client_hello_body()));
+        let mut tls = TlsAnalyzer::default();
+        tls.process_segment("10.0.0.5:50000", "1.1.1.1:443", &tcp(100), &hello, 1.0);
+        tls.process_segment("10.0.0.5:50000", "1.1.1.1:443", &tcp(100), &hello, 1.5); // retransmission
+        // The same ports, a new connection with a fresh ISN.
+        tls.process_segment("10.0.0.5:50000", "1.1.1.1:443", &tcp(3_000_000_000), &hello, 60.0);
+
+        let mut server_body = vec![3, 3];
+        server_body.extend_from_slice(&[9; 32]);
+        server_body.extend_from_slice(&[0, 0xc0, 0x2f, 0, 0, 0]);
+        let server = record(&handshake(SERVER_HELLO, &server_body));
+        tls.process_segment("1.1.1.1:443", "10.0.0.5:50000", &tcp(7000), &server, 60.1);
+
+        let sessions = tls.finish(&mut Vec::new());
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions[0].negotiated_cipher.is_none());
+        assert_eq!(sessions[1].timestamp, 60.0);
+        assert_eq!(sessions[1].negotiated_cipher.as_deref(), Some("TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"));
+    }
+}
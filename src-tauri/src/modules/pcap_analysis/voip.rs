@@ -0,0 +1,494 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::modules::utils::{PcapIssue, RtpStreamStats, VoipCall};
+use super::endpoint;
+
+const SIP_METHODS: [&str; 8] = ["INVITE", "ACK", "BYE", "CANCEL", "OPTIONS", "REGISTER", "UPDATE", "INFO"];
+// One-way delay is invisible from a single capture point; assume a jitter buffer of twice
+// the measured jitter plus 20ms packetization when estimating the E-model delay impairment.
+const PACKETIZATION_DELAY_MS: f64 = 20.0;
+
+struct SipMessage {
+    method: Option<String>, // None for responses
+    status: Option<u16>,
+    reason: String,
+    call_id: String,
+    cseq_method: String,
+    from: String,
+    to: String,
+    body: String,
+}
+
+#[derive(Default)]
+struct Call {
+    call_id: String,
+    from: String,
+    to: String,
+    caller: String,
+    callee: String,
+    invite_ts: f64,
+    answer_ts: Option<f64>,
+    bye_ts: Option<f64>,
+    failure: Option<String>,
+    cancelled: bool,
+    // payload type -> (encoding name, clock rate)
+    payload_types: BTreeMap<u8, (String, u32)>,
+}
+
+#[derive(Default)]
+struct RtpStream {
+    call_id: String,
+    source: String,
+    destination: String,
+    ssrc: u32,
+    payload_type: u8,
+    packets: u64,
+    base_seq: u32,
+    max_seq: u16,
+    cycles: u32,
+    out_of_order: u64,
+    last_arrival: f64,
+    last_rtp_ts: u32,
+    max_delta: f64,
+    jitter: f64, // in RTP timestamp units, per RFC 3550 A.8
+    max_jitter: f64,
+    jitter_sum: f64,
+}
+
+/// Finds SIP calls and the RTP streams negotiated in their SDP bodies.
+#[derive(Default)]
+pub struct VoipAnalyzer {
+    calls: HashMap<String, Call>,
+    call_order: Vec<String>,
+    // SDP media endpoint -> Call-ID
+    media: HashMap<String, String>,
+    streams: HashMap<(String, String, u32), RtpStream>,
+}
+
+impl VoipAnalyzer {
+    /// Handles a UDP datagram: SIP if it parses as SIP, otherwise RTP if it targets a known media endpoint.
+    pub fn process_udp(&mut self, src: &str, dst: &str, payload: &[u8], ts: f64) {
+        if self.process_sip(src, dst, payload, ts) { return; }
+        let call_id = match self.media.get(dst).or_else(|| self.media.get(src)) {
+            Some(id) => id.clone(),
+            None => return,
+        };
+        self.process_rtp(call_id, src, dst, payload, ts);
+    }
+
+    /// Returns false if the payload is not a SIP message.
+    pub fn process_sip(&mut self, src: &str, dst: &str, payload: &[u8], ts: f64) -> bool {
+        let Some(msg) = parse_sip(payload) else { return false };
+        if msg.call_id.is_empty() { return true; }
+
+        if !self.calls.contains_key(&msg.call_id) {
+            if msg.method.as_deref() != Some("INVITE") { return true; }
+            self.call_order.push(msg.call_id.clone());
+            self.calls.insert(msg.call_id.clone(), Call {
+                call_id: msg.call_id.clone(),
+                from: msg.from.clone(),
+                to: msg.to.clone(),
+                caller: src.to_string(),
+                callee: dst.to_string(),
+                invite_ts: ts,
+                ..Default::default()
+            });
+        }
+        let Some(call) = self.calls.get_mut(&msg.call_id) else { return true };
+
+        match (msg.method.as_deref(), msg.status) {
+            (Some("BYE"), _) => { call.bye_ts.get_or_insert(ts); },
+            (Some("CANCEL"), _) => call.cancelled = true,
+            (None, Some(status)) if msg.cseq_method == "INVITE" => {
+                if (200..300).contains(&status) {
+                    call.answer_ts.get_or_insert(ts);
+                } else if status >= 300 && call.answer_ts.is_none() && !matches!(status, 401 | 407 | 487) {
+                    // 401/407 are auth challenges followed by a fresh INVITE; 487 answers a CANCEL.
+                    call.failure = Some(format!("{} {}", status, msg.reason));
+                }
+            },
+            _ => {},
+        }
+
+        if !msg.body.is_empty() {
+            for media in parse_sdp(&msg.body, &mut call.payload_types) {
+                self.media.insert(media, msg.call_id.clone());
+            }
+        }
+        true
+    }
+
+    fn process_rtp(&mut self, call_id: String, src: &str, dst: &str, payload: &[u8], ts: f64) {
+        // RTP version 2, fixed 12-byte header; RTCP (PT 72-76 with the marker bit) is skipped.
+        if payload.len() < 12 || payload[0] >> 6 != 2 { return; }
+        let payload_type = payload[1] & 0x7f;
+        if (72..=76).contains(&payload_type) { return; }
+        let seq = u16::from_be_bytes([payload[2], payload[3]]);
+        let rtp_ts = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]);
+        let ssrc = u32::from_be_bytes([payload[8], payload[9], payload[10], payload[11]]);
+
+        let clock_rate = self.calls.get(&call_id)
+            .and_then(|c| c.payload_types.get(&payload_type))
+            .map(|(_, rate)| *rate)
+            .or_else(|| static_payload_type(payload_type).map(|(_, rate)| rate))
+            .unwrap_or(8000) as f64;
+
+        let key = (src.to_string(), dst.to_string(), ssrc);
+        let stream = self.streams.entry(key).or_insert_with(|| RtpStream {
+            call_id,
+            source: src.to_string(),
+            destination: dst.to_string(),
+            ssrc,
+            payload_type,
+            base_seq: seq as u32,
+            max_seq: seq,
+            ..Default::default()
+        });
+
+        if stream.packets > 0 {
+            let delta = ts - stream.last_arrival;
+            stream.max_delta = stream.max_delta.max(delta);
+
+            // RFC 3550 A.8: D(i,j) = (Rj - Ri) - (Sj - Si), J += (|D| - J) / 16
+            let d = delta * clock_rate - rtp_ts.wrapping_sub(stream.last_rtp_ts) as i32 as f64;
+            stream.jitter += (d.abs() - stream.jitter) / 16.0;
+            stream.max_jitter = stream.max_jitter.max(stream.jitter);
+            stream.jitter_sum += stream.jitter;
+
+            let step = seq.wrapping_sub(stream.max_seq) as i16;
+            if step > 0 {
+                if seq < stream.max_seq { stream.cycles += 1 << 16; }
+                stream.max_seq = seq;
+            } else {
+                stream.out_of_order += 1;
+            }
+        }
+
+        stream.packets += 1;
+        stream.last_arrival = ts;
+        stream.last_rtp_ts = rtp_ts;
+    }
+
+    pub fn finish(mut self, issues: &mut Vec<PcapIssue>) -> Vec<VoipCall> {
+        let mut streams_by_call: HashMap<String, Vec<RtpStreamStats>> = HashMap::new();
+        for stream in self.streams.into_values() {
+            let call = self.calls.get(&stream.call_id);
+            let (codec, clock_rate) = call
+                .and_then(|c| c.payload_types.get(&stream.payload_type).cloned())
+                .or_else(|| static_payload_type(stream.payload_type).map(|(n, r)| (n.to_string(), r)))
+                .unwrap_or_else(|| (format!("PT {}", stream.payload_type), 8000));
+
+            let ext_max = stream.cycles + stream.max_seq as u32;
+            let expected = (ext_max as u64 + 1).saturating_sub(stream.base_seq as u64);
+            let lost = expected.saturating_sub(stream.packets);
+            let loss_pct = if expected > 0 { lost as f64 / expected as f64 * 100.0 } else { 0.0 };
+            let to_ms = 1000.0 / clock_rate as f64;
+            let jitter_ms = stream.jitter * to_ms;
+            let mean_jitter_ms = if stream.packets > 1 { stream.jitter_sum / (stream.packets - 1) as f64 * to_ms } else { 0.0 };
+            let r_factor = r_factor(&codec, mean_jitter_ms, loss_pct);
+
+            streams_by_call.entry(stream.call_id.clone()).or_default().push(RtpStreamStats {
+                source: stream.source,
+                destination: stream.destination,
+                ssrc: format!("0x{:08X}", stream.ssrc),
+                codec,
+                packets: stream.packets,
+                expected,
+                lost,
+                loss_pct,
+                out_of_order: stream.out_of_order,
+                max_delta_ms: stream.max_delta * 1000.0,
+                jitter_ms,
+                mean_jitter_ms,
+                max_jitter_ms: stream.max_jitter * to_ms,
+                r_factor,
+                mos: mos(r_factor),
+            });
+        }
+
+        let mut calls = Vec::new();
+        let mut poor = Vec::new();
+        let mut failed = Vec::new();
+        for call_id in self.call_order {
+            let Some(call) = self.calls.remove(&call_id) else { continue };
+            let mut streams = streams_by_call.remove(&call_id).unwrap_or_default();
+            streams.sort_by(|a, b| a.source.cmp(&b.source));
+
+            // A call is only as good as its worst direction.
+            let worst = streams.iter().min_by(|a, b| a.r_factor.total_cmp(&b.r_factor));
+            let r = worst.map(|s| s.r_factor);
+            let state = if let Some(reason) = &call.failure {
+                format!("Failed ({})", reason)
+            } else if call.answer_ts.is_some() {
+                if call.bye_ts.is_some() { "Completed".to_string() } else { "In Progress".to_string() }
+            } else if call.cancelled {
+                "Cancelled".to_string()
+            } else {
+                "Unanswered".to_string()
+            };
+
+            if let Some(reason) = &call.failure { failed.push(format!("{} ({})", call.to, reason)); }
+            if let Some(m) = r.map(mos).filter(|m| *m < 3.6) { poor.push((m, call.from.clone(), call.to.clone())); }
+
+            let mut codecs: Vec<String> = call.payload_types.values().map(|(name, _)| name.clone()).collect();
+            codecs.sort();
+            codecs.dedup();
+            calls.push(VoipCall {
+                call_id: call.call_id,
+                from: call.from,
+                to: call.to,
+                caller: call.caller,
+                callee: call.callee,
+                start_ts: call.invite_ts,
+                setup_time_ms: call.answer_ts.map(|a| (a - call.invite_ts) * 1000.0),
+                duration_sec: match (call.answer_ts, call.bye_ts) {
+                    (Some(a), Some(b)) => Some(b - a),
+                    _ => None,
+                },
+                state,
+                codecs,
+                streams,
+                r_factor: r,
+                mos: r.map(mos),
+            });
+        }
+
+        if !poor.is_empty() {
+            poor.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let worst = poor[0].0;
+            let examples: Vec<String> = poor.iter().take(3).map(|(m, f, t)| format!("{} -> {} (MOS {:.1})", f, t, m)).collect();
+            issues.push(PcapIssue {
+                severity: if worst < 3.1 { "critical".into() } else { "warn".into() },
+                title: "Poor Call Quality".into(),
+                description: format!("{} call(s) below MOS 3.6: {}.", poor.len(), examples.join(", ")),
+                timestamp: None,
+            });
+        }
+
+        if !failed.is_empty() {
+            issues.push(PcapIssue {
+                severity: "warn".into(),
+                title: "Failed SIP Calls".into(),
+                description: format!("{} call(s) rejected: {}.", failed.len(), failed.iter().take(5).cloned().collect::<Vec<_>>().join(", ")),
+                timestamp: None,
+            });
+        }
+
+        calls
+    }
+}
+
+fn parse_sip(payload: &[u8]) -> Option<SipMessage> {
+    // Cheap rejection for RTP and other binary traffic before any UTF-8 work.
+    if !payload.first()?.is_ascii_uppercase() { return None; }
+    let text = std::str::from_utf8(payload).ok()?;
+    let (head, body) = text.split_once("\r\n\r\n").unwrap_or((text, ""));
+    let mut lines = head.lines();
+    let start = lines.next()?;
+
+    let (method, status, reason) = if let Some(rest) = start.strip_prefix("SIP/2.0 ") {
+        let (code, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+        (None, Some(code.parse().ok()?), reason.to_string())
+    } else {
+        let method = start.split(' ').next()?;
+        if !SIP_METHODS.contains(&method) || !start.ends_with("SIP/2.0") { return None; }
+        (Some(method.to_string()), None, String::new())
+    };
+
+    let mut msg = SipMessage {
+        method, status, reason,
+        call_id: String::new(), cseq_method: String::new(),
+        from: String::new(), to: String::new(),
+        body: body.to_string(),
+    };
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "call-id" | "i" => msg.call_id = value.to_string(),
+            "cseq" => msg.cseq_method = value.split_whitespace().nth(1).unwrap_or("").to_string(),
+            "from" | "f" => msg.from = sip_uri(value),
+            "to" | "t" => msg.to = sip_uri(value),
+            _ => {},
+        }
+    }
+    Some(msg)
+}
+
+/// `"Alice" <sip:alice@example.com>;tag=1` -> `sip:alice@example.com`
+fn sip_uri(value: &str) -> String {
+    let uri = match (value.find('<'), value.find('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value.split(';').next().unwrap_or(value),
+    };
+    uri.trim().to_string()
+}
+
+/// Returns the audio/video media endpoints and records `a=rtpmap` payload types.
+fn parse_sdp(body: &str, payload_types: &mut BTreeMap<u8, (String, u32)>) -> Vec<String> {
+    let mut session_addr: Option<String> = None;
+    // Port of the current m= section and its own c= line, if any.
+    let mut section: Option<(Option<u16>, Option<String>)> = None;
+    let mut endpoints = Vec::new();
+
+    let mut flush = |section: Option<(Option<u16>, Option<String>)>, session_addr: &Option<String>| {
+        if let Some((Some(port), addr)) = section {
+            if let Some(addr) = addr.as_ref().or(session_addr.as_ref()) {
+                if port != 0 { endpoints.push(endpoint(addr, port)); }
+            }
+        }
+    };
+
+    for line in body.lines() {
+        if let Some(conn) = line.strip_prefix("c=") {
+            // c=IN IP4 192.0.2.10
+            let addr = conn.split_whitespace().nth(2).map(|a| a.split('/').next().unwrap_or(a).to_string());
+            match section.as_mut() {
+                Some((_, section_addr)) => *section_addr = addr,
+                None => session_addr = addr,
+            }
+        } else if let Some(media) = line.strip_prefix("m=") {
+            flush(section.take(), &session_addr);
+            // m=audio 49170 RTP/AVP 0 8 101
+            let mut fields = media.split_whitespace();
+            let kind = fields.next().unwrap_or("");
+            let port = fields.next().and_then(|p| p.split('/').next()?.parse().ok());
+            let is_rtp = matches!(kind, "audio" | "video");
+            section = Some((port.filter(|_| is_rtp), None));
+            if is_rtp {
+                for pt in fields.skip(1).filter_map(|f| f.parse::<u8>().ok()) {
+                    if let Some((name, rate)) = static_payload_type(pt) {
+                        payload_types.entry(pt).or_insert((name.to_string(), rate));
+                    }
+                }
+            }
+        } else if let Some(map) = line.strip_prefix("a=rtpmap:") {
+            // a=rtpmap:111 opus/48000/2
+            let mut parts = map.split_whitespace();
+            let pt = parts.next().and_then(|p| p.parse::<u8>().ok());
+            let mut enc = parts.next().unwrap_or("").split('/');
+            if let (Some(pt), Some(name)) = (pt, enc.next()) {
+                let rate = enc.next().and_then(|r| r.parse().ok()).unwrap_or(8000);
+                payload_types.insert(pt, (name.to_string(), rate));
+            }
+        }
+    }
+    flush(section, &session_addr);
+    endpoints
+}
+
+/// RFC 3551 static audio payload types. G.722 keeps an 8kHz RTP clock for historical reasons.
+fn static_payload_type(pt: u8) -> Option<(&'static str, u32)> {
+    match pt {
+        0 => Some(("PCMU", 8000)),
+        3 => Some(("GSM", 8000)),
+        4 => Some(("G723", 8000)),
+        8 => Some(("PCMA", 8000)),
+        9 => Some(("G722", 8000)),
+        18 => Some(("G729", 8000)),
+        _ => None,
+    }
+}
+
+/// Simplified ITU-T G.107 E-model with G.113 equipment impairment (Ie) and
+/// packet-loss robustness (Bpl) values; codecs without published values use G.711's.
+fn r_factor(codec: &str, jitter_ms: f64, loss_pct: f64) -> f64 {
+    let (ie, bpl) = match codec.to_ascii_uppercase().as_str() {
+        "G729" => (11.0, 19.0),
+        "G723" => (15.0, 16.1),
+        "GSM" => (20.0, 10.0),
+        _ => (0.0, 25.1),
+    };
+    let ta = 2.0 * jitter_ms + PACKETIZATION_DELAY_MS;
+    let id = 0.024 * ta + if ta > 177.3 { 0.11 * (ta - 177.3) } else { 0.0 };
+    let ie_eff = ie + (95.0 - ie) * loss_pct / (loss_pct + bpl);
+    (93.2 - id - ie_eff).clamp(0.0, 100.0)
+}
+
+fn mos(r: f64) -> f64 {
+    if r <= 0.0 { return 1.0; }
+    if r >= 100.0 { return 4.5; }
+    1.0 + 0.035 * r + r * (r - 60.0) * (100.0 - r) * 7e-6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "10.0.0.5:5060";
+    const PBX: &str = "192.0.2.1:5060";
+
+    fn sip(start: &str, cseq: &str, sdp: Option<&str>) -> Vec<u8> {
+        let mut msg = format!(
+            "{}\r\nVia: SIP/2.0/UDP 10.0.0.5:5060\r\nFrom: \"Alice\" <sip:alice@example.com>;tag=a1\r\nTo: <sip:bob@example.com>\r\ni: call-1@10.0.0.5\r\nCSeq: {}\r\n",
+            start, cseq
+        );
+        match sdp {
+            Some(body) => msg.push_str(&format!("Content-Type: application/sdp\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)),
+            None => msg.push_str("Content-Length: 0\r\n\r\n"),
+        }
+        msg.into_bytes()
+    }
+
+    fn rtp(seq: u16, ts: u32) -> Vec<u8> {
+        let mut pkt = vec![0x80, 0];
+        pkt.extend_from_slice(&seq.to_be_bytes());
+        pkt.extend_from_slice(&ts.to_be_bytes());
+        pkt.extend_from_slice(&0x1234_5678u32.to_be_bytes());
+        pkt.extend_from_slice(&[0xff; 160]);
+        pkt
+    }
+
+    #[test]
+    fn test_call_with_rtp_stream() {
+        let offer = "v=0\r\nc=IN IP4 10.0.0.5\r\nm=audio 4000 RTP/AVP 0 101\r\na=rtpmap:101 telephone-event/8000\r\n";
+        let answer = "v=0\r\nc=IN IP4 192.0.2.50\r\nm=audio 30000 RTP/AVP 0\r\n";
+        let mut voip = VoipAnalyzer::default();
+        voip.process_udp(ALICE, PBX, &sip("INVITE sip:bob@example.com SIP/2.0", "1 INVITE", Some(offer)), 0.0);
+        voip.process_udp(PBX, ALICE, &sip("SIP/2.0 180 Ringing", "1 INVITE", None), 0.1);
+        voip.process_udp(PBX, ALICE, &sip("SIP/2.0 200 OK", "1 INVITE", Some(answer)), 2.0);
+
+        // 20ms G.711 packets: seq 3 lost, seq 6 arrives after 7, one packet 30ms late.
+        let order = [(0u16, 2.10), (1, 2.12), (2, 2.14), (4, 2.18), (5, 2.23), (7, 2.24), (6, 2.245), (8, 2.26)];
+        for (seq, arrival) in order {
+            voip.process_udp("192.0.2.50:30000", "10.0.0.5:4000", &rtp(seq, seq as u32 * 160), arrival);
+        }
+        // Unrelated UDP is ignored.
+        voip.process_udp("10.0.0.7:9999", "10.0.0.5:4002", &rtp(0, 0), 2.3);
+        voip.process_udp(ALICE, PBX, &sip("BYE sip:bob@example.com SIP/2.0", "2 BYE", None), 32.0);
+
+        let calls = voip.finish(&mut Vec::new());
+        assert_eq!(calls.len(), 1);
+        let call = &calls[0];
+        assert_eq!(call.state, "Completed");
+        assert_eq!(call.from, "sip:alice@example.com");
+        assert_eq!(call.codecs, vec!["PCMU", "telephone-event"]);
+        assert!((call.setup_time_ms.unwrap() - 2000.0).abs() < 1e-6);
+        assert!((call.duration_sec.unwrap() - 30.0).abs() < 1e-9);
+
+        assert_eq!(call.streams.len(), 1);
+        let s = &call.streams[0];
+        assert_eq!(s.codec, "PCMU");
+        assert_eq!((s.packets, s.expected, s.lost, s.out_of_order), (8, 9, 1, 1));
+        assert!((s.max_delta_ms - 50.0).abs() < 1e-6);
+        assert!(s.jitter_ms > 0.0 && s.max_jitter_ms >= s.jitter_ms);
+        assert!(call.mos.unwrap() < 4.4 && call.mos.unwrap() > 1.0);
+    }
+
+    #[test]
+    fn test_rejected_call_and_e_model() {
+        let mut voip = VoipAnalyzer::default();
+        voip.process_udp(ALICE, PBX, &sip("INVITE sip:bob@example.com SIP/2.0", "1 INVITE", None), 0.0);
+        voip.process_udp(PBX, ALICE, &sip("SIP/2.0 407 Proxy Authentication Required", "1 INVITE", None), 0.05);
+        voip.process_udp(PBX, ALICE, &sip("SIP/2.0 486 Busy Here", "2 INVITE", None), 0.3);
+        let mut issues = Vec::new();
+        let calls = voip.finish(&mut issues);
+        assert_eq!(calls[0].state, "Failed (486 Busy Here)");
+        assert!(issues.iter().any(|i| i.title == "Failed SIP Calls"));
+
+        // Clean G.711 scores ~4.4; 10% loss with heavy jitter drops well below toll quality.
+        assert!((mos(r_factor("PCMU", 0.0, 0.0)) - 4.4).abs() < 0.05);
+        assert!(mos(r_factor("PCMU", 40.0, 10.0)) < 3.6);
+        assert!(r_factor("G729", 0.0, 0.0) < r_factor("PCMA", 0.0, 0.0));
+    }
+}
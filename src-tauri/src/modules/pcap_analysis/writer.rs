@@ -0,0 +1,144 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use pcap_parser::Linktype;
+
+/// Writes legacy pcap or pcapng (one interface per link type), microsecond timestamps.
+pub struct CaptureWriter {
+    out: BufWriter<File>,
+    pcapng: bool,
+    interfaces: Vec<Linktype>,
+    // Bytes written so far, so packets can be indexed while the file is still open.
+    position: u64,
+}
+
+impl CaptureWriter {
+    pub fn create(path: &str, pcapng: bool) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let mut writer = CaptureWriter { out: BufWriter::new(file), pcapng, interfaces: Vec::new(), position: 0 };
+        if pcapng {
+            // Section Header Block: byte-order magic, version 1.0, unknown section length.
+            let mut shb = Vec::with_capacity(28);
+            shb.extend_from_slice(&0x0a0d_0d0au32.to_le_bytes());
+            shb.extend_from_slice(&28u32.to_le_bytes());
+            shb.extend_from_slice(&0x1a2b_3c4du32.to_le_bytes());
+            shb.extend_from_slice(&1u16.to_le_bytes());
+            shb.extend_from_slice(&0u16.to_le_bytes());
+            shb.extend_from_slice(&(-1i64).to_le_bytes());
+            shb.extend_from_slice(&28u32.to_le_bytes());
+            writer.put(&shb).map_err(|e| e.to_string())?;
+        }
+        Ok(writer)
+    }
+
+    /// Appends one packet and returns the file offset its data was written at.
+    pub fn write(&mut self, linktype: Linktype, ts: f64, data: &[u8], orig_len: u32) -> std::io::Result<u64> {
+        let micros = (ts * 1e6).round().max(0.0) as u64;
+        let caplen = data.len() as u32;
+        let orig_len = orig_len.max(caplen);
+        let if_id = match self.interfaces.iter().position(|l| *l == linktype) {
+            Some(id) => id,
+            None => {
+                self.start_interface(linktype)?;
+                self.interfaces.len() - 1
+            },
+        };
+
+        if !self.pcapng {
+            self.put(&((micros / 1_000_000) as u32).to_le_bytes())?;
+            self.put(&((micros % 1_000_000) as u32).to_le_bytes())?;
+            self.put(&caplen.to_le_bytes())?;
+            self.put(&orig_len.to_le_bytes())?;
+            let data_offset = self.position;
+            self.put(data)?;
+            return Ok(data_offset);
+        }
+        let padding = (4 - data.len() % 4) % 4;
+        let block_len = (32 + data.len() + padding) as u32;
+        self.put(&6u32.to_le_bytes())?;
+        self.put(&block_len.to_le_bytes())?;
+        self.put(&(if_id as u32).to_le_bytes())?;
+        self.put(&((micros >> 32) as u32).to_le_bytes())?;
+        self.put(&(micros as u32).to_le_bytes())?;
+        self.put(&caplen.to_le_bytes())?;
+        self.put(&orig_len.to_le_bytes())?;
+        let data_offset = self.position;
+        self.put(data)?;
+        self.put(&[0; 3][..padding])?;
+        self.put(&block_len.to_le_bytes())?;
+        Ok(data_offset)
+    }
+
+    /// A pcap file header, or a pcapng Interface Description Block.
+    fn start_interface(&mut self, linktype: Linktype) -> std::io::Result<()> {
+        self.interfaces.push(linktype);
+        if self.pcapng {
+            self.put(&1u32.to_le_bytes())?;
+            self.put(&20u32.to_le_bytes())?;
+            self.put(&(linktype.0 as u16).to_le_bytes())?;
+            self.put(&0u16.to_le_bytes())?;
+            self.put(&262_144u32.to_le_bytes())?;
+            self.put(&20u32.to_le_bytes())
+        } else if self.interfaces.len() == 1 {
+            self.put(&0xa1b2_c3d4u32.to_le_bytes())?;
+            self.put(&2u16.to_le_bytes())?;
+            self.put(&4u16.to_le_bytes())?;
+            self.put(&[0; 8])?;
+            self.put(&262_144u32.to_le_bytes())?;
+            self.put(&linktype.0.to_le_bytes())
+        } else {
+            // Legacy pcap has one link type per file, and so does its input.
+            Ok(())
+        }
+    }
+
+    /// Pushes buffered packets to disk so readers of the file see them.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        if !self.pcapng && self.interfaces.is_empty() {
+            self.start_interface(Linktype::ETHERNET)?;
+        }
+        self.out.flush()
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::ControlFlow;
+    use super::super::reader::CaptureReader;
+
+    #[test]
+    fn test_data_offsets_match_reader() {
+        let frames: [&[u8]; 3] = [b"\x01\x02\x03", b"\x04\x05\x06\x07\x08", &[0xaa; 64]];
+        for pcapng in [false, true] {
+            let path = std::env::temp_dir().join(format!("trubleshoota_{}_writer_{}.pcap", std::process::id(), pcapng));
+            let path = path.to_string_lossy().to_string();
+            let mut writer = CaptureWriter::create(&path, pcapng).unwrap();
+            let offsets: Vec<u64> = frames.iter().enumerate()
+                .map(|(i, frame)| writer.write(Linktype::ETHERNET, 1_700_000_000.0 + i as f64, frame, 0).unwrap())
+                .collect();
+            writer.finish().unwrap();
+
+            let mut read = Vec::new();
+            CaptureReader::open(&path).unwrap().read_packets(|p| {
+                read.push((p.data_offset, p.data.to_vec()));
+                ControlFlow::Continue(())
+            }).unwrap();
+            std::fs::remove_file(&path).ok();
+
+            assert_eq!(read.len(), frames.len());
+            for ((offset, data), (expected, frame)) in read.iter().zip(offsets.iter().zip(frames)) {
+                assert_eq!((offset, data.as_slice()), (expected, frame));
+            }
+        }
+    }
+}
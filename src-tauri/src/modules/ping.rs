@@ -0,0 +1,354 @@
+use std::net::{IpAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+#[cfg(not(windows))]
+use std::{io::{self, Read}, net::SocketAddr};
+#[cfg(not(windows))]
+use socket2::{Domain, Protocol, Socket, Type};
+#[cfg(windows)]
+use windows_sys::Win32::{
+    Foundation::{HANDLE, INVALID_HANDLE_VALUE},
+    NetworkManagement::IpHelper::{
+        Icmp6CreateFile, Icmp6SendEcho2, IcmpCloseHandle, IcmpCreateFile, IcmpSendEcho2Ex,
+        ICMPV6_ECHO_REPLY_LH, ICMP_ECHO_REPLY, IP_FLAG_DF, IP_OPTION_INFORMATION, IP_SUCCESS,
+    },
+    Networking::WinSock::{AF_INET6, IN6_ADDR, IN6_ADDR_0, SOCKADDR_IN6},
+};
+
+use crate::modules::utils::PingOptions;
+
+const MAX_COUNT: u32 = 10_000;
+// 65535 less the IPv4 and ICMP headers.
+const MAX_SIZE: usize = 65_507;
+
+#[cfg(not(windows))]
+const ECHO_REQUEST_V4: u8 = 8;
+#[cfg(not(windows))]
+const ECHO_REPLY_V4: u8 = 0;
+#[cfg(not(windows))]
+const ECHO_REQUEST_V6: u8 = 128;
+#[cfg(not(windows))]
+const ECHO_REPLY_V6: u8 = 129;
+
+/// Resolves `host` to the address to ping: an IP literal as is, otherwise the first
+/// address the system resolver returns.
+pub fn resolve(host: &str) -> Result<IpAddr, String> {
+    let host = host.trim().trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = host.parse() {
+        return Ok(ip);
+    }
+    (host, 0).to_socket_addrs()
+        .map_err(|e| format!("Could not resolve {}: {}", host, e))?
+        .next()
+        .map(|addr| addr.ip())
+        .ok_or_else(|| format!("{} has no addresses", host))
+}
+
+/// Sends `options.count` echo requests to `target`, one every `interval_ms`, and
+/// returns each probe's round-trip time in milliseconds, or None if no reply came
+/// within `timeout_ms`. Errors are for problems setting up the socket, not for loss.
+pub fn ping(target: IpAddr, options: &PingOptions) -> Result<Vec<Option<f64>>, String> {
+    if options.count == 0 || options.count > MAX_COUNT {
+        return Err(format!("The count must be between 1 and {}", MAX_COUNT));
+    }
+    if options.size > MAX_SIZE {
+        return Err(format!("The size can be at most {} bytes", MAX_SIZE));
+    }
+    if options.timeout_ms == 0 {
+        return Err("The timeout must be at least 1 ms".into());
+    }
+    let socket = EchoSocket::open(target, options)?;
+    let interval = Duration::from_millis(options.interval_ms);
+    let timeout = Duration::from_millis(options.timeout_ms);
+
+    let start = Instant::now();
+    let mut rtts = Vec::with_capacity(options.count as usize);
+    for seq in 0..options.count {
+        if let Some(wait) = (interval * seq).checked_sub(start.elapsed()) {
+            std::thread::sleep(wait);
+        }
+        rtts.push(socket.probe(seq as u16, options.size, timeout));
+    }
+    Ok(rtts)
+}
+
+/// Opens an echo socket for `target` without sending anything, so a sweep can report a
+/// missing permission once instead of as silence from every host.
+pub fn check_access(target: IpAddr) -> Result<(), String> {
+    EchoSocket::open(target, &PingOptions::default()).map(|_| ())
+}
+
+#[cfg(not(windows))]
+struct EchoSocket {
+    socket: Socket,
+    v6: bool,
+    ident: u16,
+    // Linux datagram sockets replace the identifier and only deliver their own replies.
+    check_ident: bool,
+}
+
+#[cfg(not(windows))]
+impl EchoSocket {
+    /// Prefers an unprivileged datagram ICMP socket and falls back to a raw one.
+    fn open(target: IpAddr, options: &PingOptions) -> Result<Self, String> {
+        let v6 = target.is_ipv6();
+        let (domain, protocol) = if v6 { (Domain::IPV6, Protocol::ICMPV6) } else { (Domain::IPV4, Protocol::ICMPV4) };
+        let (socket, raw) = match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+            Ok(socket) => (socket, false),
+            Err(dgram_err) => match Socket::new(domain, Type::RAW, Some(protocol)) {
+                Ok(socket) => (socket, true),
+                Err(_) => return Err(permission_error(&dgram_err)),
+            },
+        };
+        if let Some(ttl) = options.ttl {
+            let set = if v6 { socket.set_unicast_hops_v6(ttl) } else { socket.set_ttl_v4(ttl) };
+            set.map_err(|e| format!("Failed to set the TTL to {}: {}", ttl, e))?;
+        }
+        if options.dont_fragment {
+            set_dont_fragment(&socket, v6).map_err(|e| format!("Failed to set the DF bit: {}", e))?;
+        }
+        // A connected socket only hears from the target, which matters for raw sockets.
+        socket.connect(&SocketAddr::new(target, 0).into()).map_err(|e| format!("Cannot reach {}: {}", target, e))?;
+        Ok(EchoSocket {
+            socket,
+            v6,
+            ident: rand::random(),
+            check_ident: raw || !cfg!(any(target_os = "linux", target_os = "android")),
+        })
+    }
+
+    fn probe(&self, seq: u16, size: usize, timeout: Duration) -> Option<f64> {
+        let request = echo_request(self.v6, self.ident, seq, size);
+        let sent = Instant::now();
+        // Sending fails outright when DF is set and the packet exceeds the known path MTU.
+        self.socket.send(&request).ok()?;
+        let mut buf = vec![0u8; request.len() + 128];
+        loop {
+            let remaining = timeout.checked_sub(sent.elapsed()).filter(|d| !d.is_zero())?;
+            self.socket.set_read_timeout(Some(remaining)).ok()?;
+            let len = (&self.socket).read(&mut buf).ok()?;
+            let rtt = sent.elapsed();
+            // Anything else is a late reply to an earlier probe, or another ICMP message.
+            if parse_reply(self.v6, &buf[..len], self.check_ident.then_some(self.ident)) == Some(seq) {
+                return Some(rtt.as_secs_f64() * 1000.0);
+            }
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn echo_request(v6: bool, ident: u16, seq: u16, size: usize) -> Vec<u8> {
+    let mut packet = vec![if v6 { ECHO_REQUEST_V6 } else { ECHO_REQUEST_V4 }, 0, 0, 0];
+    packet.extend_from_slice(&ident.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend((0..size).map(|i| i as u8));
+    // The kernel fills in the ICMPv6 checksum, which covers a pseudo-header we can't see.
+    if !v6 {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+    packet
+}
+
+/// The sequence number of an echo reply, skipping the IPv4 header raw sockets (and
+/// non-Linux datagram sockets) deliver. `ident` is checked when given.
+#[cfg(not(windows))]
+fn parse_reply(v6: bool, data: &[u8], ident: Option<u16>) -> Option<u16> {
+    // An ICMP message starts with its type, never 0x4_; an IPv4 header always does.
+    let icmp = match data.first() {
+        Some(b) if !v6 && b >> 4 == 4 => data.get((b & 0x0f) as usize * 4..)?,
+        _ => data,
+    };
+    let reply_type = if v6 { ECHO_REPLY_V6 } else { ECHO_REPLY_V4 };
+    if icmp.len() < 8 || icmp[0] != reply_type || icmp[1] != 0 {
+        return None;
+    }
+    if ident.is_some_and(|ident| ident != u16::from_be_bytes([icmp[4], icmp[5]])) {
+        return None;
+    }
+    Some(u16::from_be_bytes([icmp[6], icmp[7]]))
+}
+
+/// RFC 1071 Internet checksum.
+#[cfg(not(windows))]
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data.chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_dont_fragment(socket: &Socket, v6: bool) -> io::Result<()> {
+    let (level, name, value) = if v6 {
+        (libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER, libc::IPV6_PMTUDISC_DO)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, libc::IP_PMTUDISC_DO)
+    };
+    set_int_option(socket, level, name, value)
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+fn set_dont_fragment(socket: &Socket, v6: bool) -> io::Result<()> {
+    let (level, name) = if v6 {
+        (libc::IPPROTO_IPV6, libc::IPV6_DONTFRAG)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_DONTFRAG)
+    };
+    set_int_option(socket, level, name, 1)
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd")))]
+fn set_dont_fragment(_socket: &Socket, _v6: bool) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "not supported on this platform"))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+fn set_int_option(socket: &Socket, level: libc::c_int, name: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(), level, name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if rc == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+#[cfg(not(windows))]
+fn permission_error(err: &io::Error) -> String {
+    if cfg!(target_os = "linux") {
+        format!(
+            "Not allowed to open an ICMP socket ({}). Allow unprivileged ping with `sysctl -w net.ipv4.ping_group_range=\"0 2147483647\"`, or grant CAP_NET_RAW: sudo setcap cap_net_raw=eip {}",
+            err,
+            std::env::current_exe().map(|p| p.display().to_string()).unwrap_or_else(|_| "<path to app>".into()),
+        )
+    } else {
+        format!("Not allowed to open an ICMP socket ({}). Run the app with administrator rights.", err)
+    }
+}
+
+/// Windows has no unprivileged ICMP socket, but the IP Helper echo API needs no rights.
+#[cfg(windows)]
+struct EchoSocket {
+    handle: HANDLE,
+    target: IpAddr,
+    options: IP_OPTION_INFORMATION,
+}
+
+#[cfg(windows)]
+impl EchoSocket {
+    fn open(target: IpAddr, options: &PingOptions) -> Result<Self, String> {
+        let handle = unsafe { if target.is_ipv6() { Icmp6CreateFile() } else { IcmpCreateFile() } };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(format!("Failed to open an ICMP handle: {}", std::io::Error::last_os_error()));
+        }
+        let ttl = options.ttl.unwrap_or(128);
+        let ttl = u8::try_from(ttl).map_err(|_| format!("Failed to set the TTL to {}: at most 255", ttl))?;
+        Ok(EchoSocket {
+            handle,
+            target,
+            options: IP_OPTION_INFORMATION {
+                Ttl: ttl,
+                Flags: if options.dont_fragment { IP_FLAG_DF as u8 } else { 0 },
+                ..Default::default()
+            },
+        })
+    }
+
+    fn probe(&self, _seq: u16, size: usize, timeout: Duration) -> Option<f64> {
+        let data: Vec<u8> = (0..size).map(|i| i as u8).collect();
+        // One reply with the echoed data, room for an ICMP error, and the IO_STATUS_BLOCK
+        // the API keeps at the end of the buffer.
+        let mut reply = vec![0u8; std::mem::size_of::<ICMPV6_ECHO_REPLY_LH>().max(std::mem::size_of::<ICMP_ECHO_REPLY>()) + size + 8 + 16];
+        let timeout_ms = timeout.as_millis().clamp(1, u32::MAX as u128) as u32;
+        let sent = Instant::now();
+        // Called without an event or APC routine, both block until the reply or the timeout.
+        let status = match self.target {
+            IpAddr::V4(ip) => unsafe {
+                let replies = IcmpSendEcho2Ex(
+                    self.handle, std::ptr::null_mut(), None, std::ptr::null(),
+                    0, u32::from_ne_bytes(ip.octets()),
+                    data.as_ptr().cast(), size as u16, &self.options,
+                    reply.as_mut_ptr().cast(), reply.len() as u32, timeout_ms,
+                );
+                if replies == 0 { return None; }
+                std::ptr::read_unaligned(reply.as_ptr().cast::<ICMP_ECHO_REPLY>()).Status
+            },
+            IpAddr::V6(ip) => unsafe {
+                let source = SOCKADDR_IN6 { sin6_family: AF_INET6, ..Default::default() };
+                let destination = SOCKADDR_IN6 {
+                    sin6_family: AF_INET6,
+                    sin6_addr: IN6_ADDR { u: IN6_ADDR_0 { Byte: ip.octets() } },
+                    ..Default::default()
+                };
+                let replies = Icmp6SendEcho2(
+                    self.handle, std::ptr::null_mut(), None, std::ptr::null(),
+                    &source, &destination,
+                    data.as_ptr().cast(), size as u16, &self.options,
+                    reply.as_mut_ptr().cast(), reply.len() as u32, timeout_ms,
+                );
+                if replies == 0 { return None; }
+                std::ptr::read_unaligned(reply.as_ptr().cast::<ICMPV6_ECHO_REPLY_LH>()).Status
+            },
+        };
+        // Anything else is an ICMP error, e.g. IP_PACKET_TOO_BIG when DF is set.
+        (status == IP_SUCCESS).then(|| sent.elapsed().as_secs_f64() * 1000.0)
+    }
+}
+
+#[cfg(windows)]
+impl Drop for EchoSocket {
+    fn drop(&mut self) {
+        unsafe { IcmpCloseHandle(self.handle); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_echo_request_checksum() {
+        let request = echo_request(false, 0x1234, 7, 56);
+        assert_eq!(request.len(), 64);
+        assert_eq!(&request[..2], &[ECHO_REQUEST_V4, 0]);
+        assert_eq!(&request[4..8], &[0x12, 0x34, 0, 7]);
+        // A packet including its own checksum sums to zero.
+        assert_eq!(checksum(&request), 0);
+        assert_eq!(&echo_request(true, 1, 1, 0)[..4], &[ECHO_REQUEST_V6, 0, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_parse_reply() {
+        let mut reply = echo_request(false, 0x1234, 9, 8);
+        reply[0] = ECHO_REPLY_V4;
+        assert_eq!(parse_reply(false, &reply, None), Some(9));
+        assert_eq!(parse_reply(false, &reply, Some(0x1234)), Some(9));
+        assert_eq!(parse_reply(false, &reply, Some(0x4321)), None);
+
+        // Raw sockets hand over the IPv4 header too.
+        let mut raw = vec![0x45, 0, 0, 36, 0, 0, 0, 0, 64, 1, 0, 0, 127, 0, 0, 1, 127, 0, 0, 1];
+        raw.extend_from_slice(&reply);
+        assert_eq!(parse_reply(false, &raw, Some(0x1234)), Some(9));
+
+        // Our own request, looped back on a raw socket, is not a reply.
+        assert_eq!(parse_reply(false, &echo_request(false, 0x1234, 9, 8), None), None);
+        let mut v6 = echo_request(true, 1, 3, 8);
+        v6[0] = ECHO_REPLY_V6;
+        assert_eq!(parse_reply(true, &v6, None), Some(3));
+        assert_eq!(parse_reply(false, &v6, None), None);
+    }
+
+    #[test]
+    fn test_resolve_literals() {
+        assert_eq!(resolve("127.0.0.1").unwrap(), IpAddr::from([127, 0, 0, 1]));
+        assert_eq!(resolve("[::1]").unwrap(), "::1".parse::<IpAddr>().unwrap());
+        assert!(ping(IpAddr::from([127, 0, 0, 1]), &PingOptions { count: 0, ..Default::default() }).is_err());
+    }
+}
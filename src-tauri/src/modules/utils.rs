@@ -6,11 +6,38 @@ use std::os::windows::process::CommandExt;
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PingResult {
     pub host: String,
+    pub address: Option<String>, // what `host` resolved to
     pub status: String,
-    pub time_ms: Option<u64>,
+    pub time_ms: Option<f64>, // first reply
+    pub rtts_ms: Vec<Option<f64>>, // one per probe; None if it got no reply
     pub output: String,
 }
 
+/// Options for the ICMP echo engine.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PingOptions {
+    pub count: u32,
+    pub interval_ms: u64,
+    pub size: usize, // ICMP payload bytes, as `ping -s`
+    pub ttl: Option<u32>,
+    pub dont_fragment: bool,
+    pub timeout_ms: u64, // per probe
+}
+
+impl Default for PingOptions {
+    fn default() -> Self {
+        PingOptions {
+            count: 1,
+            interval_ms: 1000,
+            size: 56,
+            ttl: None,
+            dont_fragment: false,
+            timeout_ms: 1000,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TcpResult {
     pub host: String,
@@ -22,9 +49,10 @@ pub struct TcpResult {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct JitterResult {
     pub host: String,
-    pub avg_latency: u64,
+    pub avg_latency: f64,
     pub jitter: f64,
     pub packet_loss: f64,
+    pub rtts_ms: Vec<Option<f64>>,
     pub details: String,
 }
 
@@ -45,11 +73,90 @@ pub struct NatResult {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DnsRecord {
+    pub name: String,
     pub record_type: String,
     pub value: String,
     pub ttl: u32,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DnsLookupResult {
+    pub domain: String,
+    pub record_type: String,
+    pub nameserver: String, // the server that answered, or the last one tried
+    pub transport: String, // UDP or TCP
+    pub status: String, // response code (NOERROR, NXDOMAIN, ...) or Error
+    pub authoritative: bool,
+    pub truncated: bool,
+    pub recursion_available: bool,
+    pub time_ms: Option<f64>,
+    pub records: Vec<DnsRecord>, // answer section
+    pub details: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EncryptedDnsResult {
+    pub protocol: String, // DoH, DoT or DoQ
+    pub target: String,
+    pub server: Option<String>, // address connected to
+    pub status: String, // OK, Certificate Error, Blocked or Error
+    pub connect_ms: Option<f64>, // TCP connect; None for DoQ
+    pub handshake_ms: Option<f64>, // TLS (plus HTTP/2 setup for DoH), or the whole QUIC handshake
+    pub query_ms: Option<f64>,
+    pub certificate_valid: Option<bool>,
+    pub certificate_error: Option<String>,
+    pub response: Option<DnsLookupResult>,
+    pub plain: Option<DnsLookupResult>, // the same query over UDP port 53
+    pub answers_match: Option<bool>,
+    pub details: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DnsBenchmarkResult {
+    pub domains: Vec<String>,
+    pub resolvers: Vec<DnsBenchmarkResolver>, // fastest (cold median) first
+    pub disagreements: Vec<DnsDisagreement>,
+    pub fastest: Option<String>,
+    pub time_ms: u64,
+    pub details: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DnsBenchmarkResolver {
+    pub name: String, // System, Gateway, Cloudflare, Google, Quad9 or Custom
+    pub address: String,
+    pub queries: usize,
+    pub failures: usize, // timeouts, errors, SERVFAIL, REFUSED
+    pub failure_rate: f64, // percent
+    pub cold_avg_ms: Option<f64>, // first query for each domain
+    pub cold_median_ms: Option<f64>,
+    pub cached_avg_ms: Option<f64>, // immediate repeat, normally served from cache
+    pub cached_median_ms: Option<f64>,
+    pub nxdomain_hijack: bool, // answered a name that cannot exist
+    pub hijack_answers: Vec<String>,
+    pub disagreements: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DnsDisagreement {
+    pub domain: String,
+    pub resolver: String, // name (address)
+    pub status: String,
+    pub answers: Vec<String>,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DnsBenchmarkProgress {
+    pub resolver: String,
+    pub domain: String,
+    pub phase: String, // cold, cached or nxdomain
+    pub status: String,
+    pub time_ms: Option<f64>,
+    pub completed: usize,
+    pub total: usize,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TracerouteHop {
     pub hop: u8,
@@ -113,13 +220,39 @@ pub struct Conversation {
     pub protocol: String,
     pub bytes: u64,
     pub packets: u64,
+    pub flows: usize,
 }
 
+/// One TCP or UDP flow, keyed by 5-tuple. The client is the side that opened it.
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
-pub struct TcpAnalysisStats {
+pub struct FlowStats {
+    pub protocol: String,
+    pub client: String,
+    pub server: String,
+    pub packets_to_server: u64,
+    pub bytes_to_server: u64,
+    pub packets_to_client: u64,
+    pub bytes_to_client: u64,
+    pub start_ts: f64,
+    pub end_ts: f64,
+    pub duration_sec: f64,
+    pub handshake_rtt_ms: Option<f64>,
     pub retransmissions: usize,
+    pub close_reason: Option<String>, // "FIN", "RST", "Timeout"; None if still open
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TcpAnalysisStats {
+    pub retransmissions: usize, // all kinds, including fast and spurious
+    pub fast_retransmissions: usize,
+    pub spurious_retransmissions: usize,
+    pub out_of_order: usize,
+    pub duplicate_acks: usize,
+    pub lost_segments: usize,
     pub zero_window: usize,
+    pub zero_window_probes: usize,
     pub window_full: usize,
+    pub keep_alives: usize,
     pub resets: usize,
     pub avg_rtt_ms: Option<f64>,
 }
@@ -127,12 +260,549 @@ pub struct TcpAnalysisStats {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PcapAnalysisResult {
     pub packet_count: usize,
+    pub filtered_out: usize, // packets skipped by the display filter; not in packet_count
     pub duration_sec: f64,
     pub issues: Vec<PcapIssue>,
     pub top_talkers: Vec<String>,
     pub conversations: Vec<Conversation>,
+    pub flows: Vec<FlowStats>,
     pub protocol_distribution: HashMap<String, usize>,
     pub tcp_stats: TcpAnalysisStats,
+    pub tls_sessions: Vec<TlsSession>,
+    pub quic: QuicSummary,
+    pub http: Vec<HttpTransaction>,
+    pub dns: DnsSummary,
+    pub voip_calls: Vec<VoipCall>,
+    pub fragmentation: FragmentationStats,
+    pub dhcp: DhcpSummary,
+    pub arp: ArpSummary,
+    pub icmp_errors: IcmpErrorSummary,
+    pub cancelled: bool, // stopped early; everything above covers only the packets read
+}
+
+/// One row of the packet list.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapPacketSummary {
+    pub number: usize, // 1-based
+    pub timestamp: f64,
+    pub relative_time: f64, // since the first packet
+    pub length: usize,
+    pub source: String,
+    pub destination: String,
+    pub protocol: String,
+    pub info: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapPacketPage {
+    pub total: usize,
+    pub packets: Vec<PcapPacketSummary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapField {
+    pub name: String,
+    pub value: String,
+}
+
+/// One protocol layer of a decoded packet, outermost first.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapLayer {
+    pub name: String,
+    pub fields: Vec<PcapField>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapPacketDetail {
+    pub summary: PcapPacketSummary,
+    pub layers: Vec<PcapLayer>,
+    pub hex_dump: String,
+}
+
+/// The reassembled payload of a TCP or UDP conversation, in capture order.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapStream {
+    pub protocol: String,
+    pub client: String,
+    pub server: String,
+    pub format: String, // "ascii" or "hex"
+    pub segments: Vec<PcapStreamSegment>,
+    pub bytes_to_server: u64,
+    pub bytes_to_client: u64,
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapStreamSegment {
+    pub from_client: bool,
+    pub timestamp: f64,
+    pub data: String,
+}
+
+/// The same traffic captured at two points, e.g. either side of a NAT or firewall.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapComparison {
+    pub file_a: String,
+    pub file_b: String,
+    pub packets_a: usize,
+    pub packets_b: usize,
+    pub matched: usize,
+    pub clock_offset_ms: Option<f64>, // B's clock minus A's; None unless traffic crossed both ways
+    pub flows: Vec<ComparedFlow>,
+    pub nat_translations: Vec<NatTranslation>,
+    pub issues: Vec<PcapIssue>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ComparedFlow {
+    pub protocol: String,
+    pub direction: String, // "a_to_b", "b_to_a", "a_only", "b_only"
+    pub source: String, // as seen by the sending side
+    pub destination: String,
+    pub translated_source: String, // as seen by the receiving side; empty if never seen there
+    pub translated_destination: String,
+    pub packets_sent: usize,
+    pub packets_received: usize,
+    pub dropped: usize,
+    pub ttl_decrement: Option<i32>,
+    pub dscp_sent: Option<u8>,
+    pub dscp_received: Option<u8>,
+    pub dscp_remarked: usize, // packets whose DSCP changed in transit
+    pub min_latency_ms: Option<f64>,
+    pub avg_latency_ms: Option<f64>,
+    pub max_latency_ms: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NatTranslation {
+    pub protocol: String,
+    pub seen_in_a: String,
+    pub seen_in_b: String,
+    pub flows: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PcapSanitizeOptions {
+    pub key: Option<String>, // same key, same mapping across files; random when absent
+    pub anonymize_ips: bool,
+    pub rewrite_macs: bool,
+    pub hash_dns_names: bool,
+    pub payload: String, // "keep", "zero" or "truncate"
+    pub payload_bytes: usize, // with "truncate", leading payload bytes kept
+}
+
+impl Default for PcapSanitizeOptions {
+    fn default() -> Self {
+        PcapSanitizeOptions {
+            key: None,
+            anonymize_ips: true,
+            rewrite_macs: true,
+            hash_dns_names: true,
+            payload: "truncate".into(),
+            payload_bytes: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapSanitizeReport {
+    pub output_path: String,
+    pub format: String, // "pcap" or "pcapng", as the input
+    pub packets: usize,
+    pub addresses_anonymized: usize,
+    pub macs_rewritten: usize,
+    pub dns_names_hashed: usize, // DNS messages rewritten
+    pub payload_bytes_removed: u64,
+    pub undecoded_packets: usize, // only the link header kept, the rest zeroed
+}
+
+/// A flow as listed in `PcapAnalysisResult::flows`; client and server may be swapped.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapFlowRef {
+    pub protocol: String, // "TCP" or "UDP"
+    pub client: String,
+    pub server: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PcapIoGraphOptions {
+    pub interval_ms: u64, // bucket width
+    pub flow: Option<PcapFlowRef>, // chart one flow instead of the whole capture
+    pub line_rate_mbps: Option<f64>, // intervals faster than this are microbursts
+}
+
+impl Default for PcapIoGraphOptions {
+    fn default() -> Self {
+        PcapIoGraphOptions { interval_ms: 1000, flow: None, line_rate_mbps: None }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct IoGraphBucket {
+    pub start: f64, // seconds since the first packet of the capture
+    pub packets: usize,
+    pub bytes: u64, // on the wire
+    pub packets_per_sec: f64,
+    pub bits_per_sec: f64,
+    pub retransmissions: usize,
+    pub resets: usize,
+    pub dns_queries: usize,
+    pub new_flows: usize,
+}
+
+/// Consecutive intervals above the line rate.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Microburst {
+    pub start: f64, // seconds since the first packet of the capture
+    pub duration_ms: f64,
+    pub bytes: u64,
+    pub peak_bits_per_sec: f64,
+    pub peak_utilization: f64, // percent of the line rate, over 100
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapIoGraph {
+    pub interval_ms: u64,
+    pub start_ts: f64, // absolute time of `start` 0
+    pub flow: Option<String>, // "TCP a <-> b" when charting one flow
+    pub buckets: Vec<IoGraphBucket>, // every interval, empty ones included
+    pub peak_bits_per_sec: f64,
+    pub microbursts: Vec<Microburst>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PcapCaptureOptions {
+    pub bpf_filter: Option<String>, // compiled program as printed by `tcpdump -dd` or `-ddd`
+    pub snaplen: u32,
+    pub ring_buffer_mb: u32,
+    pub max_packets: Option<usize>,
+    pub max_bytes: Option<u64>,
+    pub max_seconds: Option<u64>,
+}
+
+impl Default for PcapCaptureOptions {
+    fn default() -> Self {
+        PcapCaptureOptions {
+            bpf_filter: None,
+            snaplen: 262_144,
+            ring_buffer_mb: 32,
+            max_packets: None,
+            max_bytes: None,
+            max_seconds: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapCaptureStats {
+    pub job_id: String,
+    pub packets: usize,
+    pub bytes: u64, // on the wire
+    pub dropped: u64, // by the kernel, ring buffer full
+    pub elapsed_sec: f64,
+    pub packets_per_sec: f64, // over the last interval
+    pub bits_per_sec: f64,
+    pub flagged_packets: usize, // packets flagged so far; issues are only summarised at the end
+}
+
+/// Payload of the `pcap_progress` event.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PcapProgress {
+    pub job_id: String,
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+    pub packets: usize,
+    pub flagged_packets: usize, // packets flagged so far; issues are only summarised at the end
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TlsSession {
+    pub client: String,
+    pub server: String,
+    pub timestamp: f64,
+    pub sni: Option<String>,
+    pub offered_versions: Vec<String>,
+    pub offered_ciphers: Vec<String>,
+    pub alpn_offered: Vec<String>,
+    pub ja3: Option<String>,
+    pub ja3_hash: Option<String>,
+    pub negotiated_version: Option<String>,
+    pub negotiated_cipher: Option<String>,
+    pub alpn_selected: Option<String>,
+    pub ja3s: Option<String>,
+    pub ja3s_hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct QuicSummary {
+    pub packets: usize,
+    pub versions: HashMap<String, usize>, // long-header packets per version
+    pub version_negotiations: usize,
+    pub http3_connections: usize, // "h3" offered in ALPN
+    pub blocked_connections: usize, // Initials never answered
+    pub connections: Vec<QuicConnection>,
+}
+
+/// One QUIC connection, grouped by connection ID. The client is the side that sent
+/// the first Initial.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct QuicConnection {
+    pub client: String,
+    pub server: String,
+    pub version: String,
+    pub original_dcid: String, // hex, from the client's first Initial
+    pub client_cid: String,
+    pub server_cid: Option<String>,
+    pub first_seen: f64,
+    pub last_seen: f64,
+    pub packets_to_server: usize,
+    pub packets_to_client: usize,
+    pub bytes_to_server: u64,
+    pub bytes_to_client: u64,
+    pub initials: usize, // from the client
+    pub sni: Option<String>, // from the ClientHello in the decrypted Initials
+    pub alpn_offered: Vec<String>,
+    pub answered: bool,
+    pub tcp_fallback: bool, // client then opened TCP/443 to the same server
+}
+
+/// One HTTP/1.x request and the response paired with it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct HttpTransaction {
+    pub client: String,
+    pub server: String,
+    pub timestamp: f64,
+    pub method: String,
+    pub host: Option<String>,
+    pub uri: String,
+    pub user_agent: Option<String>,
+    pub status: Option<u16>, // None if no response was seen
+    pub content_type: Option<String>,
+    pub body_bytes: Option<u64>, // response body, de-chunked
+    pub response_time_ms: Option<f64>, // last request byte to first response byte
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DnsSummary {
+    pub queries: usize,
+    pub responses: usize,
+    pub unanswered: usize,
+    pub nxdomain: usize,
+    pub servfail: usize,
+    pub avg_latency_ms: Option<f64>,
+    pub top_domains: Vec<DnsDomainStats>,
+    pub slow_responses: Vec<DnsSlowResponse>,
+    pub resolvers: Vec<DnsResolverStats>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DnsDomainStats {
+    pub domain: String,
+    pub queries: usize,
+    pub unique_subdomains: usize,
+    pub avg_query_len: f64,
+    pub max_query_len: usize,
+    pub avg_label_entropy: f64,
+    pub txt_null_queries: usize,
+    pub nxdomain: usize,
+    pub queries_per_sec: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DnsSlowResponse {
+    pub query: String,
+    pub record_type: String,
+    pub client: String,
+    pub resolver: String,
+    pub latency_ms: f64,
+    pub rcode: String,
+    pub answers: Vec<String>,
+    pub timestamp: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DnsResolverStats {
+    pub resolver: String,
+    pub queries: usize,
+    pub responses: usize,
+    pub failures: usize,
+    pub unanswered: usize,
+    pub avg_latency_ms: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FragmentationStats {
+    pub fragments: usize,
+    pub reassembled: usize,
+    pub overlapping: usize,
+    pub conflicting: usize, // overlaps whose bytes disagree
+    pub incomplete: usize,  // still waiting for fragments when the capture ended
+    pub timed_out: usize,
+    pub dropped: usize,     // not tracked because too many datagrams were pending
+    pub flows: Vec<FragmentedFlow>,
+}
+
+/// Fragments per flow. Ports are included when the first fragment was seen.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FragmentedFlow {
+    pub source: String,
+    pub destination: String,
+    pub protocol: String,
+    pub fragments: usize,
+    pub datagrams: usize,
+    pub reassembled: usize,
+    pub max_datagram_size: u32,
+    pub max_fragment_size: u64, // on the wire
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DhcpSummary {
+    pub discovers: usize,
+    pub offers: usize,
+    pub requests: usize,
+    pub acks: usize,
+    pub naks: usize,
+    pub unanswered_discovers: usize, // transactions no server made an offer for
+    pub avg_time_to_lease_ms: Option<f64>,
+    pub max_time_to_lease_ms: Option<f64>,
+    pub clients: Vec<DhcpClient>,
+    pub servers: Vec<DhcpServerStats>,
+}
+
+/// One client's DHCP exchanges, keyed by its hardware address. Lease details come
+/// from the last ACK it received.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DhcpClient {
+    pub mac: String,
+    pub hostname: Option<String>,
+    pub discovers: usize,
+    pub offers: usize,
+    pub requests: usize,
+    pub acks: usize,
+    pub naks: usize,
+    pub unanswered_discovers: usize,
+    pub offered_by: Vec<String>,
+    pub leased_ip: Option<String>,
+    pub server: Option<String>,
+    pub time_to_lease_ms: Option<f64>, // first DISCOVER (or REQUEST, for renewals) to the ACK
+    pub lease_time_sec: Option<u32>,
+    pub subnet_mask: Option<String>,
+    pub routers: Vec<String>,
+    pub dns_servers: Vec<String>,
+    pub domain: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DhcpServerStats {
+    pub server: String, // server identifier option, or the reply's source address
+    pub offers: usize,
+    pub acks: usize,
+    pub naks: usize,
+    pub clients: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ArpSummary {
+    pub requests: usize,
+    pub replies: usize,
+    pub gratuitous: usize, // announcements: sender and target address are the same
+    pub probes: usize, // address conflict checks, sent from 0.0.0.0
+    pub unanswered_requests: usize,
+    pub hosts: usize, // distinct IPv4 addresses announced or resolved
+    pub unanswered: Vec<ArpUnanswered>,
+    pub duplicate_ips: Vec<ArpDuplicateIp>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ArpUnanswered {
+    pub target: String,
+    pub requests: usize,
+    pub requesters: Vec<String>,
+}
+
+/// An address answered for by more than one MAC at about the same time.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ArpDuplicateIp {
+    pub ip: String,
+    pub macs: Vec<String>,
+    pub timestamp: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct IcmpErrorSummary {
+    pub errors: usize,
+    pub by_kind: HashMap<String, usize>, // e.g. "Port Unreachable", "TTL Exceeded"
+    pub unmatched: usize,                // errors whose quoted header couldn't be read
+    pub flows: Vec<IcmpErrorFlow>,
+    pub routers: Vec<IcmpErrorRouter>,
+}
+
+/// ICMP errors about one flow, keyed by the quoted header of the packet that caused them.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IcmpErrorFlow {
+    pub protocol: String,
+    pub source: String,
+    pub destination: String,
+    pub errors: usize,
+    pub kinds: Vec<String>,
+    pub reported_by: Vec<String>,
+    pub next_hop_mtu: Option<u32>, // smallest MTU from Fragmentation Needed / Packet Too Big
+    pub first_seen: f64,
+    pub last_seen: f64,
+}
+
+/// ICMP errors sent by one router or host.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IcmpErrorRouter {
+    pub router: String,
+    pub errors: usize,
+    pub unreachable: usize,
+    pub prohibited: usize, // administratively filtered
+    pub ttl_exceeded: usize,
+    pub too_big: usize,
+    pub redirects: usize,
+    pub flows: usize,
+    pub next_hop_mtu: Option<u32>,
+}
+
+/// A SIP dialog found in a capture, with the RTP streams negotiated in its SDP.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VoipCall {
+    pub call_id: String,
+    pub from: String,
+    pub to: String,
+    pub caller: String,
+    pub callee: String,
+    pub start_ts: f64,
+    pub setup_time_ms: Option<f64>, // INVITE to 200 OK
+    pub duration_sec: Option<f64>,  // 200 OK to BYE
+    pub state: String,
+    pub codecs: Vec<String>,
+    pub streams: Vec<RtpStreamStats>,
+    pub r_factor: Option<f64>, // worst stream
+    pub mos: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RtpStreamStats {
+    pub source: String,
+    pub destination: String,
+    pub ssrc: String,
+    pub codec: String,
+    pub packets: u64,
+    pub expected: u64,
+    pub lost: u64,
+    pub loss_pct: f64,
+    pub out_of_order: u64,
+    pub max_delta_ms: f64,
+    pub jitter_ms: f64, // RFC 3550 estimate at end of stream
+    pub mean_jitter_ms: f64,
+    pub max_jitter_ms: f64,
+    pub r_factor: f64,
+    pub mos: f64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -150,26 +820,6 @@ pub struct PortScanProgress {
 }
 
 // --- Helpers ---
-pub fn parse_ping_time(output: &str) -> Option<u64> {
-     if let Some(pos) = output.find("time") {
-         let part = &output[pos..];
-         let end = part.find("ms").or_else(|| part.find(" "))?;
-         let raw = part[..end]
-            .replace("time", "")
-            .replace("=", "")
-            .replace("<", "")
-            .trim()
-            .to_string();
-         
-         if let Ok(val) = raw.parse::<f64>() {
-             return Some(val.round() as u64);
-         }
-         raw.parse::<u64>().ok()
-     } else {
-         None
-     }
-}
-
 pub fn get_mac_from_arp(ip: &str) -> String {
     #[cfg(target_os = "windows")]
     let args = ["-a"];
@@ -197,31 +847,42 @@ pub fn get_mac_from_arp(ip: &str) -> String {
     "Unknown".to_string()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parse_ping_time_windows() {
-        let output = "Reply from 8.8.8.8: bytes=32 time=14ms TTL=118";
-        assert_eq!(parse_ping_time(output), Some(14));
+/// The IPv4 default gateway, from the routing table.
+pub fn get_default_gateway() -> Option<std::net::IpAddr> {
+    #[cfg(target_os = "linux")]
+    {
+        // Columns: Iface Destination Gateway ..., with addresses in little-endian hex.
+        let table = std::fs::read_to_string("/proc/net/route").ok()?;
+        table.lines().skip(1).find_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 3 || parts[1] != "00000000" {
+                return None;
+            }
+            let gateway = u32::from_str_radix(parts[2], 16).ok().filter(|&g| g != 0)?;
+            Some(std::net::IpAddr::from(gateway.to_le_bytes()))
+        })
     }
+    #[cfg(not(target_os = "linux"))]
+    {
+        #[cfg(target_os = "windows")]
+        let args = ["print", "-4", "0.0.0.0"];
+        #[cfg(not(target_os = "windows"))]
+        let args = ["-n", "get", "default"];
 
-    #[test]
-    fn test_parse_ping_time_linux() {
-        // Linux often outputs float like 14.2 ms
-        let output = "64 bytes from 8.8.8.8: icmp_seq=1 ttl=118 time=14.2 ms";
-        // This is expected to FAIL with current implementation if it contains dot
-        let parsed = parse_ping_time(output);
-        // We want it to be handled, maybe truncated or rounded.
-        // If implementation is strict u64, it returns None.
-        // Let's assert what we WANT (robustness).
-        assert!(parsed.is_some(), "Should parse linux float time");
-    }
-    
-    #[test]
-    fn test_parse_ping_time_lessthan1() {
-        let output = "Reply from 8.8.8.8: bytes=32 time<1ms TTL=118";
-        assert_eq!(parse_ping_time(output), Some(1)); // The replace("<", "") logic should handle this
+        let mut cmd = Command::new("route");
+        cmd.args(args);
+        #[cfg(target_os = "windows")]
+        cmd.creation_flags(0x08000000);
+
+        let output = cmd.output().ok()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout.lines().find_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                // Windows: "0.0.0.0  0.0.0.0  <gateway>  <interface>  <metric>"; macOS: "gateway: <gateway>"
+                ["0.0.0.0", "0.0.0.0", gateway, ..] | ["gateway:", gateway] => gateway.parse().ok(),
+                _ => None,
+            }
+        })
     }
 }
@@ -122,7 +122,7 @@ function App() {
           } else {
             addLog({ target: "LAN", type: "SCAN", status: "PASS", details: "Isolation Verified (Minimal Traffic)" });
           }
-        } catch (e) { console.error(e); }
+        } catch (e) { addLog({ target: "LAN", type: "SCAN", status: "FAIL", details: `Scan Error: ${e}` }); }
       }
 
       if (activeProfile.mtu_check) {
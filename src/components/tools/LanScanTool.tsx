@@ -7,10 +7,12 @@ import { LanDevice } from "../../types";
 export const LanScanTool = () => {
     const [devices, setDevices] = useState<LanDevice[]>([]);
     const [scanning, setScanning] = useState(false);
+    const [error, setError] = useState<string | null>(null);
     const unlistenRef = useRef<(() => void) | null>(null);
 
     const startScan = async () => {
         setDevices([]);
+        setError(null);
         setScanning(true);
 
         unlistenRef.current = await listen<LanDevice>('lan_scan_progress', (event) => {
@@ -31,6 +33,7 @@ export const LanScanTool = () => {
             setDevices(res);
         } catch (e) {
             console.error(e);
+            setError(String(e));
         } finally {
             setScanning(false);
             if (unlistenRef.current) unlistenRef.current();
@@ -77,7 +80,8 @@ export const LanScanTool = () => {
                     {devices.length === 0 && !scanning && (
                         <div className="col-span-full flex flex-col items-center justify-center text-green-900/50 h-64">
                             <Radar size={48} className="mb-2 opacity-50" />
-                            <span className="uppercase tracking-widest text-xs">Sector Silent. Initiate Scan.</span>
+                            <span className="uppercase tracking-widest text-xs">{error ? "Sweep Failed" : "Sector Silent. Initiate Scan."}</span>
+                            {error && <span className="text-red-500 text-xs font-mono mt-2 max-w-xl text-center">{error}</span>}
                         </div>
                     )}
                 </div>
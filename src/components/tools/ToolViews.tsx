@@ -2,26 +2,51 @@ import { useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import { Search, Network, Zap } from "lucide-react";
-import { DnsRecord, PortScanResult, ThroughputResult } from "../../types";
+import { DnsLookupResult, PortScanResult, ThroughputResult } from "../../types";
+
+const DNS_RECORD_TYPES = ["A", "AAAA", "CNAME", "MX", "NS", "TXT", "SOA", "SRV", "CAA", "PTR"];
 
 export const DnsTool = ({ host }: { host: string }) => {
-    const [results, setResults] = useState<DnsRecord[]>([]);
+    const [recordType, setRecordType] = useState("A");
+    const [nameserver, setNameserver] = useState("");
+    const [transport, setTransport] = useState("udp");
+    const [result, setResult] = useState<DnsLookupResult | null>(null);
 
     const run = async () => {
-        setResults([]);
-        try { setResults(await invoke<DnsRecord[]>('run_nslookup', { domain: host, typeStr: "A" })); } catch (e) { console.error(e); }
+        setResult(null);
+        try {
+            setResult(await invoke<DnsLookupResult>('run_nslookup', {
+                domain: host, typeStr: recordType, nameserver: nameserver.trim() || null, transport,
+            }));
+        } catch (e) { console.error(e); }
     };
 
+    const flags = result ? [result.authoritative && "AA", result.truncated && "TC", result.recursion_available && "RA"].filter(Boolean).join(" ") : "";
+
     return (
         <div className="bg-black/40 border border-green-900/30 rounded-xl p-6 shadow-sm h-full flex flex-col">
             <div className="flex justify-between items-center mb-4">
                 <h3 className="font-bold uppercase tracking-wider text-sm flex items-center gap-2 text-green-400"><Search size={16} /> Git DA IP (DNS)</h3>
                 <button onClick={run} className="px-4 py-1.5 bg-green-900/40 text-green-400 border border-green-900/50 rounded text-sm hover:bg-green-900/60 uppercase font-bold tracking-wider">Lookup</button>
             </div>
+            <div className="flex gap-2 items-center bg-green-900/10 border border-green-900/30 p-2 rounded-lg mb-4 text-green-400">
+                <select value={recordType} onChange={(e) => setRecordType(e.target.value)} className="bg-black border border-green-900/50 text-green-400 text-xs rounded p-1 outline-none font-bold uppercase cursor-pointer">
+                    {DNS_RECORD_TYPES.map(t => <option key={t} value={t} className="bg-black text-green-400">{t}</option>)}
+                </select>
+                <input type="text" placeholder="System resolver" className="flex-1 min-w-0 bg-transparent border-b border-green-700/50 text-sm outline-none text-green-300 placeholder:text-green-900" value={nameserver} onChange={(e) => setNameserver(e.target.value)} />
+                <select value={transport} onChange={(e) => setTransport(e.target.value)} className="bg-black border border-green-900/50 text-green-400 text-xs rounded p-1 outline-none font-bold uppercase cursor-pointer">
+                    <option value="udp" className="bg-black text-green-400">UDP</option>
+                    <option value="tcp" className="bg-black text-green-400">TCP</option>
+                </select>
+            </div>
             <div className="bg-black/50 rounded-lg p-4 font-mono text-xs flex-1 overflow-auto border border-green-900/20">
-                {results.length === 0 ? <span className="opacity-30 text-green-700">Waiting for target...</span> :
-                    results.map((r, i) => <div key={i} className="flex justify-between border-b border-green-900/20 pb-1 mb-1"><span className="text-green-500 font-bold">{r.record_type}</span><span className="text-green-200">{r.value}</span></div>)
-                }
+                {!result ? <span className="opacity-30 text-green-700">Waiting for target...</span> : <>
+                    <div className="border-b border-green-900/40 pb-1 mb-2">
+                        <span className={result.status === "NOERROR" ? "text-green-400 font-bold" : "text-red-500 font-bold"}>{result.status}{flags && ` [${flags}]`}</span>
+                        <div className="text-green-700">{result.details}</div>
+                    </div>
+                    {result.records.map((r, i) => <div key={i} className="flex justify-between gap-2 border-b border-green-900/20 pb-1 mb-1"><span className="text-green-500 font-bold">{r.record_type}</span><span className="text-green-200 break-all">{r.value}</span><span className="text-green-700">{r.ttl}s</span></div>)}
+                </>}
             </div>
         </div>
     );
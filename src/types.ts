@@ -1,10 +1,25 @@
 // ... existing imports
 export interface DnsRecord {
+    name: string;
     record_type: string;
     value: string;
     ttl: number;
 }
 
+export interface DnsLookupResult {
+    domain: string;
+    record_type: string;
+    nameserver: string;
+    transport: string;
+    status: string;
+    authoritative: boolean;
+    truncated: boolean;
+    recursion_available: boolean;
+    time_ms: number | null;
+    records: DnsRecord[];
+    details: string;
+}
+
 export interface TracerouteHop {
     hop: number;
     ip: string;